@@ -0,0 +1,3 @@
+-- Tags used to target newsletter issues at a segment of subscribers.
+ALTER TABLE subscriptions ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}';
+CREATE INDEX subscriptions_tags_idx ON subscriptions USING GIN (tags);
@@ -0,0 +1,31 @@
+-- Publications run from this deployment. Each list has its own sender and
+-- confirmation copy; a NULL sender falls back to the email client default.
+CREATE TABLE lists (
+    list_id uuid NOT NULL,
+    slug TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    sender_name TEXT NULL,
+    sender_email TEXT NULL,
+    confirmation_subject TEXT NOT NULL,
+    confirmation_message TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY (list_id)
+);
+
+-- Existing subscribers and issues belong to the default list.
+INSERT INTO lists (
+    list_id,
+    slug,
+    name,
+    confirmation_subject,
+    confirmation_message,
+    created_at
+)
+VALUES (
+    '5f0d1c38-7b8e-4d9a-9c3e-2a6b1f4e8d10',
+    'default',
+    'Newsletter',
+    'Welcome!',
+    'Welcome to our newsletter!',
+    now()
+);
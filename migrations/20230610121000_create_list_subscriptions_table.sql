@@ -0,0 +1,16 @@
+-- Membership of a subscriber in a list. `subscriptions` keeps one row per
+-- email address, confirmation status is tracked per list.
+CREATE TABLE list_subscriptions (
+    list_id uuid NOT NULL
+        REFERENCES lists (list_id),
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id),
+    status TEXT NOT NULL,
+    subscribed_at timestamptz NOT NULL,
+    PRIMARY KEY (list_id, subscriber_id)
+);
+
+INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+SELECT list_id, id, status, subscribed_at
+FROM subscriptions, lists
+WHERE lists.slug = 'default';
@@ -0,0 +1,10 @@
+BEGIN;
+    -- Confirmation tokens confirm the membership of a single list.
+    ALTER TABLE subscription_tokens ADD COLUMN list_id uuid NULL
+        REFERENCES lists (list_id);
+
+    UPDATE subscription_tokens
+        SET list_id = (SELECT list_id FROM lists WHERE slug = 'default');
+
+    ALTER TABLE subscription_tokens ALTER COLUMN list_id SET NOT NULL;
+COMMIT;
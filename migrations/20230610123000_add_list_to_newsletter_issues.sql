@@ -0,0 +1,10 @@
+BEGIN;
+    -- Issues are sent to the confirmed members of a single list.
+    ALTER TABLE newsletter_issues ADD COLUMN list_id uuid NULL
+        REFERENCES lists (list_id);
+
+    UPDATE newsletter_issues
+        SET list_id = (SELECT list_id FROM lists WHERE slug = 'default');
+
+    ALTER TABLE newsletter_issues ALTER COLUMN list_id SET NOT NULL;
+COMMIT;
@@ -0,0 +1,2 @@
+-- Subscribers can pause delivery from the preference center.
+ALTER TABLE subscriptions ADD COLUMN paused_until timestamptz NULL;
@@ -0,0 +1,9 @@
+-- Pending changes of a subscriber's address, confirmed from the new address.
+CREATE TABLE email_change_requests(
+    email_change_token TEXT NOT NULL,
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id) ON DELETE CASCADE,
+    new_email TEXT NOT NULL,
+    requested_at timestamptz NOT NULL,
+    PRIMARY KEY (email_change_token)
+);
@@ -0,0 +1,18 @@
+BEGIN;
+    -- Queue rows point at the subscriber, whose address is looked up at send time.
+    ALTER TABLE issue_delivery_queue ADD COLUMN subscriber_id uuid NULL
+        REFERENCES subscriptions (id) ON DELETE CASCADE;
+
+    UPDATE issue_delivery_queue q
+        SET subscriber_id = s.id
+        FROM subscriptions s
+        WHERE s.email = q.subscriber_email;
+
+    -- Nobody left to deliver to.
+    DELETE FROM issue_delivery_queue WHERE subscriber_id IS NULL;
+
+    ALTER TABLE issue_delivery_queue ALTER COLUMN subscriber_id SET NOT NULL;
+    ALTER TABLE issue_delivery_queue DROP CONSTRAINT issue_delivery_queue_pkey;
+    ALTER TABLE issue_delivery_queue ADD PRIMARY KEY (newsletter_issue_id, subscriber_id);
+    ALTER TABLE issue_delivery_queue DROP COLUMN subscriber_email;
+COMMIT;
@@ -0,0 +1,10 @@
+-- Deliveries the worker dropped because the subscriber was no longer eligible.
+CREATE TABLE skipped_deliveries (
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id) ON DELETE CASCADE,
+    reason TEXT NOT NULL,
+    skipped_at timestamptz NOT NULL,
+    PRIMARY KEY (newsletter_issue_id, subscriber_id)
+);
@@ -0,0 +1,5 @@
+-- Optional second factor for admin logins. The last accepted time step is
+-- kept so that a code cannot be replayed.
+ALTER TABLE users
+    ADD COLUMN totp_secret TEXT NULL,
+    ADD COLUMN totp_last_used_step BIGINT NULL;
@@ -0,0 +1,8 @@
+-- One-time codes to log in without the authenticator app, stored hashed.
+CREATE TABLE totp_recovery_codes(
+    user_id uuid NOT NULL
+        REFERENCES users (user_id) ON DELETE CASCADE,
+    code_hash TEXT NOT NULL,
+    used_at timestamptz NULL,
+    PRIMARY KEY (user_id, code_hash)
+);
@@ -0,0 +1,2 @@
+-- Where password reset links are sent. Optional for existing users.
+ALTER TABLE users ADD COLUMN email TEXT NULL UNIQUE;
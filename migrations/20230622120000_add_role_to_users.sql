@@ -0,0 +1,7 @@
+-- Users from before roles existed keep full access, new ones start with the least.
+BEGIN;
+    ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'owner'
+        CHECK (role IN ('owner', 'editor', 'author', 'viewer'));
+
+    ALTER TABLE users ALTER COLUMN role SET DEFAULT 'viewer';
+COMMIT;
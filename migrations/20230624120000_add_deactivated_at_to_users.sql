@@ -0,0 +1,2 @@
+-- Deactivated users cannot log in until an owner reactivates them.
+ALTER TABLE users ADD COLUMN deactivated_at timestamptz NULL;
@@ -0,0 +1,9 @@
+-- Invitations to become an admin user, sent by email by an owner.
+CREATE TABLE user_invitations (
+    token_hash TEXT NOT NULL,
+    email TEXT NOT NULL,
+    role TEXT NOT NULL CHECK (role IN ('owner', 'editor', 'author', 'viewer')),
+    invited_by uuid NULL REFERENCES users (user_id) ON DELETE SET NULL,
+    invited_at timestamptz NOT NULL,
+    PRIMARY KEY (token_hash)
+);
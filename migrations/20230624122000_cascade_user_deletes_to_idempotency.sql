@@ -0,0 +1,6 @@
+-- Saved responses belong to their user and go away with them.
+BEGIN;
+    ALTER TABLE idempotency DROP CONSTRAINT idempotency_user_id_fkey;
+    ALTER TABLE idempotency ADD CONSTRAINT idempotency_user_id_fkey
+        FOREIGN KEY (user_id) REFERENCES users (user_id) ON DELETE CASCADE;
+COMMIT;
@@ -0,0 +1,12 @@
+-- Personal tokens for programmatic access to the JSON API. Only a hash of the
+-- token is stored, it is shown to its owner once.
+CREATE TABLE api_tokens (
+    api_token_id uuid NOT NULL,
+    user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    scopes TEXT[] NOT NULL,
+    created_at timestamptz NOT NULL,
+    last_used_at timestamptz NULL,
+    PRIMARY KEY (api_token_id)
+);
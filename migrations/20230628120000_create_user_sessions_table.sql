@@ -0,0 +1,13 @@
+-- One row per logged in session, so that users can see and sign out their
+-- sessions. The session itself lives in Redis; a session without its row is
+-- logged out.
+CREATE TABLE user_sessions (
+    session_id uuid NOT NULL,
+    user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL,
+    last_seen_at timestamptz NOT NULL,
+    ip TEXT NULL,
+    user_agent TEXT NULL,
+    PRIMARY KEY (session_id)
+);
+CREATE INDEX user_sessions_user_id_idx ON user_sessions (user_id);
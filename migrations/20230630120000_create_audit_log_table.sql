@@ -0,0 +1,24 @@
+-- What was done in the admin area, by whom. Rows are never changed or
+-- deleted, so the actor's username is copied rather than referenced.
+CREATE TABLE audit_log (
+    audit_event_id uuid NOT NULL,
+    occurred_at timestamptz NOT NULL,
+    actor_id uuid NULL,
+    actor_username TEXT NULL,
+    action TEXT NOT NULL,
+    target TEXT NOT NULL,
+    ip TEXT NULL,
+    changes JSONB NOT NULL,
+    PRIMARY KEY (audit_event_id)
+);
+CREATE INDEX audit_log_occurred_at_idx ON audit_log (occurred_at);
+
+CREATE FUNCTION reject_audit_log_changes() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'The audit log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_log_is_append_only
+    BEFORE UPDATE OR DELETE ON audit_log
+    FOR EACH ROW EXECUTE FUNCTION reject_audit_log_changes();
@@ -0,0 +1,3 @@
+-- Issues written before this are left without an author.
+ALTER TABLE newsletter_issues
+    ADD COLUMN author_id uuid NULL REFERENCES users (user_id) ON DELETE SET NULL;
@@ -0,0 +1,8 @@
+-- Single-use sign-in links sent by email. Only a hash of the token is stored.
+CREATE TABLE login_link_tokens(
+    token_hash TEXT NOT NULL,
+    user_id uuid NOT NULL
+        REFERENCES users (user_id) ON DELETE CASCADE,
+    requested_at timestamptz NOT NULL,
+    PRIMARY KEY (token_hash)
+);
@@ -0,0 +1,2 @@
+-- Sessions logged in with "remember me" outlast the idle timeout.
+ALTER TABLE user_sessions ADD COLUMN remember_me BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,3 @@
+-- Tells a retry from another request reusing the key. Keys saved before have
+-- none and match any request.
+ALTER TABLE idempotency ADD COLUMN request_hash TEXT;
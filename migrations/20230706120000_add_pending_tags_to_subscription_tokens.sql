@@ -0,0 +1,3 @@
+-- Tags submitted for an existing subscriber only apply once the token is
+-- used, so that strangers cannot retag someone else's address.
+ALTER TABLE subscription_tokens ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,22 @@
+-- Subscribers of a list an issue goes to, narrowed down by a segment. Both
+-- the recipient preview and publishing select from this, so that the count
+-- shown is the number of emails sent. Empty `include_tags` matches everyone.
+CREATE FUNCTION segment_recipients(
+    list_id uuid,
+    include_tags TEXT[],
+    exclude_tags TEXT[],
+    subscribed_after timestamptz,
+    subscribed_before timestamptz
+) RETURNS TABLE (subscriber_id uuid) AS $$
+    SELECT s.id
+    FROM list_subscriptions l
+    JOIN subscriptions s ON s.id = l.subscriber_id
+    WHERE
+        l.list_id = segment_recipients.list_id AND
+        l.status = 'confirmed' AND
+        (s.paused_until IS NULL OR s.paused_until <= now()) AND
+        (cardinality(include_tags) = 0 OR s.tags && include_tags) AND
+        NOT (s.tags && exclude_tags) AND
+        (subscribed_after IS NULL OR l.subscribed_at >= subscribed_after) AND
+        (subscribed_before IS NULL OR l.subscribed_at < subscribed_before)
+$$ LANGUAGE sql STABLE;
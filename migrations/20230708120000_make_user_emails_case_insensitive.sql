@@ -0,0 +1,5 @@
+-- Single sign-on finds users by lower(email), so two accounts must not have
+-- addresses differing only in case. The index keeps the constraint's name,
+-- which invitations rely on to tell a taken address from a taken username.
+ALTER TABLE users DROP CONSTRAINT users_email_key;
+CREATE UNIQUE INDEX users_email_key ON users (lower(email));
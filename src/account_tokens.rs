@@ -0,0 +1,162 @@
+use anyhow::Context;
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    domain::SubscriberEmail, email_client::EmailClient, routes::generate_subscription_token,
+};
+
+/// Tokens that stand in for a user's credentials (reset and sign-in links,
+/// invitations, API tokens) are stored hashed, as they are as good as a
+/// password while valid.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Single-use links mailed to the address of a user.
+#[derive(Clone, Copy, Debug)]
+pub enum AccountLink {
+    PasswordReset,
+    SignIn,
+}
+
+impl AccountLink {
+    fn subject(self) -> &'static str {
+        match self {
+            Self::PasswordReset => "Reset your password",
+            Self::SignIn => "Your sign-in link",
+        }
+    }
+
+    /// What following the link does, to complete "Click here to ...".
+    fn action(self) -> &'static str {
+        match self {
+            Self::PasswordReset => "choose a new password",
+            Self::SignIn => "sign in",
+        }
+    }
+
+    fn validity(self) -> &'static str {
+        match self {
+            Self::PasswordReset => "valid for one hour",
+            Self::SignIn => "valid for 15 minutes and can only be used once",
+        }
+    }
+
+    /// Only the latest link of a kind can be used.
+    async fn replace_token(
+        self,
+        transaction: &mut Transaction<'static, Postgres>,
+        user_id: Uuid,
+        token: &str,
+    ) -> Result<(), anyhow::Error> {
+        match self {
+            Self::PasswordReset => {
+                sqlx::query!(
+                    r#"DELETE FROM password_reset_tokens WHERE user_id = $1"#,
+                    user_id
+                )
+                .execute(&mut *transaction)
+                .await
+                .context("Failed to drop previous password reset tokens.")?;
+                sqlx::query!(
+                    r#"
+                    INSERT INTO password_reset_tokens (token_hash, user_id, requested_at)
+                    VALUES ($1, $2, $3)
+                    "#,
+                    hash_token(token),
+                    user_id,
+                    Utc::now()
+                )
+                .execute(&mut *transaction)
+                .await
+                .context("Failed to store the password reset token.")?;
+            }
+            Self::SignIn => {
+                sqlx::query!(
+                    r#"DELETE FROM login_link_tokens WHERE user_id = $1"#,
+                    user_id
+                )
+                .execute(&mut *transaction)
+                .await
+                .context("Failed to drop previous sign-in links.")?;
+                sqlx::query!(
+                    r#"
+                    INSERT INTO login_link_tokens (token_hash, user_id, requested_at)
+                    VALUES ($1, $2, $3)
+                    "#,
+                    hash_token(token),
+                    user_id,
+                    Utc::now()
+                )
+                .execute(&mut *transaction)
+                .await
+                .context("Failed to store the sign-in link token.")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Mail a link of `kind` if an active user has this address, replacing the
+/// previous one. Nothing tells the caller whether a user does, so that the
+/// forms cannot be used to probe for accounts. `link` builds the URL from the
+/// new token.
+pub async fn mail_account_link(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    kind: AccountLink,
+    link: impl FnOnce(&str) -> String,
+) -> Result<(), anyhow::Error> {
+    let Some(user) = sqlx::query!(
+        r#"SELECT user_id FROM users WHERE email = $1 AND deactivated_at IS NULL"#,
+        email.as_ref()
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up the user by email.")?
+    else {
+        tracing::info!("No user has this email address.");
+        return Ok(());
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let token = generate_subscription_token();
+    kind.replace_token(&mut transaction, user.user_id, &token)
+        .await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store the link token.")?;
+
+    let link = link(&token);
+    email_client
+        .send_mail(
+            email,
+            kind.subject(),
+            &format!(
+                "Click <a href=\"{}\">here</a> to {}. The link is {}.<br />\
+                If you did not ask for it, you can ignore this email.",
+                link,
+                kind.action(),
+                kind.validity()
+            ),
+            &format!(
+                "Visit {} to {}. The link is {}.\n\
+                If you did not ask for it, you can ignore this email.",
+                link,
+                kind.action(),
+                kind.validity()
+            ),
+        )
+        .await
+        .context("Failed to send the link email.")?;
+
+    Ok(())
+}
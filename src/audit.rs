@@ -0,0 +1,264 @@
+use actix_web::HttpRequest;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+/// Something done in the admin area that is worth recording.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AuditAction {
+    Login,
+    FailedLogin,
+    PublishIssue,
+    ChangePassword,
+    ResetPassword,
+    InviteUser,
+    ChangeRole,
+    DeactivateUser,
+    ReactivateUser,
+    DeleteUser,
+    SetSubscriberTags,
+    ChangeSubscriberEmail,
+    ImportSubscribers,
+    ProvisionUser,
+    ChangeAccountEmail,
+    CreateApiToken,
+    RevokeApiToken,
+    AcceptInvitation,
+    CreateList,
+    UpdateList,
+}
+
+impl AuditAction {
+    pub const ALL: [Self; 20] = [
+        Self::Login,
+        Self::FailedLogin,
+        Self::PublishIssue,
+        Self::ChangePassword,
+        Self::ResetPassword,
+        Self::InviteUser,
+        Self::ChangeRole,
+        Self::DeactivateUser,
+        Self::ReactivateUser,
+        Self::DeleteUser,
+        Self::SetSubscriberTags,
+        Self::ChangeSubscriberEmail,
+        Self::ImportSubscribers,
+        Self::ProvisionUser,
+        Self::ChangeAccountEmail,
+        Self::CreateApiToken,
+        Self::RevokeApiToken,
+        Self::AcceptInvitation,
+        Self::CreateList,
+        Self::UpdateList,
+    ];
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|action| action.as_str() == s)
+            .ok_or_else(|| format!("{} is not a known action.", s))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Login => "login",
+            Self::FailedLogin => "failed_login",
+            Self::PublishIssue => "publish_issue",
+            Self::ChangePassword => "change_password",
+            Self::ResetPassword => "reset_password",
+            Self::InviteUser => "invite_user",
+            Self::ChangeRole => "change_role",
+            Self::DeactivateUser => "deactivate_user",
+            Self::ReactivateUser => "reactivate_user",
+            Self::DeleteUser => "delete_user",
+            Self::SetSubscriberTags => "set_subscriber_tags",
+            Self::ChangeSubscriberEmail => "change_subscriber_email",
+            Self::ImportSubscribers => "import_subscribers",
+            Self::ProvisionUser => "provision_user",
+            Self::ChangeAccountEmail => "change_account_email",
+            Self::CreateApiToken => "create_api_token",
+            Self::RevokeApiToken => "revoke_api_token",
+            Self::AcceptInvitation => "accept_invitation",
+            Self::CreateList => "create_list",
+            Self::UpdateList => "update_list",
+        }
+    }
+}
+
+impl std::fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An entry of the audit log, as shown on the audit page.
+pub struct AuditEvent {
+    pub occurred_at: DateTime<Utc>,
+    /// `None` for anonymous requests, e.g. failed logins.
+    pub actor_username: Option<String>,
+    pub action: String,
+    pub target: String,
+    pub ip: Option<String>,
+    pub changes: Value,
+}
+
+impl AuditEvent {
+    pub fn has_changes(&self) -> bool {
+        self.changes != serde_json::json!({})
+    }
+}
+
+/// Which events to list. Empty fields match everything, as the filter form
+/// sends them.
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+pub struct AuditFilter {
+    #[serde(default)]
+    pub action: String,
+    #[serde(default)]
+    pub actor: String,
+    /// Matches any part of the target, ignoring case.
+    #[serde(default)]
+    pub target: String,
+}
+
+/// Describe a changed value in the `changes` of an event.
+pub fn change(from: impl serde::Serialize, to: impl serde::Serialize) -> Value {
+    serde_json::json!({ "from": from, "to": to })
+}
+
+/// Append an event to the audit log. `target` names what was acted on, e.g.
+/// a username, and `changes` is a JSON object describing what changed.
+#[tracing::instrument(name = "Record audit event", skip(executor, request, changes))]
+pub async fn record_event(
+    executor: impl PgExecutor<'_>,
+    request: &HttpRequest,
+    actor_id: Option<Uuid>,
+    action: AuditAction,
+    target: &str,
+    changes: Value,
+) -> Result<(), anyhow::Error> {
+    // The peer address, as forwarding headers can be set by the client.
+    let ip = request.peer_addr().map(|addr| addr.ip().to_string());
+    sqlx::query!(
+        r#"
+        INSERT INTO audit_log (
+            audit_event_id,
+            occurred_at,
+            actor_id,
+            actor_username,
+            action,
+            target,
+            ip,
+            changes
+        )
+        VALUES ($1, now(), $2, (SELECT username FROM users WHERE user_id = $2), $3, $4, $5, $6)
+        "#,
+        Uuid::new_v4(),
+        actor_id,
+        action.as_str(),
+        target,
+        ip,
+        changes
+    )
+    .execute(executor)
+    .await
+    .context("Failed to record the audit event.")?;
+    Ok(())
+}
+
+/// Newest first. Without a `limit`, every matching event is returned.
+#[tracing::instrument(name = "Get audit events", skip(pool))]
+pub async fn get_audit_events(
+    pool: &PgPool,
+    filter: &AuditFilter,
+    limit: Option<i64>,
+) -> Result<Vec<AuditEvent>, anyhow::Error> {
+    sqlx::query_as!(
+        AuditEvent,
+        r#"
+        SELECT occurred_at, actor_username, action, target, ip, changes
+        FROM audit_log
+        WHERE
+            ($1 = '' OR action = $1) AND
+            ($2 = '' OR actor_username = $2) AND
+            ($3 = '' OR strpos(lower(target), lower($3)) > 0)
+        ORDER BY occurred_at DESC
+        LIMIT $4
+        "#,
+        filter.action,
+        filter.actor,
+        filter.target,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the audit events.")
+}
+
+/// One line per event, for spreadsheets.
+pub fn to_csv(events: &[AuditEvent]) -> String {
+    let mut csv = String::from("occurred_at,actor,action,target,ip,changes\r\n");
+    for event in events {
+        let fields = [
+            event.occurred_at.to_rfc3339(),
+            event.actor_username.clone().unwrap_or_default(),
+            event.action.clone(),
+            event.target.clone(),
+            event.ip.clone().unwrap_or_default(),
+            event.changes.to_string(),
+        ];
+        let line: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&line.join(","));
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+/// Quote the field if needed. Values a spreadsheet would run as a formula are
+/// prefixed with a quote, targets come from user input.
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", value)
+    } else {
+        value.to_owned()
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{csv_field, AuditAction};
+
+    #[test]
+    fn actions_round_trip_through_their_names() {
+        for action in AuditAction::ALL {
+            assert_eq!(AuditAction::parse(action.as_str()), Ok(action));
+        }
+        assert!(AuditAction::parse("drop_tables").is_err());
+    }
+
+    #[test]
+    fn plain_csv_fields_are_left_alone() {
+        assert_eq!(csv_field("ursula"), "ursula");
+    }
+
+    #[test]
+    fn csv_fields_with_separators_or_quotes_are_quoted() {
+        assert_eq!(csv_field("le guin, ursula"), "\"le guin, ursula\"");
+        assert_eq!(csv_field(r#"{"to":"x"}"#), r#""{""to"":""x""}""#);
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn csv_fields_looking_like_formulas_are_defused() {
+        assert_eq!(csv_field("=HYPERLINK(\"x\")"), "\"'=HYPERLINK(\"\"x\"\")\"");
+        assert_eq!(csv_field("@SUM(A1)"), "'@SUM(A1)");
+        assert_eq!(csv_field("-1"), "'-1");
+    }
+}
@@ -0,0 +1,200 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use super::Role;
+use crate::account_tokens::hash_token;
+
+/// Prefix of every token, so that leaked ones are easy to recognise.
+const TOKEN_PREFIX: &str = "z2p_";
+
+/// What an API token may be used for, on top of what the role of its owner
+/// allows.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ApiScope {
+    IssuesRead,
+    IssuesWrite,
+    SubscribersRead,
+    SubscribersWrite,
+}
+
+impl ApiScope {
+    pub const ALL: [ApiScope; 4] = [
+        ApiScope::IssuesRead,
+        ApiScope::IssuesWrite,
+        ApiScope::SubscribersRead,
+        ApiScope::SubscribersWrite,
+    ];
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid scope.", s))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::IssuesRead => "issues:read",
+            Self::IssuesWrite => "issues:write",
+            Self::SubscribersRead => "subscribers:read",
+            Self::SubscribersWrite => "subscribers:write",
+        }
+    }
+}
+
+impl std::fmt::Display for ApiScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A token as listed to its owner. The token itself cannot be shown again.
+pub struct ApiToken {
+    pub api_token_id: Uuid,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// The user behind a valid token, with what the token allows.
+pub struct ApiTokenOwner {
+    pub user_id: Uuid,
+    pub role: Role,
+    pub scopes: Vec<ApiScope>,
+}
+
+/// Store a new token for the user and return it. This is the only time the
+/// token is available in clear.
+#[tracing::instrument(name = "Create an API token", skip(executor))]
+pub async fn create_api_token(
+    user_id: Uuid,
+    name: &str,
+    scopes: &[ApiScope],
+    executor: impl PgExecutor<'_>,
+) -> Result<String, anyhow::Error> {
+    let mut rng = thread_rng();
+    let secret: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(40)
+        .collect();
+    let token = format!("{}{}", TOKEN_PREFIX, secret);
+    let scopes: Vec<String> = scopes.iter().map(|s| s.as_str().to_owned()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO api_tokens (api_token_id, user_id, name, token_hash, scopes, created_at)
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        Uuid::new_v4(),
+        user_id,
+        name,
+        hash_token(&token),
+        &scopes
+    )
+    .execute(executor)
+    .await
+    .context("Failed to store the API token.")?;
+    Ok(token)
+}
+
+#[tracing::instrument(name = "Get API tokens", skip(pool))]
+pub async fn get_api_tokens(user_id: Uuid, pool: &PgPool) -> Result<Vec<ApiToken>, anyhow::Error> {
+    sqlx::query_as!(
+        ApiToken,
+        r#"
+        SELECT api_token_id, name, scopes, created_at, last_used_at
+        FROM api_tokens
+        WHERE user_id = $1
+        ORDER BY created_at DESC
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the API tokens.")
+}
+
+/// Returns the name of the revoked token, `None` if the user has no such
+/// token.
+#[tracing::instrument(name = "Revoke an API token", skip(executor))]
+pub async fn revoke_api_token(
+    user_id: Uuid,
+    api_token_id: Uuid,
+    executor: impl PgExecutor<'_>,
+) -> Result<Option<String>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        DELETE FROM api_tokens
+        WHERE api_token_id = $1 AND user_id = $2
+        RETURNING name
+        "#,
+        api_token_id,
+        user_id
+    )
+    .fetch_optional(executor)
+    .await
+    .context("Failed to revoke the API token.")?;
+    Ok(row.map(|r| r.name))
+}
+
+/// Look up the owner of a token and record that it was used. Tokens of
+/// deactivated users are not valid.
+#[tracing::instrument(name = "Authenticate an API token", skip(token, pool))]
+pub async fn authenticate_api_token(
+    token: &str,
+    pool: &PgPool,
+) -> Result<Option<ApiTokenOwner>, anyhow::Error> {
+    let Some(row) = sqlx::query!(
+        r#"
+        UPDATE api_tokens t
+        SET last_used_at = now()
+        FROM users u
+        WHERE
+            t.token_hash = $1 AND
+            u.user_id = t.user_id AND
+            u.deactivated_at IS NULL
+        RETURNING t.user_id, t.scopes, u.role
+        "#,
+        hash_token(token)
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up the API token.")?
+    else {
+        return Ok(None);
+    };
+
+    let role = Role::parse(&row.role).map_err(anyhow::Error::msg)?;
+    // Scopes that no longer exist are dropped rather than failing the request.
+    let scopes = row
+        .scopes
+        .iter()
+        .filter_map(|s| ApiScope::parse(s).ok())
+        .collect();
+    Ok(Some(ApiTokenOwner {
+        user_id: row.user_id,
+        role,
+        scopes,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ApiScope;
+
+    #[test]
+    fn scopes_round_trip_through_their_names() {
+        for scope in ApiScope::ALL {
+            assert_eq!(ApiScope::parse(scope.as_str()), Ok(scope));
+        }
+    }
+
+    #[test]
+    fn unknown_scopes_are_rejected() {
+        assert!(ApiScope::parse("issues:delete").is_err());
+        assert!(ApiScope::parse("").is_err());
+    }
+}
@@ -4,14 +4,27 @@ use actix_web::{
     body::MessageBody,
     dev::{ServiceRequest, ServiceResponse},
     error::InternalError,
-    FromRequest, HttpMessage,
+    http::{
+        header::{self, ContentType, HeaderValue},
+        Method, StatusCode,
+    },
+    web, FromRequest, HttpMessage, HttpResponse,
 };
 use actix_web_lab::middleware::Next;
+use askama::Template;
+use sqlx::PgPool;
 use uuid::Uuid;
 
+use super::{
+    authenticate_api_token, get_role, get_session_epoch, start_session, touch_session, ApiScope,
+    Role,
+};
 use crate::{
+    configuration::SessionSettings,
+    problem::Problem,
     session_state::TypedSession,
-    utils::{e500, see_other},
+    templates::{CsrfErrorTemplate, ForbiddenTemplate},
+    utils::{e500, peek_body, see_other},
 };
 
 #[derive(Copy, Clone, Debug)]
@@ -31,6 +44,38 @@ impl Deref for UserId {
     }
 }
 
+/// The logged-in user with their role, available to every handler of the
+/// admin scope.
+#[derive(Copy, Clone, Debug)]
+pub struct AuthenticatedUser {
+    pub user_id: UserId,
+    pub role: Role,
+}
+
+impl AuthenticatedUser {
+    /// Answer with a 403 page unless the user has at least `role`.
+    pub fn require(&self, role: Role) -> Result<(), actix_web::Error> {
+        if self.role >= role {
+            return Ok(());
+        }
+        let html = ForbiddenTemplate {
+            required_role: role.as_str(),
+        }
+        .render()
+        .expect("Could not render forbidden template.");
+        let response = HttpResponse::Forbidden()
+            .content_type(ContentType::html())
+            .body(html);
+        let e = anyhow::anyhow!("The user's role is {}, {} is needed", self.role, role);
+        Err(InternalError::from_response(e, response).into())
+    }
+}
+
+/// Only sessions holding a user id are logged in. A session that passed the
+/// password check but still owes its second factor holds a pending user id
+/// instead, and is sent back to the login page like an anonymous one.
+/// Sessions from before the user's sessions were revoked, that were signed
+/// out from the sessions page or that have expired are logged out.
 pub async fn reject_anonymous_users(
     mut req: ServiceRequest,
     next: Next<impl MessageBody>,
@@ -40,15 +85,200 @@ pub async fn reject_anonymous_users(
         TypedSession::from_request(http_request, payload).await
     }?;
 
-    match session.get_user_id().map_err(e500)? {
-        Some(user_id) => {
-            req.extensions_mut().insert(UserId(user_id));
-            next.call(req).await
-        }
+    let Some(user_id) = session.get_user_id().map_err(e500)? else {
+        let response = see_other("/login");
+        let e = anyhow::anyhow!("The user has not logged in");
+        return Err(InternalError::from_response(e, response).into());
+    };
+
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .ok_or_else(|| e500("The database pool is not configured."))?;
+    let session_settings = req
+        .app_data::<web::Data<SessionSettings>>()
+        .ok_or_else(|| e500("The session settings are not configured."))?;
+    let session_epoch = get_session_epoch(user_id, pool).await.map_err(e500)?;
+    if session_epoch != Some(session.get_session_epoch().map_err(e500)?) {
+        session.log_out();
+        let response = see_other("/login");
+        let e = anyhow::anyhow!("The session has been revoked");
+        return Err(InternalError::from_response(e, response).into());
+    }
+
+    let session_id = match session.get_session_id().map_err(e500)? {
+        Some(session_id) => session_id,
         None => {
-            let response = see_other("/login");
-            let e = anyhow::anyhow!("The user has not logged in");
-            Err(InternalError::from_response(e, response).into())
+            // Logged in before sessions were tracked.
+            let session_id = start_session(user_id, false, req.request(), session_settings, pool)
+                .await
+                .map_err(e500)?;
+            session.insert_session_id(session_id).map_err(e500)?;
+            session_id
+        }
+    };
+    if !touch_session(user_id, session_id, session_settings, pool)
+        .await
+        .map_err(e500)?
+    {
+        session.log_out();
+        let response = see_other("/login");
+        let e = anyhow::anyhow!("The session has been signed out or has expired");
+        return Err(InternalError::from_response(e, response).into());
+    }
+
+    let role = get_role(user_id, pool).await.map_err(e500)?;
+    req.extensions_mut().insert(UserId(user_id));
+    req.extensions_mut().insert(AuthenticatedUser {
+        user_id: UserId(user_id),
+        role,
+    });
+    next.call(req).await
+}
+
+/// The synchronizer token of the session, which every admin form embeds in a
+/// hidden `csrf_token` field.
+#[derive(Clone, Debug)]
+pub struct CsrfToken(String);
+
+impl Deref for CsrfToken {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Header for clients that send the token without a form, e.g. from scripts.
+const CSRF_TOKEN_HEADER: &str = "X-CSRF-Token";
+
+/// Give the session a CSRF token if it has none yet, and reject any request
+/// but GET and HEAD that does not send it back, so that other sites cannot
+/// make a logged in browser submit admin forms. Must run after
+/// `reject_anonymous_users`. The JSON API is not concerned: it does not use
+/// cookies.
+pub async fn reject_forged_requests(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let session = {
+        let (http_request, payload) = req.parts_mut();
+        TypedSession::from_request(http_request, payload).await
+    }?;
+    let token = session.get_or_insert_csrf_token().map_err(e500)?;
+
+    if req.method() != Method::GET && req.method() != Method::HEAD {
+        let sent = match req
+            .headers()
+            .get(CSRF_TOKEN_HEADER)
+            .and_then(|value| value.to_str().ok())
+        {
+            Some(sent) => Some(sent.to_owned()),
+            None => form_csrf_token(&mut req).await?,
+        };
+        if !sent.is_some_and(|sent| tokens_match(&sent, &token)) {
+            let html = CsrfErrorTemplate {}
+                .render()
+                .expect("Could not render CSRF error template.");
+            let response = HttpResponse::Forbidden()
+                .content_type(ContentType::html())
+                .body(html);
+            let e = anyhow::anyhow!("The request did not carry the CSRF token of the session");
+            return Err(InternalError::from_response(e, response).into());
         }
     }
+
+    req.extensions_mut().insert(CsrfToken(token));
+    next.call(req).await
+}
+
+/// Read the `csrf_token` field of a form body, leaving the body in place for
+/// the handler.
+async fn form_csrf_token(req: &mut ServiceRequest) -> Result<Option<String>, actix_web::Error> {
+    let body = peek_body(req).await?;
+    let fields: Vec<(String, String)> = serde_urlencoded::from_bytes(&body).unwrap_or_default();
+    Ok(fields
+        .into_iter()
+        .find(|(key, _)| key == "csrf_token")
+        .map(|(_, value)| value))
+}
+
+/// Compares every byte, so that the time taken does not tell how much of the
+/// token was right.
+fn tokens_match(sent: &str, expected: &str) -> bool {
+    sent.len() == expected.len()
+        && sent
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+/// A request authenticated with an API token rather than a session.
+#[derive(Clone, Debug)]
+pub struct ApiClient {
+    pub user_id: UserId,
+    pub role: Role,
+    pub scopes: Vec<ApiScope>,
+}
+
+impl ApiClient {
+    /// Answer with a 403 unless the token has `scope` and its owner has at
+    /// least `role`.
+    pub fn require(&self, scope: ApiScope, role: Role) -> Result<(), actix_web::Error> {
+        let problem = if !self.scopes.contains(&scope) {
+            Problem::new(
+                StatusCode::FORBIDDEN,
+                "missing_scope",
+                format!("The API token needs the {} scope.", scope),
+            )
+        } else if self.role < role {
+            Problem::new(
+                StatusCode::FORBIDDEN,
+                "insufficient_role",
+                format!("The owner of the API token needs the {} role.", role),
+            )
+        } else {
+            return Ok(());
+        };
+        Err(problem.into())
+    }
+}
+
+/// Requests to the JSON API carry an `Authorization: Bearer` header with one
+/// of the user's API tokens. Session cookies are not accepted there.
+pub async fn reject_invalid_api_tokens(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|token| !token.is_empty());
+    let Some(token) = token else {
+        return Err(unauthorized("missing_api_token", "Missing bearer token."));
+    };
+
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .ok_or_else(|| e500("The database pool is not configured."))?;
+    let Some(owner) = authenticate_api_token(token, pool).await.map_err(e500)? else {
+        return Err(unauthorized("invalid_api_token", "Invalid API token."));
+    };
+
+    req.extensions_mut().insert(UserId(owner.user_id));
+    req.extensions_mut().insert(ApiClient {
+        user_id: UserId(owner.user_id),
+        role: owner.role,
+        scopes: owner.scopes,
+    });
+    next.call(req).await
+}
+
+fn unauthorized(code: &'static str, detail: &'static str) -> actix_web::Error {
+    Problem::new(StatusCode::UNAUTHORIZED, code, detail)
+        .with_header(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"))
+        .into()
 }
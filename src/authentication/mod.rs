@@ -1,8 +1,36 @@
+mod api_tokens;
 mod middleware;
+mod oidc;
 mod password;
+mod password_policy;
+mod roles;
+mod sessions;
+mod throttle;
+pub mod totp;
+mod two_factor;
 
 pub use password::{
-    change_password, compute_password_hash, validate_credentials, AuthError, Credentials,
+    change_password, change_user_email, compute_password_hash, get_user_email,
+    validate_credentials, AuthError, Credentials, PasswordHashing,
 };
 
-pub use middleware::{reject_anonymous_users, UserId};
+pub use api_tokens::{
+    authenticate_api_token, create_api_token, get_api_tokens, revoke_api_token, ApiScope, ApiToken,
+    ApiTokenOwner,
+};
+pub use middleware::{
+    reject_anonymous_users, reject_forged_requests, reject_invalid_api_tokens, ApiClient,
+    AuthenticatedUser, CsrfToken, UserId,
+};
+pub use oidc::{OidcClient, PendingSsoLogin, SsoAccount, SsoError, SsoIdentity};
+pub use password_policy::PasswordPolicy;
+pub use roles::{get_role, Role};
+pub use sessions::{
+    end_session, get_active_sessions, get_session_epoch, revoke_other_sessions, revoke_sessions,
+    start_session, touch_session, ActiveSession,
+};
+pub use throttle::{FailureOutcome, LoginAllowance, LoginThrottle};
+pub use two_factor::{
+    count_unused_recovery_codes, disable_two_factor, enable_two_factor, get_totp_secret,
+    verify_second_factor,
+};
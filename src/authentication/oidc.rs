@@ -0,0 +1,514 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use reqwest::Client;
+use secrecy::{ExposeSecret, Secret};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{compute_password_hash, PasswordHashing, Role};
+use crate::configuration::OidcSettings;
+use crate::routes::error_chain_fmt;
+use crate::telemetry::spawn_blocking_with_tracing;
+
+/// How long the provider's discovery document is used before it is fetched
+/// again, so that a sign-in does not fetch it for both of its requests.
+const METADATA_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Signs admins in through an OpenID Connect provider, using the
+/// authorization code flow with PKCE.
+pub struct OidcClient {
+    http_client: Client,
+    settings: OidcSettings,
+    redirect_uri: String,
+    default_role: Role,
+    group_roles: HashMap<String, Role>,
+    /// The last discovery document and when it was fetched.
+    metadata: Mutex<Option<(Instant, ProviderMetadata)>>,
+}
+
+/// What the callback needs to check the provider's answer, kept in the
+/// session between the redirect and the callback.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct PendingSsoLogin {
+    state: String,
+    nonce: String,
+    code_verifier: String,
+}
+
+/// The user the provider vouched for.
+#[derive(Debug)]
+pub struct SsoIdentity {
+    pub email: String,
+    pub preferred_username: Option<String>,
+    pub groups: Vec<String>,
+}
+
+/// The account an identity was signed in as.
+pub struct SsoAccount {
+    pub user_id: Uuid,
+    pub username: String,
+    /// Set when the account was created for this login.
+    pub provisioned: bool,
+    /// The previous role, when the group mapping changed it.
+    pub previous_role: Option<String>,
+    pub role: Role,
+}
+
+#[derive(serde::Deserialize, Clone)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+}
+
+#[derive(serde::Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Audience {
+    fn contains(&self, client_id: &str) -> bool {
+        match self {
+            Self::One(aud) => aud == client_id,
+            Self::Many(auds) => auds.iter().any(|aud| aud == client_id),
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct IdTokenClaims {
+    iss: String,
+    aud: Audience,
+    exp: i64,
+    nonce: Option<String>,
+    email: Option<String>,
+    email_verified: Option<bool>,
+    preferred_username: Option<String>,
+    #[serde(flatten)]
+    other: serde_json::Map<String, Value>,
+}
+
+#[derive(thiserror::Error)]
+pub enum SsoError {
+    #[error("The single sign-on request has expired. Please try again.")]
+    InvalidState,
+    #[error("Single sign-on failed: {0}")]
+    Refused(String),
+    #[error("The identity provider sent an invalid ID token.")]
+    InvalidIdToken(#[source] anyhow::Error),
+    #[error("The identity provider did not share a verified email address.")]
+    NoVerifiedEmail,
+    #[error("There is no account for {0}. Ask an owner for an invitation.")]
+    UnknownUser(String),
+    #[error("Your account has been deactivated.")]
+    Deactivated,
+    #[error("An account was just created for {0}. Please sign in again.")]
+    AlreadyProvisioned(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SsoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl OidcClient {
+    /// Fails if a configured role does not exist.
+    pub fn new(settings: OidcSettings, base_url: &str) -> Result<Self, anyhow::Error> {
+        let default_role = Role::parse(&settings.default_role).map_err(anyhow::Error::msg)?;
+        let group_roles = settings
+            .group_roles
+            .iter()
+            .map(|(group, role)| Ok((group.clone(), Role::parse(role)?)))
+            .collect::<Result<_, String>>()
+            .map_err(anyhow::Error::msg)?;
+        let http_client = Client::builder()
+            .timeout(std::time::Duration::from_secs(10))
+            .build()
+            .context("Failed to build the OpenID Connect HTTP client.")?;
+        Ok(Self {
+            http_client,
+            redirect_uri: format!("{}/login/sso/callback", base_url),
+            settings,
+            default_role,
+            group_roles,
+            metadata: Mutex::new(None),
+        })
+    }
+
+    pub fn provider_name(&self) -> &str {
+        &self.settings.provider_name
+    }
+
+    /// The provider's configuration, fetched at most once per `METADATA_TTL`.
+    async fn discover(&self) -> Result<ProviderMetadata, anyhow::Error> {
+        if let Some((fetched_at, metadata)) = &*self.metadata.lock().unwrap() {
+            if fetched_at.elapsed() < METADATA_TTL {
+                return Ok(metadata.clone());
+            }
+        }
+        let metadata = self.fetch_metadata().await?;
+        *self.metadata.lock().unwrap() = Some((Instant::now(), metadata.clone()));
+        Ok(metadata)
+    }
+
+    #[tracing::instrument(name = "Discover OpenID provider", skip(self))]
+    async fn fetch_metadata(&self) -> Result<ProviderMetadata, anyhow::Error> {
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            self.settings.issuer.trim_end_matches('/')
+        );
+        let metadata: ProviderMetadata = self
+            .http_client
+            .get(&url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .context("Failed to fetch the OpenID provider configuration.")?
+            .json()
+            .await
+            .context("Failed to read the OpenID provider configuration.")?;
+        // See OpenID Connect Discovery 4.3.
+        if metadata.issuer != self.settings.issuer {
+            anyhow::bail!(
+                "The OpenID provider configuration is for another issuer, {}.",
+                metadata.issuer
+            );
+        }
+        Ok(metadata)
+    }
+
+    /// Where to send the user to sign in, along with what the callback needs
+    /// to check the answer.
+    pub async fn authorization_url(&self) -> Result<(String, PendingSsoLogin), anyhow::Error> {
+        let metadata = self.discover().await?;
+        let pending = PendingSsoLogin {
+            state: random_string(32),
+            nonce: random_string(32),
+            code_verifier: random_string(64),
+        };
+        let mut url = reqwest::Url::parse(&metadata.authorization_endpoint)
+            .context("The authorization endpoint is not a valid URL.")?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.settings.client_id)
+            .append_pair("redirect_uri", &self.redirect_uri)
+            .append_pair("scope", "openid email profile")
+            .append_pair("state", &pending.state)
+            .append_pair("nonce", &pending.nonce)
+            .append_pair("code_challenge", &code_challenge(&pending.code_verifier))
+            .append_pair("code_challenge_method", "S256");
+        Ok((url.into(), pending))
+    }
+
+    /// Trade the authorization code for the user's identity.
+    ///
+    /// The ID token comes straight from the token endpoint over TLS, in
+    /// exchange for the client secret, so its signature is not checked (see
+    /// OpenID Connect Core 3.1.3.7); its claims are.
+    #[tracing::instrument(name = "Exchange SSO authorization code", skip_all)]
+    pub async fn exchange_code(
+        &self,
+        pending: PendingSsoLogin,
+        state: &str,
+        code: &str,
+    ) -> Result<SsoIdentity, SsoError> {
+        if state != pending.state {
+            return Err(SsoError::InvalidState);
+        }
+        let metadata = self.discover().await?;
+        let response = self
+            .http_client
+            .post(&metadata.token_endpoint)
+            .basic_auth(
+                &self.settings.client_id,
+                Some(self.settings.client_secret.expose_secret()),
+            )
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", &self.redirect_uri),
+                ("code_verifier", &pending.code_verifier),
+            ])
+            .send()
+            .await
+            .context("Failed to reach the token endpoint.")?;
+        if response.status().is_client_error() {
+            let error: Value = response.json().await.unwrap_or_default();
+            let error = error["error"]
+                .as_str()
+                .unwrap_or("invalid_grant")
+                .to_owned();
+            return Err(SsoError::Refused(error));
+        }
+        let tokens: TokenResponse = response
+            .error_for_status()
+            .context("The token endpoint failed.")?
+            .json()
+            .await
+            .context("Failed to read the token response.")?;
+
+        let claims = decode_id_token(&tokens.id_token).map_err(SsoError::InvalidIdToken)?;
+        self.validate_claims(&claims, &pending.nonce)
+            .map_err(SsoError::InvalidIdToken)?;
+        let email = match (claims.email, claims.email_verified) {
+            (Some(email), Some(true)) => email,
+            _ => return Err(SsoError::NoVerifiedEmail),
+        };
+        let groups = claims
+            .other
+            .get(&self.settings.groups_claim)
+            .and_then(Value::as_array)
+            .map(|groups| {
+                groups
+                    .iter()
+                    .filter_map(|group| group.as_str().map(str::to_owned))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(SsoIdentity {
+            email,
+            preferred_username: claims.preferred_username,
+            groups,
+        })
+    }
+
+    fn validate_claims(&self, claims: &IdTokenClaims, nonce: &str) -> Result<(), anyhow::Error> {
+        if claims.iss != self.settings.issuer {
+            anyhow::bail!("Unexpected issuer {}.", claims.iss);
+        }
+        if !claims.aud.contains(&self.settings.client_id) {
+            anyhow::bail!("The ID token was issued to another client.");
+        }
+        if claims.exp <= chrono::Utc::now().timestamp() {
+            anyhow::bail!("The ID token has expired.");
+        }
+        if claims.nonce.as_deref() != Some(nonce) {
+            anyhow::bail!("The ID token nonce does not match.");
+        }
+        Ok(())
+    }
+
+    /// The highest role mapped from any of the groups.
+    fn mapped_role(&self, groups: &[String]) -> Option<Role> {
+        groups
+            .iter()
+            .filter_map(|group| self.group_roles.get(group))
+            .max()
+            .copied()
+    }
+
+    /// Find the account with the identity's email address, or create one if
+    /// provisioning is enabled. The group mapping, if any of the groups has a
+    /// role, is applied either way.
+    #[tracing::instrument(name = "Sign in SSO user", skip(self, pool, hashing))]
+    pub async fn sign_in(
+        &self,
+        identity: &SsoIdentity,
+        pool: &PgPool,
+        hashing: &PasswordHashing,
+    ) -> Result<SsoAccount, SsoError> {
+        let mapped_role = self.mapped_role(&identity.groups);
+        let existing = sqlx::query!(
+            r#"
+            SELECT user_id, username, role, deactivated_at
+            FROM users
+            WHERE lower(email) = lower($1)
+            "#,
+            identity.email
+        )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to look up the user by email.")?;
+
+        let Some(user) = existing else {
+            if !self.settings.provision_users {
+                return Err(SsoError::UnknownUser(identity.email.clone()));
+            }
+            let role = mapped_role.unwrap_or(self.default_role);
+            let (user_id, username) = provision_user(identity, role, pool, hashing).await?;
+            return Ok(SsoAccount {
+                user_id,
+                username,
+                provisioned: true,
+                previous_role: None,
+                role,
+            });
+        };
+        if user.deactivated_at.is_some() {
+            return Err(SsoError::Deactivated);
+        }
+
+        let current_role = Role::parse(&user.role).map_err(anyhow::Error::msg)?;
+        let role = mapped_role.unwrap_or(current_role);
+        let mut previous_role = None;
+        if role != current_role {
+            sqlx::query!(
+                r#"UPDATE users SET role = $2 WHERE user_id = $1"#,
+                user.user_id,
+                role.as_str()
+            )
+            .execute(pool)
+            .await
+            .context("Failed to apply the mapped role.")?;
+            previous_role = Some(user.role);
+        }
+        Ok(SsoAccount {
+            user_id: user.user_id,
+            username: user.username,
+            provisioned: false,
+            previous_role,
+            role,
+        })
+    }
+}
+
+/// The username is the preferred one from the provider, or the local part of
+/// the email address, falling back to the whole address and then to the
+/// address with a random suffix when those are taken.
+async fn provision_user(
+    identity: &SsoIdentity,
+    role: Role,
+    pool: &PgPool,
+    hashing: &PasswordHashing,
+) -> Result<(Uuid, String), SsoError> {
+    let preferred = identity
+        .preferred_username
+        .clone()
+        .filter(|username| !username.trim().is_empty())
+        .unwrap_or_else(|| {
+            identity
+                .email
+                .split('@')
+                .next()
+                .unwrap_or_default()
+                .to_owned()
+        });
+
+    // Nobody knows this password: the user signs in through the provider, or
+    // resets it to get one.
+    let password = Secret::new(random_string(32));
+    let hashing = hashing.clone();
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password, &hashing))
+            .await
+            .context("Failed to spawn blocking task.")?
+            .context("Failed to hash password")?;
+
+    let candidates = [preferred, identity.email.clone()]
+        .into_iter()
+        .filter(|username| !username.is_empty())
+        .chain(std::iter::repeat_with(|| {
+            format!("{}-{}", identity.email, random_string(6))
+        }))
+        .take(5);
+    for username in candidates {
+        let user_id = Uuid::new_v4();
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO users (user_id, username, email, password, role)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            user_id,
+            username,
+            identity.email,
+            password_hash.expose_secret(),
+            role.as_str()
+        )
+        .execute(pool)
+        .await;
+        match result {
+            Ok(_) => return Ok((user_id, username)),
+            // Another sign-in with the same address got there first.
+            Err(sqlx::Error::Database(e)) if e.constraint() == Some("users_email_key") => {
+                return Err(SsoError::AlreadyProvisioned(identity.email.clone()))
+            }
+            Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some("23505") => {
+                tracing::info!("The username {} is taken.", username);
+            }
+            Err(e) => {
+                return Err(anyhow::Error::new(e)
+                    .context("Failed to provision the user.")
+                    .into())
+            }
+        }
+    }
+    Err(anyhow::anyhow!("Failed to find a free username to provision the user.").into())
+}
+
+/// The claims of a JWT, without checking its signature.
+fn decode_id_token(id_token: &str) -> Result<IdTokenClaims, anyhow::Error> {
+    let payload = id_token
+        .split('.')
+        .nth(1)
+        .context("The ID token is not a JWT.")?;
+    let payload = URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .context("The ID token payload is not base64url.")?;
+    serde_json::from_slice(&payload).context("The ID token claims could not be read.")
+}
+
+fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+fn random_string(length: usize) -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(length)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{code_challenge, decode_id_token, Audience};
+
+    #[test]
+    fn code_challenge_follows_rfc_7636() {
+        // The example from RFC 7636, appendix B.
+        assert_eq!(
+            code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn id_token_claims_are_read_from_the_payload() {
+        let claims = decode_id_token(
+            "eyJhbGciOiJub25lIn0.eyJpc3MiOiJodHRwczovL2lkcCIsImF1ZCI6WyJhIiwiYiJdLCJleHAiOjEsImdyb3VwcyI6WyJ4Il19.sig",
+        )
+        .unwrap();
+        assert_eq!(claims.iss, "https://idp");
+        assert!(claims.aud.contains("b"));
+        assert_eq!(claims.other["groups"][0], "x");
+    }
+
+    #[test]
+    fn tokens_that_are_not_jwts_are_rejected() {
+        assert!(decode_id_token("not-a-token").is_err());
+        assert!(decode_id_token("a.!!!.c").is_err());
+    }
+
+    #[test]
+    fn a_single_audience_must_match_exactly() {
+        assert!(Audience::One("zero2prod".into()).contains("zero2prod"));
+        assert!(!Audience::One("zero2prod-admin".into()).contains("zero2prod"));
+    }
+}
@@ -1,12 +1,16 @@
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
 use anyhow::Context;
 use argon2::{
     password_hash::SaltString, Algorithm, Argon2, Params, PasswordHash, PasswordHasher,
     PasswordVerifier, Version,
 };
 use secrecy::{ExposeSecret, Secret};
-use sqlx::PgPool;
+use sqlx::{PgExecutor, PgPool};
 
-use crate::telemetry::spawn_blocking_with_tracing;
+use crate::{
+    configuration::PasswordHashingSettings, domain::SubscriberEmail, problem::Problem,
+    telemetry::spawn_blocking_with_tracing,
+};
 
 #[derive(thiserror::Error, Debug)]
 pub enum AuthError {
@@ -16,23 +20,89 @@ pub enum AuthError {
     UnexpectedError(#[from] anyhow::Error),
 }
 
+impl From<&AuthError> for Problem {
+    fn from(e: &AuthError) -> Self {
+        match e {
+            AuthError::InvalidCredentials(_) => Problem::new(
+                StatusCode::UNAUTHORIZED,
+                "invalid_credentials",
+                "Invalid username or password.",
+            ),
+            AuthError::UnexpectedError(e) => Problem::internal(e),
+        }
+    }
+}
+
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+        Problem::from(self).status_code()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        Problem::from(self).error_response()
+    }
+}
+
 pub struct Credentials {
     pub username: String,
     pub password: Secret<String>,
 }
 
-#[tracing::instrument(name = "Validating credentials", skip(credentials, pool))]
+/// The Argon2 parameters new hashes are computed with.
+#[derive(Clone)]
+pub struct PasswordHashing {
+    params: Params,
+    /// Verified against when the username is unknown, so that failing takes
+    /// as long as for a known user.
+    dummy_hash: Secret<String>,
+}
+
+impl PasswordHashing {
+    pub fn new(settings: &PasswordHashingSettings) -> Result<Self, anyhow::Error> {
+        let params = Params::new(
+            settings.memory_kib,
+            settings.iterations,
+            settings.parallelism,
+            None,
+        )
+        .map_err(|e| anyhow::anyhow!("Invalid Argon2 parameters: {}", e))?;
+        let mut hashing = Self {
+            params,
+            dummy_hash: Secret::new(String::new()),
+        };
+        hashing.dummy_hash =
+            compute_password_hash(Secret::new(uuid::Uuid::new_v4().to_string()), &hashing)?;
+        Ok(hashing)
+    }
+
+    /// Whether a hash was computed with other parameters than the current ones.
+    fn is_outdated(&self, password_hash: &PasswordHash) -> bool {
+        if password_hash.algorithm != Algorithm::Argon2id.ident()
+            || password_hash.version != Some(Version::V0x13.into())
+        {
+            return true;
+        }
+        match Params::try_from(password_hash) {
+            Ok(params) => {
+                params.m_cost() != self.params.m_cost()
+                    || params.t_cost() != self.params.t_cost()
+                    || params.p_cost() != self.params.p_cost()
+            }
+            Err(_) => true,
+        }
+    }
+}
+
+/// A hash computed with outdated parameters is replaced once the password
+/// has been checked, as it is the only time the password is known.
+#[tracing::instrument(name = "Validating credentials", skip(credentials, hashing, pool))]
 pub async fn validate_credentials(
     credentials: Credentials,
+    hashing: &PasswordHashing,
     pool: &PgPool,
 ) -> Result<uuid::Uuid, AuthError> {
     let mut user_id = None;
-    let mut expected_password_hash = Secret::new(
-        "$argon2id$v=19$m=15000,t=2,p=1$\
-        gZiV/M1gPc22ElAH/Jh1Hw$\
-        CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno"
-            .to_string(),
-    );
+    let mut expected_password_hash = hashing.dummy_hash.clone();
 
     if let Some((stored_user_id, stored_password_hash)) =
         get_stored_credentials(&credentials.username, pool)
@@ -43,26 +113,48 @@ pub async fn validate_credentials(
         expected_password_hash = stored_password_hash;
     }
 
-    spawn_blocking_with_tracing(move || {
-        verify_password_hash(expected_password_hash, credentials.password)
+    let stored_password_hash = expected_password_hash.clone();
+    let current_hashing = hashing.clone();
+    let new_password_hash = spawn_blocking_with_tracing(move || {
+        verify_password_hash(
+            expected_password_hash,
+            credentials.password,
+            &current_hashing,
+        )
     })
     .await
     .context("Failed to spawn blocking task.")
     .map_err(AuthError::UnexpectedError)??;
 
-    user_id.ok_or_else(|| {
+    let user_id = user_id.ok_or_else(|| {
         AuthError::InvalidCredentials(anyhow::anyhow!("Invalid username or password."))
-    })
+    })?;
+
+    if let Some(new_password_hash) = new_password_hash {
+        if let Err(e) =
+            update_password_hash(user_id, &stored_password_hash, &new_password_hash, pool).await
+        {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to rehash a password with the current parameters."
+            );
+        }
+    }
+    Ok(user_id)
 }
 
+/// Returns the password hashed with the current parameters if the expected
+/// hash is outdated.
 #[tracing::instrument(
     name = "Verify password hash",
-    skip(expected_password_hash, password_candidate)
+    skip(expected_password_hash, password_candidate, hashing)
 )]
 fn verify_password_hash(
     expected_password_hash: Secret<String>,
     password_candidate: Secret<String>,
-) -> Result<(), AuthError> {
+    hashing: &PasswordHashing,
+) -> Result<Option<Secret<String>>, AuthError> {
     let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
         .context("Failed to parse hash in PHC string format.")
         .map_err(AuthError::UnexpectedError)?;
@@ -73,7 +165,38 @@ fn verify_password_hash(
             &expected_password_hash,
         )
         .context("Invalid username or password.")
-        .map_err(AuthError::InvalidCredentials)
+        .map_err(AuthError::InvalidCredentials)?;
+
+    if !hashing.is_outdated(&expected_password_hash) {
+        return Ok(None);
+    }
+    compute_password_hash(password_candidate, hashing)
+        .map(Some)
+        .map_err(AuthError::UnexpectedError)
+}
+
+/// Only replaces the hash that was verified, in case the password has been
+/// changed in the meantime.
+#[tracing::instrument(
+    name = "Update password hash",
+    skip(stored_password_hash, new_password_hash, pool)
+)]
+async fn update_password_hash(
+    user_id: uuid::Uuid,
+    stored_password_hash: &Secret<String>,
+    new_password_hash: &Secret<String>,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"UPDATE users SET password = $3 WHERE user_id = $1 AND password = $2"#,
+        user_id,
+        stored_password_hash.expose_secret(),
+        new_password_hash.expose_secret()
+    )
+    .execute(pool)
+    .await
+    .context("Failed to update the password hash.")?;
+    Ok(())
 }
 
 #[tracing::instrument(name = "Get stored credentials", skip(username, pool))]
@@ -85,7 +208,7 @@ async fn get_stored_credentials(
         r#"
         SELECT user_id, password
         FROM users
-        WHERE username = $1
+        WHERE username = $1 AND deactivated_at IS NULL
         "#,
         username,
     )
@@ -96,15 +219,18 @@ async fn get_stored_credentials(
     Ok(row)
 }
 
-#[tracing::instrument(name = "Change password", skip(password, pool))]
+#[tracing::instrument(name = "Change password", skip(password, hashing, pool))]
 pub async fn change_password(
     user_id: uuid::Uuid,
     password: Secret<String>,
+    hashing: &PasswordHashing,
     pool: &PgPool,
 ) -> Result<(), anyhow::Error> {
-    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
-        .await?
-        .context("Failed to hash password")?;
+    let hashing = hashing.clone();
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password, &hashing))
+            .await?
+            .context("Failed to hash password")?;
     sqlx::query!(
         r#"
         UPDATE users
@@ -120,14 +246,85 @@ pub async fn change_password(
     Ok(())
 }
 
-pub fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
-    let salt = SaltString::generate(&mut rand::thread_rng());
-    let password_hash = Argon2::new(
-        Algorithm::Argon2id,
-        Version::V0x13,
-        Params::new(15000, 2, 1, None).unwrap(),
+#[tracing::instrument(name = "Get user email", skip(pool))]
+pub async fn get_user_email(
+    user_id: uuid::Uuid,
+    pool: &PgPool,
+) -> Result<Option<String>, anyhow::Error> {
+    let row = sqlx::query!(r#"SELECT email FROM users WHERE user_id = $1"#, user_id)
+        .fetch_one(pool)
+        .await
+        .context("Failed to retrieve the user's email.")?;
+    Ok(row.email)
+}
+
+/// Returns `false` if another user already has the address.
+#[tracing::instrument(name = "Change user email", skip(executor))]
+pub async fn change_user_email(
+    user_id: uuid::Uuid,
+    email: &SubscriberEmail,
+    executor: impl PgExecutor<'_>,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"UPDATE users SET email = $2 WHERE user_id = $1"#,
+        user_id,
+        email.as_ref()
     )
-    .hash_password(password.expose_secret().as_bytes(), &salt)?
-    .to_string();
+    .execute(executor)
+    .await;
+    match result {
+        Ok(_) => Ok(true),
+        Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some("23505") => Ok(false),
+        Err(e) => Err(anyhow::Error::new(e).context("Failed to change user's email.")),
+    }
+}
+
+pub fn compute_password_hash(
+    password: Secret<String>,
+    hashing: &PasswordHashing,
+) -> Result<Secret<String>, anyhow::Error> {
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let password_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, hashing.params.clone())
+        .hash_password(password.expose_secret().as_bytes(), &salt)?
+        .to_string();
     Ok(Secret::new(password_hash))
 }
+
+#[cfg(test)]
+mod tests {
+    use argon2::PasswordHash;
+    use secrecy::{ExposeSecret, Secret};
+
+    use super::{compute_password_hash, PasswordHashing};
+    use crate::configuration::PasswordHashingSettings;
+
+    fn hashing(memory_kib: u32) -> PasswordHashing {
+        PasswordHashing::new(&PasswordHashingSettings {
+            memory_kib,
+            iterations: 1,
+            parallelism: 1,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn hashes_with_other_parameters_are_outdated() {
+        let old = hashing(8192);
+        let current = hashing(9216);
+        let hash = compute_password_hash(Secret::new("a-password".into()), &old).unwrap();
+        let hash = PasswordHash::new(hash.expose_secret()).unwrap();
+
+        assert!(current.is_outdated(&hash));
+        assert!(!old.is_outdated(&hash));
+    }
+
+    #[test]
+    fn invalid_parameters_are_rejected() {
+        let settings = PasswordHashingSettings {
+            memory_kib: 15000,
+            iterations: 0,
+            parallelism: 1,
+        };
+        assert!(PasswordHashing::new(&settings).is_err());
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::HashSet;
+
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+
+use crate::configuration::PasswordPolicySettings;
+
+/// Passwords that are rejected whatever the configuration.
+const COMMON_PASSWORDS: &str = include_str!("common_passwords.txt");
+
+/// What a new password has to satisfy. Current passwords are not checked, so
+/// tightening the policy does not lock anyone out.
+pub struct PasswordPolicy {
+    min_length: usize,
+    max_length: usize,
+    /// Lowercase, so that case variations are rejected too.
+    common_passwords: HashSet<String>,
+}
+
+impl PasswordPolicy {
+    /// Load the bundled list of common passwords, plus the configured one if
+    /// any.
+    pub fn new(settings: &PasswordPolicySettings) -> Result<Self, anyhow::Error> {
+        let mut common_passwords = parse_password_list(COMMON_PASSWORDS);
+        if let Some(path) = &settings.common_passwords_file {
+            let list = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read the password list {}.", path))?;
+            common_passwords.extend(parse_password_list(&list));
+        }
+        Ok(Self {
+            min_length: settings.min_length,
+            max_length: settings.max_length,
+            common_passwords,
+        })
+    }
+
+    /// The error is meant for the user choosing the password.
+    pub fn check(&self, username: &str, password: &Secret<String>) -> Result<(), String> {
+        let password = password.expose_secret();
+        let length = password.chars().count();
+        if length < self.min_length {
+            return Err(format!(
+                "The password must be at least {} characters long.",
+                self.min_length
+            ));
+        }
+        if length > self.max_length {
+            return Err(format!(
+                "The password must be at most {} characters long.",
+                self.max_length
+            ));
+        }
+
+        let password = password.to_lowercase();
+        let username = username.trim().to_lowercase();
+        if !username.is_empty() && password.contains(&username) {
+            return Err("The password must not contain the username.".into());
+        }
+        if self.common_passwords.contains(&password) {
+            return Err("This password is too common, please choose another one.".into());
+        }
+        Ok(())
+    }
+}
+
+/// One password per line, `#` starts a comment line.
+fn parse_password_list(list: &str) -> HashSet<String> {
+    list.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+    use secrecy::Secret;
+
+    use super::PasswordPolicy;
+    use crate::configuration::PasswordPolicySettings;
+
+    fn policy() -> PasswordPolicy {
+        PasswordPolicy::new(&PasswordPolicySettings {
+            min_length: 12,
+            max_length: 128,
+            common_passwords_file: None,
+        })
+        .unwrap()
+    }
+
+    fn check(username: &str, password: &str) -> Result<(), String> {
+        policy().check(username, &Secret::new(password.into()))
+    }
+
+    #[test]
+    fn a_long_uncommon_password_is_accepted() {
+        assert_ok!(check("ursula", "violet-tractor-sings"));
+    }
+
+    #[test]
+    fn passwords_shorter_than_the_minimum_are_rejected() {
+        assert_err!(check("ursula", "short"));
+        assert_ok!(check("ursula", "ab-cd-ef-ghi"));
+    }
+
+    #[test]
+    fn length_is_counted_in_characters_not_bytes() {
+        // Twelve bytes, but only six characters.
+        assert_err!(check("ursula", "éééééé"));
+    }
+
+    #[test]
+    fn passwords_longer_than_the_maximum_are_rejected() {
+        assert_err!(check("ursula", &"a-".repeat(65)));
+    }
+
+    #[test]
+    fn passwords_containing_the_username_are_rejected() {
+        assert_err!(check("ursula", "my-name-is-ursula"));
+        assert_err!(check("ursula", "my-name-is-URSULA"));
+    }
+
+    #[test]
+    fn common_passwords_are_rejected_whatever_their_case() {
+        assert_err!(check("ursula", "password1234"));
+        assert_err!(check("ursula", "Password1234"));
+    }
+
+    #[test]
+    fn a_configured_list_adds_to_the_bundled_one() {
+        let path = std::env::temp_dir().join(format!("passwords-{}.txt", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "# Leaked last week\nviolet-tractor-sings\n").unwrap();
+        let policy = PasswordPolicy::new(&PasswordPolicySettings {
+            min_length: 12,
+            max_length: 128,
+            common_passwords_file: Some(path.to_string_lossy().into_owned()),
+        })
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_err!(policy.check("ursula", &Secret::new("violet-tractor-sings".into())));
+        assert_err!(policy.check("ursula", &Secret::new("password1234".into())));
+    }
+}
@@ -0,0 +1,75 @@
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// What a user may do in the admin area. Every role can do what the roles
+/// below it can: authors write drafts, editors publish them and manage
+/// subscribers, owners manage users and settings.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Viewer,
+    Author,
+    Editor,
+    Owner,
+}
+
+impl Role {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "viewer" => Ok(Self::Viewer),
+            "author" => Ok(Self::Author),
+            "editor" => Ok(Self::Editor),
+            "owner" => Ok(Self::Owner),
+            other => Err(format!("{} is not a valid role.", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Viewer => "viewer",
+            Self::Author => "author",
+            Self::Editor => "editor",
+            Self::Owner => "owner",
+        }
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[tracing::instrument(name = "Get user role", skip(pool))]
+pub async fn get_role(user_id: Uuid, pool: &PgPool) -> Result<Role, anyhow::Error> {
+    let row = sqlx::query!(r#"SELECT role FROM users WHERE user_id = $1"#, user_id)
+        .fetch_one(pool)
+        .await
+        .context("Failed to retrieve the user's role.")?;
+    Role::parse(&row.role).map_err(anyhow::Error::msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Role;
+
+    #[test]
+    fn roles_round_trip_through_their_names() {
+        for role in [Role::Viewer, Role::Author, Role::Editor, Role::Owner] {
+            assert_eq!(Role::parse(role.as_str()), Ok(role));
+        }
+    }
+
+    #[test]
+    fn unknown_roles_are_rejected() {
+        assert!(Role::parse("admin").is_err());
+        assert!(Role::parse("Owner").is_err());
+    }
+
+    #[test]
+    fn each_role_includes_the_ones_below_it() {
+        assert!(Role::Owner > Role::Editor);
+        assert!(Role::Editor > Role::Author);
+        assert!(Role::Author > Role::Viewer);
+    }
+}
@@ -0,0 +1,250 @@
+use actix_web::{http::header, HttpRequest};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::configuration::SessionSettings;
+
+/// Longer user agents are cut, they are only shown to the user.
+const MAX_USER_AGENT_LENGTH: usize = 512;
+
+/// A logged in session of the user, as shown on the sessions page.
+pub struct ActiveSession {
+    pub session_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub remember_me: bool,
+}
+
+/// The epoch sessions of the user must carry to stay valid, or `None` if the
+/// user no longer exists.
+#[tracing::instrument(name = "Get session epoch", skip(pool))]
+pub async fn get_session_epoch(user_id: Uuid, pool: &PgPool) -> Result<Option<i32>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT session_epoch FROM users WHERE user_id = $1"#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the session epoch.")?;
+    Ok(row.map(|r| r.session_epoch))
+}
+
+/// Log the user out of every session they currently have.
+#[tracing::instrument(name = "Revoke all sessions", skip(transaction))]
+pub async fn revoke_sessions(
+    user_id: Uuid,
+    transaction: &mut Transaction<'static, Postgres>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"UPDATE users SET session_epoch = session_epoch + 1 WHERE user_id = $1"#,
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to revoke the user's sessions.")?;
+    sqlx::query!(r#"DELETE FROM user_sessions WHERE user_id = $1"#, user_id)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to delete the user's sessions.")?;
+    Ok(())
+}
+
+/// Log the user out of every session but `current_session_id`, including
+/// sessions waiting for their second factor. Returns the new epoch, which
+/// the current session must carry from now on.
+#[tracing::instrument(name = "Revoke other sessions", skip(pool))]
+pub async fn revoke_other_sessions(
+    user_id: Uuid,
+    current_session_id: Uuid,
+    pool: &PgPool,
+) -> Result<i32, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let row = sqlx::query!(
+        r#"
+        UPDATE users SET session_epoch = session_epoch + 1
+        WHERE user_id = $1
+        RETURNING session_epoch
+        "#,
+        user_id
+    )
+    .fetch_one(&mut transaction)
+    .await
+    .context("Failed to revoke the user's sessions.")?;
+    sqlx::query!(
+        r#"DELETE FROM user_sessions WHERE user_id = $1 AND session_id <> $2"#,
+        user_id,
+        current_session_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the user's other sessions.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to revoke the other sessions.")?;
+    Ok(row.session_epoch)
+}
+
+/// The session limits in seconds, as the queries take them: the idle timeout,
+/// the lifetime and, if remember-me is enabled, how long remembered sessions
+/// last. Remembered sessions are held to the usual limits once it is not.
+fn limits(settings: &SessionSettings) -> (f64, f64, Option<f64>) {
+    (
+        settings.idle_timeout().as_secs_f64(),
+        settings.lifetime().as_secs_f64(),
+        settings
+            .remember_me_lifetime()
+            .map(|lifetime| lifetime.as_secs_f64()),
+    )
+}
+
+/// Record a new session of the user, logged in with `request`, and return its
+/// id. Expired sessions of the user are forgotten on the way.
+#[tracing::instrument(name = "Start a session", skip(request, settings, pool))]
+pub async fn start_session(
+    user_id: Uuid,
+    remember_me: bool,
+    request: &HttpRequest,
+    settings: &SessionSettings,
+    pool: &PgPool,
+) -> Result<Uuid, anyhow::Error> {
+    let (idle_timeout, lifetime, remembered_lifetime) = limits(settings);
+    sqlx::query!(
+        r#"
+        DELETE FROM user_sessions
+        WHERE user_id = $1 AND NOT (
+            last_seen_at > now() - make_interval(secs => CASE
+                WHEN remember_me AND $4::float8 IS NOT NULL THEN $4 ELSE $2 END) AND
+            created_at > now() - make_interval(secs => CASE
+                WHEN remember_me AND $4::float8 IS NOT NULL THEN $4 ELSE $3 END)
+        )
+        "#,
+        user_id,
+        idle_timeout,
+        lifetime,
+        remembered_lifetime
+    )
+    .execute(pool)
+    .await
+    .context("Failed to delete expired sessions.")?;
+
+    let session_id = Uuid::new_v4();
+    // The peer address, as forwarding headers can be set by the client.
+    let ip = request.peer_addr().map(|addr| addr.ip().to_string());
+    let user_agent = request
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(|agent| {
+            agent
+                .chars()
+                .take(MAX_USER_AGENT_LENGTH)
+                .collect::<String>()
+        });
+    sqlx::query!(
+        r#"
+        INSERT INTO user_sessions (
+            session_id, user_id, created_at, last_seen_at, ip, user_agent, remember_me
+        )
+        VALUES ($1, $2, now(), now(), $3, $4, $5)
+        "#,
+        session_id,
+        user_id,
+        ip,
+        user_agent,
+        remember_me && settings.remember_me_lifetime().is_some()
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the session.")?;
+    Ok(session_id)
+}
+
+/// Record activity on a session. Returns `false` if it has been signed out
+/// or has expired, see `SessionSettings`.
+#[tracing::instrument(name = "Touch a session", skip(settings, pool))]
+pub async fn touch_session(
+    user_id: Uuid,
+    session_id: Uuid,
+    settings: &SessionSettings,
+    pool: &PgPool,
+) -> Result<bool, anyhow::Error> {
+    let (idle_timeout, lifetime, remembered_lifetime) = limits(settings);
+    let result = sqlx::query!(
+        r#"
+        UPDATE user_sessions SET last_seen_at = now()
+        WHERE
+            session_id = $1 AND
+            user_id = $2 AND
+            last_seen_at > now() - make_interval(secs => CASE
+                WHEN remember_me AND $5::float8 IS NOT NULL THEN $5 ELSE $3 END) AND
+            created_at > now() - make_interval(secs => CASE
+                WHEN remember_me AND $5::float8 IS NOT NULL THEN $5 ELSE $4 END)
+        "#,
+        session_id,
+        user_id,
+        idle_timeout,
+        lifetime,
+        remembered_lifetime
+    )
+    .execute(pool)
+    .await
+    .context("Failed to update the session.")?;
+    Ok(result.rows_affected() == 1)
+}
+
+#[tracing::instrument(name = "Get active sessions", skip(settings, pool))]
+pub async fn get_active_sessions(
+    user_id: Uuid,
+    settings: &SessionSettings,
+    pool: &PgPool,
+) -> Result<Vec<ActiveSession>, anyhow::Error> {
+    let (idle_timeout, lifetime, remembered_lifetime) = limits(settings);
+    sqlx::query_as!(
+        ActiveSession,
+        r#"
+        SELECT session_id, created_at, last_seen_at, ip, user_agent, remember_me
+        FROM user_sessions
+        WHERE
+            user_id = $1 AND
+            last_seen_at > now() - make_interval(secs => CASE
+                WHEN remember_me AND $4::float8 IS NOT NULL THEN $4 ELSE $2 END) AND
+            created_at > now() - make_interval(secs => CASE
+                WHEN remember_me AND $4::float8 IS NOT NULL THEN $4 ELSE $3 END)
+        ORDER BY last_seen_at DESC
+        "#,
+        user_id,
+        idle_timeout,
+        lifetime,
+        remembered_lifetime
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the sessions.")
+}
+
+/// Sign out one session of the user. Returns `false` if there is no such
+/// session.
+#[tracing::instrument(name = "End a session", skip(pool))]
+pub async fn end_session(
+    user_id: Uuid,
+    session_id: Uuid,
+    pool: &PgPool,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"DELETE FROM user_sessions WHERE session_id = $1 AND user_id = $2"#,
+        session_id,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to delete the session.")?;
+    Ok(result.rows_affected() == 1)
+}
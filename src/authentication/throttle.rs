@@ -0,0 +1,327 @@
+use std::{net::IpAddr, time::Duration};
+
+use anyhow::Context;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+
+use crate::{
+    configuration::LoginThrottlingSettings, domain::SubscriberEmail, email_client::EmailClient,
+};
+
+/// Whether a login attempt may go ahead.
+#[derive(Debug, PartialEq, Eq)]
+pub enum LoginAllowance {
+    /// Check the credentials once `delay` has passed.
+    Allowed {
+        delay: Duration,
+    },
+    LockedOut {
+        retry_after: Duration,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FailureOutcome {
+    Counted,
+    /// This failure locked the account.
+    AccountLocked,
+    /// This failure locked every login from the client's address.
+    AddressLocked,
+}
+
+/// Failed login tracking, shared between all instances through Redis.
+#[derive(Clone)]
+pub struct LoginThrottle {
+    redis: ConnectionManager,
+    settings: LoginThrottlingSettings,
+    alert_recipients: Vec<SubscriberEmail>,
+}
+
+enum Target<'a> {
+    Account(&'a str),
+    Address(IpAddr),
+}
+
+impl LoginThrottle {
+    pub async fn new(
+        redis_uri: &Secret<String>,
+        settings: LoginThrottlingSettings,
+    ) -> Result<Self, anyhow::Error> {
+        let redis = redis::Client::open(redis_uri.expose_secret().as_str())?
+            .get_tokio_connection_manager()
+            .await
+            .context("Failed to connect to Redis.")?;
+        let alert_recipients = settings
+            .alert_recipients()
+            .map_err(anyhow::Error::msg)
+            .context("Invalid login alert recipient.")?;
+        Ok(Self {
+            redis,
+            settings,
+            alert_recipients,
+        })
+    }
+
+    #[tracing::instrument(name = "Check login throttling", skip(self))]
+    pub async fn check(
+        &self,
+        username: &str,
+        ip: Option<IpAddr>,
+    ) -> Result<LoginAllowance, anyhow::Error> {
+        let mut failures = 0;
+        for target in targets(username, ip) {
+            let mut redis = self.redis.clone();
+            let ttl: i64 = redis
+                .ttl(self.key("lockout", &target))
+                .await
+                .context("Failed to check the login lockout.")?;
+            if ttl > 0 {
+                return Ok(LoginAllowance::LockedOut {
+                    retry_after: Duration::from_secs(ttl as u64),
+                });
+            }
+            let count: Option<u32> = redis
+                .get(self.key("failures", &target))
+                .await
+                .context("Failed to read the failed login count.")?;
+            failures = failures.max(count.unwrap_or(0));
+        }
+        Ok(LoginAllowance::Allowed {
+            delay: delay_after(&self.settings, failures),
+        })
+    }
+
+    #[tracing::instrument(name = "Record a failed login", skip(self))]
+    pub async fn record_failure(
+        &self,
+        username: &str,
+        ip: Option<IpAddr>,
+    ) -> Result<FailureOutcome, anyhow::Error> {
+        let mut outcome = FailureOutcome::Counted;
+        for target in targets(username, ip) {
+            let mut redis = self.redis.clone();
+            let failures_key = self.key("failures", &target);
+            let failures: u32 = redis
+                .incr(&failures_key, 1)
+                .await
+                .context("Failed to count a failed login.")?;
+            if failures == 1 {
+                redis
+                    .expire::<_, ()>(&failures_key, self.settings.failure_window_seconds as usize)
+                    .await
+                    .context("Failed to expire the failed login count.")?;
+            }
+
+            let limit = match target {
+                Target::Account(_) => self.settings.account_failures_before_lockout,
+                Target::Address(_) => self.settings.ip_failures_before_lockout,
+            };
+            if failures < limit {
+                continue;
+            }
+            redis
+                .set_ex::<_, _, ()>(
+                    self.key("lockout", &target),
+                    1,
+                    self.settings.lockout_seconds as usize,
+                )
+                .await
+                .context("Failed to lock out logins.")?;
+            redis
+                .del::<_, ()>(&failures_key)
+                .await
+                .context("Failed to reset the failed login count.")?;
+            if outcome == FailureOutcome::Counted {
+                outcome = match target {
+                    Target::Account(_) => FailureOutcome::AccountLocked,
+                    Target::Address(_) => FailureOutcome::AddressLocked,
+                };
+            }
+        }
+        Ok(outcome)
+    }
+
+    /// Count a request for a sign-in or password reset link to `email`, and
+    /// tell whether it may be honoured: each address, and each client
+    /// address, only gets a few links per window, whatever their kind, so
+    /// that the forms cannot flood an inbox.
+    #[tracing::instrument(name = "Check sign-in link throttling", skip(self))]
+    pub async fn allow_link_request(
+        &self,
+        email: &str,
+        ip: Option<IpAddr>,
+    ) -> Result<bool, anyhow::Error> {
+        let mut allowed = true;
+        for target in targets(email, ip) {
+            let mut redis = self.redis.clone();
+            let requests_key = self.key("link_requests", &target);
+            let requests: u32 = redis
+                .incr(&requests_key, 1)
+                .await
+                .context("Failed to count a sign-in link request.")?;
+            if requests == 1 {
+                redis
+                    .expire::<_, ()>(&requests_key, self.settings.failure_window_seconds as usize)
+                    .await
+                    .context("Failed to expire the sign-in link request count.")?;
+            }
+            let limit = match target {
+                Target::Account(_) => self.settings.link_requests_per_address,
+                Target::Address(_) => self.settings.link_requests_per_ip,
+            };
+            allowed &= requests <= limit;
+        }
+        Ok(allowed)
+    }
+
+    /// Record a failed attempt, alerting the admins if it locked the account.
+    /// A failed alert is only logged: the failure was counted all the same.
+    pub async fn record_failure_and_alert(
+        &self,
+        pool: &PgPool,
+        email_client: &EmailClient,
+        username: &str,
+        ip: Option<IpAddr>,
+    ) -> Result<FailureOutcome, anyhow::Error> {
+        let outcome = self.record_failure(username, ip).await?;
+        if outcome == FailureOutcome::AccountLocked {
+            if let Err(e) = self.alert_lockout(pool, email_client, username, ip).await {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to alert the admins of a locked account."
+                );
+            }
+        }
+        Ok(outcome)
+    }
+
+    /// Forget the failures of an account once a login completed, second
+    /// factor included. Failures from the address keep counting, so that an
+    /// attacker cannot reset them by logging in to an account of their own.
+    #[tracing::instrument(name = "Record a successful login", skip(self))]
+    pub async fn record_success(&self, username: &str) -> Result<(), anyhow::Error> {
+        let mut redis = self.redis.clone();
+        redis
+            .del::<_, ()>(self.key("failures", &Target::Account(username)))
+            .await
+            .context("Failed to reset the failed login count.")?;
+        Ok(())
+    }
+
+    pub fn lockout_duration(&self) -> Duration {
+        Duration::from_secs(self.settings.lockout_seconds)
+    }
+
+    /// Warn the admins that an account was locked. Attempts on usernames that
+    /// do not exist lock them too, but are not worth an alert.
+    #[tracing::instrument(name = "Send a lockout alert", skip(self, pool, email_client))]
+    pub async fn alert_lockout(
+        &self,
+        pool: &PgPool,
+        email_client: &EmailClient,
+        username: &str,
+        ip: Option<IpAddr>,
+    ) -> Result<(), anyhow::Error> {
+        let exists = sqlx::query!(
+            r#"SELECT EXISTS(SELECT 1 FROM users WHERE username = $1) as "exists!""#,
+            username
+        )
+        .fetch_one(pool)
+        .await
+        .context("Failed to check whether the user exists.")?
+        .exists;
+        if !exists {
+            return Ok(());
+        }
+
+        let minutes = self.settings.lockout_seconds.div_ceil(60);
+        let source = ip.map_or_else(|| "an unknown address".into(), |ip| ip.to_string());
+        let text_body = format!(
+            "The account {} has been locked for {} minutes after repeated failed logins, \
+            the last one from {}.",
+            username, minutes, source
+        );
+        let html_body = format!("<p>{}</p>", htmlescape::encode_minimal(&text_body));
+        for recipient in &self.alert_recipients {
+            email_client
+                .send_mail(recipient, "Admin account locked", &html_body, &text_body)
+                .await
+                .context("Failed to send a lockout alert.")?;
+        }
+        Ok(())
+    }
+
+    fn key(&self, kind: &str, target: &Target) -> String {
+        match target {
+            Target::Account(username) => {
+                format!("{}:{}:user:{}", self.settings.key_prefix, kind, username)
+            }
+            Target::Address(ip) => format!("{}:{}:ip:{}", self.settings.key_prefix, kind, ip),
+        }
+    }
+}
+
+fn targets(username: &str, ip: Option<IpAddr>) -> Vec<Target<'_>> {
+    let mut targets = vec![Target::Account(username)];
+    targets.extend(ip.map(Target::Address));
+    targets
+}
+
+/// How long to hold back a login after `failures` recent failed attempts.
+/// The delay doubles with every failure past the threshold, up to the cap.
+fn delay_after(settings: &LoginThrottlingSettings, failures: u32) -> Duration {
+    if failures < settings.failures_before_delay {
+        return Duration::ZERO;
+    }
+    let doublings = (failures - settings.failures_before_delay).min(31);
+    let delay = settings
+        .base_delay_milliseconds
+        .saturating_mul(1 << doublings)
+        .min(settings.max_delay_milliseconds);
+    Duration::from_millis(delay)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::delay_after;
+    use crate::configuration::LoginThrottlingSettings;
+
+    fn settings() -> LoginThrottlingSettings {
+        LoginThrottlingSettings {
+            key_prefix: "login_throttling".into(),
+            failure_window_seconds: 900,
+            failures_before_delay: 3,
+            base_delay_milliseconds: 500,
+            max_delay_milliseconds: 4000,
+            account_failures_before_lockout: 10,
+            ip_failures_before_lockout: 50,
+            lockout_seconds: 900,
+            alert_recipients: vec![],
+            link_requests_per_address: 3,
+            link_requests_per_ip: 20,
+        }
+    }
+
+    #[test]
+    fn the_first_failures_are_not_delayed() {
+        assert_eq!(delay_after(&settings(), 0), Duration::ZERO);
+        assert_eq!(delay_after(&settings(), 2), Duration::ZERO);
+    }
+
+    #[test]
+    fn the_delay_doubles_with_each_failure() {
+        assert_eq!(delay_after(&settings(), 3), Duration::from_millis(500));
+        assert_eq!(delay_after(&settings(), 4), Duration::from_millis(1000));
+        assert_eq!(delay_after(&settings(), 5), Duration::from_millis(2000));
+    }
+
+    #[test]
+    fn the_delay_is_capped() {
+        assert_eq!(delay_after(&settings(), 6), Duration::from_millis(4000));
+        assert_eq!(delay_after(&settings(), 1000), Duration::from_millis(4000));
+    }
+}
@@ -1,10 +1,15 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use actix_web::cookie::SameSite;
 use secrecy::{ExposeSecret, Secret};
 use serde_aux::{
-    field_attributes::deserialize_number_from_string, prelude::deserialize_bool_from_anything,
+    field_attributes::{deserialize_number_from_string, deserialize_option_number_from_string},
+    prelude::deserialize_bool_from_anything,
 };
 use sqlx::{postgres::PgConnectOptions, ConnectOptions};
 
-use crate::{domain::SubscriberEmail, email_client::EmailClient};
+use crate::{domain::SubscriberEmail, email_client::EmailClient, signing::HmacKeys};
 
 #[derive(serde::Deserialize, Clone)]
 pub struct Settings {
@@ -47,20 +52,226 @@ pub struct ApplicationSettings {
     pub port: u16,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    /// Secrets `hmac_secret` replaced. They still verify cookies and links
+    /// signed before the rotation, but sign nothing new.
+    #[serde(default)]
+    pub previous_hmac_secrets: Vec<Secret<String>>,
     pub signup: SignupSettings,
+    pub login_throttling: LoginThrottlingSettings,
+    pub session: SessionSettings,
+    pub password_policy: PasswordPolicySettings,
+    pub password_hashing: PasswordHashingSettings,
+    pub idempotency: IdempotencySettings,
+    #[serde(default)]
+    pub oidc: Option<OidcSettings>,
 }
 
 impl ApplicationSettings {
     pub fn get_listen_addr(&self) -> String {
         format!("{}:{}", self.host, self.port)
     }
+
+    pub fn hmac_keys(&self) -> HmacKeys {
+        HmacKeys::new(self.hmac_secret.clone(), self.previous_hmac_secrets.clone())
+    }
 }
 
+/// Open signups for anyone holding the shared token. Off by default: owners
+/// invite new users from the admin area instead.
 #[derive(serde::Deserialize, Clone)]
 pub struct SignupSettings {
     #[serde(deserialize_with = "deserialize_bool_from_anything")]
     pub enabled: bool,
-    pub token: Secret<String>,
+    #[serde(default)]
+    pub token: Option<Secret<String>>,
+}
+
+/// Limits on failed logins, tracked in Redis per username and per client IP.
+#[derive(serde::Deserialize, Clone)]
+pub struct LoginThrottlingSettings {
+    /// Prefix of the Redis keys, so that several deployments can share a server.
+    pub key_prefix: String,
+    /// How long a failed attempt keeps counting.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub failure_window_seconds: u64,
+    /// Failures after which each further attempt is delayed, doubling the
+    /// delay every time.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub failures_before_delay: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub base_delay_milliseconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_delay_milliseconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub account_failures_before_lockout: u32,
+    /// Higher than the account limit, as several users can share an address.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub ip_failures_before_lockout: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub lockout_seconds: u64,
+    /// Who is told when an account gets locked.
+    pub alert_recipients: Vec<String>,
+    /// Sign-in and password reset links mailed to one address per failure
+    /// window.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub link_requests_per_address: u32,
+    /// Sign-in and password reset links requested from one client address
+    /// per failure window.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub link_requests_per_ip: u32,
+}
+
+impl LoginThrottlingSettings {
+    pub fn alert_recipients(&self) -> Result<Vec<SubscriberEmail>, String> {
+        self.alert_recipients
+            .iter()
+            .map(|email| SubscriberEmail::parse(email.clone()))
+            .collect()
+    }
+}
+
+/// How long admin sessions last and how their cookie is set.
+///
+/// A session ends `idle_timeout_minutes` after its last request, and at the
+/// latest `lifetime_minutes` after logging in. Sessions logged in with
+/// "remember me" last `remember_me_days` instead, however idle; the option is
+/// only offered when that is set.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct SessionSettings {
+    pub cookie_name: String,
+    /// Only send the cookie over HTTPS.
+    #[serde(deserialize_with = "deserialize_bool_from_anything")]
+    pub cookie_secure: bool,
+    pub same_site: SameSitePolicy,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub lifetime_minutes: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub idle_timeout_minutes: u64,
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub remember_me_days: Option<u64>,
+}
+
+impl SessionSettings {
+    pub fn lifetime(&self) -> Duration {
+        Duration::from_secs(self.lifetime_minutes * 60)
+    }
+
+    pub fn idle_timeout(&self) -> Duration {
+        Duration::from_secs(self.idle_timeout_minutes * 60)
+    }
+
+    /// `None` when remember-me is disabled.
+    pub fn remember_me_lifetime(&self) -> Option<Duration> {
+        self.remember_me_days
+            .map(|days| Duration::from_secs(days * 24 * 60 * 60))
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SameSitePolicy {
+    Strict,
+    Lax,
+    None,
+}
+
+impl From<SameSitePolicy> for SameSite {
+    fn from(policy: SameSitePolicy) -> Self {
+        match policy {
+            SameSitePolicy::Strict => SameSite::Strict,
+            SameSitePolicy::Lax => SameSite::Lax,
+            SameSitePolicy::None => SameSite::None,
+        }
+    }
+}
+
+/// How long idempotency keys are honored, and how expired ones are deleted.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct IdempotencySettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub retention_hours: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub cleanup_interval_seconds: u64,
+    /// Keys deleted per statement, so that deletes stay short.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub cleanup_batch_size: u32,
+}
+
+impl IdempotencySettings {
+    pub fn retention(&self) -> Duration {
+        Duration::from_secs(self.retention_hours * 60 * 60)
+    }
+
+    pub fn cleanup_interval(&self) -> Duration {
+        Duration::from_secs(self.cleanup_interval_seconds)
+    }
+}
+
+/// Rules for new passwords, see `PasswordPolicy`.
+#[derive(serde::Deserialize, Clone)]
+pub struct PasswordPolicySettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_length: usize,
+    /// Keeps hashing cheap enough that long inputs cannot tie up the server.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_length: usize,
+    /// Extra common or breached passwords, one per line, on top of the
+    /// bundled list.
+    #[serde(default)]
+    pub common_passwords_file: Option<String>,
+}
+
+/// Argon2id parameters for new password hashes. Hashes computed with other
+/// ones are upgraded when their user logs in.
+#[derive(serde::Deserialize, Clone)]
+pub struct PasswordHashingSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub memory_kib: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub iterations: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub parallelism: u32,
+}
+
+/// Single sign-on through an OpenID Connect provider, offered next to the
+/// password form when configured.
+#[derive(serde::Deserialize, Clone)]
+pub struct OidcSettings {
+    /// The provider's discovery document is read from
+    /// `{issuer}/.well-known/openid-configuration`, and must name this issuer
+    /// exactly, as must the ID tokens.
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Secret<String>,
+    /// Shown on the login button.
+    #[serde(default = "default_provider_name")]
+    pub provider_name: String,
+    /// Create an account for verified email addresses without one, instead
+    /// of refusing them.
+    #[serde(default, deserialize_with = "deserialize_bool_from_anything")]
+    pub provision_users: bool,
+    /// Role of provisioned users none of whose groups have one.
+    #[serde(default = "default_sso_role")]
+    pub default_role: String,
+    /// The ID token claim listing the user's groups.
+    #[serde(default = "default_groups_claim")]
+    pub groups_claim: String,
+    /// Group name to role. When any of the user's groups has a role, the
+    /// highest one is applied on every single sign-on.
+    #[serde(default)]
+    pub group_roles: HashMap<String, String>,
+}
+
+fn default_provider_name() -> String {
+    "single sign-on".into()
+}
+
+fn default_sso_role() -> String {
+    "viewer".into()
+}
+
+fn default_groups_claim() -> String {
+    "groups".into()
 }
 
 pub fn get_configuration() -> Result<Settings, config::ConfigError> {
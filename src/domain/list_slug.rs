@@ -0,0 +1,67 @@
+use utoipa::ToSchema;
+
+/// URL friendly identifier of a newsletter list, e.g. `weekly-digest`.
+#[derive(ToSchema, Clone, Debug, PartialEq, Eq)]
+pub struct ListSlug(String);
+
+impl ListSlug {
+    pub fn parse(s: String) -> Result<ListSlug, String> {
+        let is_empty = s.is_empty();
+        let is_too_long = s.len() > 64;
+        let contains_forbidden_characters = s
+            .chars()
+            .any(|c| !(c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-'));
+        let has_dangling_dash = s.starts_with('-') || s.ends_with('-');
+
+        if is_empty || is_too_long || contains_forbidden_characters || has_dangling_dash {
+            Err(format!("{} is not a valid list identifier", s))
+        } else {
+            Ok(Self(s))
+        }
+    }
+}
+
+impl AsRef<str> for ListSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::ListSlug;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn a_valid_slug_is_parsed_successfully() {
+        assert_ok!(ListSlug::parse("weekly-digest-2".to_string()));
+    }
+
+    #[test]
+    fn empty_string_is_rejected() {
+        assert_err!(ListSlug::parse("".to_string()));
+    }
+
+    #[test]
+    fn uppercase_characters_are_rejected() {
+        assert_err!(ListSlug::parse("Weekly".to_string()));
+    }
+
+    #[test]
+    fn slugs_with_invalid_characters_are_rejected() {
+        for slug in &[
+            "weekly digest",
+            "weekly_digest",
+            "weekly/digest",
+            "-weekly",
+            "weekly-",
+        ] {
+            assert_err!(ListSlug::parse(slug.to_string()));
+        }
+    }
+
+    #[test]
+    fn a_slug_longer_than_64_characters_is_rejected() {
+        assert_err!(ListSlug::parse("a".repeat(65)));
+    }
+}
@@ -1,7 +1,13 @@
+mod list_slug;
 mod new_subscriber;
+mod segment;
 mod subscriber_email;
 mod subscriber_name;
+mod subscriber_tag;
 
+pub use list_slug::ListSlug;
 pub use new_subscriber::SubscriptionRequest;
+pub use segment::{Segment, SegmentError};
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use subscriber_tag::SubscriberTag;
@@ -1,6 +1,6 @@
 use utoipa::ToSchema;
 
-use crate::domain::SubscriberName;
+use crate::domain::{ListSlug, SubscriberName, SubscriberTag};
 
 use super::SubscriberEmail;
 
@@ -12,4 +12,12 @@ pub struct SubscriptionRequest {
 
     #[schema(example = "jonhdoe@example.com")]
     pub email: SubscriberEmail,
+
+    /// Optional comma separated tags, e.g. set by a hidden field on the subscribe form.
+    #[schema(value_type = Option<String>, example = "rust,early-adopter")]
+    pub tags: Vec<SubscriberTag>,
+
+    /// List to subscribe to, the default list if omitted.
+    #[schema(value_type = Option<String>, example = "weekly-digest")]
+    pub list: Option<ListSlug>,
 }
@@ -0,0 +1,165 @@
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+
+use super::SubscriberTag;
+
+/// The subset of confirmed subscribers a newsletter issue is sent to.
+///
+/// A subscriber matches if they carry at least one of `include_tags` (or
+/// `include_tags` is empty), none of `exclude_tags`, and subscribed within the
+/// (inclusive) `subscribed_from` - `subscribed_until` date range.
+#[derive(Clone, Debug, Default)]
+pub struct Segment {
+    pub include_tags: Vec<SubscriberTag>,
+    pub exclude_tags: Vec<SubscriberTag>,
+    pub subscribed_from: Option<NaiveDate>,
+    pub subscribed_until: Option<NaiveDate>,
+}
+
+/// Why a segment is invalid, naming the field at fault.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SegmentError {
+    pub field: &'static str,
+    pub message: String,
+}
+
+impl SegmentError {
+    fn new(field: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            field,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for SegmentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Segment {
+    pub fn parse(
+        include_tags: &str,
+        exclude_tags: &str,
+        subscribed_from: &str,
+        subscribed_until: &str,
+    ) -> Result<Segment, SegmentError> {
+        let segment = Self {
+            include_tags: SubscriberTag::parse_list(include_tags)
+                .map_err(|e| SegmentError::new("include_tags", e))?,
+            exclude_tags: SubscriberTag::parse_list(exclude_tags)
+                .map_err(|e| SegmentError::new("exclude_tags", e))?,
+            subscribed_from: parse_date(subscribed_from)
+                .map_err(|e| SegmentError::new("subscribed_from", e))?,
+            subscribed_until: parse_date(subscribed_until)
+                .map_err(|e| SegmentError::new("subscribed_until", e))?,
+        };
+
+        if let (Some(from), Some(until)) = (segment.subscribed_from, segment.subscribed_until) {
+            if from > until {
+                return Err(SegmentError::new(
+                    "subscribed_until",
+                    format!(
+                        "The subscription range start ({}) is after its end ({}).",
+                        from, until
+                    ),
+                ));
+            }
+        }
+
+        if segment
+            .include_tags
+            .iter()
+            .any(|t| segment.exclude_tags.contains(t))
+        {
+            return Err(SegmentError::new(
+                "exclude_tags",
+                "A tag cannot be both included and excluded.",
+            ));
+        }
+
+        Ok(segment)
+    }
+
+    pub fn include_tags(&self) -> Vec<String> {
+        SubscriberTag::to_strings(&self.include_tags)
+    }
+
+    pub fn exclude_tags(&self) -> Vec<String> {
+        SubscriberTag::to_strings(&self.exclude_tags)
+    }
+
+    /// Inclusive lower bound on `subscribed_at`.
+    pub fn subscribed_after(&self) -> Option<DateTime<Utc>> {
+        self.subscribed_from.map(start_of_day)
+    }
+
+    /// Exclusive upper bound on `subscribed_at`.
+    pub fn subscribed_before(&self) -> Option<DateTime<Utc>> {
+        self.subscribed_until
+            .map(|d| start_of_day(d) + Duration::days(1))
+    }
+}
+
+fn parse_date(s: &str) -> Result<Option<NaiveDate>, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Ok(None);
+    }
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map(Some)
+        .map_err(|_| format!("{} is not a valid date (expected YYYY-MM-DD)", s))
+}
+
+fn start_of_day(date: NaiveDate) -> DateTime<Utc> {
+    DateTime::from_utc(date.and_hms_opt(0, 0, 0).unwrap(), Utc)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::Segment;
+    use claims::{assert_err, assert_none, assert_ok};
+
+    #[test]
+    fn an_empty_segment_matches_everybody() {
+        let segment = Segment::parse("", "", "", "").unwrap();
+        assert!(segment.include_tags.is_empty());
+        assert!(segment.exclude_tags.is_empty());
+        assert_none!(segment.subscribed_after());
+        assert_none!(segment.subscribed_before());
+    }
+
+    #[test]
+    fn the_date_range_is_inclusive_of_both_ends() {
+        let segment = Segment::parse("", "", "2023-05-01", "2023-05-31").unwrap();
+        assert_eq!(
+            segment.subscribed_after().unwrap().to_rfc3339(),
+            "2023-05-01T00:00:00+00:00"
+        );
+        assert_eq!(
+            segment.subscribed_before().unwrap().to_rfc3339(),
+            "2023-06-01T00:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn a_single_day_range_is_valid() {
+        assert_ok!(Segment::parse("", "", "2023-05-01", "2023-05-01"));
+    }
+
+    #[test]
+    fn an_inverted_date_range_is_rejected() {
+        assert_err!(Segment::parse("", "", "2023-05-31", "2023-05-01"));
+    }
+
+    #[test]
+    fn an_invalid_date_is_rejected() {
+        let e = assert_err!(Segment::parse("", "", "01/05/2023", ""));
+        assert_eq!(e.field, "subscribed_from");
+    }
+
+    #[test]
+    fn a_tag_cannot_be_both_included_and_excluded() {
+        assert_err!(Segment::parse("rust,go", "go", "", ""));
+    }
+}
@@ -0,0 +1,92 @@
+use utoipa::ToSchema;
+
+/// Tag attached to a subscriber, used to target newsletter issues at a segment.
+#[derive(ToSchema, Clone, Debug, PartialEq, Eq)]
+pub struct SubscriberTag(String);
+
+impl SubscriberTag {
+    pub fn parse(s: String) -> Result<SubscriberTag, String> {
+        let tag = s.trim().to_lowercase();
+
+        let is_empty = tag.is_empty();
+        let is_too_long = tag.chars().count() > 64;
+        let contains_forbidden_characters = tag
+            .chars()
+            .any(|c| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+
+        if is_empty || is_too_long || contains_forbidden_characters {
+            Err(format!("{} is not a valid subscriber tag", s))
+        } else {
+            Ok(Self(tag))
+        }
+    }
+
+    /// Parse a comma separated list of tags, dropping empty entries and duplicates.
+    pub fn parse_list(s: &str) -> Result<Vec<SubscriberTag>, String> {
+        let mut tags: Vec<SubscriberTag> = Vec::new();
+        for part in s.split(',').filter(|p| !p.trim().is_empty()) {
+            let tag = Self::parse(part.to_string())?;
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        Ok(tags)
+    }
+
+    /// Convert tags to the `TEXT[]` representation stored in the database.
+    pub fn to_strings(tags: &[SubscriberTag]) -> Vec<String> {
+        tags.iter().map(|t| t.0.clone()).collect()
+    }
+}
+
+impl AsRef<str> for SubscriberTag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberTag;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn a_valid_tag_is_parsed_successfully() {
+        assert_ok!(SubscriberTag::parse("early-adopter_2023".to_string()));
+    }
+
+    #[test]
+    fn tags_are_normalised_to_lowercase() {
+        let tag = SubscriberTag::parse("  Rust ".to_string()).unwrap();
+        assert_eq!(tag.as_ref(), "rust");
+    }
+
+    #[test]
+    fn empty_string_is_rejected() {
+        assert_err!(SubscriberTag::parse(" ".to_string()));
+    }
+
+    #[test]
+    fn a_tag_longer_than_64_characters_is_rejected() {
+        assert_err!(SubscriberTag::parse("a".repeat(65)));
+    }
+
+    #[test]
+    fn tags_containing_an_invalid_character_are_rejected() {
+        for tag in &["two words", "semi;colon", "<b>", "emoji🎉"] {
+            assert_err!(SubscriberTag::parse(tag.to_string()));
+        }
+    }
+
+    #[test]
+    fn a_list_of_tags_is_split_and_deduplicated() {
+        let tags = SubscriberTag::parse_list("rust, Go,,rust ").unwrap();
+        let tags: Vec<&str> = tags.iter().map(AsRef::as_ref).collect();
+        assert_eq!(tags, vec!["rust", "go"]);
+    }
+
+    #[test]
+    fn a_list_with_an_invalid_tag_is_rejected() {
+        assert_err!(SubscriberTag::parse_list("rust,not valid"));
+    }
+}
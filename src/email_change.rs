@@ -0,0 +1,202 @@
+use actix_web::HttpRequest;
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit::{change, record_event, AuditAction},
+    domain::SubscriberEmail,
+    email_client::EmailClient,
+    manage_link::{append_manage_link, manage_link},
+    routes::{error_chain_fmt, generate_subscription_token},
+    signing::HmacKeys,
+};
+
+#[derive(thiserror::Error)]
+pub enum EmailChangeError {
+    #[error("The new email address is the same as the current one.")]
+    SameAddress,
+
+    #[error("{0} is already used by another subscription.")]
+    AddressInUse(String),
+
+    #[error("The subscriber does not exist.")]
+    UnknownSubscriber,
+
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for EmailChangeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+/// Ask the subscriber to confirm their new address. The current address stays
+/// in use until the link sent to the new one is followed. Requests made by a
+/// user on the subscriber's behalf are audited.
+#[tracing::instrument(
+    name = "Request an email change",
+    skip(pool, email_client, base_url, hmac_keys, new_email, requested_by),
+    fields(new_email = %new_email.as_ref())
+)]
+pub async fn request_email_change(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    hmac_keys: &HmacKeys,
+    subscriber_id: Uuid,
+    new_email: &SubscriberEmail,
+    requested_by: Option<(&HttpRequest, Uuid)>,
+) -> Result<(), EmailChangeError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    let current = sqlx::query!(
+        r#"SELECT email FROM subscriptions WHERE id = $1"#,
+        subscriber_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to retrieve the subscriber.")?
+    .ok_or(EmailChangeError::UnknownSubscriber)?;
+    if current.email == new_email.as_ref() {
+        return Err(EmailChangeError::SameAddress);
+    }
+    let in_use = sqlx::query!(
+        r#"SELECT EXISTS(SELECT 1 FROM subscriptions WHERE email = $1) as "in_use!""#,
+        new_email.as_ref()
+    )
+    .fetch_one(&mut transaction)
+    .await
+    .context("Failed to check whether the new address is in use.")?
+    .in_use;
+    if in_use {
+        return Err(EmailChangeError::AddressInUse(new_email.as_ref().into()));
+    }
+
+    // Only the latest request can be confirmed.
+    sqlx::query!(
+        r#"DELETE FROM email_change_requests WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to drop previous email change requests.")?;
+    let token = generate_subscription_token();
+    sqlx::query!(
+        r#"
+        INSERT INTO email_change_requests (
+            email_change_token, subscriber_id, new_email, requested_at
+        )
+        VALUES ($1, $2, $3, $4)
+        "#,
+        token,
+        subscriber_id,
+        new_email.as_ref(),
+        Utc::now()
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to store the email change request.")?;
+    if let Some((request, user_id)) = requested_by {
+        // The change only happens once the subscriber confirms it.
+        record_event(
+            &mut transaction,
+            request,
+            Some(user_id),
+            AuditAction::ChangeSubscriberEmail,
+            &current.email,
+            serde_json::json!({ "email": change(&current.email, new_email.as_ref()) }),
+        )
+        .await?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store the email change request.")?;
+
+    let confirmation_link = format!(
+        "{}/subscriptions/confirm_email?email_change_token={}",
+        base_url, token
+    );
+    let (html_body, plain_body) = append_manage_link(
+        &format!(
+            "Click <a href=\"{}\">here</a> to confirm your new email address.",
+            confirmation_link
+        ),
+        &format!(
+            "Visit {} to confirm your new email address.",
+            confirmation_link
+        ),
+        &manage_link(base_url, hmac_keys, subscriber_id),
+    );
+    email_client
+        .send_mail(
+            new_email,
+            "Confirm your new email address",
+            &html_body,
+            &plain_body,
+        )
+        .await
+        .context("Failed to send the email change confirmation.")?;
+
+    Ok(())
+}
+
+/// Switch the subscriber to the address confirmed by the token, returning the
+/// subscriber id, or `None` if the token is unknown or has expired.
+#[tracing::instrument(name = "Confirm an email change", skip(pool, token))]
+pub async fn confirm_email_change(
+    pool: &PgPool,
+    token: &str,
+) -> Result<Option<Uuid>, EmailChangeError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    let Some(request) = sqlx::query!(
+        r#"
+        DELETE FROM email_change_requests
+        WHERE
+            email_change_token = $1 AND
+            requested_at > now() - interval '24 hours'
+        RETURNING subscriber_id, new_email
+        "#,
+        token
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to retrieve the email change request.")?
+    else {
+        return Ok(None);
+    };
+
+    sqlx::query!(
+        r#"UPDATE subscriptions SET email = $2 WHERE id = $1"#,
+        request.subscriber_id,
+        request.new_email,
+    )
+    .execute(&mut transaction)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(e) if e.code().as_deref() == Some("23505") => {
+            EmailChangeError::AddressInUse(request.new_email.clone())
+        }
+        e => anyhow::Error::new(e)
+            .context("Failed to update the subscriber's email.")
+            .into(),
+    })?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to change the email.")?;
+
+    Ok(Some(request.subscriber_id))
+}
@@ -34,11 +34,36 @@ impl EmailClient {
         subject: &str,
         html_content: &str,
         text_content: &str,
+    ) -> Result<(), reqwest::Error> {
+        self.send_mail_as(
+            &Sender::default(),
+            recipient,
+            subject,
+            html_content,
+            text_content,
+        )
+        .await
+    }
+
+    /// Send an email, overriding the default sender with the one provided.
+    pub async fn send_mail_as(
+        &self,
+        sender: &Sender,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
     ) -> Result<(), reqwest::Error> {
         let url = format!("{}/email", self.base_url);
 
+        let sender_email = sender.email.as_ref().unwrap_or(&self.sender).as_ref();
+        let from = match &sender.name {
+            Some(name) => format!("\"{}\" <{}>", name.replace('"', ""), sender_email),
+            None => sender_email.to_string(),
+        };
+
         let body = SendEmailRequest {
-            from: self.sender.as_ref(),
+            from: &from,
             to: recipient.as_ref(),
             subject,
             html_body: html_content,
@@ -60,6 +85,13 @@ impl EmailClient {
     }
 }
 
+/// Sender of an email, fields left empty fall back to the client's default sender.
+#[derive(Clone, Debug, Default)]
+pub struct Sender {
+    pub name: Option<String>,
+    pub email: Option<SubscriberEmail>,
+}
+
 #[derive(serde::Serialize)]
 #[serde(rename_all = "PascalCase")]
 struct SendEmailRequest<'a> {
@@ -82,11 +114,14 @@ mod tests {
     };
     use secrecy::Secret;
     use wiremock::{
-        matchers::{any, header, header_exists, method, path},
+        matchers::{any, body_partial_json, header, header_exists, method, path},
         Mock, MockServer, Request, ResponseTemplate,
     };
 
-    use crate::{domain::SubscriberEmail, email_client::EmailClient};
+    use crate::{
+        domain::SubscriberEmail,
+        email_client::{EmailClient, Sender},
+    };
 
     #[tokio::test]
     async fn send_email_sends_the_expected_request() {
@@ -110,6 +145,31 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn send_email_as_uses_the_given_sender() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(path("/email"))
+            .and(body_partial_json(serde_json::json!({
+                "From": "\"Weekly Digest\" <digest@example.com>"
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let sender = Sender {
+            name: Some("Weekly Digest".into()),
+            email: Some(SubscriberEmail::parse("digest@example.com".into()).unwrap()),
+        };
+        assert_ok!(
+            email_client
+                .send_mail_as(&sender, &email(), &subject(), &content(), &content())
+                .await
+        );
+    }
+
     #[tokio::test]
     async fn send_email_fails_if_the_server_returns_500() {
         let mock_server = MockServer::start().await;
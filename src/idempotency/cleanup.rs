@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::{
+    configuration::{IdempotencySettings, Settings},
+    startup::get_connection_pool,
+};
+
+/// Delete up to `batch_size` keys older than `retention`, returning how many
+/// were deleted. Rows locked by a request being processed are skipped rather
+/// than waited for.
+#[tracing::instrument(name = "Delete expired idempotency keys", skip(pool))]
+pub async fn delete_expired_keys(
+    pool: &PgPool,
+    retention: Duration,
+    batch_size: u32,
+) -> Result<u64, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM idempotency
+        WHERE (user_id, idempotency_key) IN (
+            SELECT user_id, idempotency_key
+            FROM idempotency
+            WHERE created_at < now() - make_interval(secs => $1)
+            LIMIT $2
+            FOR UPDATE SKIP LOCKED
+        )
+        "#,
+        retention.as_secs_f64(),
+        i64::from(batch_size)
+    )
+    .execute(pool)
+    .await
+    .context("Failed to delete expired idempotency keys.")?;
+    Ok(result.rows_affected())
+}
+
+async fn cleanup_loop(pool: PgPool, settings: IdempotencySettings) -> Result<(), anyhow::Error> {
+    let batch_size = settings.cleanup_batch_size;
+    loop {
+        match delete_expired_keys(&pool, settings.retention(), batch_size).await {
+            // A full batch means there may be more to delete.
+            Ok(deleted) if deleted == u64::from(batch_size) => {}
+            Ok(_) | Err(_) => tokio::time::sleep(settings.cleanup_interval()).await,
+        }
+    }
+}
+
+pub async fn run_cleanup_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    cleanup_loop(connection_pool, configuration.application.idempotency).await
+}
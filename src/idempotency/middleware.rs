@@ -0,0 +1,103 @@
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::StatusCode,
+    web, HttpMessage,
+};
+use actix_web_lab::middleware::Next;
+use sqlx::PgPool;
+
+use super::{request_hash, save_response, try_processing, IdempotencyKey, NextAction};
+use crate::{
+    authentication::UserId,
+    configuration::IdempotencySettings,
+    problem::Problem,
+    utils::{e500, peek_body},
+};
+
+/// Header carrying the idempotency key of a request.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// Marks a request whose key was handled by `honor_idempotency_keys`, so that
+/// handlers with their own idempotency do not process the key a second time.
+pub struct IdempotentRequest;
+
+/// Requests carrying an `Idempotency-Key` header are processed once per user:
+/// repeating the key gets the response saved the first time. Must run after
+/// the middleware authenticating the user, as keys are scoped to the user the
+/// session or API token belongs to. Requests without the header, GET and HEAD
+/// requests and anonymous requests are passed through.
+///
+/// Reusing a key for another method, path, query string or body is refused
+/// with a 422, and a retry arriving while the first request is processed gets
+/// a 409. Server errors are not saved, so that the request can be retried.
+pub async fn honor_idempotency_keys(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let key = req
+        .headers()
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .map(|value| value.to_str().unwrap_or_default().to_owned());
+    let user_id = req.extensions().get::<UserId>().copied();
+    let (Some(key), Some(user_id), false) = (key, user_id, req.method().is_safe()) else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    let key = IdempotencyKey::try_from(key).map_err(|e| {
+        Problem::new(
+            StatusCode::BAD_REQUEST,
+            "invalid_idempotency_key",
+            format!("Invalid {} header: {}", IDEMPOTENCY_KEY_HEADER, e),
+        )
+    })?;
+
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .ok_or_else(|| e500("The database pool is not configured."))?
+        .clone();
+    let retention = req
+        .app_data::<web::Data<IdempotencySettings>>()
+        .ok_or_else(|| e500("The idempotency settings are not configured."))?
+        .retention();
+    let body = peek_body(&mut req).await?;
+    let target = req
+        .uri()
+        .path_and_query()
+        .map_or(req.path(), |target| target.as_str());
+    let request_line_and_body = [
+        req.method().as_str().as_bytes(),
+        b" ",
+        target.as_bytes(),
+        b"\n",
+        &body,
+    ]
+    .concat();
+    let transaction = match try_processing(
+        &pool,
+        &key,
+        *user_id,
+        &request_hash(&request_line_and_body),
+        retention,
+    )
+    .await?
+    {
+        NextAction::StartProcessing(transaction) => transaction,
+        NextAction::ReturnSavedResponse(saved_response) => {
+            return Ok(req.into_response(saved_response));
+        }
+    };
+
+    req.extensions_mut().insert(IdempotentRequest);
+    let (request, response) = next.call(req).await?.into_parts();
+    if response.status().is_server_error() {
+        // Dropping the transaction releases the key.
+        return Ok(ServiceResponse::new(
+            request,
+            response.map_into_boxed_body(),
+        ));
+    }
+    let response = save_response(transaction, &key, *user_id, response.map_into_boxed_body())
+        .await
+        .map_err(e500)?;
+    Ok(ServiceResponse::new(request, response))
+}
@@ -1,5 +1,19 @@
+use sha2::{Digest, Sha256};
+
+mod cleanup;
+mod middleware;
 mod persistence;
-pub use persistence::{get_saved_response, save_response, try_processing, NextAction};
+pub use cleanup::{delete_expired_keys, run_cleanup_until_stopped};
+pub use middleware::{honor_idempotency_keys, IdempotentRequest, IDEMPOTENCY_KEY_HEADER};
+pub use persistence::{
+    get_saved_response, save_response, try_processing, IdempotencyError, NextAction,
+};
+
+/// Hash of what a request asks for, to tell a retry from another request
+/// reusing its idempotency key.
+pub fn request_hash(request: &[u8]) -> String {
+    hex::encode(Sha256::digest(request))
+}
 
 #[derive(Debug)]
 pub struct IdempotencyKey(String);
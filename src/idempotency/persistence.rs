@@ -1,14 +1,20 @@
-use actix_web::{body::to_bytes, HttpResponse};
+use std::time::Duration;
+
+use actix_web::{body::to_bytes, http::header::RETRY_AFTER, HttpResponse, ResponseError};
+use anyhow::Context;
 use reqwest::StatusCode;
 use sqlx::{postgres::PgHasArrayType, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 use super::IdempotencyKey;
+use crate::{problem::Problem, routes::error_chain_fmt};
 
+/// The response saved for the key, unless it is older than `retention`.
 pub async fn get_saved_response(
     pool: &PgPool,
     idempotency_key: &IdempotencyKey,
     user_id: Uuid,
+    retention: Duration,
 ) -> Result<Option<HttpResponse>, anyhow::Error> {
     let saved_respnose = sqlx::query!(
         r#"
@@ -19,10 +25,13 @@ pub async fn get_saved_response(
         FROM idempotency
         WHERE
             user_id = $1 AND
-            idempotency_key = $2
+            idempotency_key = $2 AND
+            response_status_code IS NOT NULL AND
+            created_at > now() - make_interval(secs => $3)
         "#,
         user_id,
-        idempotency_key.as_ref()
+        idempotency_key.as_ref(),
+        retention.as_secs_f64()
     )
     .fetch_optional(pool)
     .await?;
@@ -104,36 +113,141 @@ pub enum NextAction {
     ReturnSavedResponse(HttpResponse),
 }
 
+#[derive(thiserror::Error)]
+pub enum IdempotencyError {
+    #[error("This idempotency key was already used for a different request.")]
+    KeyReused,
+
+    #[error("A request with this idempotency key is still being processed.")]
+    RequestInProgress,
+
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for IdempotencyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl From<&IdempotencyError> for Problem {
+    fn from(e: &IdempotencyError) -> Self {
+        match e {
+            IdempotencyError::KeyReused => Problem::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "idempotency_key_reused",
+                e.to_string(),
+            ),
+            IdempotencyError::RequestInProgress => {
+                Problem::new(StatusCode::CONFLICT, "request_in_progress", e.to_string())
+                    .with_header(RETRY_AFTER, RETRY_AFTER_SECONDS)
+            }
+            IdempotencyError::UnexpectedError(e) => Problem::internal(e),
+        }
+    }
+}
+
+impl ResponseError for IdempotencyError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            IdempotencyError::KeyReused => StatusCode::UNPROCESSABLE_ENTITY,
+            IdempotencyError::RequestInProgress => StatusCode::CONFLICT,
+            IdempotencyError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        Problem::from(self).error_response()
+    }
+}
+
+/// Processing a request seldom takes longer.
+const RETRY_AFTER_SECONDS: u32 = 1;
+
+/// Claim the key for this request, or get what to answer instead: the saved
+/// response if the request was processed already, an error if the key was
+/// used for another request or if a request with it is being processed.
+///
+/// The claim is a transaction-level advisory lock, so that a duplicate is
+/// told to retry instead of waiting for the first request to finish. Keys
+/// older than `retention` are claimed again as if they were new.
 pub async fn try_processing(
     pool: &PgPool,
     idempotency_key: &IdempotencyKey,
     user_id: Uuid,
-) -> Result<NextAction, anyhow::Error> {
-    let mut transaction = pool.begin().await?;
+    request_hash: &str,
+    retention: Duration,
+) -> Result<NextAction, IdempotencyError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let locked = sqlx::query_scalar!(
+        r#"SELECT pg_try_advisory_xact_lock(hashtextextended($1 || ':' || $2, 0)) AS "locked!""#,
+        user_id.to_string(),
+        idempotency_key.as_ref()
+    )
+    .fetch_one(&mut transaction)
+    .await
+    .context("Failed to lock the idempotency key.")?;
+    if !locked {
+        return Err(IdempotencyError::RequestInProgress);
+    }
+
     let n_inserted_rows = sqlx::query!(
         r#"
         INSERT INTO idempotency (
             user_id,
             idempotency_key,
+            request_hash,
             created_at
         )
-        VALUES ($1, $2, now())
-        ON CONFLICT DO NOTHING
+        VALUES ($1, $2, $3, now())
+        ON CONFLICT (user_id, idempotency_key) DO UPDATE
+        SET
+            request_hash = EXCLUDED.request_hash,
+            response_status_code = NULL,
+            response_headers = NULL,
+            response_body = NULL,
+            created_at = EXCLUDED.created_at
+        WHERE idempotency.created_at <= now() - make_interval(secs => $4)
         "#,
         user_id,
-        idempotency_key.as_ref()
+        idempotency_key.as_ref(),
+        request_hash,
+        retention.as_secs_f64()
     )
     .execute(&mut transaction)
-    .await?
+    .await
+    .context("Failed to store the idempotency key.")?
     .rows_affected();
-
     if n_inserted_rows > 0 {
-        Ok(NextAction::StartProcessing(transaction))
-    } else {
-        let saved_response = get_saved_response(pool, idempotency_key, user_id)
-            .await?
-            .ok_or_else(|| anyhow::anyhow!("Failed to fetch a saved response from DB."))?;
+        return Ok(NextAction::StartProcessing(transaction));
+    }
 
-        Ok(NextAction::ReturnSavedResponse(saved_response))
+    let saved_hash = sqlx::query_scalar!(
+        r#"
+        SELECT request_hash
+        FROM idempotency
+        WHERE
+            user_id = $1 AND
+            idempotency_key = $2
+        "#,
+        user_id,
+        idempotency_key.as_ref()
+    )
+    .fetch_one(&mut transaction)
+    .await
+    .context("Failed to retrieve the request hash of the idempotency key.")?;
+    if saved_hash.is_some_and(|saved_hash| saved_hash != request_hash) {
+        return Err(IdempotencyError::KeyReused);
     }
+
+    // A key without a response was claimed by a request that never
+    // finished, e.g. because the application stopped.
+    let saved_response = get_saved_response(pool, idempotency_key, user_id, retention)
+        .await?
+        .ok_or(IdempotencyError::RequestInProgress)?;
+    Ok(NextAction::ReturnSavedResponse(saved_response))
 }
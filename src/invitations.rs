@@ -0,0 +1,256 @@
+use actix_web::HttpRequest;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    account_tokens::hash_token,
+    audit::{record_event, AuditAction},
+    authentication::{compute_password_hash, PasswordHashing, Role},
+    domain::SubscriberEmail,
+    email_client::EmailClient,
+    routes::{error_chain_fmt, generate_subscription_token},
+    telemetry::spawn_blocking_with_tracing,
+};
+
+#[derive(thiserror::Error)]
+pub enum InvitationError {
+    #[error("{0} already has an account.")]
+    AlreadyRegistered(String),
+
+    #[error("This invitation link is invalid or has expired.")]
+    InvalidToken,
+
+    #[error("The username {0} is already taken.")]
+    UsernameTaken(String),
+
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for InvitationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+/// An invitation that has been sent but not accepted yet.
+pub struct PendingInvitation {
+    pub email: String,
+    pub role: String,
+    pub invited_at: DateTime<Utc>,
+}
+
+/// Mail a link to create an account with `role`. Inviting an address again
+/// replaces its previous invitation. The invitation is audited along with
+/// `request`.
+#[tracing::instrument(
+    name = "Invite a user",
+    skip(pool, email_client, base_url, request, email),
+    fields(email = %email.as_ref())
+)]
+pub async fn invite_user(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    request: &HttpRequest,
+    email: &SubscriberEmail,
+    role: Role,
+    invited_by: Uuid,
+) -> Result<(), InvitationError> {
+    let registered = sqlx::query!(
+        r#"SELECT EXISTS(SELECT 1 FROM users WHERE email = $1) as "exists!""#,
+        email.as_ref()
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to check whether the address has an account.")?
+    .exists;
+    if registered {
+        return Err(InvitationError::AlreadyRegistered(email.as_ref().into()));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    sqlx::query!(
+        r#"DELETE FROM user_invitations WHERE email = $1"#,
+        email.as_ref()
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to drop previous invitations.")?;
+    let token = generate_subscription_token();
+    sqlx::query!(
+        r#"
+        INSERT INTO user_invitations (token_hash, email, role, invited_by, invited_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        hash_token(&token),
+        email.as_ref(),
+        role.as_str(),
+        invited_by,
+        Utc::now()
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to store the invitation.")?;
+    record_event(
+        &mut transaction,
+        request,
+        Some(invited_by),
+        AuditAction::InviteUser,
+        email.as_ref(),
+        serde_json::json!({ "role": role.as_str() }),
+    )
+    .await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store the invitation.")?;
+
+    let invitation_link = format!("{}/invitation?token={}", base_url, token);
+    email_client
+        .send_mail(
+            email,
+            "You have been invited to Zero2Prod",
+            &format!(
+                "You have been invited to help run the newsletter as {}. \
+                Click <a href=\"{}\">here</a> to create your account. \
+                The link is valid for seven days.",
+                role, invitation_link
+            ),
+            &format!(
+                "You have been invited to help run the newsletter as {}.\n\
+                Visit {} to create your account. The link is valid for seven days.",
+                role, invitation_link
+            ),
+        )
+        .await
+        .context("Failed to send the invitation email.")?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get pending invitations", skip(pool))]
+pub async fn get_pending_invitations(
+    pool: &PgPool,
+) -> Result<Vec<PendingInvitation>, anyhow::Error> {
+    sqlx::query_as!(
+        PendingInvitation,
+        r#"
+        SELECT email, role, invited_at
+        FROM user_invitations
+        WHERE invited_at > now() - interval '7 days'
+        ORDER BY invited_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the pending invitations.")
+}
+
+/// The address an invitation was sent to, or `None` if the token cannot be
+/// used.
+#[tracing::instrument(name = "Get invitation", skip(pool, token))]
+pub async fn get_invitation_email(
+    pool: &PgPool,
+    token: &str,
+) -> Result<Option<String>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT email FROM user_invitations
+        WHERE token_hash = $1 AND invited_at > now() - interval '7 days'
+        "#,
+        hash_token(token)
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the invitation.")?;
+    Ok(row.map(|r| r.email))
+}
+
+/// Create the invited user, auditing it along with `request`. The token can
+/// only be used once.
+#[tracing::instrument(
+    name = "Accept an invitation",
+    skip(pool, request, hashing, token, password)
+)]
+pub async fn accept_invitation(
+    pool: &PgPool,
+    request: &HttpRequest,
+    hashing: &PasswordHashing,
+    token: &str,
+    username: &str,
+    password: Secret<String>,
+) -> Result<Uuid, InvitationError> {
+    let hashing = hashing.clone();
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password, &hashing))
+            .await
+            .context("Failed to spawn blocking task.")?
+            .context("Failed to hash password")?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let invitation = sqlx::query!(
+        r#"
+        DELETE FROM user_invitations
+        WHERE
+            token_hash = $1 AND
+            invited_at > now() - interval '7 days'
+        RETURNING email, role
+        "#,
+        hash_token(token)
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to use the invitation.")?
+    .ok_or(InvitationError::InvalidToken)?;
+
+    let user_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, email, password, role)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        user_id,
+        username,
+        invitation.email,
+        password_hash.expose_secret(),
+        invitation.role
+    )
+    .execute(&mut transaction)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db) if db.constraint() == Some("users_email_key") => {
+            InvitationError::AlreadyRegistered(invitation.email.clone())
+        }
+        sqlx::Error::Database(ref db) if db.code().as_deref() == Some("23505") => {
+            InvitationError::UsernameTaken(username.into())
+        }
+        e => anyhow::Error::new(e)
+            .context("Failed to create the invited user.")
+            .into(),
+    })?;
+    record_event(
+        &mut transaction,
+        request,
+        Some(user_id),
+        AuditAction::AcceptInvitation,
+        username,
+        serde_json::json!({ "email": invitation.email, "role": invitation.role }),
+    )
+    .await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to accept the invitation.")?;
+
+    Ok(user_id)
+}
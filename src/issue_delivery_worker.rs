@@ -1,11 +1,17 @@
 use std::time::Duration;
 
+use chrono::Utc;
 use sqlx::{PgPool, Postgres, Transaction};
 use tracing::{field::display, Span};
 use uuid::Uuid;
 
 use crate::{
-    configuration::Settings, domain::SubscriberEmail, email_client::EmailClient,
+    configuration::Settings,
+    domain::SubscriberEmail,
+    email_client::EmailClient,
+    lists::get_list,
+    manage_link::{append_manage_link, manage_link},
+    signing::HmacKeys,
     startup::get_connection_pool,
 };
 
@@ -18,27 +24,36 @@ pub enum ExecutionOutcome {
     skip_all,
     fields(
         newsletter_issue_id=tracing::field::Empty,
-        subscriber_email=tracing::field::Empty
+        subscriber_id=tracing::field::Empty
     )
 )]
 pub async fn try_execute_task(
     pool: &PgPool,
     email_client: &EmailClient,
+    base_url: &str,
+    hmac_keys: &HmacKeys,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
-    if let Some((transaction, issue_id, email)) = dequeue_task(pool).await? {
+    if let Some((mut transaction, issue_id, subscriber_id)) = dequeue_task(pool).await? {
         Span::current()
-            .record("newsletter_issue_id", &display(issue_id))
-            .record("subscriber_email", &display(&email));
+            .record("newsletter_issue_id", display(issue_id))
+            .record("subscriber_id", display(subscriber_id));
 
-        match SubscriberEmail::parse(email.clone()) {
-            Ok(email) => {
-                let issue = get_newsletter_issue(pool, issue_id).await?;
+        let issue = get_newsletter_issue(pool, issue_id).await?;
+        match check_eligibility(pool, subscriber_id, issue.list_id).await? {
+            Eligibility::Eligible(email) => {
+                let list = get_list(pool, issue.list_id).await?;
+                let (html_content, text_content) = append_manage_link(
+                    &issue.html_content,
+                    &issue.text_content,
+                    &manage_link(base_url, hmac_keys, subscriber_id),
+                );
                 if let Err(e) = email_client
-                    .send_mail(
+                    .send_mail_as(
+                        &list.sender(),
                         &email,
                         &issue.title,
-                        &issue.html_content,
-                        &issue.text_content,
+                        &html_content,
+                        &text_content,
                     )
                     .await
                 {
@@ -50,17 +65,16 @@ pub async fn try_execute_task(
                     );
                 }
             }
-            Err(e) => {
-                tracing::error!(
-                    error.cause_chain = ?e,
-                    error.message = %e,
-                    "Skipping a confirmed subscriber. \
-                    Their stored contact details are invalid.",
+            Eligibility::Skip(reason) => {
+                tracing::info!(
+                    reason = reason.as_str(),
+                    "Skipping a subscriber who is no longer eligible."
                 );
+                record_skip(&mut transaction, issue_id, subscriber_id, reason).await?;
             }
         }
 
-        delete_task(transaction, issue_id, email).await?;
+        delete_task(transaction, issue_id, subscriber_id).await?;
 
         return Ok(ExecutionOutcome::TaskCompleted);
     }
@@ -71,13 +85,11 @@ pub async fn try_execute_task(
 type PgTransaction = Transaction<'static, Postgres>;
 
 #[tracing::instrument(skip_all)]
-async fn dequeue_task(
-    pool: &PgPool,
-) -> Result<Option<(PgTransaction, Uuid, String)>, anyhow::Error> {
+async fn dequeue_task(pool: &PgPool) -> Result<Option<(PgTransaction, Uuid, Uuid)>, anyhow::Error> {
     let mut transaction = pool.begin().await?;
     let r = sqlx::query!(
         r#"
-        SELECT newsletter_issue_id, subscriber_email
+        SELECT newsletter_issue_id, subscriber_id
         FROM issue_delivery_queue
         FOR UPDATE
         SKIP LOCKED
@@ -88,11 +100,7 @@ async fn dequeue_task(
     .await?;
 
     if let Some(r) = r {
-        Ok(Some((
-            transaction,
-            r.newsletter_issue_id,
-            r.subscriber_email,
-        )))
+        Ok(Some((transaction, r.newsletter_issue_id, r.subscriber_id)))
     } else {
         Ok(None)
     }
@@ -102,17 +110,17 @@ async fn dequeue_task(
 async fn delete_task(
     mut transaction: PgTransaction,
     issue_id: Uuid,
-    email: String,
+    subscriber_id: Uuid,
 ) -> Result<(), anyhow::Error> {
     sqlx::query!(
         r#"
         DELETE FROM issue_delivery_queue
         WHERE
             newsletter_issue_id = $1 AND
-            subscriber_email = $2
+            subscriber_id = $2
         "#,
         issue_id,
-        email
+        subscriber_id
     )
     .execute(&mut transaction)
     .await?;
@@ -121,7 +129,103 @@ async fn delete_task(
     Ok(())
 }
 
+/// Why a queued delivery was dropped without sending it.
+#[derive(Debug, Clone, Copy)]
+enum SkipReason {
+    /// Left the list, or never confirmed the membership.
+    NotSubscribed,
+    Paused,
+    InvalidEmail,
+}
+
+impl SkipReason {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SkipReason::NotSubscribed => "not_subscribed",
+            SkipReason::Paused => "paused",
+            SkipReason::InvalidEmail => "invalid_email",
+        }
+    }
+}
+
+enum Eligibility {
+    Eligible(SubscriberEmail),
+    Skip(SkipReason),
+}
+
+/// Subscribers can change their address, pause or leave the list between
+/// publishing and delivery, so their current details are checked at send time.
+#[tracing::instrument(skip(pool))]
+async fn check_eligibility(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<Eligibility, anyhow::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT
+            s.email,
+            s.paused_until,
+            ls.status as "list_status?"
+        FROM subscriptions s
+        LEFT JOIN list_subscriptions ls
+            ON ls.subscriber_id = s.id AND ls.list_id = $2
+        WHERE s.id = $1
+        "#,
+        subscriber_id,
+        list_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    let Some(r) = r else {
+        return Ok(Eligibility::Skip(SkipReason::NotSubscribed));
+    };
+    if r.list_status.as_deref() != Some("confirmed") {
+        return Ok(Eligibility::Skip(SkipReason::NotSubscribed));
+    }
+    if r.paused_until.is_some_and(|until| until > Utc::now()) {
+        return Ok(Eligibility::Skip(SkipReason::Paused));
+    }
+    match SubscriberEmail::parse(r.email) {
+        Ok(email) => Ok(Eligibility::Eligible(email)),
+        Err(e) => {
+            tracing::error!(
+                error.message = %e,
+                "Skipping a confirmed subscriber. \
+                Their stored contact details are invalid.",
+            );
+            Ok(Eligibility::Skip(SkipReason::InvalidEmail))
+        }
+    }
+}
+
+#[tracing::instrument(skip(transaction))]
+async fn record_skip(
+    transaction: &mut PgTransaction,
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+    reason: SkipReason,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO skipped_deliveries (
+            newsletter_issue_id, subscriber_id, reason, skipped_at
+        )
+        VALUES ($1, $2, $3, now())
+        ON CONFLICT DO NOTHING
+        "#,
+        issue_id,
+        subscriber_id,
+        reason.as_str()
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
 struct NewsletterIssue {
+    list_id: Uuid,
     title: String,
     text_content: String,
     html_content: String,
@@ -135,7 +239,7 @@ async fn get_newsletter_issue(
     let issue = sqlx::query_as!(
         NewsletterIssue,
         r#"
-        SELECT title, text_content, html_content
+        SELECT list_id, title, text_content, html_content
         FROM newsletter_issues
         WHERE
             newsletter_issue_id = $1
@@ -148,9 +252,14 @@ async fn get_newsletter_issue(
     Ok(issue)
 }
 
-async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
+async fn worker_loop(
+    pool: PgPool,
+    email_client: EmailClient,
+    base_url: String,
+    hmac_keys: HmacKeys,
+) -> Result<(), anyhow::Error> {
     loop {
-        match try_execute_task(&pool, &email_client).await {
+        match try_execute_task(&pool, &email_client, &base_url, &hmac_keys).await {
             Ok(ExecutionOutcome::EmptyQueue) => {
                 tokio::time::sleep(Duration::from_secs(10)).await;
             }
@@ -165,6 +274,13 @@ async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyh
 pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
     let connection_pool = get_connection_pool(&configuration.database);
     let email_client = configuration.email_client.client();
+    let hmac_keys = configuration.application.hmac_keys();
 
-    worker_loop(connection_pool, email_client).await
+    worker_loop(
+        connection_pool,
+        email_client,
+        configuration.application.base_url,
+        hmac_keys,
+    )
+    .await
 }
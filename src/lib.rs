@@ -1,14 +1,25 @@
 //! src/lib.rs
 
+pub mod account_tokens;
+pub mod audit;
 pub mod authentication;
 pub mod configuration;
 pub mod domain;
+pub mod email_change;
 pub mod email_client;
 pub mod idempotency;
+pub mod invitations;
 pub mod issue_delivery_worker;
+pub mod lists;
+pub mod login_link;
+pub mod manage_link;
+pub mod password_reset;
+pub mod problem;
 pub mod routes;
 pub mod session_state;
+pub mod signing;
 pub mod startup;
 pub mod telemetry;
 pub mod templates;
+pub mod users;
 pub mod utils;
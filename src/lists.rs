@@ -0,0 +1,93 @@
+use anyhow::Context;
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::{domain::SubscriberEmail, email_client::Sender};
+
+/// Slug of the list used when a request does not name one.
+pub const DEFAULT_LIST_SLUG: &str = "default";
+
+/// A publication run from this deployment.
+pub struct NewsletterList {
+    pub list_id: Uuid,
+    pub slug: String,
+    pub name: String,
+    pub sender_name: Option<String>,
+    pub sender_email: Option<String>,
+    pub confirmation_subject: String,
+    pub confirmation_message: String,
+}
+
+impl NewsletterList {
+    pub fn sender(&self) -> Sender {
+        Sender {
+            name: self.sender_name.clone(),
+            // Sender addresses are validated when the list is saved.
+            email: self
+                .sender_email
+                .clone()
+                .and_then(|e| SubscriberEmail::parse(e).ok()),
+        }
+    }
+}
+
+#[tracing::instrument(name = "Get list by slug", skip(executor))]
+pub async fn get_list_by_slug(
+    executor: impl PgExecutor<'_>,
+    slug: &str,
+) -> Result<Option<NewsletterList>, anyhow::Error> {
+    let slug = if slug.is_empty() {
+        DEFAULT_LIST_SLUG
+    } else {
+        slug
+    };
+    sqlx::query_as!(
+        NewsletterList,
+        r#"
+        SELECT list_id, slug, name, sender_name, sender_email,
+            confirmation_subject, confirmation_message
+        FROM lists
+        WHERE slug = $1
+        "#,
+        slug,
+    )
+    .fetch_optional(executor)
+    .await
+    .context("Failed to perform a query to retrieve a list.")
+}
+
+#[tracing::instrument(name = "Get list", skip(executor))]
+pub async fn get_list(
+    executor: impl PgExecutor<'_>,
+    list_id: Uuid,
+) -> Result<NewsletterList, anyhow::Error> {
+    sqlx::query_as!(
+        NewsletterList,
+        r#"
+        SELECT list_id, slug, name, sender_name, sender_email,
+            confirmation_subject, confirmation_message
+        FROM lists
+        WHERE list_id = $1
+        "#,
+        list_id,
+    )
+    .fetch_one(executor)
+    .await
+    .context("Failed to perform a query to retrieve a list.")
+}
+
+#[tracing::instrument(name = "Get all lists", skip(pool))]
+pub async fn get_all_lists(pool: &PgPool) -> Result<Vec<NewsletterList>, anyhow::Error> {
+    sqlx::query_as!(
+        NewsletterList,
+        r#"
+        SELECT list_id, slug, name, sender_name, sender_email,
+            confirmation_subject, confirmation_message
+        FROM lists
+        ORDER BY created_at
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve lists.")
+}
@@ -0,0 +1,146 @@
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    account_tokens::{hash_token, mail_account_link, AccountLink},
+    domain::SubscriberEmail,
+    email_client::EmailClient,
+    signing::HmacKeys,
+};
+
+/// The user a sign-in link is for.
+pub struct LoginLinkUser {
+    pub user_id: Uuid,
+    pub username: String,
+}
+
+/// Mail a sign-in link if an active user has this address. Like password
+/// resets, nothing tells the caller whether one does.
+#[tracing::instrument(
+    name = "Request a sign-in link",
+    skip(pool, email_client, base_url, hmac_keys, email),
+    fields(email = %email.as_ref())
+)]
+pub async fn request_login_link(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    hmac_keys: &HmacKeys,
+    email: &SubscriberEmail,
+) -> Result<(), anyhow::Error> {
+    mail_account_link(pool, email_client, email, AccountLink::SignIn, |token| {
+        format!(
+            "{}/login/link?token={}&signature={}",
+            base_url,
+            token,
+            sign(hmac_keys, token)
+        )
+    })
+    .await
+}
+
+/// The user the link signs in, without using it up. `None` if the link is
+/// unknown, has expired or the user was deactivated since.
+#[tracing::instrument(name = "Get the user of a sign-in link", skip(pool, token))]
+pub async fn get_login_link_user(
+    pool: &PgPool,
+    token: &str,
+) -> Result<Option<LoginLinkUser>, anyhow::Error> {
+    sqlx::query_as!(
+        LoginLinkUser,
+        r#"
+        SELECT u.user_id, u.username
+        FROM login_link_tokens t
+        JOIN users u ON u.user_id = t.user_id
+        WHERE
+            t.token_hash = $1 AND
+            t.requested_at > now() - interval '15 minutes' AND
+            u.deactivated_at IS NULL
+        "#,
+        hash_token(token)
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the user of the sign-in link.")
+}
+
+/// Use up the link. Returns `false` if it could not be used, e.g. because
+/// another request just did.
+#[tracing::instrument(name = "Use a sign-in link", skip(pool, token))]
+pub async fn use_login_link(
+    pool: &PgPool,
+    token: &str,
+    user_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM login_link_tokens
+        WHERE
+            token_hash = $1 AND
+            user_id = $2 AND
+            requested_at > now() - interval '15 minutes'
+        "#,
+        hash_token(token),
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to use the sign-in link.")?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Links are signed, so that a forged one is turned away before touching
+/// the database.
+pub fn verify_signature(hmac_keys: &HmacKeys, token: &str, signature: &str) -> bool {
+    hmac_keys.verify(PURPOSE, token.as_bytes(), signature)
+}
+
+fn sign(hmac_keys: &HmacKeys, token: &str) -> String {
+    hmac_keys.sign(PURPOSE, token.as_bytes())
+}
+
+const PURPOSE: &[u8] = b"login-link:";
+
+#[cfg(test)]
+mod tests {
+    use super::{sign, verify_signature};
+    use crate::signing::HmacKeys;
+    use secrecy::Secret;
+
+    fn keys(secret: &str) -> HmacKeys {
+        HmacKeys::new(Secret::new(secret.to_string()), vec![])
+    }
+
+    #[test]
+    fn a_signature_is_verified_for_its_token() {
+        let secret = keys("secret");
+        let signature = sign(&secret, "token");
+
+        assert!(verify_signature(&secret, "token", &signature));
+        assert!(!verify_signature(&secret, "another-token", &signature));
+    }
+
+    #[test]
+    fn a_signature_made_with_another_secret_is_rejected() {
+        let signature = sign(&keys("secret"), "token");
+
+        let other_secret = keys("another-secret");
+        assert!(!verify_signature(&other_secret, "token", &signature));
+    }
+
+    #[test]
+    fn a_manage_link_signature_does_not_sign_in() {
+        // The same secret signs preference center links, which are public.
+        let secret = keys("secret");
+        let subscriber_id = uuid::Uuid::new_v4();
+        let link = crate::manage_link::manage_link("http://localhost", &secret, subscriber_id);
+        let signature = link.split("signature=").nth(1).unwrap();
+
+        assert!(!verify_signature(
+            &secret,
+            &subscriber_id.to_string(),
+            signature
+        ));
+    }
+}
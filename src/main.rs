@@ -4,6 +4,7 @@ use std::fmt::Display;
 use tokio::task::JoinError;
 use zero2prod::{
     configuration::get_configuration,
+    idempotency::run_cleanup_until_stopped,
     issue_delivery_worker::run_worker_until_stopped,
     startup::Application,
     telemetry::{get_subscriber, init_subscriber},
@@ -21,11 +22,13 @@ async fn main() -> anyhow::Result<()> {
     let application = Application::build(configuration.clone()).await?;
 
     let application_task = tokio::spawn(application.run_until_stopped());
-    let worker_task = tokio::spawn(run_worker_until_stopped(configuration));
+    let worker_task = tokio::spawn(run_worker_until_stopped(configuration.clone()));
+    let cleanup_task = tokio::spawn(run_cleanup_until_stopped(configuration));
 
     tokio::select! {
         o = application_task => { report_exit("API", o) },
         o = worker_task => { report_exit("Background worker", o) },
+        o = cleanup_task => { report_exit("Idempotency key cleanup", o) },
     }
 
     Ok(())
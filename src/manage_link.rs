@@ -0,0 +1,194 @@
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+
+use crate::signing::HmacKeys;
+
+/// How long a preference center link works. Every email carries a fresh
+/// one, so only links from old emails stop working.
+const MAX_AGE_DAYS: i64 = 90;
+
+/// Link to the preference center of a subscriber, included in every email.
+///
+/// The link is signed with the application HMAC secret, so knowing a
+/// subscriber id is not enough to change their preferences. The signature
+/// also covers when the link was issued, so that a leaked link expires.
+pub fn manage_link(base_url: &str, hmac_keys: &HmacKeys, subscriber_id: Uuid) -> String {
+    format!("{}{}", base_url, manage_path(hmac_keys, subscriber_id))
+}
+
+/// Path and query of the preference center, for redirects within the app.
+pub fn manage_path(hmac_keys: &HmacKeys, subscriber_id: Uuid) -> String {
+    let issued_at = Utc::now().timestamp();
+    format!(
+        "/subscriptions/manage?subscriber_id={}&issued_at={}&signature={}",
+        subscriber_id,
+        issued_at,
+        sign(hmac_keys, subscriber_id, issued_at)
+    )
+}
+
+/// Append the preference center link to the HTML and plain text bodies of an email.
+pub fn append_manage_link(html_body: &str, text_body: &str, link: &str) -> (String, String) {
+    (
+        format!(
+            "{}<hr /><p><a href=\"{}\">Manage your subscription</a></p>",
+            html_body, link
+        ),
+        format!("{}\n\n--\nManage your subscription: {}", text_body, link),
+    )
+}
+
+pub fn verify_signature(
+    hmac_keys: &HmacKeys,
+    subscriber_id: Uuid,
+    issued_at: i64,
+    signature: &str,
+) -> bool {
+    hmac_keys.verify(PURPOSE, &message(subscriber_id, issued_at), signature)
+}
+
+/// Whether a link issued at `issued_at`, a Unix timestamp, is too old to use.
+pub fn is_expired(issued_at: i64) -> bool {
+    Utc::now().timestamp() - issued_at > Duration::days(MAX_AGE_DAYS).num_seconds()
+}
+
+fn sign(hmac_keys: &HmacKeys, subscriber_id: Uuid, issued_at: i64) -> String {
+    hmac_keys.sign(PURPOSE, &message(subscriber_id, issued_at))
+}
+
+fn message(subscriber_id: Uuid, issued_at: i64) -> Vec<u8> {
+    [
+        subscriber_id.as_bytes().as_slice(),
+        &issued_at.to_be_bytes(),
+    ]
+    .concat()
+}
+
+const PURPOSE: &[u8] = b"manage-subscription:";
+
+#[cfg(test)]
+mod tests {
+    use super::{is_expired, manage_link, sign, verify_signature, MAX_AGE_DAYS};
+    use crate::signing::HmacKeys;
+    use chrono::{Duration, Utc};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn keys(secret: &str) -> HmacKeys {
+        HmacKeys::new(Secret::new(secret.to_string()), vec![])
+    }
+
+    fn query_value<'a>(link: &'a str, key: &str) -> &'a str {
+        link.split(['?', '&'])
+            .find_map(|pair| pair.strip_prefix(key)?.strip_prefix('='))
+            .unwrap()
+    }
+
+    /// The issue time and signature of a link.
+    fn signed_parts(link: &str) -> (i64, &str) {
+        (
+            query_value(link, "issued_at").parse().unwrap(),
+            query_value(link, "signature"),
+        )
+    }
+
+    #[test]
+    fn a_link_is_verified_for_its_subscriber() {
+        let secret = keys("secret");
+        let subscriber_id = Uuid::new_v4();
+        let link = manage_link("http://localhost", &secret, subscriber_id);
+        let (issued_at, signature) = signed_parts(&link);
+
+        assert!(verify_signature(
+            &secret,
+            subscriber_id,
+            issued_at,
+            signature
+        ));
+        assert!(!is_expired(issued_at));
+    }
+
+    #[test]
+    fn a_link_is_rejected_for_another_subscriber() {
+        let secret = keys("secret");
+        let link = manage_link("http://localhost", &secret, Uuid::new_v4());
+        let (issued_at, signature) = signed_parts(&link);
+
+        assert!(!verify_signature(
+            &secret,
+            Uuid::new_v4(),
+            issued_at,
+            signature
+        ));
+    }
+
+    #[test]
+    fn a_link_is_rejected_with_another_issue_time() {
+        let secret = keys("secret");
+        let subscriber_id = Uuid::new_v4();
+        let link = manage_link("http://localhost", &secret, subscriber_id);
+        let (issued_at, signature) = signed_parts(&link);
+
+        assert!(!verify_signature(
+            &secret,
+            subscriber_id,
+            issued_at + 1,
+            signature
+        ));
+    }
+
+    #[test]
+    fn an_old_link_has_expired() {
+        let secret = keys("secret");
+        let subscriber_id = Uuid::new_v4();
+        let issued_at = (Utc::now() - Duration::days(MAX_AGE_DAYS + 1)).timestamp();
+        let signature = sign(&secret, subscriber_id, issued_at);
+
+        assert!(verify_signature(
+            &secret,
+            subscriber_id,
+            issued_at,
+            &signature
+        ));
+        assert!(is_expired(issued_at));
+    }
+
+    #[test]
+    fn a_link_signed_with_another_secret_is_rejected() {
+        let subscriber_id = Uuid::new_v4();
+        let link = manage_link("http://localhost", &keys("secret"), subscriber_id);
+        let (issued_at, signature) = signed_parts(&link);
+
+        let other_secret = keys("another-secret");
+        assert!(!verify_signature(
+            &other_secret,
+            subscriber_id,
+            issued_at,
+            signature
+        ));
+    }
+
+    #[test]
+    fn a_link_signed_before_a_rotation_is_still_verified() {
+        let subscriber_id = Uuid::new_v4();
+        let link = manage_link("http://localhost", &keys("old-secret"), subscriber_id);
+        let (issued_at, signature) = signed_parts(&link);
+
+        let rotated = HmacKeys::new(
+            Secret::new("new-secret".to_string()),
+            vec![Secret::new("old-secret".to_string())],
+        );
+        assert!(verify_signature(
+            &rotated,
+            subscriber_id,
+            issued_at,
+            signature
+        ));
+    }
+
+    #[test]
+    fn a_malformed_signature_is_rejected() {
+        let secret = keys("secret");
+        assert!(!verify_signature(&secret, Uuid::new_v4(), 0, "not-hex"));
+    }
+}
@@ -0,0 +1,142 @@
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    account_tokens::{hash_token, mail_account_link, AccountLink},
+    authentication::{compute_password_hash, revoke_sessions, PasswordHashing},
+    domain::SubscriberEmail,
+    email_client::EmailClient,
+    telemetry::spawn_blocking_with_tracing,
+};
+
+/// Mail a reset link if an active user has this address. Nothing tells the
+/// caller whether one does, so that the form cannot be used to probe for
+/// accounts: see `forgot_password`, which runs this in the background.
+#[tracing::instrument(
+    name = "Request a password reset",
+    skip(pool, email_client, base_url, email),
+    fields(email = %email.as_ref())
+)]
+pub async fn request_password_reset(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    email: &SubscriberEmail,
+) -> Result<(), anyhow::Error> {
+    mail_account_link(
+        pool,
+        email_client,
+        email,
+        AccountLink::PasswordReset,
+        |token| format!("{}/login/reset_password?token={}", base_url, token),
+    )
+    .await
+}
+
+/// Whether the token can still be used, without using it up. Tokens of users
+/// deactivated since they were issued cannot.
+#[tracing::instrument(name = "Check a password reset token", skip(pool, token))]
+pub async fn is_valid_reset_token(pool: &PgPool, token: &str) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT EXISTS(
+            SELECT 1
+            FROM password_reset_tokens t
+            JOIN users u ON u.user_id = t.user_id
+            WHERE
+                t.token_hash = $1 AND
+                t.requested_at > now() - interval '1 hour' AND
+                u.deactivated_at IS NULL
+        ) as "valid!"
+        "#,
+        hash_token(token)
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to check the password reset token.")?;
+    Ok(row.valid)
+}
+
+/// The username of the account the token resets, or `None` if the token
+/// cannot be used.
+#[tracing::instrument(name = "Get the user of a password reset token", skip(pool, token))]
+pub async fn get_reset_token_username(
+    pool: &PgPool,
+    token: &str,
+) -> Result<Option<String>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT u.username
+        FROM password_reset_tokens t
+        JOIN users u ON u.user_id = t.user_id
+        WHERE
+            t.token_hash = $1 AND
+            t.requested_at > now() - interval '1 hour' AND
+            u.deactivated_at IS NULL
+        "#,
+        hash_token(token)
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the user of the password reset token.")?;
+    Ok(row.map(|r| r.username))
+}
+
+/// Set a new password with the token, which can only be used once. Every
+/// session of the user is logged out. Returns `None` if the token is unknown
+/// or has expired.
+#[tracing::instrument(name = "Reset a password", skip(pool, hashing, token, new_password))]
+pub async fn reset_password(
+    pool: &PgPool,
+    hashing: &PasswordHashing,
+    token: &str,
+    new_password: Secret<String>,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let hashing = hashing.clone();
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(new_password, &hashing))
+            .await?
+            .context("Failed to hash password")?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let Some(request) = sqlx::query!(
+        r#"
+        DELETE FROM password_reset_tokens t
+        USING users u
+        WHERE
+            u.user_id = t.user_id AND
+            t.token_hash = $1 AND
+            t.requested_at > now() - interval '1 hour' AND
+            u.deactivated_at IS NULL
+        RETURNING t.user_id
+        "#,
+        hash_token(token)
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to use the password reset token.")?
+    else {
+        return Ok(None);
+    };
+
+    sqlx::query!(
+        r#"UPDATE users SET password = $2 WHERE user_id = $1"#,
+        request.user_id,
+        password_hash.expose_secret()
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to change user's password in the database.")?;
+    revoke_sessions(request.user_id, &mut transaction).await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to reset the password.")?;
+
+    Ok(Some(request.user_id))
+}
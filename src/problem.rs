@@ -0,0 +1,335 @@
+//! Error responses as RFC 7807 problem documents, rendered as an HTML page
+//! for browsers.
+
+use actix_web::{
+    body::{to_bytes, BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    error::InternalError,
+    http::{
+        header::{self, Accept, Header, HeaderName, HeaderValue},
+        StatusCode,
+    },
+    web, HttpMessage, HttpResponse, ResponseError,
+};
+use actix_web_lab::middleware::Next;
+use askama::Template;
+use utoipa::ToSchema;
+
+use crate::templates::ErrorTemplate;
+
+/// Media type of problem documents.
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Body of an error response, see RFC 7807.
+#[derive(Debug, serde::Serialize, serde::Deserialize, ToSchema)]
+pub struct ProblemDetails {
+    /// Always `about:blank`: `code` tells problems apart.
+    #[serde(rename = "type")]
+    #[schema(example = "about:blank")]
+    pub problem_type: String,
+    /// Reason phrase of the status code.
+    #[schema(example = "Bad Request")]
+    pub title: String,
+    #[schema(example = 400)]
+    pub status: u16,
+    /// What went wrong, for a human.
+    #[schema(example = "The request has invalid fields.")]
+    pub detail: String,
+    /// Stable identifier of the problem, for programs.
+    #[schema(example = "validation_failed")]
+    pub code: String,
+    /// The invalid fields of the request, for validation problems.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
+
+/// Why a field of the request is invalid.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize, ToSchema)]
+pub struct FieldError {
+    #[schema(example = "email")]
+    pub field: String,
+    #[schema(example = "not-an-email is not a valid subscriber email.")]
+    pub message: String,
+}
+
+/// An error answered with a problem document.
+pub struct Problem {
+    status: StatusCode,
+    code: &'static str,
+    detail: String,
+    errors: Vec<FieldError>,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    /// Logged, but never sent to the client.
+    cause: Option<String>,
+}
+
+impl Problem {
+    /// `code` must not change once clients may rely on it.
+    pub fn new(status: StatusCode, code: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            detail: detail.into(),
+            errors: Vec::new(),
+            headers: Vec::new(),
+            cause: None,
+        }
+    }
+
+    /// A request with an invalid field.
+    pub fn invalid_field(field: &str, message: impl Into<String>) -> Self {
+        let message = message.into();
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            "validation_failed",
+            message.clone(),
+        )
+        .with_field_error(FieldError {
+            field: field.to_owned(),
+            message,
+        })
+    }
+
+    /// A failure on our side. The cause is logged, the client only learns
+    /// that something went wrong.
+    pub fn internal(cause: impl std::fmt::Debug) -> Self {
+        Self {
+            cause: Some(format!("{:?}", cause)),
+            ..Self::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal_error",
+                "Something went wrong on our side, please try again later.",
+            )
+        }
+    }
+
+    pub fn with_field_error(mut self, error: FieldError) -> Self {
+        self.errors.push(error);
+        self
+    }
+
+    pub fn with_header(mut self, name: HeaderName, value: impl Into<HeaderValue>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.code
+    }
+
+    pub fn details(&self) -> ProblemDetails {
+        ProblemDetails {
+            problem_type: "about:blank".into(),
+            title: self
+                .status
+                .canonical_reason()
+                .unwrap_or_default()
+                .to_owned(),
+            status: self.status.as_u16(),
+            detail: self.detail.clone(),
+            code: self.code.to_owned(),
+            errors: self.errors.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.detail)
+    }
+}
+
+impl std::fmt::Debug for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} ({})", self.detail, self.code)?;
+        if let Some(cause) = &self.cause {
+            writeln!(f, "\nCaused by:\n\t{}", cause)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Problem {}
+
+impl ResponseError for Problem {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status);
+        for header in &self.headers {
+            response.insert_header(header.clone());
+        }
+        response
+            .content_type(PROBLEM_JSON)
+            .body(serde_json::to_string(&self.details()).unwrap_or_default())
+    }
+}
+
+/// Form bodies, query strings and JSON bodies that cannot be deserialised
+/// are answered with a problem too, rather than with actix-web's plain text.
+pub fn form_config() -> web::FormConfig {
+    web::FormConfig::default().error_handler(|e, _| invalid_request("invalid_form", e))
+}
+
+pub fn query_config() -> web::QueryConfig {
+    web::QueryConfig::default().error_handler(|e, _| invalid_request("invalid_query", e))
+}
+
+pub fn json_config() -> web::JsonConfig {
+    web::JsonConfig::default().error_handler(|e, _| invalid_request("invalid_body", e))
+}
+
+fn invalid_request(code: &'static str, e: impl std::fmt::Display) -> actix_web::Error {
+    let detail = e.to_string();
+    match missing_field(&detail) {
+        Some(field) => Problem::invalid_field(field, format!("{} is missing.", field)),
+        None => Problem::new(StatusCode::BAD_REQUEST, code, detail),
+    }
+    .into()
+}
+
+/// The field named in serde's "missing field `name`" errors.
+fn missing_field(message: &str) -> Option<&str> {
+    let (_, rest) = message.split_once("missing field `")?;
+    rest.split_once('`').map(|(field, _)| field)
+}
+
+/// The format a client asked for in its `Accept` header.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PreferredFormat {
+    Html,
+    Json,
+}
+
+/// `None` when the client accepts anything, e.g. with `*/*`.
+pub fn preferred_format(req: &impl HttpMessage) -> Option<PreferredFormat> {
+    let accept = Accept::parse(req).ok()?;
+    accept.ranked().iter().find_map(|mime| {
+        let essence = mime.essence_str();
+        if essence == "text/html" {
+            Some(PreferredFormat::Html)
+        } else if essence == "application/json" || essence.ends_with("+json") {
+            Some(PreferredFormat::Json)
+        } else {
+            None
+        }
+    })
+}
+
+/// Browsers asking for HTML get problems as an error page rather than as a
+/// problem document, be they responses or errors of inner middlewares.
+pub async fn render_problems_for_browsers(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    if preferred_format(&req) != Some(PreferredFormat::Html) {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    }
+    match next.call(req).await {
+        Ok(response) => {
+            let (request, response) = response.map_into_boxed_body().into_parts();
+            Ok(ServiceResponse::new(
+                request,
+                render_problem_as_html(response).await,
+            ))
+        }
+        // The error response can only be taken once, so the error is passed
+        // on with the rendered one.
+        Err(e) => {
+            let response = render_problem_as_html(e.error_response()).await;
+            Err(InternalError::from_response(e, response).into())
+        }
+    }
+}
+
+/// The error page for a problem document, other responses are left alone.
+async fn render_problem_as_html(response: HttpResponse) -> HttpResponse {
+    let is_problem = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value == PROBLEM_JSON);
+    if !is_problem {
+        return response;
+    }
+
+    let status = response.status();
+    let (head, body) = response.into_parts();
+    let body = to_bytes(body).await.unwrap_or_default();
+    let Ok(problem) = serde_json::from_slice::<ProblemDetails>(&body) else {
+        return head.set_body(BoxBody::new(body));
+    };
+    let html = ErrorTemplate { problem: &problem }
+        .render()
+        .expect("Could not render error template.");
+    let mut response = head.set_body(BoxBody::new(html));
+    *response.status_mut() = status;
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("text/html; charset=utf-8"),
+    );
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{missing_field, preferred_format, PreferredFormat, Problem};
+    use actix_web::{http::StatusCode, test::TestRequest, ResponseError};
+
+    fn format_for(accept: &str) -> Option<PreferredFormat> {
+        preferred_format(
+            &TestRequest::default()
+                .insert_header(("Accept", accept))
+                .to_http_request(),
+        )
+    }
+
+    #[test]
+    fn browsers_prefer_html() {
+        assert_eq!(
+            format_for("text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8"),
+            Some(PreferredFormat::Html)
+        );
+    }
+
+    #[test]
+    fn json_clients_prefer_json() {
+        assert_eq!(format_for("application/json"), Some(PreferredFormat::Json));
+        assert_eq!(
+            format_for("application/problem+json, text/html;q=0.5"),
+            Some(PreferredFormat::Json)
+        );
+    }
+
+    #[test]
+    fn clients_accepting_anything_have_no_preference() {
+        assert_eq!(format_for("*/*"), None);
+        assert_eq!(
+            preferred_format(&TestRequest::default().to_http_request()),
+            None
+        );
+    }
+
+    #[test]
+    fn missing_fields_are_found_in_deserialisation_errors() {
+        assert_eq!(
+            missing_field("Parse error: missing field `email`."),
+            Some("email")
+        );
+        assert_eq!(
+            missing_field("Json deserialize error: missing field `name` at line 1 column 33"),
+            Some("name")
+        );
+        assert_eq!(missing_field("unknown variant `bogus`"), None);
+    }
+
+    #[test]
+    fn internal_problems_do_not_leak_their_cause() {
+        let problem = Problem::internal("password authentication failed for user postgres");
+
+        assert_eq!(problem.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(!problem.details().detail.contains("postgres"));
+        assert!(format!("{:?}", problem).contains("postgres"));
+    }
+}
@@ -0,0 +1,47 @@
+use actix_web::{get, http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{IncomingFlashMessages, Level};
+use askama::Template;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    authentication::{get_api_tokens, CsrfToken, UserId},
+    templates::AdminApiTokensTemplate,
+    utils::e500,
+};
+
+#[get("/api_tokens")]
+pub async fn api_tokens_page(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut error = String::new();
+
+    for m in flash_messages.iter().filter(|m| m.level() == Level::Error) {
+        writeln!(error, "{}", m.content()).unwrap();
+    }
+
+    let mut info = String::new();
+
+    for m in flash_messages.iter().filter(|m| m.level() == Level::Info) {
+        writeln!(info, "{}", m.content()).unwrap();
+    }
+
+    let tokens = get_api_tokens(**user_id, &pool).await.map_err(e500)?;
+
+    let html = AdminApiTokensTemplate {
+        error: &error,
+        info: &info,
+        tokens,
+        new_token: None,
+        csrf_token: &csrf_token,
+    }
+    .render()
+    .expect("Could not render API tokens template.");
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html))
+}
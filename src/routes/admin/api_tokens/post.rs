@@ -0,0 +1,134 @@
+use actix_web::{http::header::ContentType, post, web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use askama::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_event, AuditAction},
+    authentication::{self, get_api_tokens, ApiScope, CsrfToken, UserId},
+    templates::AdminApiTokensTemplate,
+    utils::{e500, see_other},
+};
+
+/// The form repeats `scope` once per checked box, which a struct cannot
+/// capture.
+#[post("/api_tokens")]
+#[tracing::instrument(
+    name = "Create an API token",
+    skip(request, form, pool, user_id, csrf_token)
+)]
+pub async fn create_api_token(
+    request: HttpRequest,
+    form: web::Form<Vec<(String, String)>>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let mut name = "";
+    let mut scopes = Vec::new();
+    for (key, value) in form.iter() {
+        match key.as_str() {
+            "name" => name = value.trim(),
+            "scope" => match ApiScope::parse(value) {
+                Ok(scope) => scopes.push(scope),
+                Err(e) => {
+                    FlashMessage::error(e).send();
+                    return Ok(see_other("/admin/api_tokens"));
+                }
+            },
+            _ => {}
+        }
+    }
+    if name.is_empty() {
+        FlashMessage::error("The token needs a name.").send();
+        return Ok(see_other("/admin/api_tokens"));
+    }
+    if scopes.is_empty() {
+        FlashMessage::error("The token needs at least one scope.").send();
+        return Ok(see_other("/admin/api_tokens"));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let token = authentication::create_api_token(*user_id, name, &scopes, &mut transaction)
+        .await
+        .map_err(e500)?;
+    record_event(
+        &mut transaction,
+        &request,
+        Some(*user_id),
+        AuditAction::CreateApiToken,
+        name,
+        serde_json::json!({
+            "scopes": scopes.iter().map(|s| s.as_str()).collect::<Vec<_>>(),
+        }),
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to create the API token.")
+        .map_err(e500)?;
+    let tokens = get_api_tokens(*user_id, &pool).await.map_err(e500)?;
+
+    // Only the hash is stored, so this is the one chance to show the token.
+    let html = AdminApiTokensTemplate {
+        error: "",
+        info: "The API token has been created. Copy it now, it will not be shown again.",
+        tokens,
+        new_token: Some(token),
+        csrf_token: &csrf_token,
+    }
+    .render()
+    .expect("Could not render API tokens template.");
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html))
+}
+
+#[post("/api_tokens/{api_token_id}/revoke")]
+#[tracing::instrument(name = "Revoke an API token", skip(request, pool, user_id))]
+pub async fn revoke_api_token(
+    request: HttpRequest,
+    api_token_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    if let Some(name) = authentication::revoke_api_token(**user_id, *api_token_id, &mut transaction)
+        .await
+        .map_err(e500)?
+    {
+        record_event(
+            &mut transaction,
+            &request,
+            Some(**user_id),
+            AuditAction::RevokeApiToken,
+            &name,
+            serde_json::json!({ "api_token_id": *api_token_id }),
+        )
+        .await
+        .map_err(e500)?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to revoke the API token.")
+            .map_err(e500)?;
+        FlashMessage::info("The API token has been revoked.").send();
+    } else {
+        FlashMessage::error("The API token does not exist.").send();
+    }
+    Ok(see_other("/admin/api_tokens"))
+}
@@ -0,0 +1,60 @@
+use actix_web::{
+    get,
+    http::header::{self, ContentType},
+    web, HttpResponse,
+};
+use askama::Template;
+use sqlx::PgPool;
+
+use crate::{
+    audit::{get_audit_events, to_csv, AuditFilter},
+    authentication::{AuthenticatedUser, Role},
+    templates::AdminAuditTemplate,
+    utils::e500,
+};
+
+/// The page shows the newest events only, the export has all of them.
+const PAGE_SIZE: i64 = 200;
+
+#[get("/audit")]
+pub async fn audit_page(
+    filter: web::Query<AuditFilter>,
+    pool: web::Data<PgPool>,
+    user: web::ReqData<AuthenticatedUser>,
+) -> Result<HttpResponse, actix_web::Error> {
+    user.require(Role::Owner)?;
+    let events = get_audit_events(&pool, &filter, Some(PAGE_SIZE))
+        .await
+        .map_err(e500)?;
+    let export_query = serde_urlencoded::to_string(&*filter).map_err(e500)?;
+
+    let html = AdminAuditTemplate {
+        filter: &filter,
+        events,
+        export_query: &export_query,
+    }
+    .render()
+    .expect("Could not render audit log template.");
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html))
+}
+
+#[get("/audit/export")]
+pub async fn export_audit_log(
+    filter: web::Query<AuditFilter>,
+    pool: web::Data<PgPool>,
+    user: web::ReqData<AuthenticatedUser>,
+) -> Result<HttpResponse, actix_web::Error> {
+    user.require(Role::Owner)?;
+    let events = get_audit_events(&pool, &filter, None).await.map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"audit-log.csv\"",
+        ))
+        .body(to_csv(&events)))
+}
@@ -4,19 +4,24 @@ use askama::Template;
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::{authentication::UserId, templates::AdminDashboardTemplate, utils::e500};
+use crate::{
+    authentication::{AuthenticatedUser, CsrfToken, Role},
+    templates::AdminDashboardTemplate,
+    utils::e500,
+};
 
 #[get("/dashboard")]
 pub async fn admin_dashboard(
-    user_id: web::ReqData<UserId>,
+    user: web::ReqData<AuthenticatedUser>,
     pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let user_id = user_id.into_inner();
-
-    let username = get_username(*user_id, &pool).await.map_err(e500)?;
+    let username = get_username(*user.user_id, &pool).await.map_err(e500)?;
 
     let html = AdminDashboardTemplate {
         username: &username,
+        can_manage_users: user.role >= Role::Owner,
+        csrf_token: &csrf_token,
     }
     .render()
     .expect("Could not render admin dashboard template.");
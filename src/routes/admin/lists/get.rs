@@ -0,0 +1,43 @@
+use actix_web::{get, http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{IncomingFlashMessages, Level};
+use askama::Template;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    authentication::CsrfToken, lists::get_all_lists, templates::AdminListsTemplate, utils::e500,
+};
+
+#[get("/lists")]
+pub async fn lists_page(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut error = String::new();
+
+    for m in flash_messages.iter().filter(|m| m.level() == Level::Error) {
+        writeln!(error, "{}", m.content()).unwrap();
+    }
+
+    let mut info = String::new();
+
+    for m in flash_messages.iter().filter(|m| m.level() == Level::Info) {
+        writeln!(info, "{}", m.content()).unwrap();
+    }
+
+    let lists = get_all_lists(&pool).await.map_err(e500)?;
+
+    let html = AdminListsTemplate {
+        error: &error,
+        info: &info,
+        lists,
+        csrf_token: &csrf_token,
+    }
+    .render()
+    .expect("Could not render admin lists template.");
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html))
+}
@@ -0,0 +1,274 @@
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit::{change, record_event, AuditAction},
+    authentication::{AuthenticatedUser, Role},
+    domain::{ListSlug, SubscriberEmail},
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct ListFormData {
+    slug: String,
+    name: String,
+    #[serde(default)]
+    sender_name: String,
+    #[serde(default)]
+    sender_email: String,
+    confirmation_subject: String,
+    confirmation_message: String,
+}
+
+struct ValidList {
+    slug: ListSlug,
+    name: String,
+    sender_name: Option<String>,
+    sender_email: Option<SubscriberEmail>,
+    confirmation_subject: String,
+    confirmation_message: String,
+}
+
+impl TryFrom<ListFormData> for ValidList {
+    type Error = String;
+
+    fn try_from(value: ListFormData) -> Result<Self, Self::Error> {
+        let slug = ListSlug::parse(value.slug)?;
+        let name = non_empty(value.name, "The list name")?;
+        let confirmation_subject =
+            non_empty(value.confirmation_subject, "The confirmation subject")?;
+        let confirmation_message =
+            non_empty(value.confirmation_message, "The confirmation message")?;
+        let sender_name = Some(value.sender_name.trim().to_string()).filter(|n| !n.is_empty());
+        let sender_email = match value.sender_email.trim() {
+            "" => None,
+            email => Some(SubscriberEmail::parse(email.to_string())?),
+        };
+        Ok(Self {
+            slug,
+            name,
+            sender_name,
+            sender_email,
+            confirmation_subject,
+            confirmation_message,
+        })
+    }
+}
+
+fn non_empty(s: String, field: &str) -> Result<String, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        Err(format!("{} cannot be empty.", field))
+    } else {
+        Ok(s.to_string())
+    }
+}
+
+#[post("/lists")]
+#[tracing::instrument(name = "Create a list", skip(request, form, pool))]
+pub async fn create_list(
+    request: HttpRequest,
+    form: web::Form<ListFormData>,
+    pool: web::Data<PgPool>,
+    user: web::ReqData<AuthenticatedUser>,
+) -> Result<HttpResponse, actix_web::Error> {
+    user.require(Role::Owner)?;
+    let list = match ValidList::try_from(form.0) {
+        Ok(list) => list,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/lists"));
+        }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let n_inserted = sqlx::query!(
+        r#"
+        INSERT INTO lists (
+            list_id, slug, name, sender_name, sender_email,
+            confirmation_subject, confirmation_message, created_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, now())
+        ON CONFLICT (slug) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        list.slug.as_ref(),
+        list.name,
+        list.sender_name,
+        list.sender_email.as_ref().map(AsRef::as_ref),
+        list.confirmation_subject,
+        list.confirmation_message,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to insert a new list.")
+    .map_err(e500)?
+    .rows_affected();
+
+    if n_inserted == 0 {
+        FlashMessage::error(format!(
+            "A list with slug {} already exists.",
+            list.slug.as_ref()
+        ))
+        .send();
+    } else {
+        record_event(
+            &mut transaction,
+            &request,
+            Some(*user.user_id),
+            AuditAction::CreateList,
+            list.slug.as_ref(),
+            serde_json::json!({ "name": list.name }),
+        )
+        .await
+        .map_err(e500)?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to create a list.")
+            .map_err(e500)?;
+        FlashMessage::info(format!("The list {} has been created.", list.name)).send();
+    }
+    Ok(see_other("/admin/lists"))
+}
+
+#[post("/lists/{list_id}")]
+#[tracing::instrument(name = "Update a list", skip(request, form, pool))]
+pub async fn update_list(
+    request: HttpRequest,
+    list_id: web::Path<Uuid>,
+    form: web::Form<ListFormData>,
+    pool: web::Data<PgPool>,
+    user: web::ReqData<AuthenticatedUser>,
+) -> Result<HttpResponse, actix_web::Error> {
+    user.require(Role::Owner)?;
+    let list = match ValidList::try_from(form.0) {
+        Ok(list) => list,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/lists"));
+        }
+    };
+
+    let list_id = list_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let Some(previous) = sqlx::query!(
+        r#"
+        SELECT slug, name, sender_name, sender_email,
+            confirmation_subject, confirmation_message
+        FROM lists
+        WHERE list_id = $1
+        FOR UPDATE
+        "#,
+        list_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to retrieve the list.")
+    .map_err(e500)?
+    else {
+        FlashMessage::error("The list does not exist.").send();
+        return Ok(see_other("/admin/lists"));
+    };
+
+    let result = sqlx::query!(
+        r#"
+        UPDATE lists
+        SET slug = $2, name = $3, sender_name = $4, sender_email = $5,
+            confirmation_subject = $6, confirmation_message = $7
+        WHERE list_id = $1
+        "#,
+        list_id,
+        list.slug.as_ref(),
+        list.name,
+        list.sender_name,
+        list.sender_email.as_ref().map(AsRef::as_ref),
+        list.confirmation_subject,
+        list.confirmation_message,
+    )
+    .execute(&mut transaction)
+    .await;
+
+    match result {
+        Ok(_) => {
+            let sender_email = list.sender_email.as_ref().map(AsRef::as_ref);
+            let mut changes = serde_json::Map::new();
+            for (field, from, to) in [
+                (
+                    "slug",
+                    Some(previous.slug.as_str()),
+                    Some(list.slug.as_ref()),
+                ),
+                (
+                    "name",
+                    Some(previous.name.as_str()),
+                    Some(list.name.as_str()),
+                ),
+                (
+                    "sender_name",
+                    previous.sender_name.as_deref(),
+                    list.sender_name.as_deref(),
+                ),
+                (
+                    "sender_email",
+                    previous.sender_email.as_deref(),
+                    sender_email,
+                ),
+                (
+                    "confirmation_subject",
+                    Some(previous.confirmation_subject.as_str()),
+                    Some(list.confirmation_subject.as_str()),
+                ),
+                (
+                    "confirmation_message",
+                    Some(previous.confirmation_message.as_str()),
+                    Some(list.confirmation_message.as_str()),
+                ),
+            ] {
+                if from != to {
+                    changes.insert(field.into(), change(from, to));
+                }
+            }
+            record_event(
+                &mut transaction,
+                &request,
+                Some(*user.user_id),
+                AuditAction::UpdateList,
+                list.slug.as_ref(),
+                changes.into(),
+            )
+            .await
+            .map_err(e500)?;
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit SQL transaction to update a list.")
+                .map_err(e500)?;
+            FlashMessage::info(format!("The list {} has been updated.", list.name)).send();
+        }
+        Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some("23505") => {
+            FlashMessage::error(format!(
+                "A list with slug {} already exists.",
+                list.slug.as_ref()
+            ))
+            .send();
+        }
+        Err(e) => {
+            return Err(e500(
+                anyhow::Error::new(e).context("Failed to update a list."),
+            ))
+        }
+    }
+    Ok(see_other("/admin/lists"))
+}
@@ -1,7 +1,12 @@
-use actix_web::{post, HttpResponse};
+use actix_web::{post, web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
 
-use crate::{session_state::TypedSession, utils::see_other};
+use crate::{
+    authentication::{end_session, UserId},
+    session_state::TypedSession,
+    utils::{e500, see_other},
+};
 
 #[utoipa::path(
     responses(
@@ -12,7 +17,16 @@ use crate::{session_state::TypedSession, utils::see_other};
     tag = "zero2prod"
 )]
 #[post("/logout")]
-pub async fn log_out(session: TypedSession) -> Result<HttpResponse, actix_web::Error> {
+pub async fn log_out(
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let Some(session_id) = session.get_session_id().map_err(e500)? {
+        end_session(**user_id, session_id, &pool)
+            .await
+            .map_err(e500)?;
+    }
     session.log_out();
     FlashMessage::info("You have successfully logged out.").send();
     Ok(see_other("/login"))
@@ -1,9 +1,23 @@
+mod api_tokens;
+mod audit;
 mod dashboard;
+mod lists;
 mod logout;
 mod newsletters;
 mod password;
+mod sessions;
+mod subscribers;
+mod two_factor;
+mod users;
 
+pub use api_tokens::*;
+pub use audit::*;
 pub use dashboard::*;
+pub use lists::*;
 pub use logout::*;
 pub use newsletters::*;
 pub use password::*;
+pub use sessions::*;
+pub use subscribers::*;
+pub use two_factor::*;
+pub use users::*;
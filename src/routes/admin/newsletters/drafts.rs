@@ -0,0 +1,159 @@
+use actix_web::{post, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::{PgExecutor, PgPool};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    authentication::{AuthenticatedUser, Role},
+    lists::get_list_by_slug,
+    templates::NewsletterDraft,
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize, ToSchema)]
+pub struct DraftRequestBody {
+    /// Set to update an existing draft instead of starting a new one.
+    draft_id: Option<Uuid>,
+    title: String,
+    text_content: String,
+    html_content: String,
+    #[serde(default)]
+    list: String,
+}
+
+#[utoipa::path(
+    request_body(content=DraftRequestBody, description="Save a newsletter draft", content_type="application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "Back to the draft"),
+        (status = 403, description = "Forbidden"),
+        (status = 500, description = "Server error"),
+    ),
+    tag = "zero2prod"
+)]
+#[post("/newsletters/drafts")]
+#[tracing::instrument(name = "Save newsletter draft", skip(form, pool))]
+pub async fn save_newsletter_draft(
+    form: web::Form<DraftRequestBody>,
+    pool: web::Data<PgPool>,
+    user: web::ReqData<AuthenticatedUser>,
+) -> Result<HttpResponse, actix_web::Error> {
+    user.require(Role::Author)?;
+    let DraftRequestBody {
+        draft_id,
+        title,
+        text_content,
+        html_content,
+        list,
+    } = form.0;
+
+    let list = match get_list_by_slug(pool.get_ref(), &list)
+        .await
+        .map_err(e500)?
+    {
+        Some(list) => list,
+        None => {
+            FlashMessage::error(format!("{} is not a known list.", list)).send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+    };
+
+    let draft = NewsletterDraft {
+        newsletter_issue_id: draft_id.unwrap_or_else(Uuid::new_v4),
+        list_id: list.list_id,
+        title,
+        text_content,
+        html_content,
+    };
+    let saved = if draft_id.is_some() {
+        update_draft(pool.get_ref(), &draft).await
+    } else {
+        insert_draft(pool.get_ref(), &draft, *user.user_id)
+            .await
+            .map(|_| true)
+    }
+    .context("Failed to save the newsletter draft")
+    .map_err(e500)?;
+    if !saved {
+        FlashMessage::error("This draft has already been published.").send();
+        return Ok(see_other("/admin/newsletters"));
+    }
+
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(see_other(&format!(
+        "/admin/newsletters?draft={}",
+        draft.newsletter_issue_id
+    )))
+}
+
+/// Drafts are newsletter issues without a publication date.
+#[tracing::instrument(skip_all)]
+pub async fn insert_draft(
+    executor: impl PgExecutor<'_>,
+    draft: &NewsletterDraft,
+    author_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id,
+            list_id,
+            title,
+            text_content,
+            html_content,
+            author_id
+        )
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        draft.newsletter_issue_id,
+        draft.list_id,
+        draft.title,
+        draft.text_content,
+        draft.html_content,
+        author_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Returns `false` if there is no such draft, e.g. because it was published
+/// in the meantime.
+#[tracing::instrument(skip_all)]
+async fn update_draft(
+    executor: impl PgExecutor<'_>,
+    draft: &NewsletterDraft,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET list_id = $2, title = $3, text_content = $4, html_content = $5
+        WHERE newsletter_issue_id = $1 AND published_at IS NULL
+        "#,
+        draft.newsletter_issue_id,
+        draft.list_id,
+        draft.title,
+        draft.text_content,
+        draft.html_content
+    )
+    .execute(executor)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+#[tracing::instrument(name = "Get newsletter drafts", skip(pool))]
+pub async fn get_drafts(pool: &PgPool) -> Result<Vec<NewsletterDraft>, anyhow::Error> {
+    sqlx::query_as!(
+        NewsletterDraft,
+        r#"
+        SELECT newsletter_issue_id, list_id, title, text_content, html_content
+        FROM newsletter_issues
+        WHERE published_at IS NULL
+        ORDER BY title
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the newsletter drafts.")
+}
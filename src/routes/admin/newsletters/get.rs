@@ -1,14 +1,31 @@
-use actix_web::{get, http::header::ContentType, HttpResponse};
+use actix_web::{get, http::header::ContentType, web, HttpResponse};
 use actix_web_flash_messages::{IncomingFlashMessages, Level};
 use askama::Template;
+use sqlx::PgPool;
 use std::fmt::Write;
 use uuid::Uuid;
 
-use crate::templates::SendNewsletterTemplate;
+use super::get_drafts;
+use crate::{
+    authentication::{AuthenticatedUser, CsrfToken, Role},
+    lists::get_all_lists,
+    templates::SendNewsletterTemplate,
+    utils::e500,
+};
+
+#[derive(serde::Deserialize)]
+pub struct NewsletterFormQuery {
+    /// Draft to load into the form.
+    draft: Option<Uuid>,
+}
 
 #[get("/newsletters")]
 pub async fn newsletter_issue_form(
     flash_messages: IncomingFlashMessages,
+    query: web::Query<NewsletterFormQuery>,
+    pool: web::Data<PgPool>,
+    user: web::ReqData<AuthenticatedUser>,
+    csrf_token: web::ReqData<CsrfToken>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let mut error = String::new();
 
@@ -22,10 +39,22 @@ pub async fn newsletter_issue_form(
         writeln!(info, "{}", m.content()).unwrap();
     }
 
+    let lists = get_all_lists(&pool).await.map_err(e500)?;
+    let drafts = get_drafts(&pool).await.map_err(e500)?;
+    let draft = query
+        .draft
+        .and_then(|id| drafts.iter().find(|d| d.newsletter_issue_id == id));
+
     let html = SendNewsletterTemplate {
         error: &error,
         info: &info,
         idempotency_key: &Uuid::new_v4().to_string(),
+        lists,
+        drafts: &drafts,
+        draft,
+        can_draft: user.role >= Role::Author,
+        can_publish: user.role >= Role::Editor,
+        csrf_token: &csrf_token,
     }
     .render()
     .expect("Could not render send newsletter admin template.");
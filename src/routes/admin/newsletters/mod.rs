@@ -1,5 +1,9 @@
+mod drafts;
 mod get;
 mod post;
+mod segment;
 
+pub use drafts::*;
 pub use get::newsletter_issue_form;
 pub use post::*;
+pub use segment::*;
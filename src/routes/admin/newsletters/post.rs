@@ -1,21 +1,56 @@
 use crate::{
-    authentication::UserId,
-    idempotency::{save_response, try_processing, IdempotencyKey, NextAction},
+    audit::{record_event, AuditAction},
+    authentication::{AuthenticatedUser, Role},
+    configuration::IdempotencySettings,
+    domain::{Segment, SegmentError},
+    idempotency::{
+        request_hash, save_response, try_processing, IdempotencyError, IdempotencyKey,
+        IdempotentRequest, NextAction,
+    },
+    lists::get_list_by_slug,
+    routes::error_chain_fmt,
     utils::{e500, see_other},
 };
-use actix_web::{post, web, HttpResponse};
+use actix_web::{post, web, HttpMessage, HttpRequest, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
 use anyhow::Context;
 use sqlx::{PgPool, Postgres, Transaction};
 use utoipa::ToSchema;
 use uuid::Uuid;
 
-#[derive(serde::Deserialize, ToSchema)]
+use super::SegmentFormData;
+
+#[derive(serde::Deserialize, serde::Serialize, ToSchema)]
 pub struct NewsletterRequestBody {
+    /// Publish this draft instead of a new issue.
+    draft_id: Option<Uuid>,
     title: String,
     text_content: String,
     html_content: String,
-    idempotency_key: String,
+    /// Clients can send an `Idempotency-Key` header instead.
+    idempotency_key: Option<String>,
+    #[serde(flatten)]
+    segment: SegmentFormData,
+}
+
+impl NewsletterRequestBody {
+    /// Publish a draft with the content it was saved with.
+    pub fn for_draft(
+        draft_id: Uuid,
+        title: String,
+        text_content: String,
+        html_content: String,
+        segment: SegmentFormData,
+    ) -> Self {
+        Self {
+            draft_id: Some(draft_id),
+            title,
+            text_content,
+            html_content,
+            idempotency_key: None,
+            segment,
+        }
+    }
 }
 
 #[utoipa::path(
@@ -24,53 +59,181 @@ pub struct NewsletterRequestBody {
         (status = 200, description = "OK"),
         (status = 303, description = "Login redirect"),
         (status = 400, description = "Bad request"),
+        (status = 403, description = "Forbidden"),
         (status = 500, description = "Server error"),
     ),
     tag = "zero2prod"
 )]
 #[post("/newsletters")]
-#[tracing::instrument(name = "Publish newsletter", skip(form, pool))]
+#[tracing::instrument(name = "Publish newsletter", skip(request, form, pool))]
 pub async fn publish_newsletter(
+    request: HttpRequest,
     form: web::Form<NewsletterRequestBody>,
     pool: web::Data<PgPool>,
-    user_id: web::ReqData<UserId>,
+    user: web::ReqData<AuthenticatedUser>,
 ) -> Result<HttpResponse, actix_web::Error> {
+    user.require(Role::Editor)?;
+
+    match publish_issue(&pool, &request, *user.user_id, form.0, |_| {
+        see_other("/admin/newsletters")
+    })
+    .await
+    {
+        Ok(response) => {
+            success_message().send();
+            Ok(response)
+        }
+        Err(PublishError::UnexpectedError(e)) => Err(e500(e)),
+        Err(e) => {
+            FlashMessage::error(e.to_string()).send();
+            Ok(see_other("/admin/newsletters"))
+        }
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum PublishError {
+    #[error("{0}")]
+    InvalidIdempotencyKey(String),
+
+    #[error("{0} is not a known list.")]
+    UnknownList(String),
+
+    #[error("{0}")]
+    InvalidSegment(SegmentError),
+
+    #[error("This draft has already been published.")]
+    DraftAlreadyPublished,
+
+    #[error(transparent)]
+    Idempotency(IdempotencyError),
+
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl From<IdempotencyError> for PublishError {
+    fn from(e: IdempotencyError) -> Self {
+        match e {
+            IdempotencyError::UnexpectedError(e) => PublishError::UnexpectedError(e),
+            e => PublishError::Idempotency(e),
+        }
+    }
+}
+
+impl std::fmt::Debug for PublishError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+/// Store the issue and queue its deliveries, for the publish form and the
+/// JSON API alike. `user_id` is recorded as the author of new issues.
+/// `respond` builds the response from the id of the new issue; a request
+/// repeating an idempotency key gets the response saved the first time
+/// instead. Keys sent in the `Idempotency-Key` header are handled by
+/// `honor_idempotency_keys` before the request gets here.
+#[tracing::instrument(skip(pool, request, body, respond))]
+pub async fn publish_issue(
+    pool: &PgPool,
+    request: &HttpRequest,
+    user_id: Uuid,
+    body: NewsletterRequestBody,
+    respond: impl FnOnce(Uuid) -> HttpResponse,
+) -> Result<HttpResponse, PublishError> {
+    let body_hash = request_hash(
+        &serde_json::to_vec(&body).context("Failed to serialise the newsletter issue.")?,
+    );
     let NewsletterRequestBody {
+        draft_id,
         title,
         text_content,
         html_content,
         idempotency_key,
-    } = form.0;
-    let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e500)?;
+        segment,
+    } = body;
+    let idempotency_key: Option<IdempotencyKey> = idempotency_key
+        .filter(|_| request.extensions().get::<IdempotentRequest>().is_none())
+        .map(IdempotencyKey::try_from)
+        .transpose()
+        .map_err(|e| PublishError::InvalidIdempotencyKey(e.to_string()))?;
 
-    let mut transaction = match try_processing(&pool, &idempotency_key, **user_id)
-        .await
-        .map_err(e500)?
-    {
-        NextAction::StartProcessing(t) => t,
-        NextAction::ReturnSavedResponse(saved_response) => {
-            success_message().send();
-            return Ok(saved_response);
+    let list = get_list_by_slug(pool, &segment.list)
+        .await?
+        .ok_or_else(|| PublishError::UnknownList(segment.list.clone()))?;
+    let segment = Segment::try_from(&segment).map_err(PublishError::InvalidSegment)?;
+
+    let retention = request
+        .app_data::<web::Data<IdempotencySettings>>()
+        .context("The idempotency settings are not configured.")?
+        .retention();
+    let mut transaction = match &idempotency_key {
+        Some(idempotency_key) => {
+            match try_processing(pool, idempotency_key, user_id, &body_hash, retention).await? {
+                NextAction::StartProcessing(t) => t,
+                NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+            }
         }
+        None => pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")?,
     };
 
-    let issue_id = insert_newsletter_issue(&mut transaction, &title, &text_content, &html_content)
+    let issue_id = match draft_id {
+        Some(draft_id) => publish_draft(
+            &mut transaction,
+            draft_id,
+            list.list_id,
+            &title,
+            &text_content,
+            &html_content,
+            user_id,
+        )
         .await
-        .context("Failed to store newsletter issue details")
-        .map_err(e500)?;
-
-    enqueue_delivery_tasks(&mut transaction, issue_id)
+        .context("Failed to publish the newsletter draft")?
+        .ok_or(PublishError::DraftAlreadyPublished)?,
+        None => insert_newsletter_issue(
+            &mut transaction,
+            list.list_id,
+            &title,
+            &text_content,
+            &html_content,
+            user_id,
+        )
         .await
-        .context("Failed to enqueue delivery tasks")
-        .map_err(e500)?;
+        .context("Failed to store newsletter issue details")?,
+    };
 
-    let response = see_other("/admin/newsletters");
-    let response = save_response(transaction, &idempotency_key, **user_id, response)
+    enqueue_delivery_tasks(&mut transaction, issue_id, list.list_id, &segment)
         .await
-        .map_err(e500)?;
-    success_message().send();
+        .context("Failed to enqueue delivery tasks")?;
+    record_event(
+        &mut transaction,
+        request,
+        Some(user_id),
+        AuditAction::PublishIssue,
+        &title,
+        serde_json::json!({
+            "newsletter_issue_id": issue_id,
+            "list": list.slug,
+            "draft_id": draft_id,
+        }),
+    )
+    .await?;
 
-    Ok(response)
+    match idempotency_key {
+        Some(idempotency_key) => {
+            Ok(save_response(transaction, &idempotency_key, user_id, respond(issue_id)).await?)
+        }
+        None => {
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit SQL transaction to publish the newsletter issue.")?;
+            Ok(respond(issue_id))
+        }
+    }
 }
 
 fn success_message() -> FlashMessage {
@@ -83,48 +246,97 @@ fn success_message() -> FlashMessage {
 #[tracing::instrument(skip_all)]
 async fn insert_newsletter_issue(
     transaction: &mut Transaction<'static, Postgres>,
+    list_id: Uuid,
     title: &str,
     text_content: &str,
     html_content: &str,
+    author_id: Uuid,
 ) -> Result<Uuid, sqlx::Error> {
     let newsletter_issue_id = Uuid::new_v4();
     sqlx::query!(
         r#"
         INSERT INTO newsletter_issues (
             newsletter_issue_id,
+            list_id,
             title,
             text_content,
             html_content,
+            author_id,
             published_at
         )
-        VALUES ($1, $2, $3, $4, now())
+        VALUES ($1, $2, $3, $4, $5, $6, now())
         "#,
         newsletter_issue_id,
+        list_id,
         title,
         text_content,
-        html_content
+        html_content,
+        author_id
     )
     .execute(transaction)
     .await?;
     Ok(newsletter_issue_id)
 }
 
+/// Returns `None` if there is no such draft, e.g. because it was published
+/// in the meantime. Drafts saved before authors were recorded get `author_id`.
+#[tracing::instrument(skip_all)]
+async fn publish_draft(
+    transaction: &mut Transaction<'static, Postgres>,
+    draft_id: Uuid,
+    list_id: Uuid,
+    title: &str,
+    text_content: &str,
+    html_content: &str,
+    author_id: Uuid,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            list_id = $2,
+            title = $3,
+            text_content = $4,
+            html_content = $5,
+            author_id = COALESCE(author_id, $6),
+            published_at = now()
+        WHERE newsletter_issue_id = $1 AND published_at IS NULL
+        RETURNING newsletter_issue_id
+        "#,
+        draft_id,
+        list_id,
+        title,
+        text_content,
+        html_content,
+        author_id
+    )
+    .fetch_optional(transaction)
+    .await?;
+    Ok(row.map(|r| r.newsletter_issue_id))
+}
+
 #[tracing::instrument(skip_all)]
 async fn enqueue_delivery_tasks(
     transaction: &mut Transaction<'static, Postgres>,
     newsletter_issue_id: Uuid,
+    list_id: Uuid,
+    segment: &Segment,
 ) -> Result<(), sqlx::Error> {
     sqlx::query!(
         r#"
         INSERT INTO issue_delivery_queue (
             newsletter_issue_id,
-            subscriber_email
+            subscriber_id
         )
-        SELECT $1, email
-        FROM subscriptions
-        WHERE status = 'confirmed'
+        SELECT $1, subscriber_id
+        FROM segment_recipients($2, $3, $4, $5, $6)
         "#,
         newsletter_issue_id,
+        list_id,
+        &segment.include_tags(),
+        &segment.exclude_tags(),
+        segment.subscribed_after(),
+        segment.subscribed_before(),
     )
     .execute(transaction)
     .await?;
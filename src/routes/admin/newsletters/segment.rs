@@ -0,0 +1,114 @@
+use actix_web::{get, http::StatusCode, web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use crate::{
+    domain::{Segment, SegmentError},
+    lists::get_list_by_slug,
+    problem::{FieldError, Problem},
+    utils::e500,
+};
+
+/// Segment selection fields shared by the publish form and the recipient preview.
+#[derive(serde::Deserialize, serde::Serialize, ToSchema, IntoParams, Default)]
+pub struct SegmentFormData {
+    /// Slug of the list the issue is sent to, the default list if empty.
+    #[serde(default)]
+    pub list: String,
+    /// Comma separated tags, subscribers need at least one of them.
+    #[serde(default)]
+    include_tags: String,
+    /// Comma separated tags, subscribers with any of them are skipped.
+    #[serde(default)]
+    exclude_tags: String,
+    /// Earliest subscription date (YYYY-MM-DD), inclusive.
+    #[serde(default)]
+    subscribed_from: String,
+    /// Latest subscription date (YYYY-MM-DD), inclusive.
+    #[serde(default)]
+    subscribed_until: String,
+}
+
+impl TryFrom<&SegmentFormData> for Segment {
+    type Error = SegmentError;
+
+    fn try_from(value: &SegmentFormData) -> Result<Self, Self::Error> {
+        Segment::parse(
+            &value.include_tags,
+            &value.exclude_tags,
+            &value.subscribed_from,
+            &value.subscribed_until,
+        )
+    }
+}
+
+/// An invalid segment, naming the field at fault.
+pub fn invalid_segment(e: SegmentError) -> Problem {
+    Problem::new(StatusCode::BAD_REQUEST, "invalid_segment", e.to_string()).with_field_error(
+        FieldError {
+            field: e.field.to_owned(),
+            message: e.message,
+        },
+    )
+}
+
+#[derive(serde::Serialize, ToSchema)]
+pub struct RecipientCount {
+    recipients: i64,
+}
+
+#[utoipa::path(
+    params(SegmentFormData),
+    responses(
+        (status = 200, description = "Number of confirmed subscribers in the segment", body = RecipientCount),
+        (status = 303, description = "Login redirect"),
+        (status = 400, description = "Invalid segment or unknown list", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Server error", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    tag = "zero2prod"
+)]
+#[get("/newsletters/recipients")]
+#[tracing::instrument(name = "Preview segment recipients", skip(query, pool))]
+pub async fn preview_recipients(
+    query: web::Query<SegmentFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let segment = Segment::try_from(&query.0).map_err(invalid_segment)?;
+    let list = get_list_by_slug(pool.get_ref(), &query.list)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| {
+            Problem::invalid_field("list", format!("{} is not a known list.", query.list))
+        })?;
+
+    let recipients = count_recipients(&pool, list.list_id, &segment)
+        .await
+        .map_err(e500)?;
+
+    Ok(HttpResponse::Ok().json(RecipientCount { recipients }))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn count_recipients(
+    pool: &PgPool,
+    list_id: Uuid,
+    segment: &Segment,
+) -> Result<i64, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT COUNT(*) as "count!"
+        FROM segment_recipients($1, $2, $3, $4, $5)
+        "#,
+        list_id,
+        &segment.include_tags(),
+        &segment.exclude_tags(),
+        segment.subscribed_after(),
+        segment.subscribed_before(),
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to count the subscribers in a segment.")?;
+    Ok(row.count)
+}
@@ -1,13 +1,21 @@
-use actix_web::{get, http::header::ContentType, HttpResponse};
+use actix_web::{get, http::header::ContentType, web, HttpResponse};
 use actix_web_flash_messages::{IncomingFlashMessages, Level};
 use askama::Template;
+use sqlx::PgPool;
 use std::fmt::Write;
 
-use crate::templates::ChangePasswordTemplate;
+use crate::{
+    authentication::{get_user_email, CsrfToken, UserId},
+    templates::ChangePasswordTemplate,
+    utils::e500,
+};
 
 #[get("/password")]
 pub async fn change_password_form(
     flash_messages: IncomingFlashMessages,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let mut error = String::new();
 
@@ -21,9 +29,15 @@ pub async fn change_password_form(
         writeln!(info, "{}", m.content()).unwrap();
     }
 
+    let email = get_user_email(*user_id.into_inner(), &pool)
+        .await
+        .map_err(e500)?;
+
     let html = ChangePasswordTemplate {
         error: &error,
         info: &info,
+        email: email.as_deref().unwrap_or_default(),
+        csrf_token: &csrf_token,
     }
     .render()
     .expect("Could not render admin dashboard template.");
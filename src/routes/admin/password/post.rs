@@ -1,11 +1,18 @@
-use actix_web::{post, web, HttpResponse};
+use actix_web::{post, web, HttpRequest, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
 use secrecy::{ExposeSecret, Secret};
 use sqlx::PgPool;
 
 use crate::{
-    authentication::{validate_credentials, AuthError, Credentials, UserId},
+    audit::{change, record_event, AuditAction},
+    authentication::{
+        revoke_other_sessions, validate_credentials, AuthError, Credentials, PasswordHashing,
+        PasswordPolicy, UserId,
+    },
+    domain::SubscriberEmail,
     routes::get_username,
+    session_state::TypedSession,
     utils::{e500, see_other},
 };
 
@@ -27,11 +34,18 @@ pub struct FormData {
     tag = "zero2prod"
 )]
 #[post("/password")]
-#[tracing::instrument(name = "Changing password", skip(form, user_id, pool))]
+#[tracing::instrument(
+    name = "Changing password",
+    skip(request, form, user_id, pool, password_policy, hashing, session)
+)]
 pub async fn change_password(
+    request: HttpRequest,
     form: web::Form<FormData>,
     user_id: web::ReqData<UserId>,
     pool: web::Data<PgPool>,
+    password_policy: web::Data<PasswordPolicy>,
+    hashing: web::Data<PasswordHashing>,
+    session: TypedSession,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
 
@@ -43,11 +57,11 @@ pub async fn change_password(
     let username = get_username(*user_id, &pool).await.map_err(e500)?;
 
     let credentials = Credentials {
-        username,
+        username: username.clone(),
         password: form.0.current_password,
     };
 
-    if let Err(e) = validate_credentials(credentials, &pool).await {
+    if let Err(e) = validate_credentials(credentials, &hashing, &pool).await {
         return match e {
             AuthError::InvalidCredentials(_) => {
                 FlashMessage::error("The current password is incorrect.").send();
@@ -56,10 +70,98 @@ pub async fn change_password(
             AuthError::UnexpectedError(_) => Err(e500(e)),
         };
     }
+    if let Err(e) = password_policy.check(&username, &form.0.new_password) {
+        FlashMessage::error(e).send();
+        return Ok(see_other("/admin/password"));
+    }
 
-    crate::authentication::change_password(*user_id, form.0.new_password, &pool)
+    crate::authentication::change_password(*user_id, form.0.new_password, &hashing, &pool)
         .await
         .map_err(e500)?;
+    // Whoever may know the old password is signed out, but not the user.
+    if let Some(session_id) = session.get_session_id().map_err(e500)? {
+        let session_epoch = revoke_other_sessions(*user_id, session_id, &pool)
+            .await
+            .map_err(e500)?;
+        session.insert_session_epoch(session_epoch).map_err(e500)?;
+    }
+    record_event(
+        pool.get_ref(),
+        &request,
+        Some(*user_id),
+        AuditAction::ChangePassword,
+        &username,
+        serde_json::json!({}),
+    )
+    .await
+    .map_err(e500)?;
     FlashMessage::info("Your password has been changed.").send();
     Ok(see_other("/admin/password"))
 }
+
+#[derive(serde::Deserialize)]
+pub struct AccountEmailFormData {
+    email: String,
+}
+
+/// The address password reset links are sent to, so the change is audited:
+/// whoever controls it can take the account over.
+#[post("/email")]
+#[tracing::instrument(name = "Changing account email", skip(request, form, user_id, pool))]
+pub async fn change_account_email(
+    request: HttpRequest,
+    form: web::Form<AccountEmailFormData>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = *user_id.into_inner();
+    let email = match SubscriberEmail::parse(form.0.email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/password"));
+        }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let previous = sqlx::query!(
+        r#"SELECT username, email FROM users WHERE user_id = $1 FOR UPDATE"#,
+        user_id
+    )
+    .fetch_one(&mut transaction)
+    .await
+    .context("Failed to retrieve the user's email.")
+    .map_err(e500)?;
+    if crate::authentication::change_user_email(user_id, &email, &mut transaction)
+        .await
+        .map_err(e500)?
+    {
+        record_event(
+            &mut transaction,
+            &request,
+            Some(user_id),
+            AuditAction::ChangeAccountEmail,
+            &previous.username,
+            serde_json::json!({ "email": change(&previous.email, email.as_ref()) }),
+        )
+        .await
+        .map_err(e500)?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to change the email.")
+            .map_err(e500)?;
+        FlashMessage::info("Your email address has been changed.").send();
+    } else {
+        FlashMessage::error(format!(
+            "{} is already used by another account.",
+            email.as_ref()
+        ))
+        .send();
+    }
+    Ok(see_other("/admin/password"))
+}
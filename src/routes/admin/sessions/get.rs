@@ -0,0 +1,53 @@
+use actix_web::{get, http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{IncomingFlashMessages, Level};
+use askama::Template;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    authentication::{get_active_sessions, CsrfToken, UserId},
+    configuration::SessionSettings,
+    session_state::TypedSession,
+    templates::AdminSessionsTemplate,
+    utils::e500,
+};
+
+#[get("/sessions")]
+pub async fn sessions_page(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    session_settings: web::Data<SessionSettings>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut error = String::new();
+
+    for m in flash_messages.iter().filter(|m| m.level() == Level::Error) {
+        writeln!(error, "{}", m.content()).unwrap();
+    }
+
+    let mut info = String::new();
+
+    for m in flash_messages.iter().filter(|m| m.level() == Level::Info) {
+        writeln!(info, "{}", m.content()).unwrap();
+    }
+
+    let sessions = get_active_sessions(**user_id, &session_settings, &pool)
+        .await
+        .map_err(e500)?;
+
+    let html = AdminSessionsTemplate {
+        error: &error,
+        info: &info,
+        sessions,
+        current_session_id: session.get_session_id().map_err(e500)?,
+        csrf_token: &csrf_token,
+    }
+    .render()
+    .expect("Could not render sessions template.");
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html))
+}
@@ -0,0 +1,56 @@
+use actix_web::{post, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authentication::{end_session, revoke_other_sessions, UserId},
+    session_state::TypedSession,
+    utils::{e500, see_other},
+};
+
+#[post("/sessions/{session_id}/sign_out")]
+#[tracing::instrument(name = "Sign out a session", skip(pool, user_id, session))]
+pub async fn sign_out_session(
+    session_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let session_id = session_id.into_inner();
+    if !end_session(**user_id, session_id, &pool)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error("The session does not exist.").send();
+        return Ok(see_other("/admin/sessions"));
+    }
+
+    if session.get_session_id().map_err(e500)? == Some(session_id) {
+        session.log_out();
+        FlashMessage::info("You have successfully logged out.").send();
+        return Ok(see_other("/login"));
+    }
+    FlashMessage::info("The session has been signed out.").send();
+    Ok(see_other("/admin/sessions"))
+}
+
+#[post("/sessions/sign_out_others")]
+#[tracing::instrument(name = "Sign out the other sessions", skip(pool, user_id, session))]
+pub async fn sign_out_other_sessions(
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let session_id = session
+        .get_session_id()
+        .map_err(e500)?
+        .ok_or_else(|| e500("The session is not tracked."))?;
+
+    let session_epoch = revoke_other_sessions(**user_id, session_id, &pool)
+        .await
+        .map_err(e500)?;
+    session.insert_session_epoch(session_epoch).map_err(e500)?;
+    FlashMessage::info("Your other sessions have been signed out.").send();
+    Ok(see_other("/admin/sessions"))
+}
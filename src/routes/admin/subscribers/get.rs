@@ -0,0 +1,83 @@
+use actix_web::{get, http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{IncomingFlashMessages, Level};
+use anyhow::Context;
+use askama::Template;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    authentication::CsrfToken,
+    lists::get_all_lists,
+    templates::{AdminSubscribersTemplate, SubscriberRow},
+    utils::e500,
+};
+
+#[get("/subscribers")]
+pub async fn subscribers_page(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut error = String::new();
+
+    for m in flash_messages.iter().filter(|m| m.level() == Level::Error) {
+        writeln!(error, "{}", m.content()).unwrap();
+    }
+
+    let mut info = String::new();
+
+    for m in flash_messages.iter().filter(|m| m.level() == Level::Info) {
+        writeln!(info, "{}", m.content()).unwrap();
+    }
+
+    let subscribers = get_subscribers(&pool).await.map_err(e500)?;
+    let lists = get_all_lists(&pool).await.map_err(e500)?;
+
+    let html = AdminSubscribersTemplate {
+        error: &error,
+        info: &info,
+        subscribers,
+        lists,
+        csrf_token: &csrf_token,
+    }
+    .render()
+    .expect("Could not render admin subscribers template.");
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html))
+}
+
+#[tracing::instrument(name = "Get subscribers", skip(pool))]
+async fn get_subscribers(pool: &PgPool) -> Result<Vec<SubscriberRow>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            s.id, s.email, s.name, s.status, s.subscribed_at, s.tags,
+            ARRAY(
+                SELECT l.slug || ' (' || ls.status || ')'
+                FROM list_subscriptions ls
+                JOIN lists l ON l.list_id = ls.list_id
+                WHERE ls.subscriber_id = s.id
+                ORDER BY l.slug
+            ) as "lists!"
+        FROM subscriptions s
+        ORDER BY s.subscribed_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve subscribers.")?
+    .into_iter()
+    .map(|r| SubscriberRow {
+        id: r.id.to_string(),
+        email: r.email,
+        name: r.name,
+        status: r.status,
+        subscribed_at: r.subscribed_at.format("%Y-%m-%d").to_string(),
+        tags: r.tags.join(", "),
+        lists: r.lists.join(", "),
+    })
+    .collect();
+    Ok(rows)
+}
@@ -0,0 +1,268 @@
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit::{change, record_event, AuditAction},
+    authentication::{AuthenticatedUser, Role},
+    domain::{SubscriberEmail, SubscriberName, SubscriberTag},
+    email_change::{request_email_change, EmailChangeError},
+    email_client::EmailClient,
+    lists::get_list_by_slug,
+    startup::{ApplicationBaseUrl, HmacSecret},
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct TagsFormData {
+    #[serde(default)]
+    tags: String,
+}
+
+#[post("/subscribers/{subscriber_id}/tags")]
+#[tracing::instrument(name = "Set subscriber tags", skip(request, form, pool))]
+pub async fn set_subscriber_tags(
+    request: HttpRequest,
+    subscriber_id: web::Path<Uuid>,
+    form: web::Form<TagsFormData>,
+    pool: web::Data<PgPool>,
+    user: web::ReqData<AuthenticatedUser>,
+) -> Result<HttpResponse, actix_web::Error> {
+    user.require(Role::Editor)?;
+    let tags = match SubscriberTag::parse_list(&form.tags) {
+        Ok(tags) => tags,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/subscribers"));
+        }
+    };
+
+    let subscriber_id = subscriber_id.into_inner();
+    let tags = SubscriberTag::to_strings(&tags);
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let Some(previous) = sqlx::query!(
+        r#"SELECT email, tags FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        subscriber_id,
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to retrieve the subscriber tags.")
+    .map_err(e500)?
+    else {
+        FlashMessage::error("The subscriber does not exist.").send();
+        return Ok(see_other("/admin/subscribers"));
+    };
+    sqlx::query!(
+        r#"UPDATE subscriptions SET tags = $1 WHERE id = $2"#,
+        &tags,
+        subscriber_id,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to update subscriber tags.")
+    .map_err(e500)?;
+    record_event(
+        &mut transaction,
+        &request,
+        Some(*user.user_id),
+        AuditAction::SetSubscriberTags,
+        &previous.email,
+        serde_json::json!({ "tags": change(&previous.tags, &tags) }),
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update subscriber tags")
+        .map_err(e500)?;
+
+    FlashMessage::info("The subscriber's tags have been updated.").send();
+    Ok(see_other("/admin/subscribers"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct SubscriberEmailFormData {
+    email: String,
+}
+
+/// Start an email change on behalf of a subscriber. They still have to
+/// confirm the new address.
+#[post("/subscribers/{subscriber_id}/email")]
+#[tracing::instrument(
+    name = "Request a subscriber email change",
+    skip(request, form, pool, email_client, base_url, hmac_secret)
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn change_subscriber_email(
+    request: HttpRequest,
+    subscriber_id: web::Path<Uuid>,
+    form: web::Form<SubscriberEmailFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+    user: web::ReqData<AuthenticatedUser>,
+) -> Result<HttpResponse, actix_web::Error> {
+    user.require(Role::Editor)?;
+    let new_email = match SubscriberEmail::parse(form.0.email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/subscribers"));
+        }
+    };
+
+    let subscriber_id = subscriber_id.into_inner();
+    match request_email_change(
+        &pool,
+        &email_client,
+        &base_url.0,
+        &hmac_secret.0,
+        subscriber_id,
+        &new_email,
+        Some((&request, *user.user_id)),
+    )
+    .await
+    {
+        Ok(()) => FlashMessage::info(format!(
+            "A confirmation link has been sent to {}.",
+            new_email.as_ref()
+        ))
+        .send(),
+        Err(e @ EmailChangeError::UnexpectedError(_)) => return Err(e500(e)),
+        Err(e) => FlashMessage::error(e.to_string()).send(),
+    }
+    Ok(see_other("/admin/subscribers"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct ImportFormData {
+    /// One `email,name` pair per line.
+    subscribers: String,
+    #[serde(default)]
+    tags: String,
+    #[serde(default)]
+    list: String,
+}
+
+/// Import already confirmed subscribers into a list, e.g. when migrating from
+/// another platform. Existing subscribers get the tags added.
+#[post("/subscribers/import")]
+#[tracing::instrument(name = "Import subscribers", skip(request, form, pool))]
+pub async fn import_subscribers(
+    request: HttpRequest,
+    form: web::Form<ImportFormData>,
+    pool: web::Data<PgPool>,
+    user: web::ReqData<AuthenticatedUser>,
+) -> Result<HttpResponse, actix_web::Error> {
+    user.require(Role::Editor)?;
+    let parsed = SubscriberTag::parse_list(&form.tags)
+        .and_then(|tags| parse_import(&form.subscribers).map(|s| (tags, s)));
+    let (tags, subscribers) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/subscribers"));
+        }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let list = match get_list_by_slug(&mut transaction, &form.list)
+        .await
+        .map_err(e500)?
+    {
+        Some(list) => list,
+        None => {
+            FlashMessage::error(format!("{} is not a known list.", form.list)).send();
+            return Ok(see_other("/admin/subscribers"));
+        }
+    };
+    for (email, name) in &subscribers {
+        let subscriber = sqlx::query!(
+            r#"
+            INSERT INTO subscriptions (id, email, name, subscribed_at, status, tags)
+            VALUES ($1, $2, $3, now(), 'confirmed', $4)
+            ON CONFLICT (email) DO UPDATE
+            SET tags = subscriptions.tags || ARRAY(
+                SELECT unnest(EXCLUDED.tags)
+                EXCEPT
+                SELECT unnest(subscriptions.tags)
+            )
+            RETURNING id
+            "#,
+            Uuid::new_v4(),
+            email.as_ref(),
+            name.as_ref(),
+            &SubscriberTag::to_strings(&tags),
+        )
+        .fetch_one(&mut transaction)
+        .await
+        .context("Failed to import a subscriber.")
+        .map_err(e500)?;
+        sqlx::query!(
+            r#"
+            INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+            VALUES ($1, $2, 'confirmed', now())
+            ON CONFLICT (list_id, subscriber_id) DO UPDATE
+            SET status = 'confirmed'
+            "#,
+            list.list_id,
+            subscriber.id,
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to add an imported subscriber to the list.")
+        .map_err(e500)?;
+    }
+    record_event(
+        &mut transaction,
+        &request,
+        Some(*user.user_id),
+        AuditAction::ImportSubscribers,
+        &list.slug,
+        serde_json::json!({
+            "subscribers": subscribers.len(),
+            "tags": SubscriberTag::to_strings(&tags),
+        }),
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to import subscribers")
+        .map_err(e500)?;
+
+    FlashMessage::info(format!("Imported {} subscriber(s).", subscribers.len())).send();
+    Ok(see_other("/admin/subscribers"))
+}
+
+fn parse_import(input: &str) -> Result<Vec<(SubscriberEmail, SubscriberName)>, String> {
+    input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            let (email, name) = line
+                .split_once(',')
+                .ok_or_else(|| format!("Line {}: expected `email,name`.", i + 1))?;
+            let email = SubscriberEmail::parse(email.trim().to_string())
+                .map_err(|e| format!("Line {}: {}", i + 1, e))?;
+            let name = SubscriberName::parse(name.trim().to_string())
+                .map_err(|e| format!("Line {}: {}", i + 1, e))?;
+            Ok((email, name))
+        })
+        .collect()
+}
@@ -0,0 +1,100 @@
+use actix_web::{get, http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{IncomingFlashMessages, Level};
+use askama::Template;
+use qrcode::{render::svg, QrCode};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    authentication::{
+        count_unused_recovery_codes, get_totp_secret, totp::TotpSecret, CsrfToken, UserId,
+    },
+    routes::get_username,
+    session_state::TypedSession,
+    templates::{AdminTwoFactorTemplate, TotpEnrollment},
+    utils::e500,
+};
+
+/// Shown as the account's issuer in authenticator apps.
+const TOTP_ISSUER: &str = "Zero2Prod";
+
+#[get("/2fa")]
+pub async fn two_factor_page(
+    flash_messages: IncomingFlashMessages,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+
+    let mut error = String::new();
+
+    for m in flash_messages.iter().filter(|m| m.level() == Level::Error) {
+        writeln!(error, "{}", m.content()).unwrap();
+    }
+
+    let mut info = String::new();
+
+    for m in flash_messages.iter().filter(|m| m.level() == Level::Info) {
+        writeln!(info, "{}", m.content()).unwrap();
+    }
+
+    let enabled = get_totp_secret(*user_id, &pool)
+        .await
+        .map_err(e500)?
+        .is_some();
+    let (enrollment, unused_recovery_codes) = if enabled {
+        let count = count_unused_recovery_codes(*user_id, &pool)
+            .await
+            .map_err(e500)?;
+        (None, count)
+    } else {
+        let username = get_username(*user_id, &pool).await.map_err(e500)?;
+        let enrollment = start_enrollment(&session, &username).map_err(e500)?;
+        (Some(enrollment), 0)
+    };
+
+    let html = AdminTwoFactorTemplate {
+        error: &error,
+        info: &info,
+        enabled,
+        unused_recovery_codes,
+        enrollment,
+        recovery_codes: Vec::new(),
+        csrf_token: &csrf_token,
+    }
+    .render()
+    .expect("Could not render two-factor authentication template.");
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html))
+}
+
+/// Keep offering the same secret until it is confirmed, so that reloading the
+/// page does not invalidate a QR code that was already scanned.
+fn start_enrollment(
+    session: &TypedSession,
+    username: &str,
+) -> Result<TotpEnrollment, anyhow::Error> {
+    let secret = match session.get_pending_totp_secret()? {
+        Some(secret) => TotpSecret::parse(&secret)?,
+        None => {
+            let secret = TotpSecret::generate();
+            session.insert_pending_totp_secret(&secret.encode())?;
+            secret
+        }
+    };
+    let otpauth_uri = secret.otpauth_uri(TOTP_ISSUER, username);
+    let qr_code_svg = QrCode::new(otpauth_uri.as_bytes())?
+        .render::<svg::Color>()
+        .min_dimensions(200, 200)
+        .build();
+
+    Ok(TotpEnrollment {
+        secret: secret.encode(),
+        otpauth_uri,
+        qr_code_svg,
+    })
+}
@@ -0,0 +1,118 @@
+use actix_web::{http::header::ContentType, post, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use askama::Template;
+use secrecy::Secret;
+use sqlx::PgPool;
+
+use crate::{
+    authentication::{
+        self, get_totp_secret, totp::TotpSecret, validate_credentials, AuthError, Credentials,
+        CsrfToken, PasswordHashing, UserId,
+    },
+    routes::get_username,
+    session_state::TypedSession,
+    templates::AdminTwoFactorTemplate,
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct EnableFormData {
+    code: String,
+}
+
+#[post("/2fa/enable")]
+#[tracing::instrument(
+    name = "Enabling two-factor authentication",
+    skip(form, user_id, pool, session, csrf_token)
+)]
+pub async fn enable_two_factor(
+    form: web::Form<EnableFormData>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+
+    if get_totp_secret(*user_id, &pool)
+        .await
+        .map_err(e500)?
+        .is_some()
+    {
+        FlashMessage::error("Two-factor authentication is already enabled.").send();
+        return Ok(see_other("/admin/2fa"));
+    }
+    let Some(secret) = session.get_pending_totp_secret().map_err(e500)? else {
+        FlashMessage::error("The enrollment has expired, please scan the new QR code.").send();
+        return Ok(see_other("/admin/2fa"));
+    };
+    let secret = TotpSecret::parse(&secret).map_err(e500)?;
+
+    let Some(recovery_codes) =
+        authentication::enable_two_factor(*user_id, &secret, &form.code, &pool)
+            .await
+            .map_err(e500)?
+    else {
+        FlashMessage::error("Invalid authentication code.").send();
+        return Ok(see_other("/admin/2fa"));
+    };
+    session.remove_pending_totp_secret();
+
+    // The recovery codes are only stored hashed, so this is the one chance to
+    // show them.
+    let html = AdminTwoFactorTemplate {
+        error: "",
+        info: "Two-factor authentication has been enabled.",
+        enabled: true,
+        unused_recovery_codes: recovery_codes.len() as i64,
+        enrollment: None,
+        recovery_codes,
+        csrf_token: &csrf_token,
+    }
+    .render()
+    .expect("Could not render two-factor authentication template.");
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html))
+}
+
+#[derive(serde::Deserialize)]
+pub struct DisableFormData {
+    password: Secret<String>,
+}
+
+#[post("/2fa/disable")]
+#[tracing::instrument(
+    name = "Disabling two-factor authentication",
+    skip(form, user_id, pool, hashing)
+)]
+pub async fn disable_two_factor(
+    form: web::Form<DisableFormData>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    hashing: web::Data<PasswordHashing>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+
+    let username = get_username(*user_id, &pool).await.map_err(e500)?;
+    let credentials = Credentials {
+        username,
+        password: form.0.password,
+    };
+    if let Err(e) = validate_credentials(credentials, &hashing, &pool).await {
+        return match e {
+            AuthError::InvalidCredentials(_) => {
+                FlashMessage::error("The password is incorrect.").send();
+                Ok(see_other("/admin/2fa"))
+            }
+            AuthError::UnexpectedError(_) => Err(e500(e)),
+        };
+    }
+
+    authentication::disable_two_factor(*user_id, &pool)
+        .await
+        .map_err(e500)?;
+    FlashMessage::info("Two-factor authentication has been disabled.").send();
+    Ok(see_other("/admin/2fa"))
+}
@@ -0,0 +1,52 @@
+use actix_web::{get, http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{IncomingFlashMessages, Level};
+use askama::Template;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    authentication::{AuthenticatedUser, CsrfToken, Role},
+    invitations::get_pending_invitations,
+    templates::AdminUsersTemplate,
+    users::get_all_users,
+    utils::e500,
+};
+
+#[get("/users")]
+pub async fn users_page(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    user: web::ReqData<AuthenticatedUser>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    user.require(Role::Owner)?;
+    let mut error = String::new();
+
+    for m in flash_messages.iter().filter(|m| m.level() == Level::Error) {
+        writeln!(error, "{}", m.content()).unwrap();
+    }
+
+    let mut info = String::new();
+
+    for m in flash_messages.iter().filter(|m| m.level() == Level::Info) {
+        writeln!(info, "{}", m.content()).unwrap();
+    }
+
+    let users = get_all_users(&pool).await.map_err(e500)?;
+    let invitations = get_pending_invitations(&pool).await.map_err(e500)?;
+
+    let html = AdminUsersTemplate {
+        error: &error,
+        info: &info,
+        users,
+        invitations,
+        current_user_id: *user.user_id,
+        csrf_token: &csrf_token,
+    }
+    .render()
+    .expect("Could not render admin users template.");
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html))
+}
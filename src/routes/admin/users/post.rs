@@ -0,0 +1,274 @@
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    audit::{change, record_event, AuditAction},
+    authentication::{AuthenticatedUser, Role},
+    domain::SubscriberEmail,
+    email_client::EmailClient,
+    invitations::{invite_user, InvitationError},
+    startup::ApplicationBaseUrl,
+    users::{delete_user_account, get_user, set_user_active, set_user_role, AdminUser},
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct InviteFormData {
+    email: String,
+    role: String,
+}
+
+#[post("/users/invitations")]
+#[tracing::instrument(
+    name = "Send an invitation",
+    skip(request, form, pool, email_client, base_url)
+)]
+pub async fn send_invitation(
+    request: HttpRequest,
+    form: web::Form<InviteFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    user: web::ReqData<AuthenticatedUser>,
+) -> Result<HttpResponse, actix_web::Error> {
+    user.require(Role::Owner)?;
+    let form = form.into_inner();
+    let invitation = SubscriberEmail::parse(form.email)
+        .and_then(|email| Role::parse(&form.role).map(|role| (email, role)));
+    let (email, role) = match invitation {
+        Ok(invitation) => invitation,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+
+    match invite_user(
+        &pool,
+        &email_client,
+        &base_url.0,
+        &request,
+        &email,
+        role,
+        *user.user_id,
+    )
+    .await
+    {
+        Ok(()) => FlashMessage::info(format!(
+            "An invitation has been sent to {}.",
+            email.as_ref()
+        ))
+        .send(),
+        Err(e @ InvitationError::AlreadyRegistered(_)) => FlashMessage::error(e.to_string()).send(),
+        Err(e) => return Err(e500(e)),
+    }
+    Ok(see_other("/admin/users"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct RoleFormData {
+    role: String,
+}
+
+#[post("/users/{user_id}/role")]
+#[tracing::instrument(name = "Change a user's role", skip(request, form, pool))]
+pub async fn change_user_role(
+    request: HttpRequest,
+    user_id: web::Path<Uuid>,
+    form: web::Form<RoleFormData>,
+    pool: web::Data<PgPool>,
+    user: web::ReqData<AuthenticatedUser>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(user_id) = other_user(&user, *user_id)? else {
+        FlashMessage::error("You cannot change your own role.").send();
+        return Ok(see_other("/admin/users"));
+    };
+    let role = match Role::parse(&form.role) {
+        Ok(role) => role,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+
+    let Some(target) = existing_user(user_id, &pool).await? else {
+        return Ok(see_other("/admin/users"));
+    };
+
+    let mut transaction = begin(&pool).await?;
+    if set_user_role(user_id, role, &mut transaction)
+        .await
+        .map_err(e500)?
+    {
+        record_event(
+            &mut transaction,
+            &request,
+            Some(*user.user_id),
+            AuditAction::ChangeRole,
+            &target.username,
+            serde_json::json!({ "role": change(&target.role, role.as_str()) }),
+        )
+        .await
+        .map_err(e500)?;
+        commit(transaction).await?;
+        FlashMessage::info("The role has been changed.").send();
+    } else {
+        FlashMessage::error("The user does not exist.").send();
+    }
+    Ok(see_other("/admin/users"))
+}
+
+#[post("/users/{user_id}/deactivate")]
+#[tracing::instrument(name = "Deactivate a user", skip(request, pool))]
+pub async fn deactivate_user(
+    request: HttpRequest,
+    user_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user: web::ReqData<AuthenticatedUser>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(user_id) = other_user(&user, *user_id)? else {
+        FlashMessage::error("You cannot deactivate your own account.").send();
+        return Ok(see_other("/admin/users"));
+    };
+
+    let Some(target) = existing_user(user_id, &pool).await? else {
+        return Ok(see_other("/admin/users"));
+    };
+
+    let mut transaction = begin(&pool).await?;
+    if set_user_active(user_id, false, &mut transaction)
+        .await
+        .map_err(e500)?
+    {
+        record_event(
+            &mut transaction,
+            &request,
+            Some(*user.user_id),
+            AuditAction::DeactivateUser,
+            &target.username,
+            serde_json::json!({ "active": change(target.deactivated_at.is_none(), false) }),
+        )
+        .await
+        .map_err(e500)?;
+        commit(transaction).await?;
+        FlashMessage::info("The user has been deactivated.").send();
+    } else {
+        FlashMessage::error("The user does not exist.").send();
+    }
+    Ok(see_other("/admin/users"))
+}
+
+#[post("/users/{user_id}/reactivate")]
+#[tracing::instrument(name = "Reactivate a user", skip(request, pool))]
+pub async fn reactivate_user(
+    request: HttpRequest,
+    user_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user: web::ReqData<AuthenticatedUser>,
+) -> Result<HttpResponse, actix_web::Error> {
+    user.require(Role::Owner)?;
+
+    let Some(target) = existing_user(*user_id, &pool).await? else {
+        return Ok(see_other("/admin/users"));
+    };
+
+    let mut transaction = begin(&pool).await?;
+    if set_user_active(*user_id, true, &mut transaction)
+        .await
+        .map_err(e500)?
+    {
+        record_event(
+            &mut transaction,
+            &request,
+            Some(*user.user_id),
+            AuditAction::ReactivateUser,
+            &target.username,
+            serde_json::json!({ "active": change(target.deactivated_at.is_none(), true) }),
+        )
+        .await
+        .map_err(e500)?;
+        commit(transaction).await?;
+        FlashMessage::info("The user has been reactivated.").send();
+    } else {
+        FlashMessage::error("The user does not exist.").send();
+    }
+    Ok(see_other("/admin/users"))
+}
+
+#[post("/users/{user_id}/delete")]
+#[tracing::instrument(name = "Delete a user", skip(request, pool))]
+pub async fn delete_user(
+    request: HttpRequest,
+    user_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user: web::ReqData<AuthenticatedUser>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(user_id) = other_user(&user, *user_id)? else {
+        FlashMessage::error("You cannot delete your own account.").send();
+        return Ok(see_other("/admin/users"));
+    };
+
+    let Some(target) = existing_user(user_id, &pool).await? else {
+        return Ok(see_other("/admin/users"));
+    };
+
+    let mut transaction = begin(&pool).await?;
+    if delete_user_account(user_id, &mut transaction)
+        .await
+        .map_err(e500)?
+    {
+        record_event(
+            &mut transaction,
+            &request,
+            Some(*user.user_id),
+            AuditAction::DeleteUser,
+            &target.username,
+            serde_json::json!({ "email": target.email, "role": target.role }),
+        )
+        .await
+        .map_err(e500)?;
+        commit(transaction).await?;
+        FlashMessage::info("The user has been deleted.").send();
+    } else {
+        FlashMessage::error("The user does not exist.").send();
+    }
+    Ok(see_other("/admin/users"))
+}
+
+async fn begin(pool: &PgPool) -> Result<Transaction<'static, Postgres>, actix_web::Error> {
+    pool.begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)
+}
+
+/// The change and its audit event are committed together.
+async fn commit(transaction: Transaction<'static, Postgres>) -> Result<(), actix_web::Error> {
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to change the user.")
+        .map_err(e500)
+}
+
+/// Flashes an error if the user does not exist.
+async fn existing_user(
+    user_id: Uuid,
+    pool: &PgPool,
+) -> Result<Option<AdminUser>, actix_web::Error> {
+    let user = get_user(user_id, pool).await.map_err(e500)?;
+    if user.is_none() {
+        FlashMessage::error("The user does not exist.").send();
+    }
+    Ok(user)
+}
+
+/// Only owners manage users, and never their own account, so that there is
+/// always an owner left.
+fn other_user(user: &AuthenticatedUser, user_id: Uuid) -> Result<Option<Uuid>, actix_web::Error> {
+    user.require(Role::Owner)?;
+    Ok(Some(user_id).filter(|id| *id != *user.user_id))
+}
@@ -0,0 +1,402 @@
+use actix_web::{get, http::StatusCode, post, web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use super::{paginated_response, Pagination};
+use crate::{
+    authentication::{ApiClient, ApiScope, Role},
+    lists::get_list_by_slug,
+    problem::Problem,
+    routes::{
+        insert_draft, invalid_segment, publish_issue, NewsletterRequestBody, PublishError,
+        SegmentFormData,
+    },
+    templates::NewsletterDraft,
+    utils::e500,
+};
+
+#[derive(serde::Serialize, ToSchema)]
+pub struct PublishedIssue {
+    newsletter_issue_id: Uuid,
+}
+
+#[utoipa::path(
+    context_path = "/api/v1",
+    request_body(content=NewsletterRequestBody, description="Publish newsletter", content_type="application/json"),
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Repeating a key returns the response saved the first time"),
+    ),
+    responses(
+        (status = 202, description = "Accepted, emails will go out shortly", body = PublishedIssue),
+        (status = 400, description = "Bad request", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Forbidden", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "The draft was already published, or a request with the same idempotency key is being processed", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "The idempotency key was used for a different request", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Server error", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("api_token" = ["issues:write"])),
+    tag = "zero2prod"
+)]
+#[post("/newsletters")]
+#[tracing::instrument(name = "Publish newsletter through the API", skip(request, body, pool))]
+pub async fn publish_newsletter_issue(
+    request: HttpRequest,
+    body: web::Json<NewsletterRequestBody>,
+    pool: web::Data<PgPool>,
+    client: web::ReqData<ApiClient>,
+) -> Result<HttpResponse, actix_web::Error> {
+    client.require(ApiScope::IssuesWrite, Role::Editor)?;
+
+    publish_issue(&pool, &request, *client.user_id, body.0, accepted)
+        .await
+        .map_err(publish_problem)
+}
+
+fn accepted(newsletter_issue_id: Uuid) -> HttpResponse {
+    HttpResponse::Accepted().json(PublishedIssue {
+        newsletter_issue_id,
+    })
+}
+
+fn publish_problem(e: PublishError) -> actix_web::Error {
+    match e {
+        PublishError::UnexpectedError(e) => e500(e),
+        PublishError::Idempotency(e) => e.into(),
+        e @ PublishError::DraftAlreadyPublished => Problem::new(
+            StatusCode::CONFLICT,
+            "draft_already_published",
+            e.to_string(),
+        )
+        .into(),
+        e @ PublishError::InvalidIdempotencyKey(_) => {
+            Problem::invalid_field("idempotency_key", e.to_string()).into()
+        }
+        e @ PublishError::UnknownList(_) => Problem::invalid_field("list", e.to_string()).into(),
+        PublishError::InvalidSegment(e) => invalid_segment(e).into(),
+    }
+}
+
+#[derive(serde::Serialize, ToSchema)]
+pub struct IssueSummary {
+    newsletter_issue_id: Uuid,
+    title: String,
+    /// Slug of the list the issue is sent to.
+    list: String,
+    #[schema(example = "published")]
+    status: String,
+    /// Not set for drafts.
+    published_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize, ToSchema)]
+pub struct NewsletterIssue {
+    newsletter_issue_id: Uuid,
+    title: String,
+    /// Slug of the list the issue is sent to.
+    list: String,
+    #[schema(example = "draft")]
+    status: String,
+    /// Not set for drafts.
+    published_at: Option<DateTime<Utc>>,
+    text_content: String,
+    html_content: String,
+}
+
+#[derive(Clone, Copy, Debug, serde::Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueStatus {
+    Draft,
+    Published,
+}
+
+#[derive(Debug, serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct IssueFilters {
+    /// Only drafts, or only published issues.
+    status: Option<IssueStatus>,
+    /// Only issues sent to the list with this slug.
+    list: Option<String>,
+}
+
+#[utoipa::path(
+    context_path = "/api/v1",
+    params(Pagination, IssueFilters),
+    responses(
+        (status = 200, description = "Issues matching the filters, in publication order with drafts last", body = [IssueSummary],
+            headers(("X-Total-Count" = i64, description = "Number of matching issues across all pages"))),
+        (status = 400, description = "Invalid query parameters", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Forbidden", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Server error", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("api_token" = ["issues:read"])),
+    tag = "zero2prod"
+)]
+#[get("/issues")]
+#[tracing::instrument(name = "List newsletter issues through the API", skip(pool))]
+pub async fn list_issues(
+    pagination: web::Query<Pagination>,
+    filters: web::Query<IssueFilters>,
+    pool: web::Data<PgPool>,
+    client: web::ReqData<ApiClient>,
+) -> Result<HttpResponse, actix_web::Error> {
+    client.require(ApiScope::IssuesRead, Role::Viewer)?;
+    let published = filters
+        .status
+        .map(|status| matches!(status, IssueStatus::Published));
+
+    let issues = sqlx::query_as!(
+        IssueSummary,
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.title,
+            l.slug as list,
+            CASE WHEN i.published_at IS NULL THEN 'draft' ELSE 'published' END as "status!",
+            i.published_at::timestamptz as published_at
+        FROM newsletter_issues i
+        JOIN lists l ON l.list_id = i.list_id
+        WHERE
+            ($1::bool IS NULL OR (i.published_at IS NOT NULL) = $1) AND
+            ($2::text IS NULL OR l.slug = $2)
+        ORDER BY i.published_at::timestamptz NULLS LAST, i.newsletter_issue_id
+        LIMIT $3 OFFSET $4
+        "#,
+        published,
+        filters.list,
+        pagination.limit(),
+        pagination.offset(),
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the newsletter issues.")
+    .map_err(e500)?;
+    let total = sqlx::query!(
+        r#"
+        SELECT count(*) as "count!"
+        FROM newsletter_issues i
+        JOIN lists l ON l.list_id = i.list_id
+        WHERE
+            ($1::bool IS NULL OR (i.published_at IS NOT NULL) = $1) AND
+            ($2::text IS NULL OR l.slug = $2)
+        "#,
+        published,
+        filters.list,
+    )
+    .fetch_one(pool.get_ref())
+    .await
+    .context("Failed to count the newsletter issues.")
+    .map_err(e500)?
+    .count;
+
+    Ok(paginated_response(issues, total))
+}
+
+#[utoipa::path(
+    context_path = "/api/v1",
+    params(("newsletter_issue_id" = Uuid, Path, description = "Id of the issue")),
+    responses(
+        (status = 200, description = "The issue, with its content", body = NewsletterIssue),
+        (status = 401, description = "Missing or invalid API token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Forbidden", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "No such issue", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Server error", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("api_token" = ["issues:read"])),
+    tag = "zero2prod"
+)]
+#[get("/issues/{newsletter_issue_id}")]
+#[tracing::instrument(name = "Get a newsletter issue through the API", skip(pool))]
+pub async fn get_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    client: web::ReqData<ApiClient>,
+) -> Result<HttpResponse, actix_web::Error> {
+    client.require(ApiScope::IssuesRead, Role::Viewer)?;
+
+    match fetch_issue(&pool, newsletter_issue_id.into_inner())
+        .await
+        .map_err(e500)?
+    {
+        Some(issue) => Ok(HttpResponse::Ok().json(issue)),
+        None => Err(issue_not_found().into()),
+    }
+}
+
+async fn fetch_issue(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<NewsletterIssue>, anyhow::Error> {
+    sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.title,
+            l.slug as list,
+            CASE WHEN i.published_at IS NULL THEN 'draft' ELSE 'published' END as "status!",
+            i.published_at::timestamptz as published_at,
+            i.text_content,
+            i.html_content
+        FROM newsletter_issues i
+        JOIN lists l ON l.list_id = i.list_id
+        WHERE i.newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the newsletter issue.")
+}
+
+fn issue_not_found() -> Problem {
+    Problem::new(
+        StatusCode::NOT_FOUND,
+        "issue_not_found",
+        "The newsletter issue does not exist.",
+    )
+}
+
+#[derive(serde::Deserialize, ToSchema)]
+pub struct NewIssue {
+    title: String,
+    text_content: String,
+    html_content: String,
+    /// Slug of the list the issue is for, the default list if empty.
+    #[serde(default)]
+    list: String,
+}
+
+/// Issues are created as drafts, to be published separately.
+#[utoipa::path(
+    context_path = "/api/v1",
+    request_body(content = NewIssue, description = "Draft to save", content_type = "application/json"),
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Repeating a key returns the response saved the first time"),
+    ),
+    responses(
+        (status = 201, description = "The draft has been saved", body = NewsletterIssue,
+            headers(("Location" = String, description = "URL of the issue"))),
+        (status = 400, description = "Invalid issue", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Forbidden", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "A request with the same idempotency key is being processed", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "The idempotency key was used for a different request", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Server error", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("api_token" = ["issues:write"])),
+    tag = "zero2prod"
+)]
+#[post("/issues")]
+#[tracing::instrument(name = "Save a newsletter draft through the API", skip(body, pool))]
+pub async fn create_issue(
+    body: web::Json<NewIssue>,
+    pool: web::Data<PgPool>,
+    client: web::ReqData<ApiClient>,
+) -> Result<HttpResponse, actix_web::Error> {
+    client.require(ApiScope::IssuesWrite, Role::Author)?;
+    let NewIssue {
+        title,
+        text_content,
+        html_content,
+        list,
+    } = body.0;
+
+    let Some(list) = get_list_by_slug(pool.get_ref(), &list)
+        .await
+        .map_err(e500)?
+    else {
+        return Err(
+            Problem::invalid_field("list", format!("{} is not a known list.", list)).into(),
+        );
+    };
+    let draft = NewsletterDraft {
+        newsletter_issue_id: Uuid::new_v4(),
+        list_id: list.list_id,
+        title,
+        text_content,
+        html_content,
+    };
+    insert_draft(pool.get_ref(), &draft, *client.user_id)
+        .await
+        .context("Failed to save the newsletter draft")
+        .map_err(e500)?;
+    let issue = fetch_issue(&pool, draft.newsletter_issue_id)
+        .await
+        .and_then(|issue| issue.context("The new draft is missing."))
+        .map_err(e500)?;
+
+    Ok(HttpResponse::Created()
+        .insert_header((
+            "Location",
+            format!("/api/v1/issues/{}", draft.newsletter_issue_id),
+        ))
+        .json(issue))
+}
+
+/// The draft goes out with the content it was saved with, to the confirmed
+/// members of its list matching the segment.
+#[utoipa::path(
+    context_path = "/api/v1",
+    params(
+        ("newsletter_issue_id" = Uuid, Path, description = "Id of the draft"),
+        ("Idempotency-Key" = Option<String>, Header, description = "Repeating a key returns the response saved the first time"),
+    ),
+    request_body(content = SegmentFormData, description = "Subscribers to send the draft to, all members of its list if empty", content_type = "application/json"),
+    responses(
+        (status = 202, description = "Accepted, emails will go out shortly", body = PublishedIssue),
+        (status = 400, description = "Invalid segment", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Forbidden", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "No such issue", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "The issue was already published, or a request with the same idempotency key is being processed", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "The idempotency key was used for a different request", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Server error", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("api_token" = ["issues:write"])),
+    tag = "zero2prod"
+)]
+#[post("/issues/{newsletter_issue_id}/publish")]
+#[tracing::instrument(
+    name = "Publish a newsletter draft through the API",
+    skip(request, segment, pool)
+)]
+pub async fn publish_draft_issue(
+    request: HttpRequest,
+    newsletter_issue_id: web::Path<Uuid>,
+    segment: web::Json<SegmentFormData>,
+    pool: web::Data<PgPool>,
+    client: web::ReqData<ApiClient>,
+) -> Result<HttpResponse, actix_web::Error> {
+    client.require(ApiScope::IssuesWrite, Role::Editor)?;
+
+    let Some(issue) = fetch_issue(&pool, newsletter_issue_id.into_inner())
+        .await
+        .map_err(e500)?
+    else {
+        return Err(issue_not_found().into());
+    };
+    if issue.published_at.is_some() {
+        return Err(publish_problem(PublishError::DraftAlreadyPublished));
+    }
+    let mut segment = segment.0;
+    if segment.list.is_empty() {
+        // Otherwise the draft would be moved to the default list.
+        segment.list = issue.list;
+    }
+    let body = NewsletterRequestBody::for_draft(
+        issue.newsletter_issue_id,
+        issue.title,
+        issue.text_content,
+        issue.html_content,
+        segment,
+    );
+
+    publish_issue(&pool, &request, *client.user_id, body, accepted)
+        .await
+        .map_err(publish_problem)
+}
@@ -0,0 +1,9 @@
+//! JSON API for programmatic clients, authenticated with API tokens.
+
+mod issues;
+mod pagination;
+mod subscribers;
+
+pub use issues::*;
+pub use pagination::*;
+pub use subscribers::*;
@@ -0,0 +1,41 @@
+use actix_web::HttpResponse;
+use utoipa::IntoParams;
+
+/// Page size when a request does not set `limit`.
+const DEFAULT_LIMIT: u32 = 50;
+/// Largest page a request can ask for.
+const MAX_LIMIT: u32 = 100;
+
+/// Header carrying the number of items matching the filters of a list
+/// request, across all pages.
+pub const TOTAL_COUNT_HEADER: &str = "X-Total-Count";
+
+/// Paging parameters shared by every list endpoint.
+#[derive(Debug, serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct Pagination {
+    /// Number of items to return, 50 by default and at most 100.
+    #[param(minimum = 1, maximum = 100)]
+    limit: Option<u32>,
+    /// Number of items to skip.
+    #[serde(default)]
+    offset: u32,
+}
+
+impl Pagination {
+    pub fn limit(&self) -> i64 {
+        i64::from(self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT))
+    }
+
+    pub fn offset(&self) -> i64 {
+        i64::from(self.offset)
+    }
+}
+
+/// A page of a list, with the number of items on all pages in the
+/// `X-Total-Count` header.
+pub fn paginated_response<T: serde::Serialize>(items: Vec<T>, total: i64) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header((TOTAL_COUNT_HEADER, total))
+        .json(items)
+}
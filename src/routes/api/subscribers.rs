@@ -0,0 +1,288 @@
+use actix_web::{get, http::StatusCode, post, web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use super::{paginated_response, Pagination};
+use crate::{
+    authentication::{ApiClient, ApiScope, Role},
+    domain::{ListSlug, SubscriberEmail, SubscriberName, SubscriberTag, SubscriptionRequest},
+    email_client::EmailClient,
+    problem::Problem,
+    routes::{add_subscriber, SubscribeError},
+    startup::{ApplicationBaseUrl, HmacSecret},
+    utils::e500,
+};
+
+#[derive(serde::Serialize, ToSchema)]
+pub struct SubscriberSummary {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    tags: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, serde::Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriberStatus {
+    PendingConfirmation,
+    Confirmed,
+    Unsubscribed,
+}
+
+impl SubscriberStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::PendingConfirmation => "pending_confirmation",
+            Self::Confirmed => "confirmed",
+            Self::Unsubscribed => "unsubscribed",
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SubscriberFilters {
+    /// Only subscribers with this status.
+    status: Option<SubscriberStatus>,
+    /// Only subscribers with this tag.
+    tag: Option<String>,
+    /// Only members of the list with this slug, whatever their status there.
+    list: Option<String>,
+}
+
+#[utoipa::path(
+    context_path = "/api/v1",
+    params(Pagination, SubscriberFilters),
+    responses(
+        (status = 200, description = "Subscribers matching the filters, oldest first", body = [SubscriberSummary],
+            headers(("X-Total-Count" = i64, description = "Number of matching subscribers across all pages"))),
+        (status = 400, description = "Invalid query parameters", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Forbidden", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Server error", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("api_token" = ["subscribers:read"])),
+    tag = "zero2prod"
+)]
+#[get("/subscribers")]
+#[tracing::instrument(name = "List subscribers through the API", skip(pool))]
+pub async fn list_subscribers(
+    pagination: web::Query<Pagination>,
+    filters: web::Query<SubscriberFilters>,
+    pool: web::Data<PgPool>,
+    client: web::ReqData<ApiClient>,
+) -> Result<HttpResponse, actix_web::Error> {
+    client.require(ApiScope::SubscribersRead, Role::Viewer)?;
+    let status = filters.status.map(|s| s.as_str());
+    // Tags are stored in lowercase.
+    let tag = filters.tag.as_deref().map(str::to_lowercase);
+
+    let subscribers = sqlx::query_as!(
+        SubscriberSummary,
+        r#"
+        SELECT s.id, s.email, s.name, s.status, s.subscribed_at, s.tags
+        FROM subscriptions s
+        WHERE
+            ($1::text IS NULL OR s.status = $1) AND
+            ($2::text IS NULL OR $2 = ANY(s.tags)) AND
+            ($3::text IS NULL OR EXISTS (
+                SELECT 1
+                FROM list_subscriptions ls
+                JOIN lists l ON l.list_id = ls.list_id
+                WHERE ls.subscriber_id = s.id AND l.slug = $3
+            ))
+        ORDER BY s.subscribed_at, s.id
+        LIMIT $4 OFFSET $5
+        "#,
+        status,
+        tag,
+        filters.list,
+        pagination.limit(),
+        pagination.offset(),
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the subscribers.")
+    .map_err(e500)?;
+    let total = sqlx::query!(
+        r#"
+        SELECT count(*) as "count!"
+        FROM subscriptions s
+        WHERE
+            ($1::text IS NULL OR s.status = $1) AND
+            ($2::text IS NULL OR $2 = ANY(s.tags)) AND
+            ($3::text IS NULL OR EXISTS (
+                SELECT 1
+                FROM list_subscriptions ls
+                JOIN lists l ON l.list_id = ls.list_id
+                WHERE ls.subscriber_id = s.id AND l.slug = $3
+            ))
+        "#,
+        status,
+        tag,
+        filters.list,
+    )
+    .fetch_one(pool.get_ref())
+    .await
+    .context("Failed to count the subscribers.")
+    .map_err(e500)?
+    .count;
+
+    Ok(paginated_response(subscribers, total))
+}
+
+#[utoipa::path(
+    context_path = "/api/v1",
+    params(("subscriber_id" = Uuid, Path, description = "Id of the subscriber")),
+    responses(
+        (status = 200, description = "The subscriber", body = SubscriberSummary),
+        (status = 401, description = "Missing or invalid API token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Forbidden", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "No such subscriber", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Server error", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("api_token" = ["subscribers:read"])),
+    tag = "zero2prod"
+)]
+#[get("/subscribers/{subscriber_id}")]
+#[tracing::instrument(name = "Get a subscriber through the API", skip(pool))]
+pub async fn get_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    client: web::ReqData<ApiClient>,
+) -> Result<HttpResponse, actix_web::Error> {
+    client.require(ApiScope::SubscribersRead, Role::Viewer)?;
+
+    match fetch_subscriber(&pool, subscriber_id.into_inner())
+        .await
+        .map_err(e500)?
+    {
+        Some(subscriber) => Ok(HttpResponse::Ok().json(subscriber)),
+        None => Err(Problem::new(
+            StatusCode::NOT_FOUND,
+            "subscriber_not_found",
+            "The subscriber does not exist.",
+        )
+        .into()),
+    }
+}
+
+async fn fetch_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberSummary>, anyhow::Error> {
+    sqlx::query_as!(
+        SubscriberSummary,
+        r#"
+        SELECT id, email, name, status, subscribed_at, tags
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the subscriber.")
+}
+
+#[derive(serde::Deserialize, ToSchema)]
+pub struct NewSubscriber {
+    #[schema(example = "ursula_le_guin@gmail.com")]
+    email: String,
+    #[schema(example = "Ursula Le Guin")]
+    name: String,
+    #[serde(default)]
+    #[schema(example = json!(["rust", "early-adopter"]))]
+    tags: Vec<String>,
+    /// Slug of the list to subscribe to, the default list if omitted.
+    list: Option<String>,
+}
+
+impl TryFrom<NewSubscriber> for SubscriptionRequest {
+    type Error = SubscribeError;
+
+    fn try_from(value: NewSubscriber) -> Result<Self, Self::Error> {
+        let name = SubscriberName::parse(value.name).map_err(SubscribeError::invalid("name"))?;
+        let email =
+            SubscriberEmail::parse(value.email).map_err(SubscribeError::invalid("email"))?;
+        let mut tags: Vec<SubscriberTag> = Vec::new();
+        for tag in value.tags {
+            let tag = SubscriberTag::parse(tag).map_err(SubscribeError::invalid("tags"))?;
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        let list = value
+            .list
+            .map(ListSlug::parse)
+            .transpose()
+            .map_err(SubscribeError::invalid("list"))?;
+        Ok(Self {
+            email,
+            name,
+            tags,
+            list,
+        })
+    }
+}
+
+/// Subscribers added through the API go through the same double opt-in as
+/// the subscribe form: they get a confirmation email. The tags of an existing
+/// subscriber only change when they confirm a list they were not on yet.
+#[utoipa::path(
+    context_path = "/api/v1",
+    request_body(content = NewSubscriber, description = "Subscriber to add", content_type = "application/json"),
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Repeating a key returns the response saved the first time"),
+    ),
+    responses(
+        (status = 201, description = "Subscribed, pending confirmation unless they already confirmed", body = SubscriberSummary,
+            headers(("Location" = String, description = "URL of the subscriber"))),
+        (status = 400, description = "Invalid subscriber", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Forbidden", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "A request with the same idempotency key is being processed", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "The idempotency key was used for a different request", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Server error", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("api_token" = ["subscribers:write"])),
+    tag = "zero2prod"
+)]
+#[post("/subscribers")]
+#[tracing::instrument(
+    name = "Add a subscriber through the API",
+    skip(body, pool, email_client, base_url, hmac_secret)
+)]
+pub async fn create_subscriber(
+    body: web::Json<NewSubscriber>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+    client: web::ReqData<ApiClient>,
+) -> Result<HttpResponse, actix_web::Error> {
+    client.require(ApiScope::SubscribersWrite, Role::Editor)?;
+    let subscription_request = SubscriptionRequest::try_from(body.0)?;
+
+    let subscriber_id = add_subscriber(
+        &pool,
+        &email_client,
+        &base_url.0,
+        &hmac_secret.0,
+        subscription_request,
+    )
+    .await?;
+    let subscriber = fetch_subscriber(&pool, subscriber_id)
+        .await
+        .and_then(|s| s.context("The new subscriber is missing."))
+        .map_err(e500)?;
+
+    Ok(HttpResponse::Created()
+        .insert_header(("Location", format!("/api/v1/subscribers/{}", subscriber_id)))
+        .json(subscriber))
+}
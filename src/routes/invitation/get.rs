@@ -0,0 +1,49 @@
+use actix_web::{get, http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages, Level};
+use askama::Template;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    invitations::get_invitation_email,
+    templates::InvitationTemplate,
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct InvitationParameters {
+    token: String,
+}
+
+#[get("/invitation")]
+pub async fn invitation_form(
+    parameters: web::Query<InvitationParameters>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(email) = get_invitation_email(&pool, &parameters.token)
+        .await
+        .map_err(e500)?
+    else {
+        FlashMessage::error("This invitation link is invalid or has expired.").send();
+        return Ok(see_other("/login"));
+    };
+
+    let mut error = String::new();
+
+    for m in flash_messages.iter().filter(|m| m.level() == Level::Error) {
+        writeln!(error, "{}", m.content()).unwrap();
+    }
+
+    let html = InvitationTemplate {
+        error: &error,
+        token: &parameters.token,
+        email: &email,
+    }
+    .render()
+    .expect("Could not render invitation template.");
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html))
+}
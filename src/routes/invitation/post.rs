@@ -0,0 +1,80 @@
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+
+use crate::{
+    authentication::{PasswordHashing, PasswordPolicy},
+    invitations::{accept_invitation, InvitationError},
+    session_state::TypedSession,
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct InvitationFormData {
+    token: String,
+    username: String,
+    password: Secret<String>,
+    password_check: Secret<String>,
+}
+
+#[post("/invitation")]
+#[tracing::instrument(
+    name = "Joining with an invitation",
+    skip(request, form, pool, session, password_policy, hashing)
+)]
+pub async fn join_with_invitation(
+    request: HttpRequest,
+    form: web::Form<InvitationFormData>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    password_policy: web::Data<PasswordPolicy>,
+    hashing: web::Data<PasswordHashing>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let form = form.into_inner();
+    let retry = format!("/invitation?token={}", urlencoding::encode(&form.token));
+
+    if form.password.expose_secret() != form.password_check.expose_secret() {
+        FlashMessage::error("The passwords did not match.").send();
+        return Ok(see_other(&retry));
+    }
+    let username = form.username.trim();
+    if username.is_empty() {
+        FlashMessage::error("The username cannot be empty.").send();
+        return Ok(see_other(&retry));
+    }
+    if let Err(e) = password_policy.check(username, &form.password) {
+        FlashMessage::error(e).send();
+        return Ok(see_other(&retry));
+    }
+
+    match accept_invitation(
+        &pool,
+        &request,
+        &hashing,
+        &form.token,
+        username,
+        form.password,
+    )
+    .await
+    {
+        Ok(_) => {
+            session.renew();
+            FlashMessage::info("Your account has been created. Please log in.").send();
+            Ok(see_other("/login"))
+        }
+        Err(InvitationError::InvalidToken) => {
+            FlashMessage::error(InvitationError::InvalidToken.to_string()).send();
+            Ok(see_other("/login"))
+        }
+        Err(e @ InvitationError::UsernameTaken(_)) => {
+            FlashMessage::error(e.to_string()).send();
+            Ok(see_other(&retry))
+        }
+        Err(e @ InvitationError::AlreadyRegistered(_)) => {
+            FlashMessage::error(e.to_string()).send();
+            Ok(see_other("/login"))
+        }
+        Err(e) => Err(e500(e)),
+    }
+}
@@ -1,12 +1,18 @@
-use actix_web::{get, http::header::ContentType, HttpResponse};
+use actix_web::{get, http::header::ContentType, web, HttpResponse};
 use actix_web_flash_messages::{IncomingFlashMessages, Level};
 use askama::Template;
 use std::fmt::Write;
 
+use crate::authentication::OidcClient;
+use crate::configuration::SessionSettings;
 use crate::templates::LoginTemplate;
 
 #[get("/login")]
-pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+pub async fn login_form(
+    flash_messages: IncomingFlashMessages,
+    oidc: Option<web::Data<OidcClient>>,
+    session_settings: web::Data<SessionSettings>,
+) -> HttpResponse {
     let mut error = String::new();
 
     for m in flash_messages.iter().filter(|m| m.level() == Level::Error) {
@@ -22,6 +28,8 @@ pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
     let html = LoginTemplate {
         error: &error,
         info: &info,
+        sso_provider: oidc.as_ref().map(|oidc| oidc.provider_name()),
+        remember_me_days: session_settings.remember_me_days,
     }
     .render()
     .expect("Could not render login template.");
@@ -1,12 +1,20 @@
 use actix_web::error::InternalError;
-use actix_web::http::header::LOCATION;
-use actix_web::{post, web, HttpResponse};
+use actix_web::http::header::{LOCATION, RETRY_AFTER};
+use actix_web::http::StatusCode;
+use actix_web::{post, web, HttpRequest, HttpResponse, ResponseError};
 use actix_web_flash_messages::FlashMessage;
 use secrecy::Secret;
 use sqlx::PgPool;
 use utoipa::ToSchema;
 
-use crate::authentication::{validate_credentials, AuthError, Credentials};
+use crate::audit::{record_event, AuditAction};
+use crate::authentication::{
+    get_session_epoch, get_totp_secret, start_session, validate_credentials, AuthError,
+    Credentials, FailureOutcome, LoginAllowance, LoginThrottle, PasswordHashing,
+};
+use crate::configuration::SessionSettings;
+use crate::email_client::EmailClient;
+use crate::problem::{preferred_format, PreferredFormat, Problem};
 use crate::routes::error_chain_fmt;
 use crate::session_state::TypedSession;
 use crate::utils::see_other;
@@ -17,50 +25,129 @@ pub struct LoginFormData {
 
     #[schema(value_type = String)]
     password: Secret<String>,
+
+    /// Keep the session for longer, when the server allows it.
+    #[serde(default)]
+    remember_me: bool,
 }
 
 #[utoipa::path(
     request_body(content=LoginFormData, description="Login", content_type="application/x-www-form-urlencoded"),
     responses(
-        (status = 303, description = "Login redirect"),
-        (status = 401, description = "Unauthorized"),
-        (status = 400, description = "Bad request"),
-        (status = 500, description = "Server error"),
+        (status = 303, description = "Login redirect, to the second factor if it is enabled, or back to the login page with an error message"),
+        (status = 400, description = "Bad request", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid credentials, for clients accepting JSON", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 429, description = "Too many failed attempts, for clients accepting JSON", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Server error", body = ProblemDetails, content_type = "application/problem+json"),
     ),
     tag = "zero2prod"
 )]
 #[post("/login")]
 #[tracing::instrument(
-    skip(form, pool, session),
+    skip(request, form, pool, email_client, throttle, session, hashing, session_settings),
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn login(
+    request: HttpRequest,
     form: web::Form<LoginFormData>,
     pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    throttle: web::Data<LoginThrottle>,
     session: TypedSession,
+    hashing: web::Data<PasswordHashing>,
+    session_settings: web::Data<SessionSettings>,
 ) -> Result<HttpResponse, InternalError<LoginError>> {
+    // Clients asking for JSON get the error, browsers a flash message.
+    let wants_json = preferred_format(&request) == Some(PreferredFormat::Json);
+    let fail = |e: LoginError| login_failure(e, wants_json);
+    let remember_me = form.remember_me;
     let credentials = Credentials {
         username: form.0.username,
         password: form.0.password,
     };
     tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
+    let username = credentials.username.clone();
+    // The peer address, as forwarding headers can be set by the client.
+    let ip = request.peer_addr().map(|addr| addr.ip());
+
+    match throttle
+        .check(&username, ip)
+        .await
+        .map_err(|e| fail(LoginError::UnexpectedError(e)))?
+    {
+        LoginAllowance::LockedOut { retry_after } => {
+            return Err(fail(LoginError::LockedOut(retry_after)));
+        }
+        LoginAllowance::Allowed { delay } => tokio::time::sleep(delay).await,
+    }
 
-    match validate_credentials(credentials, &pool).await {
+    match validate_credentials(credentials, &hashing, &pool).await {
         Ok(user_id) => {
             tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
-            session.renew();
+            let totp_secret = get_totp_secret(user_id, &pool)
+                .await
+                .map_err(|e| fail(LoginError::UnexpectedError(e)))?;
+            let session_epoch = get_session_epoch(user_id, &pool)
+                .await
+                .map_err(|e| fail(LoginError::UnexpectedError(e)))?
+                .unwrap_or_default();
+            if totp_secret.is_some() {
+                // Until the code is checked the session must not hold a user id.
+                session.renew();
+                session.remove_user_id();
+                session
+                    .insert_pending_user_id(user_id, session_epoch, remember_me)
+                    .map_err(|e| fail(LoginError::UnexpectedError(e.into())))?;
+                // The failures are only reset once the code is right too.
+                return Ok(see_other("/login/2fa"));
+            }
+            throttle
+                .record_success(&username)
+                .await
+                .map_err(|e| fail(LoginError::UnexpectedError(e)))?;
+            let session_id =
+                start_session(user_id, remember_me, &request, &session_settings, &pool)
+                    .await
+                    .map_err(|e| fail(LoginError::UnexpectedError(e)))?;
+            record_event(
+                pool.get_ref(),
+                &request,
+                Some(user_id),
+                AuditAction::Login,
+                &username,
+                serde_json::json!({}),
+            )
+            .await
+            .map_err(|e| fail(LoginError::UnexpectedError(e)))?;
             session
-                .insert_user_id(user_id)
-                .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+                .log_in(user_id, session_epoch, session_id, remember_me)
+                .map_err(|e| fail(LoginError::UnexpectedError(e.into())))?;
             Ok(see_other("/admin/dashboard"))
         }
-        Err(e) => {
-            let e = match e {
-                AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
-                AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
-            };
-            Err(login_redirect(e))
+        Err(AuthError::InvalidCredentials(e)) => {
+            record_event(
+                pool.get_ref(),
+                &request,
+                None,
+                AuditAction::FailedLogin,
+                &username,
+                serde_json::json!({}),
+            )
+            .await
+            .map_err(|e| fail(LoginError::UnexpectedError(e)))?;
+            let outcome = throttle
+                .record_failure_and_alert(&pool, &email_client, &username, ip)
+                .await
+                .map_err(|e| fail(LoginError::UnexpectedError(e)))?;
+            match outcome {
+                FailureOutcome::Counted => Err(fail(LoginError::AuthError(e))),
+                FailureOutcome::AccountLocked | FailureOutcome::AddressLocked => {
+                    Err(fail(LoginError::LockedOut(throttle.lockout_duration())))
+                }
+            }
         }
+        Err(e) => Err(fail(LoginError::UnexpectedError(e.into()))),
     }
 }
 
@@ -68,6 +155,11 @@ pub async fn login(
 pub enum LoginError {
     #[error("Invalid username or password")]
     AuthError(#[source] anyhow::Error),
+    #[error(
+        "Too many failed login attempts. Try again in {} minutes.",
+        .0.as_secs().div_ceil(60)
+    )]
+    LockedOut(std::time::Duration),
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -78,8 +170,41 @@ impl std::fmt::Debug for LoginError {
     }
 }
 
-// Redirect to the login page with an error message.
-fn login_redirect(e: LoginError) -> InternalError<LoginError> {
+impl From<&LoginError> for Problem {
+    fn from(e: &LoginError) -> Self {
+        match e {
+            LoginError::AuthError(_) => Problem::new(
+                StatusCode::UNAUTHORIZED,
+                "invalid_credentials",
+                e.to_string(),
+            ),
+            LoginError::LockedOut(retry_after) => Problem::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                "too_many_login_attempts",
+                e.to_string(),
+            )
+            .with_header(RETRY_AFTER, retry_after.as_secs()),
+            LoginError::UnexpectedError(e) => Problem::internal(e),
+        }
+    }
+}
+
+impl ResponseError for LoginError {
+    fn status_code(&self) -> StatusCode {
+        Problem::from(self).status_code()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        Problem::from(self).error_response()
+    }
+}
+
+// Answer with the error, or redirect to the login page with an error message.
+fn login_failure(e: LoginError, wants_json: bool) -> InternalError<LoginError> {
+    if wants_json {
+        let response = e.error_response();
+        return InternalError::from_response(e, response);
+    }
     FlashMessage::error(e.to_string()).send();
     let response = HttpResponse::SeeOther()
         .insert_header((LOCATION, "/login"))
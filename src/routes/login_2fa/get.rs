@@ -0,0 +1,34 @@
+use actix_web::{get, http::header::ContentType, HttpResponse};
+use actix_web_flash_messages::{IncomingFlashMessages, Level};
+use askama::Template;
+use std::fmt::Write;
+
+use crate::{
+    session_state::TypedSession,
+    templates::LoginTwoFactorTemplate,
+    utils::{e500, see_other},
+};
+
+#[get("/login/2fa")]
+pub async fn login_two_factor_form(
+    flash_messages: IncomingFlashMessages,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_pending_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+
+    let mut error = String::new();
+
+    for m in flash_messages.iter().filter(|m| m.level() == Level::Error) {
+        writeln!(error, "{}", m.content()).unwrap();
+    }
+
+    let html = LoginTwoFactorTemplate { error: &error }
+        .render()
+        .expect("Could not render two-factor login template.");
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html))
+}
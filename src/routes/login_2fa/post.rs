@@ -0,0 +1,122 @@
+use std::time::Duration;
+
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+use crate::{
+    audit::{record_event, AuditAction},
+    authentication::{
+        get_session_epoch, start_session, verify_second_factor, FailureOutcome, LoginAllowance,
+        LoginThrottle,
+    },
+    configuration::SessionSettings,
+    email_client::EmailClient,
+    routes::{get_username, LoginError},
+    session_state::TypedSession,
+    utils::{e500, see_other},
+};
+
+/// Wrong codes allowed before the password has to be entered again.
+const MAX_FAILED_CODE_ATTEMPTS: u32 = 5;
+
+#[derive(serde::Deserialize, ToSchema)]
+pub struct TwoFactorFormData {
+    /// A code from the authenticator app, or an unused recovery code.
+    code: String,
+}
+
+#[utoipa::path(
+    request_body(content=TwoFactorFormData, description="Second login step", content_type="application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "Login redirect"),
+        (status = 400, description = "Bad request"),
+        (status = 500, description = "Server error"),
+    ),
+    tag = "zero2prod"
+)]
+#[post("/login/2fa")]
+#[tracing::instrument(
+    skip(request, form, pool, email_client, throttle, session, session_settings),
+    fields(user_id=tracing::field::Empty)
+)]
+pub async fn login_two_factor(
+    request: HttpRequest,
+    form: web::Form<TwoFactorFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    throttle: web::Data<LoginThrottle>,
+    session: TypedSession,
+    session_settings: web::Data<SessionSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(user_id) = session.get_pending_user_id().map_err(e500)? else {
+        return Ok(see_other("/login"));
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    let session_epoch = session.get_session_epoch().map_err(e500)?;
+    if get_session_epoch(user_id, &pool).await.map_err(e500)? != Some(session_epoch) {
+        // The user's sessions were revoked since the password was checked.
+        session.remove_pending_user_id();
+        return Ok(see_other("/login"));
+    }
+
+    // Wrong codes count towards the lockout of the account like wrong
+    // passwords, or knowing the password would allow unlimited guesses.
+    let username = get_username(user_id, &pool).await.map_err(e500)?;
+    let ip = request.peer_addr().map(|addr| addr.ip());
+    match throttle.check(&username, ip).await.map_err(e500)? {
+        LoginAllowance::LockedOut { retry_after } => {
+            return Ok(locked_out(&session, retry_after));
+        }
+        LoginAllowance::Allowed { delay } => tokio::time::sleep(delay).await,
+    }
+
+    if verify_second_factor(user_id, &form.code, &pool)
+        .await
+        .map_err(e500)?
+    {
+        throttle.record_success(&username).await.map_err(e500)?;
+        let remember_me = session.get_pending_remember_me().map_err(e500)?;
+        let session_id = start_session(user_id, remember_me, &request, &session_settings, &pool)
+            .await
+            .map_err(e500)?;
+        record_event(
+            pool.get_ref(),
+            &request,
+            Some(user_id),
+            AuditAction::Login,
+            &username,
+            serde_json::json!({}),
+        )
+        .await
+        .map_err(e500)?;
+        session
+            .log_in(user_id, session_epoch, session_id, remember_me)
+            .map_err(e500)?;
+        return Ok(see_other("/admin/dashboard"));
+    }
+
+    let outcome = throttle
+        .record_failure_and_alert(&pool, &email_client, &username, ip)
+        .await
+        .map_err(e500)?;
+    if outcome != FailureOutcome::Counted {
+        return Ok(locked_out(&session, throttle.lockout_duration()));
+    }
+    if session.record_failed_code_attempt().map_err(e500)? >= MAX_FAILED_CODE_ATTEMPTS {
+        session.remove_pending_user_id();
+        FlashMessage::error("Too many invalid authentication codes. Please log in again.").send();
+        return Ok(see_other("/login"));
+    }
+    FlashMessage::error("Invalid authentication code.").send();
+    Ok(see_other("/login/2fa"))
+}
+
+/// Back to the password, which is refused until the lockout is over.
+fn locked_out(session: &TypedSession, retry_after: Duration) -> HttpResponse {
+    session.remove_pending_user_id();
+    FlashMessage::error(LoginError::LockedOut(retry_after).to_string()).send();
+    see_other("/login")
+}
@@ -0,0 +1,67 @@
+use actix_web::{get, http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages, Level};
+use askama::Template;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    login_link::{get_login_link_user, verify_signature},
+    startup::HmacSecret,
+    templates::{LoginLinkRequestTemplate, LoginLinkTemplate},
+    utils::{e500, see_other},
+};
+
+#[get("/login/link/request")]
+pub async fn login_link_request_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+    let mut error = String::new();
+
+    for m in flash_messages.iter().filter(|m| m.level() == Level::Error) {
+        writeln!(error, "{}", m.content()).unwrap();
+    }
+
+    let html = LoginLinkRequestTemplate { error: &error }
+        .render()
+        .expect("Could not render sign-in link request template.");
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html)
+}
+
+#[derive(serde::Deserialize)]
+pub struct LoginLinkParameters {
+    token: String,
+    signature: String,
+}
+
+/// Following the link only asks for confirmation, see `LoginLinkTemplate`.
+#[get("/login/link")]
+pub async fn login_link_form(
+    parameters: web::Query<LoginLinkParameters>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user = if verify_signature(&hmac_secret.0, &parameters.token, &parameters.signature) {
+        get_login_link_user(&pool, &parameters.token)
+            .await
+            .map_err(e500)?
+    } else {
+        None
+    };
+    let Some(user) = user else {
+        FlashMessage::error("This sign-in link is invalid or has expired.").send();
+        return Ok(see_other("/login"));
+    };
+
+    let html = LoginLinkTemplate {
+        username: &user.username,
+        token: &parameters.token,
+        signature: &parameters.signature,
+    }
+    .render()
+    .expect("Could not render sign-in link template.");
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html))
+}
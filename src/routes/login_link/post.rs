@@ -0,0 +1,173 @@
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use tracing::Instrument;
+
+use crate::{
+    audit::{record_event, AuditAction},
+    authentication::{
+        get_session_epoch, get_totp_secret, start_session, LoginAllowance, LoginThrottle,
+    },
+    configuration::SessionSettings,
+    domain::SubscriberEmail,
+    email_client::EmailClient,
+    login_link::{get_login_link_user, request_login_link, use_login_link, verify_signature},
+    routes::LoginError,
+    session_state::TypedSession,
+    startup::{ApplicationBaseUrl, HmacSecret},
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct LoginLinkRequestFormData {
+    email: String,
+}
+
+/// Whether or not an account uses the address, the answer is the same and
+/// comes as fast: the link is looked up and mailed in the background.
+#[post("/login/link/request")]
+#[tracing::instrument(
+    name = "Requesting a sign-in link",
+    skip(request, form, pool, email_client, throttle, base_url, hmac_secret)
+)]
+pub async fn request_login_link_email(
+    request: HttpRequest,
+    form: web::Form<LoginLinkRequestFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    throttle: web::Data<LoginThrottle>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = match SubscriberEmail::parse(form.0.email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/login/link/request"));
+        }
+    };
+
+    // The peer address, as forwarding headers can be set by the client.
+    let ip = request.peer_addr().map(|addr| addr.ip());
+    if !throttle
+        .allow_link_request(email.as_ref(), ip)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error("Too many sign-in links were requested. Please try again later.")
+            .send();
+        return Ok(see_other("/login/link/request"));
+    }
+
+    let message = format!(
+        "If an account uses {}, a sign-in link has been sent to it.",
+        email.as_ref()
+    );
+    let pool = pool.get_ref().clone();
+    tokio::spawn(
+        async move {
+            if let Err(e) =
+                request_login_link(&pool, &email_client, &base_url.0, &hmac_secret.0, &email).await
+            {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to send a sign-in link."
+                );
+            }
+        }
+        .in_current_span(),
+    );
+    FlashMessage::info(message).send();
+    Ok(see_other("/login"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct LoginLinkFormData {
+    token: String,
+    signature: String,
+}
+
+/// Use up the link and log in, going through the second factor if the user
+/// enabled it. A locked out account cannot sign in with a link either.
+#[post("/login/link")]
+#[tracing::instrument(
+    name = "Logging in with a sign-in link",
+    skip(request, form, pool, throttle, hmac_secret, session, session_settings),
+    fields(user_id=tracing::field::Empty)
+)]
+pub async fn login_with_link(
+    request: HttpRequest,
+    form: web::Form<LoginLinkFormData>,
+    pool: web::Data<PgPool>,
+    throttle: web::Data<LoginThrottle>,
+    hmac_secret: web::Data<HmacSecret>,
+    session: TypedSession,
+    session_settings: web::Data<SessionSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user = if verify_signature(&hmac_secret.0, &form.token, &form.signature) {
+        get_login_link_user(&pool, &form.token)
+            .await
+            .map_err(e500)?
+    } else {
+        None
+    };
+    let Some(user) = user else {
+        FlashMessage::error("This sign-in link is invalid or has expired.").send();
+        return Ok(see_other("/login"));
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user.user_id));
+
+    // The peer address, as forwarding headers can be set by the client.
+    let ip = request.peer_addr().map(|addr| addr.ip());
+    if let LoginAllowance::LockedOut { retry_after } =
+        throttle.check(&user.username, ip).await.map_err(e500)?
+    {
+        FlashMessage::error(LoginError::LockedOut(retry_after).to_string()).send();
+        return Ok(see_other("/login"));
+    }
+
+    if !use_login_link(&pool, &form.token, user.user_id)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error("This sign-in link is invalid or has expired.").send();
+        return Ok(see_other("/login"));
+    }
+
+    let session_epoch = get_session_epoch(user.user_id, &pool)
+        .await
+        .map_err(e500)?
+        .unwrap_or_default();
+    if get_totp_secret(user.user_id, &pool)
+        .await
+        .map_err(e500)?
+        .is_some()
+    {
+        // Until the code is checked the session must not hold a user id.
+        session.renew();
+        session.remove_user_id();
+        session
+            .insert_pending_user_id(user.user_id, session_epoch, false)
+            .map_err(e500)?;
+        return Ok(see_other("/login/2fa"));
+    }
+
+    let session_id = start_session(user.user_id, false, &request, &session_settings, &pool)
+        .await
+        .map_err(e500)?;
+    record_event(
+        pool.get_ref(),
+        &request,
+        Some(user.user_id),
+        AuditAction::Login,
+        &user.username,
+        serde_json::json!({ "method": "link" }),
+    )
+    .await
+    .map_err(e500)?;
+    session
+        .log_in(user.user_id, session_epoch, session_id, false)
+        .map_err(e500)?;
+    Ok(see_other("/admin/dashboard"))
+}
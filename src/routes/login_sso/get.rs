@@ -0,0 +1,127 @@
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+use crate::{
+    audit::{change, record_event, AuditAction},
+    authentication::{get_session_epoch, start_session, OidcClient, PasswordHashing, SsoError},
+    configuration::SessionSettings,
+    session_state::TypedSession,
+    utils::{e500, see_other},
+};
+
+/// Send the user to the identity provider.
+#[get("/login/sso")]
+#[tracing::instrument(name = "Start single sign-on", skip(oidc, session))]
+pub async fn start_sso_login(
+    oidc: Option<web::Data<OidcClient>>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(oidc) = oidc else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let (url, pending) = oidc.authorization_url().await.map_err(e500)?;
+    session.insert_pending_sso_login(&pending).map_err(e500)?;
+    Ok(see_other(&url))
+}
+
+#[derive(serde::Deserialize)]
+pub struct SsoCallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    /// Set instead of the code when the provider refused the request.
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+/// Where the identity provider sends the user back. The provider's own
+/// second factor stands in for ours, so the user is logged in right away.
+#[get("/login/sso/callback")]
+#[tracing::instrument(
+    name = "Complete single sign-on",
+    skip(request, query, oidc, pool, hashing, session, session_settings),
+    fields(user_id=tracing::field::Empty)
+)]
+pub async fn sso_callback(
+    request: HttpRequest,
+    query: web::Query<SsoCallbackQuery>,
+    oidc: Option<web::Data<OidcClient>>,
+    pool: web::Data<PgPool>,
+    hashing: web::Data<PasswordHashing>,
+    session: TypedSession,
+    session_settings: web::Data<SessionSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(oidc) = oidc else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let query = query.into_inner();
+    let pending = session.take_pending_sso_login().map_err(e500)?;
+
+    let result = match (pending, query.error, query.state, query.code) {
+        (_, Some(error), _, _) => Err(SsoError::Refused(query.error_description.unwrap_or(error))),
+        (Some(pending), None, Some(state), Some(code)) => {
+            match oidc.exchange_code(pending, &state, &code).await {
+                Ok(identity) => oidc.sign_in(&identity, &pool, &hashing).await,
+                Err(e) => Err(e),
+            }
+        }
+        _ => Err(SsoError::InvalidState),
+    };
+    let account = match result {
+        Ok(account) => account,
+        Err(e @ SsoError::UnexpectedError(_)) => return Err(e500(e)),
+        Err(e) => {
+            tracing::warn!(error.cause_chain = ?e, "Single sign-on refused.");
+            FlashMessage::error(e.to_string()).send();
+            return Ok(see_other("/login"));
+        }
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&account.user_id));
+
+    if account.provisioned {
+        record_event(
+            pool.get_ref(),
+            &request,
+            Some(account.user_id),
+            AuditAction::ProvisionUser,
+            &account.username,
+            serde_json::json!({ "role": account.role.as_str() }),
+        )
+        .await
+        .map_err(e500)?;
+    }
+    if let Some(previous_role) = &account.previous_role {
+        record_event(
+            pool.get_ref(),
+            &request,
+            Some(account.user_id),
+            AuditAction::ChangeRole,
+            &account.username,
+            serde_json::json!({ "role": change(previous_role, account.role.as_str()) }),
+        )
+        .await
+        .map_err(e500)?;
+    }
+
+    let session_epoch = get_session_epoch(account.user_id, &pool)
+        .await
+        .map_err(e500)?
+        .unwrap_or_default();
+    let session_id = start_session(account.user_id, false, &request, &session_settings, &pool)
+        .await
+        .map_err(e500)?;
+    record_event(
+        pool.get_ref(),
+        &request,
+        Some(account.user_id),
+        AuditAction::Login,
+        &account.username,
+        serde_json::json!({ "method": "sso" }),
+    )
+    .await
+    .map_err(e500)?;
+    session
+        .log_in(account.user_id, session_epoch, session_id, false)
+        .map_err(e500)?;
+    Ok(see_other("/admin/dashboard"))
+}
@@ -1,17 +1,33 @@
 //! src/routes/mod.rs
 
 mod admin;
+mod api;
 mod health_check;
 mod home;
+mod invitation;
 mod login;
+mod login_2fa;
+mod login_link;
+mod login_sso;
+mod password_reset;
 mod signup;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_confirm_email;
+mod subscriptions_manage;
 
 pub use admin::*;
+pub use api::*;
 pub use health_check::*;
 pub use home::*;
+pub use invitation::*;
 pub use login::*;
+pub use login_2fa::*;
+pub use login_link::*;
+pub use login_sso::*;
+pub use password_reset::*;
 pub use signup::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_confirm_email::*;
+pub use subscriptions_manage::*;
@@ -0,0 +1,65 @@
+use actix_web::{get, http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages, Level};
+use askama::Template;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    password_reset::is_valid_reset_token,
+    templates::{ForgotPasswordTemplate, ResetPasswordTemplate},
+    utils::{e500, see_other},
+};
+
+#[get("/login/forgot_password")]
+pub async fn forgot_password_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+    let mut error = String::new();
+
+    for m in flash_messages.iter().filter(|m| m.level() == Level::Error) {
+        writeln!(error, "{}", m.content()).unwrap();
+    }
+
+    let html = ForgotPasswordTemplate { error: &error }
+        .render()
+        .expect("Could not render forgot password template.");
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html)
+}
+
+#[derive(serde::Deserialize)]
+pub struct ResetPasswordParameters {
+    token: String,
+}
+
+#[get("/login/reset_password")]
+pub async fn reset_password_form(
+    parameters: web::Query<ResetPasswordParameters>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !is_valid_reset_token(&pool, &parameters.token)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error("This password reset link is invalid or has expired.").send();
+        return Ok(see_other("/login/forgot_password"));
+    }
+
+    let mut error = String::new();
+
+    for m in flash_messages.iter().filter(|m| m.level() == Level::Error) {
+        writeln!(error, "{}", m.content()).unwrap();
+    }
+
+    let html = ResetPasswordTemplate {
+        error: &error,
+        token: &parameters.token,
+    }
+    .render()
+    .expect("Could not render reset password template.");
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html))
+}
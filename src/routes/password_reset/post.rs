@@ -0,0 +1,148 @@
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use tracing::Instrument;
+
+use crate::{
+    audit::{record_event, AuditAction},
+    authentication::{LoginThrottle, PasswordHashing, PasswordPolicy},
+    domain::SubscriberEmail,
+    email_client::EmailClient,
+    password_reset::{get_reset_token_username, request_password_reset, reset_password},
+    session_state::TypedSession,
+    startup::ApplicationBaseUrl,
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct ForgotPasswordFormData {
+    email: String,
+}
+
+/// Whether or not an account uses the address, the answer is the same and
+/// comes as fast: the user is looked up and mailed in the background.
+#[post("/login/forgot_password")]
+#[tracing::instrument(
+    name = "Requesting a password reset",
+    skip(request, form, pool, email_client, throttle, base_url)
+)]
+pub async fn forgot_password(
+    request: HttpRequest,
+    form: web::Form<ForgotPasswordFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    throttle: web::Data<LoginThrottle>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = match SubscriberEmail::parse(form.0.email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/login/forgot_password"));
+        }
+    };
+
+    // The peer address, as forwarding headers can be set by the client.
+    let ip = request.peer_addr().map(|addr| addr.ip());
+    if !throttle
+        .allow_link_request(email.as_ref(), ip)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error(
+            "Too many password reset links were requested. Please try again later.",
+        )
+        .send();
+        return Ok(see_other("/login/forgot_password"));
+    }
+
+    let message = format!(
+        "If an account uses {}, a password reset link has been sent to it.",
+        email.as_ref()
+    );
+    let pool = pool.get_ref().clone();
+    tokio::spawn(
+        async move {
+            if let Err(e) = request_password_reset(&pool, &email_client, &base_url.0, &email).await
+            {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to send a password reset link."
+                );
+            }
+        }
+        .in_current_span(),
+    );
+    FlashMessage::info(message).send();
+    Ok(see_other("/login"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct ResetPasswordFormData {
+    token: String,
+    new_password: Secret<String>,
+    new_password_check: Secret<String>,
+}
+
+#[post("/login/reset_password")]
+#[tracing::instrument(
+    name = "Resetting a password",
+    skip(request, form, pool, session, password_policy, hashing)
+)]
+pub async fn reset_password_with_token(
+    request: HttpRequest,
+    form: web::Form<ResetPasswordFormData>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    password_policy: web::Data<PasswordPolicy>,
+    hashing: web::Data<PasswordHashing>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let form = form.into_inner();
+    let retry = format!(
+        "/login/reset_password?token={}",
+        urlencoding::encode(&form.token)
+    );
+
+    if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
+        FlashMessage::error("The new passwords did not match.").send();
+        return Ok(see_other(&retry));
+    }
+    let Some(username) = get_reset_token_username(&pool, &form.token)
+        .await
+        .map_err(e500)?
+    else {
+        FlashMessage::error("This password reset link is invalid or has expired.").send();
+        return Ok(see_other("/login/forgot_password"));
+    };
+    if let Err(e) = password_policy.check(&username, &form.new_password) {
+        FlashMessage::error(e).send();
+        return Ok(see_other(&retry));
+    }
+
+    match reset_password(&pool, &hashing, &form.token, form.new_password)
+        .await
+        .map_err(e500)?
+    {
+        Some(user_id) => {
+            record_event(
+                pool.get_ref(),
+                &request,
+                Some(user_id),
+                AuditAction::ResetPassword,
+                &username,
+                serde_json::json!({}),
+            )
+            .await
+            .map_err(e500)?;
+            session.log_out();
+            FlashMessage::info("Your password has been reset. Please log in.").send();
+            Ok(see_other("/login"))
+        }
+        None => {
+            FlashMessage::error("This password reset link is invalid or has expired.").send();
+            Ok(see_other("/login/forgot_password"))
+        }
+    }
+}
@@ -6,8 +6,9 @@ use sqlx::PgPool;
 use utoipa::ToSchema;
 use uuid::Uuid;
 
-use crate::authentication::{compute_password_hash, Credentials};
+use crate::authentication::{compute_password_hash, Credentials, PasswordHashing, PasswordPolicy};
 use crate::configuration::SignupSettings;
+use crate::domain::SubscriberEmail;
 use crate::session_state::TypedSession;
 use crate::telemetry::spawn_blocking_with_tracing;
 use crate::utils::see_other;
@@ -19,6 +20,10 @@ pub struct SignupFormData {
 
     username: String,
 
+    /// Where password reset links are sent.
+    #[serde(default)]
+    email: String,
+
     #[schema(value_type = String)]
     password: Secret<String>,
 }
@@ -34,31 +39,61 @@ pub struct SignupFormData {
     tag = "zero2prod"
 )]
 #[post("/signup")]
-#[tracing::instrument(skip(form, pool, session, signup_settings))]
+#[tracing::instrument(skip(form, pool, session, signup_settings, password_policy, hashing))]
 pub async fn signup(
     form: web::Form<SignupFormData>,
     pool: web::Data<PgPool>,
     session: TypedSession,
     signup_settings: web::Data<SignupSettings>,
+    password_policy: web::Data<PasswordPolicy>,
+    hashing: web::Data<PasswordHashing>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    if !signup_settings.enabled {
+    let Some(signup_token) = signup_settings
+        .token
+        .as_ref()
+        .filter(|_| signup_settings.enabled)
+    else {
         tracing::warn!("Signups disabled.");
         FlashMessage::error("Signups are disabled").send();
         return Ok(see_other("/signup"));
-    }
+    };
 
-    if signup_settings.token.expose_secret() != form.0.signup_token.expose_secret() {
+    if signup_token.expose_secret() != form.0.signup_token.expose_secret() {
         tracing::warn!("Signup token did not match");
         FlashMessage::error("Invalid signup token").send();
         return Ok(see_other("/signup"));
     }
 
+    let email = if form.0.email.trim().is_empty() {
+        None
+    } else {
+        match SubscriberEmail::parse(form.0.email) {
+            Ok(email) => Some(email),
+            Err(e) => {
+                FlashMessage::error(e).send();
+                return Ok(see_other("/signup"));
+            }
+        }
+    };
+
     let credentials = Credentials {
         username: form.0.username,
         password: form.0.password,
     };
+    if let Err(e) = password_policy.check(&credentials.username, &credentials.password) {
+        FlashMessage::error(e).send();
+        return Ok(see_other("/signup"));
+    }
 
-    match insert_user(credentials.username, credentials.password, &pool).await {
+    match insert_user(
+        credentials.username,
+        email,
+        credentials.password,
+        &hashing,
+        &pool,
+    )
+    .await
+    {
         Ok(_) => {
             session.renew();
             FlashMessage::info("Successful signup!").send();
@@ -71,26 +106,32 @@ pub async fn signup(
     }
 }
 
-#[tracing::instrument(name = "Insert user details in DB", skip(pool, password))]
+#[tracing::instrument(name = "Insert user details in DB", skip(password, hashing, pool))]
 async fn insert_user(
     username: String,
+    email: Option<SubscriberEmail>,
     password: Secret<String>,
+    hashing: &PasswordHashing,
     pool: &PgPool,
 ) -> anyhow::Result<()> {
-    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
-        .await?
-        .context("Failed to hash password")?;
+    let hashing = hashing.clone();
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password, &hashing))
+            .await?
+            .context("Failed to hash password")?;
 
     sqlx::query!(
         r#"
-        INSERT INTO users (user_id, username, password)
+        INSERT INTO users (user_id, username, email, password)
         VALUES (
             $1,
             $2,
-            $3
+            $3,
+            $4
         )"#,
         Uuid::new_v4(),
         username,
+        email.as_ref().map(|e| e.as_ref()),
         password_hash.expose_secret()
     )
     .execute(pool)
@@ -7,24 +7,46 @@ use anyhow::Context;
 use sqlx::{PgPool, Postgres, Transaction};
 
 use crate::{
-    domain::{SubscriberEmail, SubscriberName, SubscriptionRequest},
+    domain::{ListSlug, SubscriberEmail, SubscriberName, SubscriberTag, SubscriptionRequest},
     email_client::EmailClient,
-    startup::ApplicationBaseUrl,
+    lists::{get_list_by_slug, NewsletterList, DEFAULT_LIST_SLUG},
+    manage_link::{append_manage_link, manage_link},
+    problem::Problem,
+    signing::HmacKeys,
+    startup::{ApplicationBaseUrl, HmacSecret},
 };
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
     email: String,
     name: String,
+    #[serde(default)]
+    tags: String,
+    #[serde(default)]
+    list: String,
 }
 
 impl TryFrom<FormData> for SubscriptionRequest {
-    type Error = String;
+    type Error = SubscribeError;
 
     fn try_from(value: FormData) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(value.name)?;
-        let email = SubscriberEmail::parse(value.email)?;
-        Ok(Self { email, name })
+        let name = SubscriberName::parse(value.name).map_err(SubscribeError::invalid("name"))?;
+        let email =
+            SubscriberEmail::parse(value.email).map_err(SubscribeError::invalid("email"))?;
+        let tags =
+            SubscriberTag::parse_list(&value.tags).map_err(SubscribeError::invalid("tags"))?;
+        let list = match value.list.trim() {
+            "" => None,
+            slug => {
+                Some(ListSlug::parse(slug.to_string()).map_err(SubscribeError::invalid("list"))?)
+            }
+        };
+        Ok(Self {
+            email,
+            name,
+            tags,
+            list,
+        })
     }
 }
 
@@ -32,18 +54,19 @@ impl TryFrom<FormData> for SubscriptionRequest {
     request_body(content=SubscriptionRequest, description="Details for subscription", content_type="application/x-www-form-urlencoded"),
     responses(
         (status = 201, description = "Subscribed successfully"),
-        (status = 400, description = "Bad request"),
-        (status = 500, description = "Server error"),
+        (status = 400, description = "Invalid fields", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Server error", body = ProblemDetails, content_type = "application/problem+json"),
     ),
     tag = "zero2prod"
 )]
 #[post("/subscriptions")]
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, pool, email_client, base_url),
+    skip(form, pool, email_client, base_url, hmac_secret),
     fields(
         subscriber_email = %form.email,
-        subscriber_name = %form.name
+        subscriber_name = %form.name,
+        list = %form.list
     )
 )]
 async fn subscribe(
@@ -51,22 +74,83 @@ async fn subscribe(
     pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
 ) -> Result<HttpResponse, SubscribeError> {
-    let subcription_request = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    let subcription_request: SubscriptionRequest = form.0.try_into()?;
+    add_subscriber(
+        &pool,
+        &email_client,
+        &base_url.0,
+        &hmac_secret.0,
+        subcription_request,
+    )
+    .await?;
+    Ok(HttpResponse::Created().finish())
+}
 
+/// Subscribe to a list, for the subscribe form and the JSON API alike. A
+/// confirmation email is sent unless the subscriber already confirmed their
+/// membership of the list. Returns the id of the subscriber, who may already
+/// have existed.
+#[tracing::instrument(
+    name = "Add a subscriber",
+    skip(pool, email_client, base_url, hmac_keys, subcription_request)
+)]
+pub async fn add_subscriber(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    hmac_keys: &HmacKeys,
+    subcription_request: SubscriptionRequest,
+) -> Result<Uuid, SubscribeError> {
     let mut transaction = pool
         .begin()
         .await
         .context("Failed to acquire a Postgres connection from the pool")?;
 
-    let subscriber_id = insert_subscriber(&mut transaction, &subcription_request)
+    let list_slug = subcription_request
+        .list
+        .as_ref()
+        .map_or(DEFAULT_LIST_SLUG, |l| l.as_ref());
+    let list = get_list_by_slug(&mut transaction, list_slug)
+        .await?
+        .ok_or_else(|| {
+            SubscribeError::ValidationError("list", format!("{} is not a known list.", list_slug))
+        })?;
+
+    let (subscriber_id, created) = insert_subscriber(&mut transaction, &subcription_request)
         .await
         .context("Failed to insert new subscriber in the DB")?;
 
-    let subscription_token = generate_subscription_token();
-    store_token(&mut transaction, subscriber_id, &subscription_token)
+    let membership_status = insert_list_subscription(&mut transaction, list.list_id, subscriber_id)
         .await
-        .context("Failed to store confirmation token for new subscriber")?;
+        .context("Failed to add the subscriber to the list")?;
+    if membership_status == "confirmed" {
+        // Nothing left to confirm, do not send another email.
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to store new subscriber")?;
+        return Ok(subscriber_id);
+    }
+
+    // The tags of an existing subscriber change once the address owner
+    // confirms, not on the word of whoever submitted the form.
+    let pending_tags = if created {
+        Vec::new()
+    } else {
+        SubscriberTag::to_strings(&subcription_request.tags)
+    };
+    let subscription_token = generate_subscription_token();
+    store_token(
+        &mut transaction,
+        subscriber_id,
+        list.list_id,
+        &subscription_token,
+        &pending_tags,
+    )
+    .await
+    .context("Failed to store confirmation token for new subscriber")?;
 
     transaction
         .commit()
@@ -74,17 +158,22 @@ async fn subscribe(
         .context("Failed to commit SQL transaction to store new subscriber")?;
 
     send_confirmation_email(
-        &email_client,
+        email_client,
+        &list,
         subcription_request,
-        &base_url.0,
+        base_url,
         &subscription_token,
+        &manage_link(base_url, hmac_keys, subscriber_id),
     )
     .await
     .context("Failed to send confirmation email")?;
 
-    Ok(HttpResponse::Created().finish())
+    Ok(subscriber_id)
 }
 
+/// Store a new subscriber with the tags of the request. Returns the id of the
+/// subscriber, and whether it was created: existing subscribers are left
+/// untouched.
 #[tracing::instrument(
     name = "Saving new subscriber details in the database.",
     skip(transaction, req)
@@ -92,57 +181,104 @@ async fn subscribe(
 async fn insert_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
     req: &SubscriptionRequest,
-) -> Result<Uuid, sqlx::Error> {
-    let subscriber_id = Uuid::new_v4();
-
-    sqlx::query!(
+) -> Result<(Uuid, bool), sqlx::Error> {
+    let inserted = sqlx::query!(
         r#"
-        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-        VALUES ($1, $2, $3, $4, 'pending_confirmation')
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, tags)
+        VALUES ($1, $2, $3, $4, 'pending_confirmation', $5)
+        ON CONFLICT (email) DO NOTHING
+        RETURNING id
         "#,
-        subscriber_id,
+        Uuid::new_v4(),
         req.email.as_ref(),
         req.name.as_ref(),
+        Utc::now(),
+        &SubscriberTag::to_strings(&req.tags)
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    if let Some(row) = inserted {
+        return Ok((row.id, true));
+    }
+
+    let existing = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE email = $1"#,
+        req.email.as_ref(),
+    )
+    .fetch_one(transaction)
+    .await?;
+    Ok((existing.id, false))
+}
+
+/// Add the subscriber to a list, pending confirmation. Returns the status of
+/// the membership, which may already exist.
+#[tracing::instrument(name = "Saving list subscription in the database.", skip(transaction))]
+async fn insert_list_subscription(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<String, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+        VALUES ($1, $2, 'pending_confirmation', $3)
+        ON CONFLICT (list_id, subscriber_id) DO UPDATE
+        SET status = list_subscriptions.status
+        RETURNING status
+        "#,
+        list_id,
+        subscriber_id,
         Utc::now()
     )
-    .execute(transaction)
+    .fetch_one(transaction)
     .await?;
 
-    Ok(subscriber_id)
+    Ok(row.status)
 }
 
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
-    skip(email_client, new_subscriber)
+    skip(email_client, list, new_subscriber)
 )]
 async fn send_confirmation_email(
     email_client: &EmailClient,
+    list: &NewsletterList,
     new_subscriber: SubscriptionRequest,
     base_url: &str,
     subscription_token: &str,
+    manage_link: &str,
 ) -> Result<(), reqwest::Error> {
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url, subscription_token
     );
 
-    let html_body = &format!(
-        "Welcome to our newsletter! <br />\
+    let html_body = format!(
+        "{} <br />\
                 Click <a href=\"{}\">here</a> to confirm your subscriptions.",
+        htmlescape::encode_minimal(&list.confirmation_message),
         confirmation_link
     );
 
-    let plain_body = &format!(
-        "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
-        confirmation_link
+    let plain_body = format!(
+        "{}\nVisit {} to confirm your subscription.",
+        list.confirmation_message, confirmation_link
     );
 
+    let (html_body, plain_body) = append_manage_link(&html_body, &plain_body, manage_link);
+
     email_client
-        .send_mail(&new_subscriber.email, "Welcome!", html_body, plain_body)
+        .send_mail_as(
+            &list.sender(),
+            &new_subscriber.email,
+            &list.confirmation_subject,
+            &html_body,
+            &plain_body,
+        )
         .await
 }
 
-fn generate_subscription_token() -> String {
+pub fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
 
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
@@ -158,13 +294,17 @@ fn generate_subscription_token() -> String {
 pub async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
+    list_id: Uuid,
     subscription_token: &str,
+    tags: &[String],
 ) -> Result<(), StoreTokenError> {
     sqlx::query!(
-        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id)
-        VALUES ($1, $2)"#,
+        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id, list_id, tags)
+        VALUES ($1, $2, $3, $4)"#,
         subscription_token,
-        subscriber_id
+        subscriber_id,
+        list_id,
+        tags
     )
     .execute(transaction)
     .await
@@ -175,8 +315,9 @@ pub async fn store_token(
 
 #[derive(thiserror::Error)]
 pub enum SubscribeError {
-    #[error("{0}")]
-    ValidationError(String),
+    /// The field that is invalid, and why.
+    #[error("{1}")]
+    ValidationError(&'static str, String),
 
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
@@ -188,13 +329,35 @@ impl std::fmt::Debug for SubscribeError {
     }
 }
 
+impl SubscribeError {
+    /// Map a parsing error of `field` to a validation error.
+    pub fn invalid(field: &'static str) -> impl Fn(String) -> Self {
+        move |message| SubscribeError::ValidationError(field, message)
+    }
+}
+
+impl From<&SubscribeError> for Problem {
+    fn from(e: &SubscribeError) -> Self {
+        match e {
+            SubscribeError::ValidationError(field, message) => {
+                Problem::invalid_field(field, message.clone())
+            }
+            SubscribeError::UnexpectedError(e) => Problem::internal(e),
+        }
+    }
+}
+
 impl ResponseError for SubscribeError {
     fn status_code(&self) -> StatusCode {
         match self {
-            SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::ValidationError(..) => StatusCode::BAD_REQUEST,
             SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        Problem::from(self).error_response()
+    }
 }
 
 pub struct StoreTokenError(sqlx::Error);
@@ -1,9 +1,9 @@
-use actix_web::{get, web, HttpResponse};
+use actix_web::{get, http::StatusCode, web, HttpResponse};
 use sqlx::PgPool;
 use utoipa::IntoParams;
 use uuid::Uuid;
 
-use crate::utils::e500;
+use crate::{problem::Problem, utils::e500};
 
 #[derive(serde::Deserialize, IntoParams)]
 pub struct Parameters {
@@ -13,8 +13,9 @@ pub struct Parameters {
 #[utoipa::path(
     responses(
         (status = 200, description = "Subscription confirmed"),
-        (status = 400, description = "Bad request"),
-        (status = 500, description = "Server error"),
+        (status = 400, description = "Bad request", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Unknown subscription token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Server error", body = ProblemDetails, content_type = "application/problem+json"),
     ),
     params(Parameters),
     tag = "zero2prod"
@@ -25,47 +26,81 @@ async fn confirm(
     parameters: web::Query<Parameters>,
     pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let id = get_subscriber_id_from_token(&pool, &parameters.subscription_token)
+    let token = get_subscriber_id_from_token(&pool, &parameters.subscription_token)
         .await
         .map_err(e500)?;
 
-    match id {
-        Some(subscriber_id) => {
-            confirm_subscriber(&pool, subscriber_id)
-                .await
-                .map_err(e500)?;
+    match token {
+        Some(token) => {
+            confirm_subscriber(&pool, token).await.map_err(e500)?;
             Ok(HttpResponse::Ok().finish())
         }
-        _ => Ok(HttpResponse::Unauthorized().finish()),
+        _ => Err(Problem::new(
+            StatusCode::UNAUTHORIZED,
+            "invalid_subscription_token",
+            "The confirmation link is invalid or has expired.",
+        )
+        .into()),
     }
 }
 
 #[tracing::instrument(
     name = "Update subscriber confirmation details in DB",
-    skip(pool, subscriber_id)
+    skip(pool, token)
 )]
-async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
+async fn confirm_subscriber(pool: &PgPool, token: ConfirmationToken) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
     sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
-        subscriber_id
+        r#"UPDATE list_subscriptions SET status = 'confirmed'
+        WHERE list_id = $1 AND subscriber_id = $2"#,
+        token.list_id,
+        token.subscriber_id
     )
-    .execute(pool)
+    .execute(&mut transaction)
     .await
     .map_err(|e| {
         tracing::error!("Failed to execute query: {:?}", e);
         e
     })?;
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = 'confirmed',
+            tags = tags || ARRAY(SELECT unnest($2::text[]) EXCEPT SELECT unnest(tags))
+        WHERE id = $1
+        "#,
+        token.subscriber_id,
+        &token.tags
+    )
+    .execute(&mut transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    transaction.commit().await?;
 
     Ok(())
 }
 
+/// What a confirmation token confirms.
+pub struct ConfirmationToken {
+    pub subscriber_id: Uuid,
+    pub list_id: Uuid,
+    /// Tags submitted along with the subscription of an existing subscriber,
+    /// applied once they confirm.
+    pub tags: Vec<String>,
+}
+
+/// Returns the subscriber and the list confirmed by the token.
 #[tracing::instrument(name = "Get subscriber_id from token", skip(subscription_token, pool))]
 pub async fn get_subscriber_id_from_token(
     pool: &PgPool,
     subscription_token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
-    let result = sqlx::query!(
-        "SELECT subscriber_id FROM subscription_tokens \
+) -> Result<Option<ConfirmationToken>, sqlx::Error> {
+    sqlx::query_as!(
+        ConfirmationToken,
+        "SELECT subscriber_id, list_id, tags FROM subscription_tokens \
 WHERE subscription_token = $1",
         subscription_token,
     )
@@ -74,6 +109,5 @@ WHERE subscription_token = $1",
     .map_err(|e| {
         tracing::error!("Failed to execute query: {:?}", e);
         e
-    })?;
-    Ok(result.map(|r| r.subscriber_id))
+    })
 }
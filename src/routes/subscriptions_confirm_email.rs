@@ -0,0 +1,47 @@
+use actix_web::{get, http::StatusCode, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+use crate::{
+    email_change::{confirm_email_change, EmailChangeError},
+    manage_link::manage_path,
+    problem::Problem,
+    startup::HmacSecret,
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct ConfirmEmailParameters {
+    email_change_token: String,
+}
+
+#[get("/subscriptions/confirm_email")]
+#[tracing::instrument(
+    name = "Confirming an email change",
+    skip(parameters, pool, hmac_secret)
+)]
+async fn confirm_email(
+    parameters: web::Query<ConfirmEmailParameters>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match confirm_email_change(&pool, &parameters.email_change_token).await {
+        Ok(Some(subscriber_id)) => {
+            FlashMessage::info("Your email address has been updated.").send();
+            Ok(see_other(&manage_path(&hmac_secret.0, subscriber_id)))
+        }
+        Ok(None) => Err(Problem::new(
+            StatusCode::UNAUTHORIZED,
+            "invalid_email_change_token",
+            "The confirmation link is invalid or has expired.",
+        )
+        .into()),
+        Err(e @ EmailChangeError::AddressInUse(_)) => Err(Problem::new(
+            StatusCode::CONFLICT,
+            "email_already_subscribed",
+            e.to_string(),
+        )
+        .into()),
+        Err(e) => Err(e500(e)),
+    }
+}
@@ -0,0 +1,483 @@
+use actix_web::{
+    get,
+    http::{header::ContentType, StatusCode},
+    post, web, HttpResponse,
+};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages, Level};
+use anyhow::Context;
+use askama::Template;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::{
+    domain::{ListSlug, SubscriberEmail, SubscriberName, SubscriberTag},
+    email_change::{request_email_change, EmailChangeError},
+    email_client::EmailClient,
+    manage_link::{is_expired, verify_signature},
+    problem::Problem,
+    signing::HmacKeys,
+    startup::{ApplicationBaseUrl, HmacSecret},
+    templates::{ListChoice, ManageSubscriptionTemplate},
+    utils::{e500, see_other},
+};
+
+/// The longest pause a subscriber can ask for.
+const MAX_PAUSE_WEEKS: i64 = 52;
+
+/// Query parameters of the signed link to the preference center.
+#[derive(serde::Deserialize)]
+pub struct ManageParameters {
+    subscriber_id: Uuid,
+    issued_at: i64,
+    signature: String,
+}
+
+impl ManageParameters {
+    fn query(&self) -> String {
+        format!(
+            "subscriber_id={}&issued_at={}&signature={}",
+            self.subscriber_id, self.issued_at, self.signature
+        )
+    }
+
+    fn page(&self) -> String {
+        format!("/subscriptions/manage?{}", self.query())
+    }
+
+    fn verify(&self, hmac_keys: &HmacKeys) -> Result<(), Problem> {
+        if !verify_signature(
+            hmac_keys,
+            self.subscriber_id,
+            self.issued_at,
+            &self.signature,
+        ) {
+            Err(Problem::new(
+                StatusCode::UNAUTHORIZED,
+                "invalid_manage_link",
+                "This link to your preferences is invalid.",
+            ))
+        } else if is_expired(self.issued_at) {
+            Err(Problem::new(
+                StatusCode::UNAUTHORIZED,
+                "expired_manage_link",
+                "This link to your preferences has expired. \
+                Use the one in a more recent email from us.",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+struct Preferences {
+    name: SubscriberName,
+    tags: Vec<SubscriberTag>,
+    lists: Vec<ListSlug>,
+    /// `None` keeps the current pause, `Some(0)` resumes delivery.
+    pause_weeks: Option<i64>,
+}
+
+/// The form is read as key-value pairs since every checked list is sent as
+/// its own `lists` field.
+impl TryFrom<Vec<(String, String)>> for Preferences {
+    type Error = String;
+
+    fn try_from(value: Vec<(String, String)>) -> Result<Self, Self::Error> {
+        let mut name = None;
+        let mut tags = Vec::new();
+        let mut lists = Vec::new();
+        let mut pause_weeks = None;
+        for (key, value) in value {
+            match key.as_str() {
+                "name" => name = Some(SubscriberName::parse(value)?),
+                "tags" => tags = SubscriberTag::parse_list(&value)?,
+                "lists" => {
+                    let list = ListSlug::parse(value)?;
+                    if !lists.contains(&list) {
+                        lists.push(list);
+                    }
+                }
+                "pause_weeks" if value.trim().is_empty() => {}
+                "pause_weeks" => match value.trim().parse() {
+                    Ok(weeks) if (0..=MAX_PAUSE_WEEKS).contains(&weeks) => {
+                        pause_weeks = Some(weeks)
+                    }
+                    _ => {
+                        return Err(format!(
+                            "Delivery can be paused for 0 to {} weeks.",
+                            MAX_PAUSE_WEEKS
+                        ))
+                    }
+                },
+                _ => {}
+            }
+        }
+        let name = name.ok_or("Your name is missing.")?;
+        // Leaving every list is unsubscribing, which has its own form.
+        if lists.is_empty() {
+            return Err(
+                "Pick at least one list, or unsubscribe to stop receiving all emails.".into(),
+            );
+        }
+        Ok(Self {
+            name,
+            tags,
+            lists,
+            pause_weeks,
+        })
+    }
+}
+
+struct Subscriber {
+    email: String,
+    name: String,
+    status: String,
+    tags: Vec<String>,
+    paused_until: Option<DateTime<Utc>>,
+}
+
+#[get("/subscriptions/manage")]
+#[tracing::instrument(
+    name = "Show subscriber preferences",
+    skip_all,
+    fields(subscriber_id = %parameters.subscriber_id)
+)]
+pub async fn manage_subscription_form(
+    parameters: web::Query<ManageParameters>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    parameters.verify(&hmac_secret.0)?;
+    let Some(subscriber) = get_subscriber(&pool, parameters.subscriber_id)
+        .await
+        .map_err(e500)?
+    else {
+        return Err(Problem::new(
+            StatusCode::NOT_FOUND,
+            "unknown_subscriber",
+            "This subscriber does not exist.",
+        )
+        .into());
+    };
+    let lists = get_list_choices(&pool, parameters.subscriber_id)
+        .await
+        .map_err(e500)?;
+
+    let mut error = String::new();
+
+    for m in flash_messages.iter().filter(|m| m.level() == Level::Error) {
+        writeln!(error, "{}", m.content()).unwrap();
+    }
+
+    let mut info = String::new();
+
+    for m in flash_messages.iter().filter(|m| m.level() == Level::Info) {
+        writeln!(info, "{}", m.content()).unwrap();
+    }
+
+    let paused_until = subscriber
+        .paused_until
+        .filter(|until| *until > Utc::now())
+        .map(|until| until.format("%Y-%m-%d").to_string())
+        .unwrap_or_default();
+
+    let html = ManageSubscriptionTemplate {
+        error: &error,
+        info: &info,
+        query: &parameters.query(),
+        email: &subscriber.email,
+        name: &subscriber.name,
+        tags: &subscriber.tags.join(", "),
+        paused_until: &paused_until,
+        unsubscribed: subscriber.status == "unsubscribed",
+        lists,
+    }
+    .render()
+    .expect("Could not render manage subscription template.");
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html))
+}
+
+#[post("/subscriptions/manage")]
+#[tracing::instrument(
+    name = "Update subscriber preferences",
+    skip_all,
+    fields(subscriber_id = %parameters.subscriber_id)
+)]
+pub async fn manage_subscription(
+    parameters: web::Query<ManageParameters>,
+    form: web::Form<Vec<(String, String)>>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    parameters.verify(&hmac_secret.0)?;
+    let preferences = match Preferences::try_from(form.into_inner()) {
+        Ok(preferences) => preferences,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&parameters.page()));
+        }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let list_ids = get_list_ids(&mut transaction, &preferences.lists)
+        .await
+        .map_err(e500)?;
+    if list_ids.len() != preferences.lists.len() {
+        FlashMessage::error("One of the selected lists does not exist.").send();
+        return Ok(see_other(&parameters.page()));
+    }
+    update_lists(&mut transaction, parameters.subscriber_id, &list_ids)
+        .await
+        .map_err(e500)?;
+    update_subscriber(&mut transaction, parameters.subscriber_id, &preferences)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the preference changes.")
+        .map_err(e500)?;
+
+    FlashMessage::info("Your preferences have been saved.").send();
+    Ok(see_other(&parameters.page()))
+}
+
+#[derive(serde::Deserialize)]
+pub struct EmailFormData {
+    email: String,
+}
+
+#[post("/subscriptions/manage/email")]
+#[tracing::instrument(
+    name = "Request an email change from the preference center",
+    skip_all,
+    fields(subscriber_id = %parameters.subscriber_id)
+)]
+pub async fn change_email(
+    parameters: web::Query<ManageParameters>,
+    form: web::Form<EmailFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    parameters.verify(&hmac_secret.0)?;
+    let new_email = match SubscriberEmail::parse(form.0.email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&parameters.page()));
+        }
+    };
+
+    match request_email_change(
+        &pool,
+        &email_client,
+        &base_url.0,
+        &hmac_secret.0,
+        parameters.subscriber_id,
+        &new_email,
+        None,
+    )
+    .await
+    {
+        Ok(()) => FlashMessage::info(format!(
+            "A confirmation link has been sent to {}. \
+            Your current address stays in use until you follow it.",
+            new_email.as_ref()
+        ))
+        .send(),
+        Err(e @ EmailChangeError::UnexpectedError(_)) => return Err(e500(e)),
+        Err(e) => FlashMessage::error(e.to_string()).send(),
+    }
+    Ok(see_other(&parameters.page()))
+}
+
+#[post("/subscriptions/manage/unsubscribe")]
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber",
+    skip_all,
+    fields(subscriber_id = %parameters.subscriber_id)
+)]
+pub async fn unsubscribe(
+    parameters: web::Query<ManageParameters>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    parameters.verify(&hmac_secret.0)?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    sqlx::query!(
+        r#"DELETE FROM list_subscriptions WHERE subscriber_id = $1"#,
+        parameters.subscriber_id,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to remove the subscriber from their lists.")
+    .map_err(e500)?;
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'unsubscribed' WHERE id = $1"#,
+        parameters.subscriber_id,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to unsubscribe the subscriber.")
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the unsubscription.")
+        .map_err(e500)?;
+
+    FlashMessage::info("You have been unsubscribed from all lists.").send();
+    Ok(see_other(&parameters.page()))
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<Subscriber>, anyhow::Error> {
+    sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT email, name, status, tags, paused_until
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the subscriber.")
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_list_choices(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<ListChoice>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT l.slug, l.name, ls.subscriber_id IS NOT NULL as "subscribed!"
+        FROM lists l
+        LEFT JOIN list_subscriptions ls
+            ON ls.list_id = l.list_id AND ls.subscriber_id = $1
+        ORDER BY l.created_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the lists of the subscriber.")?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| ListChoice {
+            slug: r.slug,
+            name: r.name,
+            subscribed: r.subscribed,
+        })
+        .collect())
+}
+
+#[tracing::instrument(skip(transaction))]
+async fn get_list_ids(
+    transaction: &mut Transaction<'_, Postgres>,
+    lists: &[ListSlug],
+) -> Result<Vec<Uuid>, anyhow::Error> {
+    let slugs: Vec<String> = lists.iter().map(|l| l.as_ref().to_string()).collect();
+    let rows = sqlx::query!(r#"SELECT list_id FROM lists WHERE slug = ANY($1)"#, &slugs,)
+        .fetch_all(transaction)
+        .await
+        .context("Failed to retrieve the selected lists.")?;
+    Ok(rows.into_iter().map(|r| r.list_id).collect())
+}
+
+/// Make the subscriber a member of exactly the given lists. Following the
+/// signed link proves ownership of the address, so new memberships do not
+/// need to be confirmed again.
+#[tracing::instrument(skip(transaction))]
+async fn update_lists(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_ids: &[Uuid],
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM list_subscriptions
+        WHERE subscriber_id = $1 AND NOT (list_id = ANY($2))
+        "#,
+        subscriber_id,
+        list_ids,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to remove the subscriber from the deselected lists.")?;
+    sqlx::query!(
+        r#"
+        INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+        SELECT list_id, $1, 'confirmed', now()
+        FROM unnest($2::uuid[]) as list_id
+        ON CONFLICT (list_id, subscriber_id) DO UPDATE
+        SET status = 'confirmed'
+        "#,
+        subscriber_id,
+        list_ids,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to add the subscriber to the selected lists.")?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(transaction, preferences))]
+async fn update_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    preferences: &Preferences,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET
+            name = $2,
+            tags = $3,
+            status = 'confirmed'
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        preferences.name.as_ref(),
+        &SubscriberTag::to_strings(&preferences.tags),
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to update the subscriber.")?;
+
+    if let Some(weeks) = preferences.pause_weeks {
+        let paused_until = Some(Utc::now() + Duration::weeks(weeks)).filter(|_| weeks > 0);
+        sqlx::query!(
+            r#"UPDATE subscriptions SET paused_until = $2 WHERE id = $1"#,
+            subscriber_id,
+            paused_until,
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to update the delivery pause.")?;
+    }
+    Ok(())
+}
@@ -1,12 +1,44 @@
 use std::future::{ready, Ready};
 
 use actix_session::{Session, SessionExt, SessionGetError, SessionInsertError};
-use actix_web::{dev::Payload, FromRequest, HttpRequest};
+use actix_web::{
+    body::MessageBody,
+    cookie::{self, Cookie},
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    web, FromRequest, HttpRequest,
+};
+use actix_web_lab::middleware::Next;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use uuid::Uuid;
+
+use crate::authentication::PendingSsoLogin;
+use crate::configuration::SessionSettings;
+use crate::utils::e500;
+
 pub struct TypedSession(Session);
 
 impl TypedSession {
     const USER_ID_KEY: &'static str = "user_id";
+    /// See `users.session_epoch`: a session from an older epoch was revoked.
+    const SESSION_EPOCH_KEY: &'static str = "session_epoch";
+    /// See `user_sessions`: the session is logged out once its row is gone.
+    const SESSION_ID_KEY: &'static str = "session_id";
+    /// Set once the password is verified, while the second factor is pending.
+    /// It must never be mistaken for a logged in user.
+    const PENDING_USER_ID_KEY: &'static str = "pending_user_id";
+    const FAILED_CODE_ATTEMPTS_KEY: &'static str = "failed_code_attempts";
+    /// Whether "remember me" was ticked, while the second factor is pending.
+    const PENDING_REMEMBER_ME_KEY: &'static str = "pending_remember_me";
+    /// Set at login when "remember me" was ticked, see
+    /// `persist_remembered_sessions`.
+    const REMEMBER_ME_KEY: &'static str = "remember_me";
+    /// The TOTP secret shown while enrolling, until a code confirms it.
+    const PENDING_TOTP_SECRET_KEY: &'static str = "pending_totp_secret";
+    /// Sent back by every admin form, see `reject_forged_requests`.
+    const CSRF_TOKEN_KEY: &'static str = "csrf_token";
+    /// Set while the user signs in at the identity provider.
+    const PENDING_SSO_LOGIN_KEY: &'static str = "pending_sso_login";
+
     pub fn renew(&self) {
         self.0.renew();
     }
@@ -17,11 +49,195 @@ impl TypedSession {
         self.0.get(Self::USER_ID_KEY)
     }
 
+    /// Start a fully authenticated session, dropping any pending login step.
+    /// The CSRF token is rotated too.
+    pub fn log_in(
+        &self,
+        user_id: Uuid,
+        session_epoch: i32,
+        session_id: Uuid,
+        remember_me: bool,
+    ) -> Result<(), SessionInsertError> {
+        self.0.renew();
+        self.remove_pending_user_id();
+        if remember_me {
+            self.0.insert(Self::REMEMBER_ME_KEY, true)?;
+        } else {
+            self.0.remove(Self::REMEMBER_ME_KEY);
+        }
+        self.0.insert(Self::CSRF_TOKEN_KEY, new_csrf_token())?;
+        self.0.insert(Self::SESSION_EPOCH_KEY, session_epoch)?;
+        self.insert_session_id(session_id)?;
+        self.insert_user_id(user_id)
+    }
+    /// Sessions created before epochs were introduced count as epoch 0.
+    pub fn get_session_epoch(&self) -> Result<i32, SessionGetError> {
+        Ok(self.0.get(Self::SESSION_EPOCH_KEY)?.unwrap_or(0))
+    }
+    /// Keep this session when the others are revoked.
+    pub fn insert_session_epoch(&self, session_epoch: i32) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::SESSION_EPOCH_KEY, session_epoch)
+    }
+
+    pub fn insert_session_id(&self, session_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::SESSION_ID_KEY, session_id)
+    }
+    /// `None` for sessions logged in before they were tracked.
+    pub fn get_session_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::SESSION_ID_KEY)
+    }
+
+    pub fn is_remembered(&self) -> Result<bool, SessionGetError> {
+        Ok(self.0.get(Self::REMEMBER_ME_KEY)?.unwrap_or(false))
+    }
+
+    pub fn remove_user_id(&self) {
+        self.0.remove(Self::USER_ID_KEY);
+    }
+
+    /// The epoch is kept too, so that revoking sessions also cancels a login
+    /// waiting for its second factor.
+    pub fn insert_pending_user_id(
+        &self,
+        user_id: Uuid,
+        session_epoch: i32,
+        remember_me: bool,
+    ) -> Result<(), SessionInsertError> {
+        self.0.remove(Self::FAILED_CODE_ATTEMPTS_KEY);
+        self.0.insert(Self::SESSION_EPOCH_KEY, session_epoch)?;
+        self.0.insert(Self::PENDING_REMEMBER_ME_KEY, remember_me)?;
+        self.0.insert(Self::PENDING_USER_ID_KEY, user_id)
+    }
+    pub fn get_pending_user_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::PENDING_USER_ID_KEY)
+    }
+    pub fn get_pending_remember_me(&self) -> Result<bool, SessionGetError> {
+        Ok(self.0.get(Self::PENDING_REMEMBER_ME_KEY)?.unwrap_or(false))
+    }
+    pub fn remove_pending_user_id(&self) {
+        self.0.remove(Self::PENDING_USER_ID_KEY);
+        self.0.remove(Self::PENDING_REMEMBER_ME_KEY);
+        self.0.remove(Self::FAILED_CODE_ATTEMPTS_KEY);
+    }
+
+    /// Count a wrong second factor code, returning the attempts made so far.
+    pub fn record_failed_code_attempt(&self) -> Result<u32, anyhow::Error> {
+        let attempts = self
+            .0
+            .get::<u32>(Self::FAILED_CODE_ATTEMPTS_KEY)?
+            .unwrap_or(0)
+            + 1;
+        self.0.insert(Self::FAILED_CODE_ATTEMPTS_KEY, attempts)?;
+        Ok(attempts)
+    }
+
+    pub fn insert_pending_totp_secret(&self, secret: &str) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::PENDING_TOTP_SECRET_KEY, secret)
+    }
+    pub fn get_pending_totp_secret(&self) -> Result<Option<String>, SessionGetError> {
+        self.0.get(Self::PENDING_TOTP_SECRET_KEY)
+    }
+    pub fn remove_pending_totp_secret(&self) {
+        self.0.remove(Self::PENDING_TOTP_SECRET_KEY);
+    }
+
+    pub fn insert_pending_sso_login(
+        &self,
+        pending: &PendingSsoLogin,
+    ) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::PENDING_SSO_LOGIN_KEY, pending)
+    }
+    /// Each sign-on request can only be answered once.
+    pub fn take_pending_sso_login(&self) -> Result<Option<PendingSsoLogin>, SessionGetError> {
+        let pending = self.0.get(Self::PENDING_SSO_LOGIN_KEY)?;
+        self.0.remove(Self::PENDING_SSO_LOGIN_KEY);
+        Ok(pending)
+    }
+
+    /// Sessions logged in before CSRF tokens were introduced get one here.
+    pub fn get_or_insert_csrf_token(&self) -> Result<String, anyhow::Error> {
+        if let Some(token) = self.0.get(Self::CSRF_TOKEN_KEY)? {
+            return Ok(token);
+        }
+        let token = new_csrf_token();
+        self.0.insert(Self::CSRF_TOKEN_KEY, &token)?;
+        Ok(token)
+    }
+
     pub fn log_out(self) {
         self.0.purge()
     }
 }
 
+/// Marks a response whose session was logged in with "remember me".
+struct RememberedSession;
+
+/// The session state is gone by the time the session middleware has set its
+/// cookie, so this runs inside it and marks the responses of remembered
+/// sessions for `persist_remembered_sessions`.
+pub async fn mark_remembered_sessions(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let mut response = next.call(req).await?;
+    let session = TypedSession(response.request().get_session());
+    if session.is_remembered().map_err(e500)? {
+        response
+            .response_mut()
+            .extensions_mut()
+            .insert(RememberedSession);
+    }
+    Ok(response)
+}
+
+/// Session cookies only last as long as the browser, except for sessions
+/// logged in with "remember me" while it is enabled: their cookie is sent
+/// with a `Max-Age` whenever the session middleware sets it.
+pub async fn persist_remembered_sessions(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let mut response = next.call(req).await?;
+    if response
+        .response()
+        .extensions()
+        .get::<RememberedSession>()
+        .is_none()
+    {
+        return Ok(response);
+    }
+    let Some(settings) = response
+        .request()
+        .app_data::<web::Data<SessionSettings>>()
+        .cloned()
+    else {
+        return Ok(response);
+    };
+    let Some(lifetime) = settings.remember_me_lifetime() else {
+        return Ok(response);
+    };
+    let session_cookie = response
+        .response()
+        .cookies()
+        .find(|cookie| cookie.name() == settings.cookie_name)
+        .map(Cookie::into_owned);
+    if let Some(mut cookie) = session_cookie {
+        cookie.set_max_age(cookie::time::Duration::seconds(lifetime.as_secs() as i64));
+        let response = response.response_mut();
+        response.del_cookie(&settings.cookie_name);
+        response.add_cookie(&cookie).map_err(e500)?;
+    }
+    Ok(response)
+}
+
+fn new_csrf_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(32)
+        .collect()
+}
+
 impl FromRequest for TypedSession {
     type Error = <Session as FromRequest>::Error;
     type Future = Ready<Result<TypedSession, Self::Error>>;
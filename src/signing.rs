@@ -0,0 +1,258 @@
+use actix_web::{
+    body::MessageBody,
+    cookie::{Cookie, CookieJar, Key},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{HeaderValue, COOKIE},
+    web,
+};
+use actix_web_lab::middleware::Next;
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+
+use crate::configuration::SessionSettings;
+
+/// Name of the cookie carrying flash messages.
+pub const FLASH_COOKIE_NAME: &str = "_flash";
+
+/// The HMAC secrets of the application: the current one signs, previous ones
+/// are still accepted so that rotating the secret does not log everyone out
+/// or break the links already sent by email.
+#[derive(Clone)]
+pub struct HmacKeys {
+    current: Secret<String>,
+    previous: Vec<Secret<String>>,
+}
+
+impl HmacKeys {
+    pub fn new(current: Secret<String>, previous: Vec<Secret<String>>) -> Self {
+        Self { current, previous }
+    }
+
+    /// Hex encoded signature of `message` with the current secret. `purpose`
+    /// keeps a signature made for one kind of link from passing for another.
+    pub fn sign(&self, purpose: &[u8], message: &[u8]) -> String {
+        hex::encode(mac(&self.current, purpose, message).finalize().into_bytes())
+    }
+
+    /// Whether any of the secrets signed `message` for `purpose`.
+    pub fn verify(&self, purpose: &[u8], message: &[u8], signature: &str) -> bool {
+        let Ok(signature) = hex::decode(signature) else {
+            return false;
+        };
+        self.secrets().any(|secret| {
+            mac(secret, purpose, message)
+                .verify_slice(&signature)
+                .is_ok()
+        })
+    }
+
+    /// The key session and flash message cookies are signed with.
+    pub fn cookie_key(&self) -> Key {
+        Key::from(self.current.expose_secret().as_bytes())
+    }
+
+    fn previous_cookie_keys(&self) -> impl Iterator<Item = Key> + '_ {
+        self.previous
+            .iter()
+            .map(|secret| Key::from(secret.expose_secret().as_bytes()))
+    }
+
+    fn secrets(&self) -> impl Iterator<Item = &Secret<String>> {
+        std::iter::once(&self.current).chain(&self.previous)
+    }
+}
+
+fn mac(secret: &Secret<String>, purpose: &[u8], message: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size");
+    mac.update(purpose);
+    mac.update(message);
+    mac
+}
+
+/// How a cookie is protected by the middleware that reads it.
+#[derive(Clone, Copy)]
+enum CookieProtection {
+    /// The session cookie is encrypted.
+    Private,
+    /// The flash message cookie is signed.
+    Signed,
+}
+
+/// Re-sign session and flash message cookies made with a previous secret, so
+/// that the session and flash message middlewares, which only know the
+/// current key, accept them.
+///
+/// The browser keeps sending the old cookie until the session changes, so a
+/// previous secret should be kept for as long as a session may last.
+pub async fn resign_rotated_cookies(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let keys = req.app_data::<web::Data<crate::startup::HmacSecret>>();
+    let settings = req.app_data::<web::Data<SessionSettings>>();
+    if let (Some(keys), Some(settings)) = (keys, settings) {
+        let protected = [
+            (settings.cookie_name.as_str(), CookieProtection::Private),
+            (FLASH_COOKIE_NAME, CookieProtection::Signed),
+        ];
+        if let Some(header) = resign_cookie_header(&req, &keys.0, &protected) {
+            req.headers_mut().insert(COOKIE, header);
+        }
+    }
+    next.call(req).await
+}
+
+/// The `Cookie` header with rotated cookies re-signed, `None` if there are
+/// none.
+fn resign_cookie_header(
+    req: &ServiceRequest,
+    keys: &HmacKeys,
+    protected: &[(&str, CookieProtection)],
+) -> Option<HeaderValue> {
+    if keys.previous.is_empty() {
+        return None;
+    }
+    let mut resigned = false;
+    let mut pairs = Vec::new();
+    for header in req.headers().get_all(COOKIE) {
+        let header = header.to_str().ok()?;
+        for pair in header.split(';').map(str::trim).filter(|s| !s.is_empty()) {
+            let upgraded = Cookie::parse_encoded(pair).ok().and_then(|cookie| {
+                let (_, protection) = protected.iter().find(|(name, _)| *name == cookie.name())?;
+                resign(cookie.into_owned(), *protection, keys)
+            });
+            match upgraded {
+                Some(cookie) => {
+                    resigned = true;
+                    pairs.push(cookie.encoded().to_string());
+                }
+                None => pairs.push(pair.to_owned()),
+            }
+        }
+    }
+    if !resigned {
+        return None;
+    }
+    HeaderValue::from_str(&pairs.join("; ")).ok()
+}
+
+/// The cookie signed with the current key if a previous one signed it.
+fn resign(
+    cookie: Cookie<'static>,
+    protection: CookieProtection,
+    keys: &HmacKeys,
+) -> Option<Cookie<'static>> {
+    let name = cookie.name().to_owned();
+    let mut jar = CookieJar::new();
+    jar.add_original(cookie);
+    let verify = |jar: &CookieJar, key: &Key| match protection {
+        CookieProtection::Private => jar.private(key).get(&name),
+        CookieProtection::Signed => jar.signed(key).get(&name),
+    };
+    if verify(&jar, &keys.cookie_key()).is_some() {
+        return None;
+    }
+    let value = keys
+        .previous_cookie_keys()
+        .find_map(|key| verify(&jar, &key))?
+        .value()
+        .to_owned();
+
+    let mut jar = CookieJar::new();
+    let current = keys.cookie_key();
+    match protection {
+        CookieProtection::Private => jar
+            .private_mut(&current)
+            .add(Cookie::new(name.clone(), value)),
+        CookieProtection::Signed => jar
+            .signed_mut(&current)
+            .add(Cookie::new(name.clone(), value)),
+    }
+    jar.get(&name).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resign, CookieProtection, HmacKeys};
+    use actix_web::cookie::{Cookie, CookieJar};
+    use secrecy::Secret;
+
+    fn keys(current: &str, previous: &[&str]) -> HmacKeys {
+        HmacKeys::new(
+            Secret::new(current.to_string()),
+            previous
+                .iter()
+                .map(|secret| Secret::new(secret.to_string()))
+                .collect(),
+        )
+    }
+
+    /// A secret long enough to derive a cookie key from.
+    fn secret(name: &str) -> String {
+        name.repeat(64)
+    }
+
+    #[test]
+    fn only_the_current_secret_signs() {
+        let old = keys("old", &[]);
+        let rotated = keys("new", &["old"]);
+
+        let signature = rotated.sign(b"purpose:", b"message");
+
+        assert!(rotated.verify(b"purpose:", b"message", &signature));
+        assert!(!old.verify(b"purpose:", b"message", &signature));
+    }
+
+    #[test]
+    fn previous_secrets_still_verify() {
+        let signature = keys("old", &[]).sign(b"purpose:", b"message");
+
+        assert!(keys("new", &["old"]).verify(b"purpose:", b"message", &signature));
+        assert!(!keys("new", &[]).verify(b"purpose:", b"message", &signature));
+        assert!(!keys("new", &["old"]).verify(b"another-purpose:", b"message", &signature));
+    }
+
+    #[test]
+    fn a_cookie_signed_with_a_previous_key_is_signed_again() {
+        let (old, new) = (secret("o"), secret("n"));
+        let rotated = keys(&new, &[&old]);
+        for protection in [CookieProtection::Private, CookieProtection::Signed] {
+            let mut jar = CookieJar::new();
+            let old_key = keys(&old, &[]).cookie_key();
+            match protection {
+                CookieProtection::Private => {
+                    jar.private_mut(&old_key).add(Cookie::new("id", "state"))
+                }
+                CookieProtection::Signed => {
+                    jar.signed_mut(&old_key).add(Cookie::new("id", "state"))
+                }
+            }
+            let cookie = jar.get("id").unwrap().clone();
+
+            let resigned = resign(cookie, protection, &rotated).unwrap();
+
+            let mut jar = CookieJar::new();
+            jar.add_original(resigned);
+            let verified = match protection {
+                CookieProtection::Private => jar.private(&rotated.cookie_key()).get("id"),
+                CookieProtection::Signed => jar.signed(&rotated.cookie_key()).get("id"),
+            };
+            assert_eq!(verified.unwrap().value(), "state");
+        }
+    }
+
+    #[test]
+    fn cookies_signed_with_the_current_or_an_unknown_key_are_left_alone() {
+        let rotated = keys(&secret("n"), &[&secret("o")]);
+        for signing_secret in [secret("n"), secret("x")] {
+            let mut jar = CookieJar::new();
+            jar.signed_mut(&keys(&signing_secret, &[]).cookie_key())
+                .add(Cookie::new("_flash", "[]"));
+            let cookie = jar.get("_flash").unwrap().clone();
+
+            assert!(resign(cookie, CookieProtection::Signed, &rotated).is_none());
+        }
+    }
+}
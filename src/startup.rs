@@ -1,9 +1,13 @@
 use std::net::TcpListener;
 
 use actix_files as fs;
-use actix_session::{storage::RedisSessionStore, SessionMiddleware};
+use actix_session::{
+    config::{BrowserSession, SessionLifecycle},
+    storage::RedisSessionStore,
+    SessionMiddleware,
+};
 use actix_web::{
-    cookie::Key,
+    cookie,
     dev::Server,
     web::{self, Data},
     App, HttpRequest, HttpServer,
@@ -20,15 +24,37 @@ use utoipa::{
 use utoipa_swagger_ui::SwaggerUi;
 
 use crate::{
-    authentication::reject_anonymous_users,
-    configuration::{DatabaseSettings, Settings, SignupSettings},
+    authentication::{
+        reject_anonymous_users, reject_forged_requests, reject_invalid_api_tokens, LoginThrottle,
+        OidcClient, PasswordHashing, PasswordPolicy,
+    },
+    configuration::{
+        DatabaseSettings, IdempotencySettings, LoginThrottlingSettings, OidcSettings,
+        PasswordHashingSettings, PasswordPolicySettings, SessionSettings, Settings, SignupSettings,
+    },
     domain,
     email_client::EmailClient,
+    idempotency::honor_idempotency_keys,
+    problem::{self, render_problems_for_browsers},
     routes::health_check,
     routes::{
-        admin_dashboard, change_password, change_password_form, confirm, home, log_out, login,
-        login_form, newsletter_issue_form, publish_newsletter, signup, signup_form, subscribe,
+        admin_dashboard, api_tokens_page, audit_page, change_account_email, change_email,
+        change_password, change_password_form, change_subscriber_email, change_user_role, confirm,
+        confirm_email, create_api_token, create_issue, create_list, create_subscriber,
+        deactivate_user, delete_user, disable_two_factor, enable_two_factor, export_audit_log,
+        forgot_password, forgot_password_form, get_issue, get_subscriber, home, import_subscribers,
+        invitation_form, join_with_invitation, list_issues, list_subscribers, lists_page, log_out,
+        login, login_form, login_link_form, login_link_request_form, login_two_factor,
+        login_two_factor_form, login_with_link, manage_subscription, manage_subscription_form,
+        newsletter_issue_form, preview_recipients, publish_draft_issue, publish_newsletter,
+        publish_newsletter_issue, reactivate_user, request_login_link_email, reset_password_form,
+        reset_password_with_token, revoke_api_token, save_newsletter_draft, send_invitation,
+        sessions_page, set_subscriber_tags, sign_out_other_sessions, sign_out_session, signup,
+        signup_form, sso_callback, start_sso_login, subscribe, subscribers_page, two_factor_page,
+        unsubscribe, update_list, users_page,
     },
+    session_state::{mark_remembered_sessions, persist_remembered_sessions},
+    signing::{resign_rotated_cookies, HmacKeys, FLASH_COOKIE_NAME},
 };
 
 pub struct Application {
@@ -46,14 +72,21 @@ impl Application {
         let listener = TcpListener::bind(addr).expect("Failed to bind to a local port.");
 
         let port = listener.local_addr().unwrap().port();
+        let hmac_keys = configuration.application.hmac_keys();
         let server = run(
             listener,
             connection_pool,
             email_client,
             configuration.application.base_url,
-            configuration.application.hmac_secret,
+            hmac_keys,
             configuration.redis_uri,
             configuration.application.signup,
+            configuration.application.login_throttling,
+            configuration.application.session,
+            configuration.application.password_policy,
+            configuration.application.password_hashing,
+            configuration.application.idempotency,
+            configuration.application.oidc,
         )
         .await?;
 
@@ -71,14 +104,21 @@ impl Application {
 
 pub struct ApplicationBaseUrl(pub String);
 
+#[allow(clippy::too_many_arguments)]
 async fn run(
     listener: TcpListener,
     connection: PgPool,
     email_client: EmailClient,
     base_url: String,
-    hmac_secret: Secret<String>,
+    hmac_keys: HmacKeys,
     redis_uri: Secret<String>,
     signup_settings: SignupSettings,
+    login_throttling: LoginThrottlingSettings,
+    session_settings: SessionSettings,
+    password_policy: PasswordPolicySettings,
+    password_hashing: PasswordHashingSettings,
+    idempotency: IdempotencySettings,
+    oidc: Option<OidcSettings>,
 ) -> Result<Server, anyhow::Error> {
     #[derive(OpenApi)]
     #[openapi(
@@ -87,16 +127,41 @@ async fn run(
             crate::routes::subscribe,
             crate::routes::confirm,
             crate::routes::publish_newsletter,
+            crate::routes::save_newsletter_draft,
+            crate::routes::preview_recipients,
             crate::routes::login,
+            crate::routes::login_two_factor,
             crate::routes::signup,
+            crate::routes::publish_newsletter_issue,
+            crate::routes::list_subscribers,
+            crate::routes::get_subscriber,
+            crate::routes::create_subscriber,
+            crate::routes::list_issues,
+            crate::routes::get_issue,
+            crate::routes::create_issue,
+            crate::routes::publish_draft_issue,
         ),
         components(
             schemas(domain::SubscriptionRequest),
             schemas(domain::SubscriberName),
             schemas(domain::SubscriberEmail),
             schemas(crate::routes::NewsletterRequestBody),
+            schemas(crate::routes::DraftRequestBody),
+            schemas(crate::routes::SegmentFormData),
+            schemas(crate::routes::RecipientCount),
             schemas(crate::routes::LoginFormData),
+            schemas(crate::routes::TwoFactorFormData),
             schemas(crate::routes::SignupFormData),
+            schemas(crate::routes::PublishedIssue),
+            schemas(crate::routes::SubscriberSummary),
+            schemas(crate::routes::SubscriberStatus),
+            schemas(crate::routes::NewSubscriber),
+            schemas(crate::routes::IssueSummary),
+            schemas(crate::routes::NewsletterIssue),
+            schemas(crate::routes::IssueStatus),
+            schemas(crate::routes::NewIssue),
+            schemas(problem::ProblemDetails),
+            schemas(problem::FieldError),
         ),
         tags(
             (name = "zero2prod", description = "Newsletter app built following the Rust: Zero to Production book.")
@@ -112,8 +177,8 @@ async fn run(
             let components = openapi.components.as_mut().unwrap();
 
             components.add_security_scheme(
-                "http_basic",
-                SecurityScheme::Http(Http::new(HttpAuthScheme::Basic)),
+                "api_token",
+                SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
             );
         }
     }
@@ -125,39 +190,121 @@ async fn run(
     let base_url = web::Data::new(ApplicationBaseUrl(base_url));
     let signup_settings = web::Data::new(signup_settings);
 
-    let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
+    let secret_key = hmac_keys.cookie_key();
 
-    let message_store = CookieMessageStore::builder(secret_key.clone()).build();
+    let message_store = CookieMessageStore::builder(secret_key.clone())
+        .cookie_name(FLASH_COOKIE_NAME.to_string())
+        .build();
     let message_framework = FlashMessagesFramework::builder(message_store).build();
 
     let redis_store = RedisSessionStore::new(redis_uri.expose_secret()).await?;
+    let session_lifecycle = session_lifecycle(&session_settings);
+    let login_throttle = web::Data::new(LoginThrottle::new(&redis_uri, login_throttling).await?);
+    let password_policy = web::Data::new(PasswordPolicy::new(&password_policy)?);
+    let password_hashing = web::Data::new(PasswordHashing::new(&password_hashing)?);
+    let idempotency = web::Data::new(idempotency);
+    // Single sign-on routes answer 404 when no provider is configured.
+    let oidc = oidc
+        .map(|settings| OidcClient::new(settings, &base_url.0))
+        .transpose()?
+        .map(web::Data::new);
 
     let server = HttpServer::new(move || {
         App::new()
             .wrap(message_framework.clone())
-            .wrap(SessionMiddleware::new(
-                redis_store.clone(),
-                secret_key.clone(),
-            ))
+            .wrap(from_fn(mark_remembered_sessions))
+            .wrap(
+                SessionMiddleware::builder(redis_store.clone(), secret_key.clone())
+                    .cookie_name(session_settings.cookie_name.clone())
+                    .cookie_secure(session_settings.cookie_secure)
+                    .cookie_same_site(session_settings.same_site.into())
+                    .cookie_http_only(true)
+                    .session_lifecycle(session_lifecycle.clone())
+                    .build(),
+            )
+            .wrap(from_fn(persist_remembered_sessions))
+            .wrap(from_fn(resign_rotated_cookies))
+            .wrap(from_fn(render_problems_for_browsers))
             .wrap(TracingLogger::default())
             .service(health_check)
             .service(subscribe)
             .service(confirm)
+            .service(confirm_email)
+            .service(manage_subscription_form)
+            .service(manage_subscription)
+            .service(unsubscribe)
+            .service(change_email)
             .service(home)
             .service(login)
             .service(login_form)
+            .service(login_two_factor)
+            .service(login_two_factor_form)
+            .service(login_link_request_form)
+            .service(request_login_link_email)
+            .service(login_link_form)
+            .service(login_with_link)
+            .service(start_sso_login)
+            .service(sso_callback)
+            .service(forgot_password_form)
+            .service(forgot_password)
+            .service(reset_password_form)
+            .service(reset_password_with_token)
             .service(signup)
             .service(signup_form)
+            .service(invitation_form)
+            .service(join_with_invitation)
             .service(
                 web::scope("/admin")
+                    .wrap(from_fn(honor_idempotency_keys))
+                    .wrap(from_fn(reject_forged_requests))
                     .wrap(from_fn(reject_anonymous_users))
                     .service(admin_dashboard)
                     .service(change_password_form)
                     .service(change_password)
+                    .service(change_account_email)
                     .service(newsletter_issue_form)
                     .service(publish_newsletter)
+                    .service(save_newsletter_draft)
+                    .service(preview_recipients)
+                    .service(subscribers_page)
+                    .service(set_subscriber_tags)
+                    .service(change_subscriber_email)
+                    .service(import_subscribers)
+                    .service(lists_page)
+                    .service(create_list)
+                    .service(update_list)
+                    .service(two_factor_page)
+                    .service(enable_two_factor)
+                    .service(disable_two_factor)
+                    .service(users_page)
+                    .service(audit_page)
+                    .service(export_audit_log)
+                    .service(send_invitation)
+                    .service(change_user_role)
+                    .service(deactivate_user)
+                    .service(reactivate_user)
+                    .service(delete_user)
+                    .service(api_tokens_page)
+                    .service(create_api_token)
+                    .service(revoke_api_token)
+                    .service(sessions_page)
+                    .service(sign_out_other_sessions)
+                    .service(sign_out_session)
                     .service(log_out),
             )
+            .service(
+                web::scope("/api/v1")
+                    .wrap(from_fn(honor_idempotency_keys))
+                    .wrap(from_fn(reject_invalid_api_tokens))
+                    .service(publish_newsletter_issue)
+                    .service(list_subscribers)
+                    .service(get_subscriber)
+                    .service(create_subscriber)
+                    .service(list_issues)
+                    .service(get_issue)
+                    .service(create_issue)
+                    .service(publish_draft_issue),
+            )
             .service(fs::Files::new("/assets", "./static/assets"))
             .service(fs::Files::new("/images", "./static/images"))
             .service(web::resource("/favicon.ico").route(web::get().to(favicon)))
@@ -168,7 +315,20 @@ async fn run(
             .app_data(email_client.clone())
             .app_data(base_url.clone())
             .app_data(signup_settings.clone())
-            .app_data(Data::new(HmacSecret(hmac_secret.clone())))
+            .app_data(web::Data::new(session_settings.clone()))
+            .app_data(login_throttle.clone())
+            .app_data(password_policy.clone())
+            .app_data(password_hashing.clone())
+            .app_data(idempotency.clone())
+            .configure(|cfg| {
+                if let Some(oidc) = &oidc {
+                    cfg.app_data(oidc.clone());
+                }
+            })
+            .app_data(Data::new(HmacSecret(hmac_keys.clone())))
+            .app_data(problem::form_config())
+            .app_data(problem::query_config())
+            .app_data(problem::json_config())
     })
     .listen(listener)?
     .run();
@@ -176,8 +336,23 @@ async fn run(
     Ok(server)
 }
 
+/// Session cookies only last as long as the browser, those of remembered
+/// sessions are given a `Max-Age` by `persist_remembered_sessions`. Redis
+/// keeps every session for as long as it may last: expiry is checked against
+/// `user_sessions`, see `touch_session`.
+fn session_lifecycle(settings: &SessionSettings) -> SessionLifecycle {
+    let state_ttl = settings
+        .remember_me_lifetime()
+        .map_or(settings.lifetime(), |lifetime| {
+            lifetime.max(settings.lifetime())
+        });
+    BrowserSession::default()
+        .state_ttl(cookie::time::Duration::seconds(state_ttl.as_secs() as i64))
+        .into()
+}
+
 #[derive(Clone)]
-pub struct HmacSecret(pub Secret<String>);
+pub struct HmacSecret(pub HmacKeys);
 
 pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
     PgPoolOptions::new()
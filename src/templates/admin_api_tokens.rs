@@ -0,0 +1,27 @@
+use askama::Template;
+
+use super::{admin_dashboard, PathPart};
+use crate::authentication::{ApiScope, ApiToken};
+
+#[derive(Template)]
+#[template(path = "admin_api_tokens.html")]
+pub struct AdminApiTokensTemplate<'a> {
+    pub error: &'a str,
+    pub info: &'a str,
+    pub tokens: Vec<ApiToken>,
+    /// Only set right after creating a token, it cannot be shown again.
+    pub new_token: Option<String>,
+    pub csrf_token: &'a str,
+}
+
+impl AdminApiTokensTemplate<'_> {
+    fn scopes(&self) -> [ApiScope; 4] {
+        ApiScope::ALL
+    }
+}
+
+pub fn path() -> Vec<PathPart<'static>> {
+    let mut path = admin_dashboard::path();
+    path.push(PathPart::new("/admin/api_tokens", "API tokens"));
+    path
+}
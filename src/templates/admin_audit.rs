@@ -0,0 +1,25 @@
+use askama::Template;
+
+use super::{admin_dashboard, PathPart};
+use crate::audit::{AuditAction, AuditEvent, AuditFilter};
+
+#[derive(Template)]
+#[template(path = "admin_audit.html")]
+pub struct AdminAuditTemplate<'a> {
+    pub filter: &'a AuditFilter,
+    pub events: Vec<AuditEvent>,
+    /// The filter, for the CSV export link.
+    pub export_query: &'a str,
+}
+
+impl AdminAuditTemplate<'_> {
+    fn actions(&self) -> [AuditAction; 20] {
+        AuditAction::ALL
+    }
+}
+
+pub fn path() -> Vec<PathPart<'static>> {
+    let mut path = admin_dashboard::path();
+    path.push(PathPart::new("/admin/audit", "Audit log"));
+    path
+}
@@ -6,6 +6,8 @@ use super::PathPart;
 #[template(path = "admin_dashboard.html")]
 pub struct AdminDashboardTemplate<'a> {
     pub username: &'a str,
+    pub can_manage_users: bool,
+    pub csrf_token: &'a str,
 }
 
 pub fn path() -> Vec<PathPart<'static>> {
@@ -0,0 +1,19 @@
+use askama::Template;
+
+use super::{admin_dashboard, PathPart};
+use crate::lists::NewsletterList;
+
+#[derive(Template)]
+#[template(path = "admin_lists.html")]
+pub struct AdminListsTemplate<'a> {
+    pub error: &'a str,
+    pub info: &'a str,
+    pub lists: Vec<NewsletterList>,
+    pub csrf_token: &'a str,
+}
+
+pub fn path() -> Vec<PathPart<'static>> {
+    let mut path = admin_dashboard::path();
+    path.push(PathPart::new("/admin/lists", "Lists"));
+    path
+}
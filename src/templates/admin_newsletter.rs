@@ -1,6 +1,17 @@
 use askama::Template;
+use uuid::Uuid;
 
 use super::{admin_dashboard, PathPart};
+use crate::lists::NewsletterList;
+
+/// An issue saved for later, which an editor still has to publish.
+pub struct NewsletterDraft {
+    pub newsletter_issue_id: Uuid,
+    pub list_id: Uuid,
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+}
 
 #[derive(Template)]
 #[template(path = "admin_newsletter.html")]
@@ -8,6 +19,13 @@ pub struct SendNewsletterTemplate<'a> {
     pub error: &'a str,
     pub info: &'a str,
     pub idempotency_key: &'a str,
+    pub lists: Vec<NewsletterList>,
+    pub drafts: &'a [NewsletterDraft],
+    /// The draft being edited, if any.
+    pub draft: Option<&'a NewsletterDraft>,
+    pub can_draft: bool,
+    pub can_publish: bool,
+    pub csrf_token: &'a str,
 }
 
 pub fn path() -> Vec<PathPart<'static>> {
@@ -0,0 +1,27 @@
+use askama::Template;
+use uuid::Uuid;
+
+use super::{admin_dashboard, PathPart};
+use crate::authentication::ActiveSession;
+
+#[derive(Template)]
+#[template(path = "admin_sessions.html")]
+pub struct AdminSessionsTemplate<'a> {
+    pub error: &'a str,
+    pub info: &'a str,
+    pub sessions: Vec<ActiveSession>,
+    pub current_session_id: Option<Uuid>,
+    pub csrf_token: &'a str,
+}
+
+impl AdminSessionsTemplate<'_> {
+    fn is_current(&self, session: &ActiveSession) -> bool {
+        self.current_session_id == Some(session.session_id)
+    }
+}
+
+pub fn path() -> Vec<PathPart<'static>> {
+    let mut path = admin_dashboard::path();
+    path.push(PathPart::new("/admin/sessions", "Sessions"));
+    path
+}
@@ -0,0 +1,30 @@
+use askama::Template;
+
+use super::{admin_dashboard, PathPart};
+use crate::lists::NewsletterList;
+
+pub struct SubscriberRow {
+    pub id: String,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: String,
+    pub tags: String,
+    pub lists: String,
+}
+
+#[derive(Template)]
+#[template(path = "admin_subscribers.html")]
+pub struct AdminSubscribersTemplate<'a> {
+    pub error: &'a str,
+    pub info: &'a str,
+    pub subscribers: Vec<SubscriberRow>,
+    pub lists: Vec<NewsletterList>,
+    pub csrf_token: &'a str,
+}
+
+pub fn path() -> Vec<PathPart<'static>> {
+    let mut path = admin_dashboard::path();
+    path.push(PathPart::new("/admin/subscribers", "Subscribers"));
+    path
+}
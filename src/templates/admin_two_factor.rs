@@ -0,0 +1,29 @@
+use askama::Template;
+
+use super::{admin_dashboard, PathPart};
+
+/// What a user needs to add the account to an authenticator app.
+pub struct TotpEnrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+    pub qr_code_svg: String,
+}
+
+#[derive(Template)]
+#[template(path = "admin_two_factor.html")]
+pub struct AdminTwoFactorTemplate<'a> {
+    pub error: &'a str,
+    pub info: &'a str,
+    pub enabled: bool,
+    pub unused_recovery_codes: i64,
+    pub enrollment: Option<TotpEnrollment>,
+    /// Only set right after enabling, the codes cannot be shown again.
+    pub recovery_codes: Vec<String>,
+    pub csrf_token: &'a str,
+}
+
+pub fn path() -> Vec<PathPart<'static>> {
+    let mut path = admin_dashboard::path();
+    path.push(PathPart::new("/admin/2fa", "Two-factor authentication"));
+    path
+}
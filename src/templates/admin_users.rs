@@ -0,0 +1,30 @@
+use askama::Template;
+use uuid::Uuid;
+
+use super::{admin_dashboard, PathPart};
+use crate::{authentication::Role, invitations::PendingInvitation, users::AdminUser};
+
+#[derive(Template)]
+#[template(path = "admin_users.html")]
+pub struct AdminUsersTemplate<'a> {
+    pub error: &'a str,
+    pub info: &'a str,
+    pub users: Vec<AdminUser>,
+    pub invitations: Vec<PendingInvitation>,
+    /// Owners cannot change their own account from this page.
+    pub current_user_id: Uuid,
+    pub csrf_token: &'a str,
+}
+
+impl AdminUsersTemplate<'_> {
+    /// Roles an owner can hand out, highest first.
+    fn roles(&self) -> [Role; 4] {
+        [Role::Owner, Role::Editor, Role::Author, Role::Viewer]
+    }
+}
+
+pub fn path() -> Vec<PathPart<'static>> {
+    let mut path = admin_dashboard::path();
+    path.push(PathPart::new("/admin/users", "Users"));
+    path
+}
@@ -7,6 +7,9 @@ use super::{admin_dashboard, PathPart};
 pub struct ChangePasswordTemplate<'a> {
     pub error: &'a str,
     pub info: &'a str,
+    /// Where password reset links are sent, empty if not set.
+    pub email: &'a str,
+    pub csrf_token: &'a str,
 }
 
 pub fn path() -> Vec<PathPart<'static>> {
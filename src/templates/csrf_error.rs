@@ -0,0 +1,11 @@
+use askama::Template;
+
+use super::{admin_dashboard, PathPart};
+
+#[derive(Template)]
+#[template(path = "csrf_error.html")]
+pub struct CsrfErrorTemplate {}
+
+pub fn path() -> Vec<PathPart<'static>> {
+    admin_dashboard::path()
+}
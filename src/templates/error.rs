@@ -0,0 +1,14 @@
+use askama::Template;
+
+use super::{home, PathPart};
+use crate::problem::ProblemDetails;
+
+#[derive(Template)]
+#[template(path = "error.html")]
+pub struct ErrorTemplate<'a> {
+    pub problem: &'a ProblemDetails,
+}
+
+pub fn path() -> Vec<PathPart<'static>> {
+    home::path()
+}
@@ -0,0 +1,13 @@
+use askama::Template;
+
+use super::{admin_dashboard, PathPart};
+
+#[derive(Template)]
+#[template(path = "forbidden.html")]
+pub struct ForbiddenTemplate<'a> {
+    pub required_role: &'a str,
+}
+
+pub fn path() -> Vec<PathPart<'static>> {
+    admin_dashboard::path()
+}
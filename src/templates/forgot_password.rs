@@ -0,0 +1,15 @@
+use askama::Template;
+
+use super::{login, PathPart};
+
+#[derive(Template)]
+#[template(path = "forgot_password.html")]
+pub struct ForgotPasswordTemplate<'a> {
+    pub error: &'a str,
+}
+
+pub fn path() -> Vec<PathPart<'static>> {
+    let mut path = login::path();
+    path.push(PathPart::new("/login/forgot_password", "Forgot password"));
+    path
+}
@@ -0,0 +1,17 @@
+use askama::Template;
+
+use super::{home, PathPart};
+
+#[derive(Template)]
+#[template(path = "invitation.html")]
+pub struct InvitationTemplate<'a> {
+    pub error: &'a str,
+    pub token: &'a str,
+    pub email: &'a str,
+}
+
+pub fn path() -> Vec<PathPart<'static>> {
+    let mut path = home::path();
+    path.push(PathPart::new("/invitation", "Invitation"));
+    path
+}
@@ -7,6 +7,10 @@ use super::{home, PathPart};
 pub struct LoginTemplate<'a> {
     pub error: &'a str,
     pub info: &'a str,
+    /// Name of the single sign-on provider, when one is configured.
+    pub sso_provider: Option<&'a str>,
+    /// How long "remember me" keeps the session, when it is offered.
+    pub remember_me_days: Option<u64>,
 }
 
 pub fn path() -> Vec<PathPart<'static>> {
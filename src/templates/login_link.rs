@@ -0,0 +1,18 @@
+use askama::Template;
+
+use super::{login, PathPart};
+
+/// Asks before using up the link, so that mail scanners following it do not.
+#[derive(Template)]
+#[template(path = "login_link.html")]
+pub struct LoginLinkTemplate<'a> {
+    pub username: &'a str,
+    pub token: &'a str,
+    pub signature: &'a str,
+}
+
+pub fn path() -> Vec<PathPart<'static>> {
+    let mut path = login::path();
+    path.push(PathPart::new("/login/link", "Sign in"));
+    path
+}
@@ -0,0 +1,15 @@
+use askama::Template;
+
+use super::{login, PathPart};
+
+#[derive(Template)]
+#[template(path = "login_link_request.html")]
+pub struct LoginLinkRequestTemplate<'a> {
+    pub error: &'a str,
+}
+
+pub fn path() -> Vec<PathPart<'static>> {
+    let mut path = login::path();
+    path.push(PathPart::new("/login/link/request", "Sign-in link"));
+    path
+}
@@ -0,0 +1,15 @@
+use askama::Template;
+
+use super::{login, PathPart};
+
+#[derive(Template)]
+#[template(path = "login_two_factor.html")]
+pub struct LoginTwoFactorTemplate<'a> {
+    pub error: &'a str,
+}
+
+pub fn path() -> Vec<PathPart<'static>> {
+    let mut path = login::path();
+    path.push(PathPart::new("/login/2fa", "Two-factor authentication"));
+    path
+}
@@ -0,0 +1,31 @@
+use askama::Template;
+
+use super::{home, PathPart};
+
+pub struct ListChoice {
+    pub slug: String,
+    pub name: String,
+    pub subscribed: bool,
+}
+
+#[derive(Template)]
+#[template(path = "manage_subscription.html")]
+pub struct ManageSubscriptionTemplate<'a> {
+    pub error: &'a str,
+    pub info: &'a str,
+    /// Signed query string identifying the subscriber.
+    pub query: &'a str,
+    pub email: &'a str,
+    pub name: &'a str,
+    pub tags: &'a str,
+    /// Date delivery resumes, empty if delivery is not paused.
+    pub paused_until: &'a str,
+    pub unsubscribed: bool,
+    pub lists: Vec<ListChoice>,
+}
+
+pub fn path() -> Vec<PathPart<'static>> {
+    let mut path = home::path();
+    path.push(PathPart::new("#", "Manage subscription"));
+    path
+}
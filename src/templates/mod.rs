@@ -1,15 +1,49 @@
+pub mod admin_api_tokens;
+pub mod admin_audit;
 pub mod admin_dashboard;
+pub mod admin_lists;
 pub mod admin_newsletter;
+pub mod admin_sessions;
+pub mod admin_subscribers;
+pub mod admin_two_factor;
+pub mod admin_users;
 pub mod change_password;
+pub mod csrf_error;
+pub mod error;
+pub mod forbidden;
+pub mod forgot_password;
 pub mod home;
+pub mod invitation;
 pub mod login;
+pub mod login_link;
+pub mod login_link_request;
+pub mod login_two_factor;
+pub mod manage_subscription;
+pub mod reset_password;
 pub mod signup;
 
+pub use admin_api_tokens::AdminApiTokensTemplate;
+pub use admin_audit::AdminAuditTemplate;
 pub use admin_dashboard::AdminDashboardTemplate;
-pub use admin_newsletter::SendNewsletterTemplate;
+pub use admin_lists::AdminListsTemplate;
+pub use admin_newsletter::{NewsletterDraft, SendNewsletterTemplate};
+pub use admin_sessions::AdminSessionsTemplate;
+pub use admin_subscribers::{AdminSubscribersTemplate, SubscriberRow};
+pub use admin_two_factor::{AdminTwoFactorTemplate, TotpEnrollment};
+pub use admin_users::AdminUsersTemplate;
 pub use change_password::ChangePasswordTemplate;
+pub use csrf_error::CsrfErrorTemplate;
+pub use error::ErrorTemplate;
+pub use forbidden::ForbiddenTemplate;
+pub use forgot_password::ForgotPasswordTemplate;
 pub use home::HomeTemplate;
+pub use invitation::InvitationTemplate;
 pub use login::LoginTemplate;
+pub use login_link::LoginLinkTemplate;
+pub use login_link_request::LoginLinkRequestTemplate;
+pub use login_two_factor::LoginTwoFactorTemplate;
+pub use manage_subscription::{ListChoice, ManageSubscriptionTemplate};
+pub use reset_password::ResetPasswordTemplate;
 pub use signup::SignupTemplate;
 
 pub struct PathPart<'a> {
@@ -0,0 +1,16 @@
+use askama::Template;
+
+use super::{login, PathPart};
+
+#[derive(Template)]
+#[template(path = "reset_password.html")]
+pub struct ResetPasswordTemplate<'a> {
+    pub error: &'a str,
+    pub token: &'a str,
+}
+
+pub fn path() -> Vec<PathPart<'static>> {
+    let mut path = login::path();
+    path.push(PathPart::new("/login/reset_password", "Reset password"));
+    path
+}
@@ -0,0 +1,103 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::authentication::{revoke_sessions, Role};
+
+/// Someone who can log in to the admin area.
+pub struct AdminUser {
+    pub user_id: Uuid,
+    pub username: String,
+    pub email: Option<String>,
+    pub role: String,
+    pub deactivated_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(name = "Get all users", skip(pool))]
+pub async fn get_all_users(pool: &PgPool) -> Result<Vec<AdminUser>, anyhow::Error> {
+    sqlx::query_as!(
+        AdminUser,
+        r#"
+        SELECT user_id, username, email, role, deactivated_at
+        FROM users
+        ORDER BY username
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve the users.")
+}
+
+#[tracing::instrument(name = "Get user", skip(pool))]
+pub async fn get_user(user_id: Uuid, pool: &PgPool) -> Result<Option<AdminUser>, anyhow::Error> {
+    sqlx::query_as!(
+        AdminUser,
+        r#"
+        SELECT user_id, username, email, role, deactivated_at
+        FROM users
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve the user.")
+}
+
+/// Returns `false` if the user does not exist.
+#[tracing::instrument(name = "Set user role", skip(transaction))]
+pub async fn set_user_role(
+    user_id: Uuid,
+    role: Role,
+    transaction: &mut Transaction<'static, Postgres>,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"UPDATE users SET role = $2 WHERE user_id = $1"#,
+        user_id,
+        role.as_str()
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to change the user's role.")?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Deactivating a user also logs them out everywhere. Returns `false` if the
+/// user does not exist.
+#[tracing::instrument(name = "Set user active", skip(transaction))]
+pub async fn set_user_active(
+    user_id: Uuid,
+    active: bool,
+    transaction: &mut Transaction<'static, Postgres>,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE users
+        SET deactivated_at = CASE WHEN $2 THEN NULL ELSE COALESCE(deactivated_at, now()) END
+        WHERE user_id = $1
+        "#,
+        user_id,
+        active
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to change whether the user is active.")?;
+    if !active {
+        revoke_sessions(user_id, transaction).await?;
+    }
+    Ok(result.rows_affected() == 1)
+}
+
+/// Returns `false` if the user does not exist.
+#[tracing::instrument(name = "Delete user", skip(transaction))]
+pub async fn delete_user_account(
+    user_id: Uuid,
+    transaction: &mut Transaction<'static, Postgres>,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(r#"DELETE FROM users WHERE user_id = $1"#, user_id)
+        .execute(transaction)
+        .await
+        .context("Failed to delete the user.")?;
+    Ok(result.rows_affected() == 1)
+}
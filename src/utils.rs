@@ -1,11 +1,16 @@
-use actix_web::HttpResponse;
+use actix_web::{dev::ServiceRequest, web, FromRequest, HttpMessage, HttpResponse};
+use actix_web_lab::util::fork_request_payload;
 use reqwest::header::LOCATION;
 
+use crate::problem::Problem;
+
+/// A failure on our side, answered with a problem document that does not
+/// reveal the cause.
 pub fn e500<T>(e: T) -> actix_web::Error
 where
     T: std::fmt::Debug + std::fmt::Display + 'static,
 {
-    actix_web::error::ErrorInternalServerError(e)
+    Problem::internal(e).into()
 }
 
 pub fn see_other(location: &str) -> HttpResponse {
@@ -13,3 +18,14 @@ pub fn see_other(location: &str) -> HttpResponse {
         .insert_header((LOCATION, location))
         .finish()
 }
+
+/// Read the body of a request in a middleware, leaving it in place for the
+/// handler.
+pub async fn peek_body(req: &mut ServiceRequest) -> Result<web::Bytes, actix_web::Error> {
+    let mut payload = req.take_payload();
+    let replay = fork_request_payload(&mut payload);
+    let body = web::Bytes::from_request(req.request(), &mut payload).await?;
+    drop(payload);
+    req.set_payload(replay);
+    Ok(body)
+}
@@ -0,0 +1,116 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+fn list_form(slug: &str) -> serde_json::Value {
+    serde_json::json!({
+        "slug": slug,
+        "name": "Weekly digest",
+        "sender_name": "The Digest",
+        "sender_email": "digest@example.com",
+        "confirmation_subject": "Confirm your digest subscription",
+        "confirmation_message": "Thanks for signing up to the weekly digest!",
+    })
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_access_the_lists_page() {
+    let app = spawn_app().await;
+    let response = app.get_admin_lists().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn admins_can_create_a_list() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+
+    let response = app.post_lists(&list_form("weekly-digest")).await;
+    assert_is_redirect_to(&response, "/admin/lists");
+
+    let html_page = app.get_admin_lists_html().await;
+    assert!(html_page.contains("The list Weekly digest has been created."));
+    let saved = sqlx::query!("SELECT name, sender_email FROM lists WHERE slug = 'weekly-digest'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "Weekly digest");
+    assert_eq!(saved.sender_email.as_deref(), Some("digest@example.com"));
+}
+
+#[tokio::test]
+async fn a_list_slug_must_be_unique() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+
+    let response = app.post_lists(&list_form("default")).await;
+    assert_is_redirect_to(&response, "/admin/lists");
+
+    let html_page = app.get_admin_lists_html().await;
+    assert!(html_page.contains("A list with slug default already exists."));
+}
+
+#[tokio::test]
+async fn an_invalid_list_is_rejected_with_a_flash_message() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+
+    let test_cases = vec![
+        (
+            "slug",
+            "Not A Slug",
+            "Not A Slug is not a valid list identifier",
+        ),
+        ("name", " ", "The list name cannot be empty."),
+        (
+            "sender_email",
+            "not-an-email",
+            "not-an-email is not a valid subscriber email.",
+        ),
+        (
+            "confirmation_message",
+            "",
+            "The confirmation message cannot be empty.",
+        ),
+    ];
+
+    for (field, value, error_message) in test_cases {
+        let mut body = list_form("weekly-digest");
+        body[field] = value.into();
+
+        let response = app.post_lists(&body).await;
+        assert_is_redirect_to(&response, "/admin/lists");
+
+        let html_page = app.get_admin_lists_html().await;
+        assert!(
+            html_page.contains(error_message),
+            "The page did not show `{}` when {} was `{}`.",
+            error_message,
+            field,
+            value
+        );
+    }
+}
+
+#[tokio::test]
+async fn admins_can_update_a_list() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    let list_id = sqlx::query!("SELECT list_id FROM lists WHERE slug = 'default'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .list_id;
+
+    let mut body = list_form("default");
+    body["name"] = "Zero2Prod news".into();
+    let response = app.post_list(list_id, &body).await;
+    assert_is_redirect_to(&response, "/admin/lists");
+
+    let html_page = app.get_admin_lists_html().await;
+    assert!(html_page.contains("The list Zero2Prod news has been updated."));
+    let saved = sqlx::query!("SELECT name FROM lists WHERE list_id = $1", list_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "Zero2Prod news");
+}
@@ -0,0 +1,150 @@
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_access_the_subscribers_page() {
+    let app = spawn_app().await;
+    let response = app.get_admin_subscribers().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn admins_can_set_the_tags_of_a_subscriber() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    let subscriber_id = insert_confirmed_subscriber(&app, "ursula@example.com").await;
+
+    let response = app
+        .post_subscriber_tags(subscriber_id, &serde_json::json!({ "tags": "Rust, vip" }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/subscribers");
+
+    let html_page = app.get_admin_subscribers_html().await;
+    assert!(html_page.contains("The subscriber&#x27;s tags have been updated."));
+    let saved = sqlx::query!("SELECT tags FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.tags, vec!["rust", "vip"]);
+}
+
+#[tokio::test]
+async fn invalid_tags_are_rejected_with_a_flash_message() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    let subscriber_id = insert_confirmed_subscriber(&app, "ursula@example.com").await;
+
+    let response = app
+        .post_subscriber_tags(subscriber_id, &serde_json::json!({ "tags": "not valid" }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/subscribers");
+
+    let html_page = app.get_admin_subscribers_html().await;
+    assert!(html_page.contains("not valid is not a valid subscriber tag"));
+}
+
+#[tokio::test]
+async fn imported_subscribers_are_confirmed_and_tagged() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    insert_confirmed_subscriber(&app, "ursula@example.com").await;
+
+    let response = app
+        .post_import_subscribers(&serde_json::json!({
+            "subscribers": "ursula@example.com,Ursula\nocto@example.com,Octavia Butler\n",
+            "tags": "imported",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/subscribers");
+
+    let html_page = app.get_admin_subscribers_html().await;
+    assert!(html_page.contains("Imported 2 subscriber(s)."));
+    let saved = sqlx::query!("SELECT email, status, tags FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 2);
+    assert_eq!(saved[0].email, "octo@example.com");
+    assert_eq!(saved[0].status, "confirmed");
+    assert_eq!(saved[0].tags, vec!["imported"]);
+    assert_eq!(saved[1].tags, vec!["existing", "imported"]);
+}
+
+#[tokio::test]
+async fn an_import_with_an_invalid_line_is_rejected() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+
+    let response = app
+        .post_import_subscribers(&serde_json::json!({
+            "subscribers": "ursula@example.com,Ursula\nnot-an-email,Nobody\n",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/subscribers");
+
+    let html_page = app.get_admin_subscribers_html().await;
+    assert!(html_page.contains("Line 2"));
+    let saved = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.count, 0);
+}
+
+#[tokio::test]
+async fn the_recipient_preview_counts_the_subscribers_in_the_segment() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    app.post_import_subscribers(&serde_json::json!({
+        "subscribers": "a@example.com,A\nb@example.com,B\n",
+        "tags": "rust",
+    }))
+    .await;
+    app.post_import_subscribers(&serde_json::json!({
+        "subscribers": "c@example.com,C\n",
+        "tags": "go",
+    }))
+    .await;
+
+    let response = app
+        .get_recipient_count(&serde_json::json!({ "include_tags": "rust" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["recipients"], 2);
+
+    let response = app.get_recipient_count(&serde_json::json!({})).await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["recipients"], 3);
+
+    let response = app
+        .get_recipient_count(&serde_json::json!({ "subscribed_from": "yesterday" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["code"], "invalid_segment");
+    assert_eq!(problem["errors"][0]["field"], "subscribed_from");
+
+    let response = app
+        .get_recipient_count(&serde_json::json!({ "list": "unknown" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["errors"][0]["field"], "list");
+}
+
+async fn insert_confirmed_subscriber(app: &TestApp, email: &str) -> Uuid {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO subscriptions (id, email, name, subscribed_at, status, tags)
+        VALUES ($1, $2, 'Ursula', now(), 'confirmed', '{existing}')",
+        subscriber_id,
+        email,
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to insert subscriber.");
+    subscriber_id
+}
@@ -0,0 +1,296 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, ConfirmationLinks, TestApp};
+
+const EMAIL: &str = "new-admin@example.com";
+
+/// Invite `EMAIL` as the test user and return the link sent to it.
+async fn invite(app: &TestApp, role: &str) -> ConfirmationLinks {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.user.login(app).await;
+    let response = app
+        .post_user_invitation(&serde_json::json!({ "email": EMAIL, "role": role }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    app.get_invitation_links(email_request)
+}
+
+fn token(link: &reqwest::Url) -> String {
+    link.query_pairs()
+        .find(|(key, _)| key == "token")
+        .unwrap()
+        .1
+        .into_owned()
+}
+
+fn account_form(link: &reqwest::Url, username: &str) -> serde_json::Value {
+    serde_json::json!({
+        "token": token(link),
+        "username": username,
+        "password": "a-new-password",
+        "password_check": "a-new-password",
+    })
+}
+
+async fn user_id(app: &TestApp, username: &str) -> Uuid {
+    sqlx::query!("SELECT user_id FROM users WHERE username = $1", username)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .user_id
+}
+
+#[tokio::test]
+async fn only_owners_can_manage_users() {
+    let app = spawn_app().await;
+    let editor = app.add_user("editor").await;
+    editor.login(&app).await;
+
+    let response = app.get_admin_users().await;
+    assert_eq!(response.status().as_u16(), 403);
+    let response = app
+        .post_user_invitation(&serde_json::json!({ "email": EMAIL, "role": "owner" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn an_invited_user_joins_with_the_role_they_were_given() {
+    let app = spawn_app().await;
+    let links = invite(&app, "author").await;
+    assert_eq!(links.html, links.plain_text);
+    let html_page = app.get_admin_users_html().await;
+    assert!(html_page.contains(&format!("An invitation has been sent to {}.", EMAIL)));
+    assert!(html_page.contains(&format!("{} as author", EMAIL)));
+    app.post_logout().await;
+
+    let html_page = app.get_invitation(&links.html).await.text().await.unwrap();
+    assert!(html_page.contains(EMAIL));
+    let response = app
+        .post_invitation(&account_form(&links.html, "newcomer"))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "newcomer",
+            "password": "a-new-password",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let saved = sqlx::query!("SELECT email, role FROM users WHERE username = 'newcomer'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email.as_deref(), Some(EMAIL));
+    assert_eq!(saved.role, "author");
+}
+
+#[tokio::test]
+async fn inviting_and_joining_are_audited() {
+    let app = spawn_app().await;
+    let links = invite(&app, "author").await;
+    app.post_logout().await;
+    app.post_invitation(&account_form(&links.html, "newcomer"))
+        .await;
+
+    let events = sqlx::query!(
+        "SELECT actor_id, action, target, changes FROM audit_log \
+        WHERE action IN ('invite_user', 'accept_invitation') ORDER BY occurred_at"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].action, "invite_user");
+    assert_eq!(
+        events[0].actor_id,
+        Some(user_id(&app, &app.user.username).await)
+    );
+    assert_eq!(events[0].target, EMAIL);
+    assert_eq!(events[1].action, "accept_invitation");
+    assert_eq!(events[1].actor_id, Some(user_id(&app, "newcomer").await));
+    assert_eq!(events[1].target, "newcomer");
+    assert_eq!(
+        events[1].changes,
+        serde_json::json!({ "email": EMAIL, "role": "author" })
+    );
+}
+
+#[tokio::test]
+async fn an_invitation_can_only_be_used_once() {
+    let app = spawn_app().await;
+    let links = invite(&app, "viewer").await;
+    app.post_logout().await;
+    app.post_invitation(&account_form(&links.html, "newcomer"))
+        .await;
+
+    let response = app
+        .post_invitation(&account_form(&links.html, "someone-else"))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("This invitation link is invalid or has expired."));
+    let response = app.get_invitation(&links.html).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn an_expired_invitation_is_rejected() {
+    let app = spawn_app().await;
+    let links = invite(&app, "viewer").await;
+    app.post_logout().await;
+    sqlx::query!("UPDATE user_invitations SET invited_at = now() - interval '8 days'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app
+        .post_invitation(&account_form(&links.html, "newcomer"))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+    let users = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM users")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    // The seeded admin and the test user.
+    assert_eq!(users.count, 2);
+}
+
+#[tokio::test]
+async fn a_taken_username_keeps_the_invitation_usable() {
+    let app = spawn_app().await;
+    let links = invite(&app, "viewer").await;
+    app.post_logout().await;
+
+    let response = app
+        .post_invitation(&account_form(&links.html, &app.user.username))
+        .await;
+    assert_is_redirect_to(&response, links.html.as_str().trim_start_matches(&app.url));
+    let html_page = app.get_invitation(&links.html).await.text().await.unwrap();
+    assert!(html_page.contains(&format!(
+        "The username {} is already taken.",
+        app.user.username
+    )));
+
+    let response = app
+        .post_invitation(&account_form(&links.html, "newcomer"))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn addresses_with_an_account_cannot_be_invited() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    app.post_account_email(&serde_json::json!({ "email": EMAIL }))
+        .await;
+
+    let response = app
+        .post_user_invitation(&serde_json::json!({ "email": EMAIL, "role": "viewer" }))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = app.get_admin_users_html().await;
+    assert!(html_page.contains(&format!("{} already has an account.", EMAIL)));
+}
+
+#[tokio::test]
+async fn deactivated_users_are_logged_out_until_reactivated() {
+    let app = spawn_app().await;
+    let editor = app.add_user("editor").await;
+    let editor_client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+    let login = |client: &reqwest::Client| {
+        client
+            .post(format!("{}/login", app.url))
+            .form(&serde_json::json!({
+                "username": editor.username,
+                "password": editor.password,
+            }))
+            .send()
+    };
+    let dashboard = format!("{}/admin/dashboard", app.url);
+    login(&editor_client).await.unwrap();
+    app.user.login(&app).await;
+
+    let editor_id = user_id(&app, &editor.username).await;
+    let response = app
+        .post_user_action(editor_id, "deactivate", &serde_json::json!({}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    let response = editor_client.get(&dashboard).send().await.unwrap();
+    assert_is_redirect_to(&response, "/login");
+    let response = login(&editor_client).await.unwrap();
+    assert_is_redirect_to(&response, "/login");
+
+    app.post_user_action(editor_id, "reactivate", &serde_json::json!({}))
+        .await;
+    let response = login(&editor_client).await.unwrap();
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn owners_can_change_roles_and_delete_users() {
+    let app = spawn_app().await;
+    let viewer = app.add_user("viewer").await;
+    let viewer_id = user_id(&app, &viewer.username).await;
+    app.user.login(&app).await;
+
+    app.post_user_action(viewer_id, "role", &serde_json::json!({ "role": "editor" }))
+        .await;
+    let saved = sqlx::query!("SELECT role FROM users WHERE user_id = $1", viewer_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.role, "editor");
+
+    let response = app
+        .post_user_action(viewer_id, "delete", &serde_json::json!({}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = app.get_admin_users_html().await;
+    assert!(html_page.contains("The user has been deleted."));
+    assert!(!html_page.contains(&viewer.username));
+}
+
+#[tokio::test]
+async fn owners_cannot_lock_themselves_out() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    let own_id = user_id(&app, &app.user.username).await;
+
+    for (action, message) in [
+        ("deactivate", "You cannot deactivate your own account."),
+        ("delete", "You cannot delete your own account."),
+    ] {
+        let response = app
+            .post_user_action(own_id, action, &serde_json::json!({}))
+            .await;
+        assert_is_redirect_to(&response, "/admin/users");
+        let html_page = app.get_admin_users_html().await;
+        assert!(html_page.contains(message));
+    }
+    let response = app
+        .post_user_action(own_id, "role", &serde_json::json!({ "role": "viewer" }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
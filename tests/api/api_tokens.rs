@@ -0,0 +1,251 @@
+use reqwest::Method;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+fn issue() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    })
+}
+
+async fn publish(app: &TestApp, token: &str) -> reqwest::Response {
+    app.api_request(Method::POST, "/newsletters")
+        .bearer_auth(token)
+        .json(&issue())
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn error_message(response: reqwest::Response) -> String {
+    let body: serde_json::Value = response.json().await.unwrap();
+    body["detail"].as_str().unwrap().to_owned()
+}
+
+#[tokio::test]
+async fn requests_without_a_valid_token_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_request(Method::GET, "/subscribers")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(response.headers()["WWW-Authenticate"], "Bearer");
+    assert_eq!(error_message(response).await, "Missing bearer token.");
+
+    let response = app
+        .api_request(Method::GET, "/subscribers")
+        .bearer_auth("z2p_not-a-real-token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(error_message(response).await, "Invalid API token.");
+}
+
+#[tokio::test]
+async fn a_session_cookie_is_not_enough_for_the_api() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/api/v1/newsletters", &app.url))
+        .json(&issue())
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn a_token_with_the_issues_scope_can_publish() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    let token = app.create_api_token(&["issues:write"]).await;
+
+    let response = publish(&app, &token).await;
+
+    assert_eq!(response.status().as_u16(), 202);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let saved = sqlx::query!("SELECT newsletter_issue_id, title FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.title, "Newsletter title");
+    assert_eq!(
+        body["newsletter_issue_id"],
+        saved.newsletter_issue_id.to_string()
+    );
+}
+
+#[tokio::test]
+async fn invalid_issues_are_rejected_with_a_bad_request() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    let token = app.create_api_token(&["issues:write"]).await;
+    let mut body = issue();
+    body["list"] = "no-such-list".into();
+
+    let response = app
+        .api_request(Method::POST, "/newsletters")
+        .bearer_auth(&token)
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn a_token_cannot_be_used_outside_its_scopes() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    let token = app.create_api_token(&["subscribers:read"]).await;
+
+    let response = publish(&app, &token).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(
+        error_message(response).await,
+        "The API token needs the issues:write scope."
+    );
+}
+
+#[tokio::test]
+async fn a_token_cannot_do_more_than_the_role_of_its_owner() {
+    let app = spawn_app().await;
+    let viewer = app.add_user("viewer").await;
+    viewer.login(&app).await;
+    let token = app.create_api_token(&["issues:write"]).await;
+
+    let response = publish(&app, &token).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(
+        error_message(response).await,
+        "The owner of the API token needs the editor role."
+    );
+}
+
+#[tokio::test]
+async fn a_token_with_the_subscribers_scope_can_list_subscribers() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    app.user.login(&app).await;
+    let token = app.create_api_token(&["subscribers:read"]).await;
+
+    let response = app
+        .api_request(Method::GET, "/subscribers")
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let subscribers: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(subscribers[0]["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(subscribers[0]["status"], "pending_confirmation");
+}
+
+#[tokio::test]
+async fn tokens_are_shown_once_and_stored_hashed() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    let token = app.create_api_token(&["issues:write"]).await;
+    assert!(token.starts_with("z2p_"));
+
+    let saved = sqlx::query!("SELECT token_hash, last_used_at FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(saved.token_hash, token);
+    assert!(saved.last_used_at.is_none());
+    let html_page = app.get_admin_api_tokens_html().await;
+    assert!(html_page.contains("test token"));
+    assert!(!html_page.contains(&token));
+
+    publish(&app, &token).await;
+    let saved = sqlx::query!("SELECT last_used_at FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.last_used_at.is_some());
+}
+
+#[tokio::test]
+async fn a_token_needs_at_least_one_scope() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+
+    let response = app.post_api_token(&[("name", "no scopes")]).await;
+
+    assert_is_redirect_to(&response, "/admin/api_tokens");
+    let html_page = app.get_admin_api_tokens_html().await;
+    assert!(html_page.contains("The token needs at least one scope."));
+}
+
+#[tokio::test]
+async fn revoked_tokens_stop_working() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    let token = app.create_api_token(&["issues:write"]).await;
+    let api_token_id = sqlx::query!("SELECT api_token_id FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .api_token_id;
+
+    let response = app
+        .api_client
+        .post(format!(
+            "{}/admin/api_tokens/{}/revoke",
+            &app.url, api_token_id
+        ))
+        .header("X-CSRF-Token", app.csrf_token().await)
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/api_tokens");
+    let html_page = app.get_admin_api_tokens_html().await;
+    assert!(html_page.contains("The API token has been revoked."));
+
+    let response = publish(&app, &token).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn tokens_of_deactivated_users_stop_working() {
+    let app = spawn_app().await;
+    let editor = app.add_user("editor").await;
+    editor.login(&app).await;
+    let token = app.create_api_token(&["issues:write"]).await;
+    sqlx::query!(
+        "UPDATE users SET deactivated_at = now() WHERE username = $1",
+        editor.username
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = publish(&app, &token).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
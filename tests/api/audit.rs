@@ -0,0 +1,290 @@
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+async fn user_id(app: &TestApp, username: &str) -> Uuid {
+    sqlx::query!("SELECT user_id FROM users WHERE username = $1", username)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .user_id
+}
+
+async fn changes_of(app: &TestApp, action: &str) -> serde_json::Value {
+    sqlx::query!("SELECT changes FROM audit_log WHERE action = $1", action)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .changes
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_audit_log() {
+    let app = spawn_app().await;
+
+    let response = app.get_admin_audit("").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn only_owners_can_see_the_audit_log() {
+    let app = spawn_app().await;
+    let editor = app.add_user("editor").await;
+    editor.login(&app).await;
+
+    assert_eq!(app.get_admin_audit("").await.status().as_u16(), 403);
+    assert_eq!(app.get_audit_export("").await.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn logins_and_failed_logins_are_recorded() {
+    let app = spawn_app().await;
+    app.post_login(&serde_json::json!({
+        "username": app.user.username,
+        "password": "not-the-password",
+    }))
+    .await;
+    app.user.login(&app).await;
+
+    let events = sqlx::query!(
+        "SELECT actor_username, action, target, ip FROM audit_log ORDER BY occurred_at"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].action, "failed_login");
+    assert_eq!(events[0].actor_username, None);
+    assert_eq!(events[0].target, app.user.username);
+    assert_eq!(events[1].action, "login");
+    assert_eq!(events[1].actor_username.as_ref(), Some(&app.user.username));
+    assert_eq!(events[1].ip.as_deref(), Some("127.0.0.1"));
+
+    let html_page = app.get_admin_audit_html("").await;
+    assert_eq!(html_page.matches("class=\"audit-event\"").count(), 2);
+}
+
+#[tokio::test]
+async fn publishing_records_the_author_and_an_event() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+
+    app.post_newsletters(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+
+    let issue = sqlx::query!("SELECT newsletter_issue_id, author_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        issue.author_id,
+        Some(user_id(&app, &app.user.username).await)
+    );
+    let changes = changes_of(&app, "publish_issue").await;
+    assert_eq!(
+        changes["newsletter_issue_id"],
+        issue.newsletter_issue_id.to_string()
+    );
+    assert_eq!(changes["list"], "default");
+}
+
+#[tokio::test]
+async fn role_changes_are_recorded_with_the_previous_role() {
+    let app = spawn_app().await;
+    let viewer = app.add_user("viewer").await;
+    app.user.login(&app).await;
+
+    app.post_user_action(
+        user_id(&app, &viewer.username).await,
+        "role",
+        &serde_json::json!({ "role": "editor" }),
+    )
+    .await;
+
+    assert_eq!(
+        changes_of(&app, "change_role").await,
+        serde_json::json!({ "role": { "from": "viewer", "to": "editor" } })
+    );
+    let html_page = app.get_admin_audit_html("action=change_role").await;
+    assert!(html_page.contains(&viewer.username));
+}
+
+#[tokio::test]
+async fn password_changes_are_recorded() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+
+    app.post_change_password(&serde_json::json!({
+        "current_password": app.user.password,
+        "new_password": "violet-tractor-sings",
+        "new_password_check": "violet-tractor-sings",
+    }))
+    .await;
+
+    let html_page = app.get_admin_audit_html("action=change_password").await;
+    assert_eq!(html_page.matches("class=\"audit-event\"").count(), 1);
+}
+
+#[tokio::test]
+async fn account_email_changes_are_recorded() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+
+    app.post_account_email(&serde_json::json!({ "email": "first@example.com" }))
+        .await;
+    app.post_account_email(&serde_json::json!({ "email": "second@example.com" }))
+        .await;
+
+    let changes: Vec<serde_json::Value> = sqlx::query!(
+        "SELECT changes FROM audit_log WHERE action = 'change_account_email' \
+        ORDER BY occurred_at"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.changes)
+    .collect();
+    assert_eq!(
+        changes,
+        [
+            serde_json::json!({ "email": { "from": null, "to": "first@example.com" } }),
+            serde_json::json!({
+                "email": { "from": "first@example.com", "to": "second@example.com" }
+            }),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn api_tokens_are_recorded_when_created_and_revoked() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+
+    app.create_api_token(&["issues:write"]).await;
+    let api_token_id = sqlx::query!("SELECT api_token_id FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .api_token_id;
+    app.api_client
+        .post(format!(
+            "{}/admin/api_tokens/{}/revoke",
+            &app.url, api_token_id
+        ))
+        .header("X-CSRF-Token", app.csrf_token().await)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(
+        changes_of(&app, "create_api_token").await,
+        serde_json::json!({ "scopes": ["issues:write"] })
+    );
+    assert_eq!(
+        changes_of(&app, "revoke_api_token").await,
+        serde_json::json!({ "api_token_id": api_token_id })
+    );
+    let html_page = app.get_admin_audit_html("action=revoke_api_token").await;
+    assert!(html_page.contains("test token"));
+}
+
+#[tokio::test]
+async fn list_changes_are_recorded() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    let mut body = serde_json::json!({
+        "slug": "weekly-digest",
+        "name": "Weekly digest",
+        "confirmation_subject": "Confirm your digest subscription",
+        "confirmation_message": "Thanks for signing up to the weekly digest!",
+    });
+
+    app.post_lists(&body).await;
+    let list_id = sqlx::query!("SELECT list_id FROM lists WHERE slug = 'weekly-digest'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .list_id;
+    body["name"] = "Monthly digest".into();
+    body["sender_email"] = "digest@example.com".into();
+    app.post_list(list_id, &body).await;
+
+    assert_eq!(
+        changes_of(&app, "create_list").await,
+        serde_json::json!({ "name": "Weekly digest" })
+    );
+    assert_eq!(
+        changes_of(&app, "update_list").await,
+        serde_json::json!({
+            "name": { "from": "Weekly digest", "to": "Monthly digest" },
+            "sender_email": { "from": null, "to": "digest@example.com" },
+        })
+    );
+}
+
+#[tokio::test]
+async fn the_audit_log_can_be_filtered() {
+    let app = spawn_app().await;
+    let editor = app.add_user("editor").await;
+    editor.login(&app).await;
+    app.user.login(&app).await;
+
+    let html_page = app
+        .get_admin_audit_html(&format!("actor={}", app.user.username))
+        .await;
+    assert_eq!(html_page.matches("class=\"audit-event\"").count(), 1);
+
+    let html_page = app
+        .get_admin_audit_html(&format!("target={}", &editor.username[..8].to_uppercase()))
+        .await;
+    assert_eq!(html_page.matches("class=\"audit-event\"").count(), 1);
+    assert!(html_page.contains(&editor.username));
+
+    let html_page = app.get_admin_audit_html("action=delete_user").await;
+    assert!(html_page.contains("No events match."));
+}
+
+#[tokio::test]
+async fn the_audit_log_can_be_exported_as_csv() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+
+    let response = app.get_audit_export("action=login").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/csv; charset=utf-8"
+    );
+    let csv = response.text().await.unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines[0], "occurred_at,actor,action,target,ip,changes");
+    assert_eq!(lines.len(), 2);
+    assert!(lines[1].contains(&format!(
+        ",{},login,{},127.0.0.1,{{}}",
+        app.user.username, app.user.username
+    )));
+}
+
+#[tokio::test]
+async fn the_audit_log_is_append_only() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+
+    let update = sqlx::query!("UPDATE audit_log SET target = 'someone else'")
+        .execute(&app.db_pool)
+        .await;
+    let delete = sqlx::query!("DELETE FROM audit_log")
+        .execute(&app.db_pool)
+        .await;
+
+    assert!(update.is_err());
+    assert!(delete.is_err());
+}
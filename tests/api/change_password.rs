@@ -76,6 +76,43 @@ async fn current_password_must_be_valid() {
     assert!(html_page.contains("The current password is incorrect."));
 }
 
+#[tokio::test]
+async fn new_password_must_follow_the_password_policy() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+
+    for (new_password, message) in [
+        (
+            "short".to_owned(),
+            "The password must be at least 12 characters long.",
+        ),
+        (
+            "a".repeat(129),
+            "The password must be at most 128 characters long.",
+        ),
+        (
+            format!("{}-is-me", app.user.username),
+            "The password must not contain the username.",
+        ),
+        (
+            "Password1234".to_owned(),
+            "This password is too common, please choose another one.",
+        ),
+    ] {
+        let response = app
+            .post_change_password(&serde_json::json!({
+                "current_password": &app.user.password,
+                "new_password": new_password,
+                "new_password_check": new_password,
+            }))
+            .await;
+        assert_is_redirect_to(&response, "/admin/password");
+
+        let html_page = app.get_change_password_html().await;
+        assert!(html_page.contains(message), "{}", message);
+    }
+}
+
 #[tokio::test]
 async fn changing_password_works() {
     // Arrange
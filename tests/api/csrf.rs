@@ -0,0 +1,113 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+fn list_form() -> Vec<(&'static str, &'static str)> {
+    vec![
+        ("slug", "weekly-digest"),
+        ("name", "Weekly digest"),
+        ("sender_name", "The Digest"),
+        ("sender_email", "digest@example.com"),
+        ("confirmation_subject", "Confirm your digest subscription"),
+        (
+            "confirmation_message",
+            "Thanks for signing up to the weekly digest!",
+        ),
+    ]
+}
+
+async fn post_lists_form(app: &TestApp, form: &[(&str, &str)]) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/admin/lists", &app.url))
+        .form(form)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn list_count(app: &TestApp) -> i64 {
+    sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM lists WHERE slug = 'weekly-digest'"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn admin_pages_embed_the_token_in_their_forms() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    let token = app.csrf_token().await;
+    assert_eq!(token.len(), 32);
+
+    let html_page = app.get_admin_lists_html().await;
+    assert!(html_page.contains(&format!(
+        r#"<input type="hidden" name="csrf_token" value="{}">"#,
+        token
+    )));
+}
+
+#[tokio::test]
+async fn forms_without_the_token_are_rejected() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+
+    let response = post_lists_form(&app, &list_form()).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("This form could not be verified."));
+    assert_eq!(list_count(&app).await, 0);
+}
+
+#[tokio::test]
+async fn forms_with_a_wrong_token_are_rejected() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    let mut form = list_form();
+    form.push(("csrf_token", "not-the-token-of-this-session"));
+
+    let response = post_lists_form(&app, &form).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(list_count(&app).await, 0);
+}
+
+#[tokio::test]
+async fn forms_with_the_token_are_accepted() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    let token = app.csrf_token().await;
+    let mut form = list_form();
+    form.push(("csrf_token", &token));
+
+    let response = post_lists_form(&app, &form).await;
+
+    assert_is_redirect_to(&response, "/admin/lists");
+    assert_eq!(list_count(&app).await, 1);
+}
+
+#[tokio::test]
+async fn the_token_changes_when_logging_in_again() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    let old_token = app.csrf_token().await;
+    app.post_logout().await;
+    app.user.login(&app).await;
+    let mut form = list_form();
+    form.push(("csrf_token", &old_token));
+
+    let response = post_lists_form(&app, &form).await;
+
+    assert_ne!(app.csrf_token().await, old_token);
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn anonymous_requests_are_sent_to_the_login_page_first() {
+    let app = spawn_app().await;
+
+    let response = post_lists_form(&app, &list_form()).await;
+
+    assert_is_redirect_to(&response, "/login");
+}
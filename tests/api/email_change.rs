@@ -0,0 +1,216 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+async fn mount_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+/// The most recent email sent to `recipient`.
+async fn last_email_to(app: &TestApp, recipient: &str) -> wiremock::Request {
+    app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .into_iter()
+        .rfind(|r| {
+            let body: serde_json::Value = serde_json::from_slice(&r.body).unwrap();
+            body["To"] == recipient
+        })
+        .unwrap_or_else(|| panic!("No email was sent to {}.", recipient))
+}
+
+/// Subscribe and confirm `email`, returning the preference center link.
+async fn create_confirmed_subscriber(app: &TestApp, email: &str) -> reqwest::Url {
+    let body = serde_urlencoded::to_string(serde_json::json!({
+        "name": "Ursula",
+        "email": email,
+    }))
+    .unwrap();
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = last_email_to(app, email).await;
+    let confirmation_link = app.get_confirmation_links(&email_request).html;
+    reqwest::get(confirmation_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.get_manage_links(&email_request).html
+}
+
+async fn subscriber_emails(app: &TestApp) -> Vec<String> {
+    sqlx::query!("SELECT email FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.email)
+        .collect()
+}
+
+#[tokio::test]
+async fn the_old_address_stays_in_use_until_the_new_one_is_confirmed() {
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+    let link = create_confirmed_subscriber(&app, "ursula@example.com").await;
+
+    let response = app
+        .post_change_email(&link, &serde_json::json!({ "email": "ursula@work.com" }))
+        .await;
+    assert_is_redirect_to(&response, link.as_str().trim_start_matches(&app.url));
+
+    let html_page = app.get_manage_subscription_html(&link).await;
+    assert!(html_page.contains("A confirmation link has been sent to ursula@work.com."));
+    assert_eq!(subscriber_emails(&app).await, vec!["ursula@example.com"]);
+
+    let email_request = last_email_to(&app, "ursula@work.com").await;
+    let confirmation_links = app.get_email_change_links(&email_request);
+    assert_eq!(confirmation_links.html, confirmation_links.plain_text);
+
+    let response = app
+        .api_client
+        .get(confirmation_links.html)
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, link.as_str().trim_start_matches(&app.url));
+
+    let html_page = app.get_manage_subscription_html(&link).await;
+    assert!(html_page.contains("Your email address has been updated."));
+    assert_eq!(subscriber_emails(&app).await, vec!["ursula@work.com"]);
+}
+
+#[tokio::test]
+async fn an_address_used_by_another_subscription_is_rejected() {
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+    let link = create_confirmed_subscriber(&app, "ursula@example.com").await;
+    create_confirmed_subscriber(&app, "octavia@example.com").await;
+
+    app.post_change_email(
+        &link,
+        &serde_json::json!({ "email": "octavia@example.com" }),
+    )
+    .await;
+
+    let html_page = app.get_manage_subscription_html(&link).await;
+    assert!(html_page.contains("octavia@example.com is already used by another subscription."));
+}
+
+#[tokio::test]
+async fn confirming_an_address_taken_in_the_meantime_is_a_conflict() {
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+    let ursula = create_confirmed_subscriber(&app, "ursula@example.com").await;
+    let octavia = create_confirmed_subscriber(&app, "octavia@example.com").await;
+
+    let new_email = serde_json::json!({ "email": "shared@example.com" });
+    app.post_change_email(&ursula, &new_email).await;
+    let ursula_request = last_email_to(&app, "shared@example.com").await;
+    app.post_change_email(&octavia, &new_email).await;
+    let octavia_request = last_email_to(&app, "shared@example.com").await;
+
+    let response = reqwest::get(app.get_email_change_links(&ursula_request).html)
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let response = reqwest::get(app.get_email_change_links(&octavia_request).html)
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 409);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["code"], "email_already_subscribed");
+    assert_eq!(
+        problem["detail"],
+        "shared@example.com is already used by another subscription."
+    );
+    assert_eq!(
+        subscriber_emails(&app).await,
+        vec!["octavia@example.com", "shared@example.com"]
+    );
+}
+
+#[tokio::test]
+async fn an_unknown_email_change_token_is_rejected() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!(
+        "{}/subscriptions/confirm_email?email_change_token=unknown",
+        app.url
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["code"], "invalid_email_change_token");
+}
+
+#[tokio::test]
+async fn pending_deliveries_go_to_the_new_address() {
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+    let link = create_confirmed_subscriber(&app, "ursula@example.com").await;
+
+    app.user.login(&app).await;
+    app.post_newsletters(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+    app.post_change_email(&link, &serde_json::json!({ "email": "ursula@work.com" }))
+        .await;
+    let email_request = last_email_to(&app, "ursula@work.com").await;
+    reqwest::get(app.get_email_change_links(&email_request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    app.dispatch_all_pending_deliveries().await;
+
+    let newsletter = last_email_to(&app, "ursula@work.com").await;
+    let body: serde_json::Value = serde_json::from_slice(&newsletter.body).unwrap();
+    assert_eq!(body["Subject"], "Newsletter title");
+}
+
+#[tokio::test]
+async fn admins_can_request_an_email_change_for_a_subscriber() {
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+    create_confirmed_subscriber(&app, "ursula@example.com").await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    app.user.login(&app).await;
+
+    let response = app
+        .post_subscriber_email(
+            subscriber_id,
+            &serde_json::json!({ "email": "ursula@work.com" }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/subscribers");
+
+    let html_page = app.get_admin_subscribers_html().await;
+    assert!(html_page.contains("A confirmation link has been sent to ursula@work.com."));
+    let email_request = last_email_to(&app, "ursula@work.com").await;
+    app.get_email_change_links(&email_request);
+    assert_eq!(subscriber_emails(&app).await, vec!["ursula@example.com"]);
+}
@@ -1,13 +1,16 @@
 use argon2::{password_hash::SaltString, Algorithm, Argon2, Params, PasswordHasher, Version};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use once_cell::sync::Lazy;
 use secrecy::Secret;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 use uuid::Uuid;
-use wiremock::MockServer;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
 use zero2prod::{
-    configuration::{get_configuration, DatabaseSettings},
+    configuration::{get_configuration, DatabaseSettings, OidcSettings, Settings},
     email_client::EmailClient,
     issue_delivery_worker::{try_execute_task, ExecutionOutcome},
+    signing::HmacKeys,
     startup::{get_connection_pool, Application},
     telemetry::{get_subscriber, init_subscriber},
 };
@@ -29,11 +32,16 @@ pub struct TestApp {
     pub url: String,
     pub db_pool: PgPool,
     pub email_server: MockServer,
+    /// Stands in for the OpenID Connect provider, see `spawn_app_with_sso`.
+    pub idp_server: MockServer,
     pub port: u16,
     pub user: TestUser,
     pub api_client: reqwest::Client,
     pub signup_token: Secret<String>,
     pub email_client: EmailClient,
+    pub base_url: String,
+    pub hmac_keys: HmacKeys,
+    configuration: Settings,
 }
 
 impl TestApp {
@@ -55,13 +63,52 @@ impl TestApp {
             .expect("Failed to execute request")
     }
 
+    /// Some links are mailed in the background, after the response: wait
+    /// until `count` emails went out.
+    pub async fn wait_for_emails(&self, count: usize) -> Vec<wiremock::Request> {
+        for _ in 0..100 {
+            let requests = self.email_server.received_requests().await.unwrap();
+            if requests.len() >= count {
+                return requests;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        panic!("{} emails were not sent.", count);
+    }
+
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
+        self.get_links(email_request, "/subscriptions/confirm")
+    }
+
+    /// Links to the preference center, included in every email.
+    pub fn get_manage_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
+        self.get_links(email_request, "/subscriptions/manage")
+    }
+
+    pub fn get_email_change_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
+        self.get_links(email_request, "/subscriptions/confirm_email")
+    }
+
+    pub fn get_password_reset_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
+        self.get_links(email_request, "/login/reset_password")
+    }
+
+    pub fn get_login_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
+        self.get_links(email_request, "/login/link")
+    }
+
+    pub fn get_invitation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
+        self.get_links(email_request, "/invitation")
+    }
+
+    fn get_links(&self, email_request: &wiremock::Request, path: &str) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
         // Extract the link from one of the request fields.
         let get_link = |s: &str| {
             let links: Vec<_> = linkify::LinkFinder::new()
                 .links(s)
                 .filter(|l| *l.kind() == linkify::LinkKind::Url)
+                .filter(|l| reqwest::Url::parse(l.as_str()).unwrap().path() == path)
                 .collect();
             assert_eq!(links.len(), 1);
             let raw_link = links[0].as_str().to_owned();
@@ -79,12 +126,30 @@ impl TestApp {
     pub async fn post_newsletters(&self, body: &serde_json::Value) -> reqwest::Response {
         self.api_client
             .post(&format!("{}/admin/newsletters", self.url))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_newsletter_draft(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/newsletters/drafts", self.url))
+            .header("X-CSRF-Token", self.csrf_token().await)
             .form(&body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
+    /// Store another admin user with the given role.
+    pub async fn add_user(&self, role: &'static str) -> TestUser {
+        let user = TestUser::with_role(role);
+        user.store(&self.db_pool).await;
+        user
+    }
+
     pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
@@ -97,6 +162,92 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn start_sso_login(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/login/sso", &self.url))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_sso_callback(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/login/sso/callback?{}", &self.url, query))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn mount_idp_discovery(&self) {
+        let issuer = self.idp_server.uri();
+        Mock::given(method("GET"))
+            .and(path("/.well-known/openid-configuration"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "issuer": issuer,
+                "authorization_endpoint": format!("{}/authorize", issuer),
+                "token_endpoint": format!("{}/token", issuer),
+            })))
+            .mount(&self.idp_server)
+            .await;
+    }
+
+    /// Go through the whole single sign-on flow, the provider vouching for
+    /// `claims`. See `complete_sso_login`.
+    pub async fn sso_login(&self, claims: serde_json::Value) -> reqwest::Response {
+        self.mount_idp_discovery().await;
+        let response = self.start_sso_login().await;
+        let authorization_url =
+            reqwest::Url::parse(response.headers()["Location"].to_str().unwrap()).unwrap();
+        self.complete_sso_login(&authorization_url, claims).await
+    }
+
+    /// Come back from the provider's `authorization_url` with an ID token
+    /// for `claims`. They override the valid issuer, audience, expiry, nonce
+    /// and verified email flag the token gets by default.
+    pub async fn complete_sso_login(
+        &self,
+        authorization_url: &reqwest::Url,
+        claims: serde_json::Value,
+    ) -> reqwest::Response {
+        let param = |name: &str| {
+            authorization_url
+                .query_pairs()
+                .find(|(key, _)| key == name)
+                .unwrap()
+                .1
+                .into_owned()
+        };
+
+        let mut id_token_claims = serde_json::json!({
+            "iss": self.idp_server.uri(),
+            "aud": "zero2prod",
+            "exp": chrono::Utc::now().timestamp() + 300,
+            "nonce": param("nonce"),
+            "email_verified": true,
+        });
+        for (key, value) in claims.as_object().unwrap() {
+            id_token_claims[key] = value.clone();
+        }
+        let id_token = format!(
+            "{}.{}.signature",
+            URL_SAFE_NO_PAD.encode(r#"{"alg":"RS256"}"#),
+            URL_SAFE_NO_PAD.encode(id_token_claims.to_string())
+        );
+        Mock::given(method("POST"))
+            .and(path("/token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "access_token": "access-token",
+                "token_type": "Bearer",
+                "id_token": id_token,
+            })))
+            .up_to_n_times(1)
+            .mount(&self.idp_server)
+            .await;
+
+        self.get_sso_callback(&format!("code=authorization-code&state={}", param("state")))
+            .await
+    }
+
     pub async fn get_login_html(&self) -> String {
         self.api_client
             .get(&format!("{}/login", &self.url))
@@ -108,6 +259,312 @@ impl TestApp {
             .unwrap()
     }
 
+    pub async fn get_login_two_factor(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/login/2fa", &self.url))
+            .send()
+            .await
+            .expect("Failed to send request.")
+    }
+
+    pub async fn get_login_two_factor_html(&self) -> String {
+        self.get_login_two_factor().await.text().await.unwrap()
+    }
+
+    pub async fn post_login_two_factor<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/login/2fa", &self.url))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_two_factor(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/2fa", &self.url))
+            .send()
+            .await
+            .expect("Failed to send request.")
+    }
+
+    pub async fn get_admin_two_factor_html(&self) -> String {
+        self.get_admin_two_factor().await.text().await.unwrap()
+    }
+
+    pub async fn post_enable_two_factor<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/2fa/enable", &self.url))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_disable_two_factor<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/2fa/disable", &self.url))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_forgot_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/login/forgot_password", &self.url))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_forgot_password_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/login/forgot_password", &self.url))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_login_link_request_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/login/link/request", &self.url))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_login_link_request<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/login/link/request", &self.url))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_login_link(&self, link: &reqwest::Url) -> reqwest::Response {
+        self.api_client
+            .get(link.clone())
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_login_link<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/login/link", &self.url))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_reset_password(&self, link: &reqwest::Url) -> reqwest::Response {
+        self.api_client
+            .get(link.clone())
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_reset_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/login/reset_password", &self.url))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_invitation(&self, link: &reqwest::Url) -> reqwest::Response {
+        self.api_client
+            .get(link.clone())
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_invitation<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/invitation", &self.url))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_users(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/users", &self.url))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_users_html(&self) -> String {
+        self.get_admin_users().await.text().await.unwrap()
+    }
+
+    pub async fn post_user_invitation<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/users/invitations", &self.url))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Run one of the actions of the users page, e.g. `deactivate`, on a user.
+    pub async fn post_user_action<Body>(
+        &self,
+        user_id: Uuid,
+        action: &str,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/users/{}/{}", &self.url, user_id, action))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_api_tokens_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/api_tokens", &self.url))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_api_token<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/api_tokens", &self.url))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Create an API token for the logged in user and return it.
+    pub async fn create_api_token(&self, scopes: &[&str]) -> String {
+        let mut body = vec![("name", "test token")];
+        body.extend(scopes.iter().map(|scope| ("scope", *scope)));
+        let html_page = self.post_api_token(&body).await.text().await.unwrap();
+        let start = html_page
+            .find("<code id=\"api-token\">")
+            .expect("The new token is not shown.")
+            + "<code id=\"api-token\">".len();
+        let end = start + html_page[start..].find("</code>").unwrap();
+        html_page[start..end].to_owned()
+    }
+
+    /// Call the JSON API, without any session cookie.
+    pub fn api_request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        reqwest::Client::new().request(method, format!("{}/api/v1{}", &self.url, path))
+    }
+
+    pub async fn get_admin_sessions_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/sessions", &self.url))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_sign_out_session(&self, session_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/sessions/{}/sign_out",
+                &self.url, session_id
+            ))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_sign_out_other_sessions(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/sessions/sign_out_others", &self.url))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_audit(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/audit?{}", &self.url, query))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_audit_html(&self, query: &str) -> String {
+        self.get_admin_audit(query).await.text().await.unwrap()
+    }
+
+    pub async fn get_audit_export(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/audit/export?{}", &self.url, query))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn get_admin_dashboard(&self) -> reqwest::Response {
         self.api_client
             .get(format!("{}/admin/dashboard", &self.url))
@@ -120,6 +577,19 @@ impl TestApp {
         self.get_admin_dashboard().await.text().await.unwrap()
     }
 
+    /// The CSRF token the admin forms embed, empty when not logged in.
+    pub async fn csrf_token(&self) -> String {
+        let html_page = self.get_admin_dashboard_html().await;
+        match html_page.find("name=\"csrf_token\" value=\"") {
+            Some(start) => {
+                let start = start + "name=\"csrf_token\" value=\"".len();
+                let end = start + html_page[start..].find('"').unwrap();
+                html_page[start..end].to_owned()
+            }
+            None => String::new(),
+        }
+    }
+
     pub async fn get_change_password(&self) -> reqwest::Response {
         self.api_client
             .get(format!("{}/admin/password", &self.url))
@@ -138,6 +608,20 @@ impl TestApp {
     {
         self.api_client
             .post(format!("{}/admin/password", self.url))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_account_email<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/email", self.url))
+            .header("X-CSRF-Token", self.csrf_token().await)
             .form(body)
             .send()
             .await
@@ -147,6 +631,7 @@ impl TestApp {
     pub async fn post_logout(&self) -> reqwest::Response {
         self.api_client
             .post(&format!("{}/admin/logout", &self.url))
+            .header("X-CSRF-Token", self.csrf_token().await)
             .send()
             .await
             .expect("Failed to execute request.")
@@ -167,6 +652,176 @@ impl TestApp {
             .expect("Could not get text from request")
     }
 
+    pub async fn get_recipient_count(&self, query: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/newsletters/recipients", &self.url))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to send request.")
+    }
+
+    pub async fn get_admin_subscribers(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers", &self.url))
+            .send()
+            .await
+            .expect("Failed to send request.")
+    }
+
+    pub async fn get_admin_subscribers_html(&self) -> String {
+        self.get_admin_subscribers().await.text().await.unwrap()
+    }
+
+    pub async fn post_subscriber_tags<Body>(
+        &self,
+        subscriber_id: Uuid,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/subscribers/{}/tags",
+                self.url, subscriber_id
+            ))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_subscriber_email<Body>(
+        &self,
+        subscriber_id: Uuid,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/subscribers/{}/email",
+                self.url, subscriber_id
+            ))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_import_subscribers<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/subscribers/import", self.url))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_lists(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/lists", &self.url))
+            .send()
+            .await
+            .expect("Failed to send request.")
+    }
+
+    pub async fn get_admin_lists_html(&self) -> String {
+        self.get_admin_lists().await.text().await.unwrap()
+    }
+
+    pub async fn post_lists<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/lists", self.url))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_list<Body>(&self, list_id: Uuid, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/lists/{}", self.url, list_id))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_manage_subscription(&self, link: &reqwest::Url) -> reqwest::Response {
+        self.api_client
+            .get(link.clone())
+            .send()
+            .await
+            .expect("Failed to send request.")
+    }
+
+    pub async fn get_manage_subscription_html(&self, link: &reqwest::Url) -> String {
+        self.get_manage_subscription(link)
+            .await
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// Preferences are sent as pairs since each selected list is its own field.
+    pub async fn post_manage_subscription(
+        &self,
+        link: &reqwest::Url,
+        body: &[(&str, &str)],
+    ) -> reqwest::Response {
+        self.api_client
+            .post(link.clone())
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_change_email<Body>(
+        &self,
+        link: &reqwest::Url,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let mut link = link.clone();
+        link.set_path("/subscriptions/manage/email");
+        self.api_client
+            .post(link)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_unsubscribe(&self, link: &reqwest::Url) -> reqwest::Response {
+        let mut link = link.clone();
+        link.set_path("/subscriptions/manage/unsubscribe");
+        self.api_client
+            .post(link)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn get_signup(&self) -> reqwest::Response {
         self.api_client
             .get(format!("{}/signup", self.url))
@@ -195,12 +850,29 @@ impl TestApp {
             .expect("Failed to send request.")
     }
 
+    /// Replace the running application with one using new settings, on the
+    /// same database, as a deployment would. Cookies are kept.
+    pub async fn redeploy_with(&mut self, configure: impl FnOnce(&mut Settings)) {
+        configure(&mut self.configuration);
+        let application = Application::build(self.configuration.clone())
+            .await
+            .expect("Failed to build application.");
+        self.port = application.port();
+        self.url = format!("http://127.0.0.1:{}", application.port());
+        self.hmac_keys = self.configuration.application.hmac_keys();
+        tokio::spawn(application.run_until_stopped());
+    }
+
     pub async fn dispatch_all_pending_deliveries(&self) {
         loop {
-            if let ExecutionOutcome::EmptyQueue =
-                try_execute_task(&self.db_pool, &self.email_client)
-                    .await
-                    .unwrap()
+            if let ExecutionOutcome::EmptyQueue = try_execute_task(
+                &self.db_pool,
+                &self.email_client,
+                &self.base_url,
+                &self.hmac_keys,
+            )
+            .await
+            .unwrap()
             {
                 break;
             }
@@ -211,13 +883,19 @@ impl TestApp {
 pub struct TestUser {
     pub username: String,
     pub password: String,
+    pub role: &'static str,
 }
 
 impl TestUser {
     fn new() -> Self {
+        Self::with_role("owner")
+    }
+
+    fn with_role(role: &'static str) -> Self {
         Self {
             username: Uuid::new_v4().to_string(),
             password: Uuid::new_v4().to_string(),
+            role,
         }
     }
 
@@ -233,11 +911,12 @@ impl TestUser {
         .to_string();
 
         sqlx::query!(
-            "INSERT INTO users (user_id, username, password)
-            VALUES ($1, $2, $3)",
+            "INSERT INTO users (user_id, username, password, role)
+            VALUES ($1, $2, $3, $4)",
             Uuid::new_v4(),
             self.username,
             password_hash,
+            self.role,
         )
         .execute(pool)
         .await
@@ -254,17 +933,41 @@ impl TestUser {
     }
 }
 
-/// Confirmation links embedded in the request to the email API.
+/// Links embedded in the request to the email API.
 pub struct ConfirmationLinks {
     pub html: reqwest::Url,
     pub plain_text: reqwest::Url,
 }
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_, _| {}).await
+}
+
+/// Single sign-on through `idp_server`. Members of the `newsletter-editors`
+/// group are editors.
+pub async fn spawn_app_with_sso(provision_users: bool) -> TestApp {
+    spawn_app_with(|c, idp_server| {
+        c.application.oidc = Some(OidcSettings {
+            issuer: idp_server.uri(),
+            client_id: "zero2prod".into(),
+            client_secret: Secret::new("client-secret".into()),
+            provider_name: "Example SSO".into(),
+            provision_users,
+            default_role: "viewer".into(),
+            groups_claim: "groups".into(),
+            group_roles: [("newsletter-editors".into(), "editor".into())].into(),
+        });
+    })
+    .await
+}
+
+/// Like `spawn_app`, with a chance to change the configuration first.
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings, &MockServer)) -> TestApp {
     Lazy::force(&TRACING);
 
     // Launch a mock server to stand in for Postmark's API
     let email_server = MockServer::start().await;
+    let idp_server = MockServer::start().await;
     let signup_token = Secret::new(Uuid::new_v4().to_string());
 
     let configuration = {
@@ -273,7 +976,14 @@ pub async fn spawn_app() -> TestApp {
         c.application.port = 0;
         c.email_client.base_url = email_server.uri();
         c.application.signup.enabled = true;
-        c.application.signup.token = signup_token.clone();
+        c.application.signup.token = Some(signup_token.clone());
+        // Each app gets its own failed login counters in the shared Redis.
+        c.application.login_throttling.key_prefix = format!("login_throttling:{}", Uuid::new_v4());
+        c.application.login_throttling.base_delay_milliseconds = 10;
+        c.application.login_throttling.max_delay_milliseconds = 100;
+        c.application.login_throttling.ip_failures_before_lockout = 15;
+        c.application.login_throttling.alert_recipients = vec!["alerts@example.com".into()];
+        configure(&mut c, &idp_server);
         c
     };
 
@@ -301,11 +1011,15 @@ pub async fn spawn_app() -> TestApp {
         url,
         db_pool,
         email_server,
+        idp_server,
         port,
         user: test_user,
         api_client,
         signup_token,
-        email_client: configuration.email_client.client(),
+        email_client: configuration.email_client.clone().client(),
+        base_url: configuration.application.base_url.clone(),
+        hmac_keys: configuration.application.hmac_keys(),
+        configuration,
     }
 }
 
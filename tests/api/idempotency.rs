@@ -0,0 +1,269 @@
+use reqwest::Method;
+use zero2prod::idempotency::delete_expired_keys;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+/// An issue without the `idempotency_key` field, leaving it to the header.
+fn issue() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    })
+}
+
+fn publish_request(
+    app: &TestApp,
+    token: &str,
+    idempotency_key: Option<&str>,
+    issue: &serde_json::Value,
+) -> reqwest::RequestBuilder {
+    let request = app
+        .api_request(Method::POST, "/newsletters")
+        .bearer_auth(token)
+        .json(issue);
+    match idempotency_key {
+        Some(key) => request.header("Idempotency-Key", key),
+        None => request,
+    }
+}
+
+async fn publish(app: &TestApp, token: &str, idempotency_key: Option<&str>) -> reqwest::Response {
+    publish_request(app, token, idempotency_key, &issue())
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn error_message(response: reqwest::Response) -> String {
+    let body: serde_json::Value = response.json().await.unwrap();
+    body["detail"].as_str().unwrap().to_owned()
+}
+
+async fn count_issues(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT count(*) as "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+async fn api_token(app: &TestApp) -> String {
+    app.user.login(app).await;
+    app.create_api_token(&["issues:write"]).await
+}
+
+#[tokio::test]
+async fn repeating_an_idempotency_key_returns_the_saved_response() {
+    let app = spawn_app().await;
+    let token = api_token(&app).await;
+    let key = uuid::Uuid::new_v4().to_string();
+
+    let first = publish(&app, &token, Some(&key)).await;
+    let second = publish(&app, &token, Some(&key)).await;
+
+    assert_eq!(first.status().as_u16(), 202);
+    assert_eq!(second.status().as_u16(), 202);
+    assert_eq!(first.text().await.unwrap(), second.text().await.unwrap());
+    assert_eq!(count_issues(&app).await, 1);
+}
+
+#[tokio::test]
+async fn different_idempotency_keys_are_processed_separately() {
+    let app = spawn_app().await;
+    let token = api_token(&app).await;
+
+    publish(&app, &token, Some(&uuid::Uuid::new_v4().to_string())).await;
+    publish(&app, &token, Some(&uuid::Uuid::new_v4().to_string())).await;
+
+    assert_eq!(count_issues(&app).await, 2);
+}
+
+#[tokio::test]
+async fn requests_without_an_idempotency_key_are_processed_every_time() {
+    let app = spawn_app().await;
+    let token = api_token(&app).await;
+
+    publish(&app, &token, None).await;
+    publish(&app, &token, None).await;
+
+    assert_eq!(count_issues(&app).await, 2);
+}
+
+#[tokio::test]
+async fn an_invalid_idempotency_key_is_rejected() {
+    let app = spawn_app().await;
+    let token = api_token(&app).await;
+
+    for key in ["", &"a".repeat(50)] {
+        let response = publish(&app, &token, Some(key)).await;
+
+        assert_eq!(response.status().as_u16(), 400);
+        assert!(error_message(response)
+            .await
+            .starts_with("Invalid Idempotency-Key header"));
+    }
+    assert_eq!(count_issues(&app).await, 0);
+}
+
+#[tokio::test]
+async fn reusing_a_key_for_a_different_request_is_refused() {
+    let app = spawn_app().await;
+    let token = api_token(&app).await;
+    let key = uuid::Uuid::new_v4().to_string();
+    publish(&app, &token, Some(&key)).await;
+    let mut other_issue = issue();
+    other_issue["title"] = "Another title".into();
+
+    let response = publish_request(&app, &token, Some(&key), &other_issue)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 422);
+    assert_eq!(
+        error_message(response).await,
+        "This idempotency key was already used for a different request."
+    );
+
+    // The same body sent with a query string is another request too.
+    let response = publish_request(&app, &token, Some(&key), &issue())
+        .query(&[("dry_run", "true")])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 422);
+    assert_eq!(count_issues(&app).await, 1);
+}
+
+#[tokio::test]
+async fn a_retry_while_the_request_is_processed_gets_a_conflict() {
+    let app = spawn_app().await;
+    let token = api_token(&app).await;
+    let key = uuid::Uuid::new_v4().to_string();
+    // Hold the first request up when it stores the issue.
+    let mut lock = app.db_pool.begin().await.unwrap();
+    sqlx::query("LOCK TABLE newsletter_issues IN EXCLUSIVE MODE")
+        .execute(&mut lock)
+        .await
+        .unwrap();
+    let first = tokio::spawn(publish_request(&app, &token, Some(&key), &issue()).send());
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+
+    let retry = publish(&app, &token, Some(&key)).await;
+
+    assert_eq!(retry.status().as_u16(), 409);
+    assert_eq!(retry.headers()["Retry-After"], "1");
+    lock.commit().await.unwrap();
+    assert_eq!(first.await.unwrap().unwrap().status().as_u16(), 202);
+    assert_eq!(
+        publish(&app, &token, Some(&key)).await.status().as_u16(),
+        202
+    );
+    assert_eq!(count_issues(&app).await, 1);
+}
+
+#[tokio::test]
+async fn reusing_a_form_key_for_a_different_issue_is_refused() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    let mut body = issue();
+    body["idempotency_key"] = uuid::Uuid::new_v4().to_string().into();
+    app.post_newsletters(&body).await;
+    body["title"] = "Another title".into();
+
+    let response = app.post_newsletters(&body).await;
+
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    assert!(app
+        .get_newsletter_publish_html()
+        .await
+        .contains("This idempotency key was already used for a different request."));
+    assert_eq!(count_issues(&app).await, 1);
+}
+
+#[tokio::test]
+async fn idempotency_keys_also_apply_to_admin_forms() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    let key = uuid::Uuid::new_v4().to_string();
+
+    for _ in 0..2 {
+        app.api_client
+            .post(format!("{}/admin/newsletters", &app.url))
+            .header("X-CSRF-Token", app.csrf_token().await)
+            .header("Idempotency-Key", &key)
+            .form(&issue())
+            .send()
+            .await
+            .expect("Failed to execute request.");
+    }
+
+    assert_eq!(count_issues(&app).await, 1);
+}
+
+async fn age_idempotency_keys(app: &TestApp, hours: i32) {
+    sqlx::query!(
+        "UPDATE idempotency SET created_at = created_at - make_interval(hours => $1)",
+        hours
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn an_expired_key_is_processed_again() {
+    let app = spawn_app().await;
+    let token = api_token(&app).await;
+    let key = uuid::Uuid::new_v4().to_string();
+    publish(&app, &token, Some(&key)).await;
+    age_idempotency_keys(&app, 25).await;
+    let mut other_issue = issue();
+    other_issue["title"] = "Another title".into();
+
+    let response = publish_request(&app, &token, Some(&key), &other_issue)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 202);
+    assert_eq!(count_issues(&app).await, 2);
+    // The key is honored again from then on.
+    publish_request(&app, &token, Some(&key), &other_issue)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(count_issues(&app).await, 2);
+}
+
+#[tokio::test]
+async fn expired_keys_are_deleted_in_batches() {
+    let app = spawn_app().await;
+    let token = api_token(&app).await;
+    for _ in 0..2 {
+        publish(&app, &token, Some(&uuid::Uuid::new_v4().to_string())).await;
+    }
+    age_idempotency_keys(&app, 25).await;
+    let live_key = uuid::Uuid::new_v4().to_string();
+    publish(&app, &token, Some(&live_key)).await;
+    let retention = std::time::Duration::from_secs(24 * 60 * 60);
+
+    let mut deleted = vec![];
+    for _ in 0..3 {
+        deleted.push(
+            delete_expired_keys(&app.db_pool, retention, 1)
+                .await
+                .unwrap(),
+        );
+    }
+
+    assert_eq!(deleted, [1, 1, 0]);
+    let remaining = sqlx::query!("SELECT idempotency_key FROM idempotency")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].idempotency_key, live_key);
+}
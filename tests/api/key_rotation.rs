@@ -0,0 +1,83 @@
+use secrecy::Secret;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+const NEW_SECRET: &str =
+    "a-new-hmac-secret-that-replaces-the-old-one-and-is-also-longer-than-64-chars";
+
+/// Redeploy with a new HMAC secret, keeping the old one for verification.
+async fn rotate_secret(app: &mut TestApp) {
+    app.redeploy_with(|c| {
+        let previous = std::mem::replace(
+            &mut c.application.hmac_secret,
+            Secret::new(NEW_SECRET.to_string()),
+        );
+        c.application.previous_hmac_secrets = vec![previous];
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn sessions_survive_a_secret_rotation() {
+    let mut app = spawn_app().await;
+    app.user.login(&app).await;
+
+    rotate_secret(&mut app).await;
+
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", app.user.username)));
+}
+
+#[tokio::test]
+async fn sessions_end_once_the_previous_secret_is_dropped() {
+    let mut app = spawn_app().await;
+    app.user.login(&app).await;
+
+    app.redeploy_with(|c| c.application.hmac_secret = Secret::new(NEW_SECRET.to_string()))
+        .await;
+
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn flash_messages_survive_a_secret_rotation() {
+    let mut app = spawn_app().await;
+    app.post_login(&serde_json::json!({
+        "username": "random-username",
+        "password": "random-password",
+    }))
+    .await;
+
+    rotate_secret(&mut app).await;
+
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("Invalid username or password"));
+}
+
+#[tokio::test]
+async fn links_signed_before_a_secret_rotation_still_work() {
+    let mut app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let mut link = app.get_manage_links(email_request).html;
+
+    rotate_secret(&mut app).await;
+    link.set_port(Some(app.port)).unwrap();
+
+    let response = app.get_manage_subscription(&link).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -1,4 +1,10 @@
-use crate::helpers::{assert_is_redirect_to, spawn_app};
+use argon2::{password_hash::SaltString, Algorithm, Argon2, Params, PasswordHasher, Version};
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
 
 #[tokio::test]
 async fn an_error_flash_message_is_set_on_failure() {
@@ -35,3 +41,180 @@ async fn redirect_to_admin_dashboard_on_login() {
     let html_page = app.get_admin_dashboard_html().await;
     assert!(html_page.contains(&format!("Welcome {}", app.user.username)))
 }
+
+async fn fail_logins(app: &TestApp, username: &str, attempts: u32) {
+    for _ in 0..attempts {
+        let response = app
+            .post_login(&serde_json::json!({
+                "username": username,
+                "password": "wrong-password",
+            }))
+            .await;
+        assert_is_redirect_to(&response, "/login");
+    }
+}
+
+#[tokio::test]
+async fn repeated_failures_lock_the_account() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    fail_logins(&app, &app.user.username, 10).await;
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Too many failed login attempts. Try again in 15 minutes."));
+
+    // Even the right password is turned away while the lockout lasts.
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": app.user.username,
+            "password": app.user.password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Too many failed login attempts."));
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn an_alert_is_sent_when_an_account_is_locked() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    fail_logins(&app, &app.user.username, 10).await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "alerts@example.com");
+    assert!(body["TextBody"].as_str().unwrap().contains(&format!(
+        "The account {} has been locked",
+        app.user.username
+    )));
+}
+
+#[tokio::test]
+async fn no_alert_is_sent_for_unknown_usernames() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    fail_logins(&app, "unknown-username", 10).await;
+
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Too many failed login attempts."));
+}
+
+#[tokio::test]
+async fn a_successful_login_resets_the_failures_of_the_account() {
+    let app = spawn_app().await;
+
+    // Together more than the account limit, but below the address limit.
+    fail_logins(&app, &app.user.username, 6).await;
+    app.user.login(&app).await;
+    app.post_logout().await;
+    fail_logins(&app, &app.user.username, 6).await;
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": app.user.username,
+            "password": app.user.password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn repeated_failures_from_one_address_lock_it_out() {
+    let app = spawn_app().await;
+
+    for i in 0..15 {
+        fail_logins(&app, &format!("username-{}", i), 1).await;
+    }
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": app.user.username,
+            "password": app.user.password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Too many failed login attempts."));
+}
+
+async fn stored_password_hash(app: &TestApp) -> String {
+    sqlx::query!(
+        "SELECT password FROM users WHERE username = $1",
+        app.user.username
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .password
+}
+
+#[tokio::test]
+async fn an_outdated_password_hash_is_upgraded_on_login() {
+    let app = spawn_app().await;
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let outdated_hash = Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        Params::new(8192, 1, 1, None).unwrap(),
+    )
+    .hash_password(app.user.password.as_bytes(), &salt)
+    .unwrap()
+    .to_string();
+    sqlx::query!(
+        "UPDATE users SET password = $1 WHERE username = $2",
+        outdated_hash,
+        app.user.username
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": app.user.username,
+            "password": "not-the-password",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    assert_eq!(stored_password_hash(&app).await, outdated_hash);
+
+    app.user.login(&app).await;
+    let upgraded_hash = stored_password_hash(&app).await;
+    assert!(upgraded_hash.starts_with("$argon2id$v=19$m=15000,t=2,p=1$"));
+
+    app.post_logout().await;
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": app.user.username,
+            "password": app.user.password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn a_current_password_hash_is_left_alone_on_login() {
+    let app = spawn_app().await;
+    let hash = stored_password_hash(&app).await;
+
+    app.user.login(&app).await;
+
+    assert_eq!(stored_password_hash(&app).await, hash);
+}
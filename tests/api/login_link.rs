@@ -0,0 +1,234 @@
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+const EMAIL: &str = "editor@example.com";
+
+/// Give the test user an address and ask for a sign-in link sent to it.
+async fn request_login_link(app: &TestApp) -> reqwest::Url {
+    sqlx::query!(
+        "UPDATE users SET email = $1 WHERE username = $2",
+        EMAIL,
+        app.user.username
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let sent = app.email_server.received_requests().await.unwrap().len();
+    let response = app
+        .post_login_link_request(&serde_json::json!({ "email": EMAIL }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let requests = app.wait_for_emails(sent + 1).await;
+    app.get_login_links(requests.last().unwrap()).html
+}
+
+fn param(link: &reqwest::Url, name: &str) -> String {
+    link.query_pairs()
+        .find(|(key, _)| key == name)
+        .unwrap()
+        .1
+        .into_owned()
+}
+
+async fn use_link(app: &TestApp, link: &reqwest::Url) -> reqwest::Response {
+    app.post_login_link(&serde_json::json!({
+        "token": param(link, "token"),
+        "signature": param(link, "signature"),
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn the_login_page_links_to_the_sign_in_link_form() {
+    let app = spawn_app().await;
+
+    let html_page = app.get_login_html().await;
+
+    assert!(html_page.contains(r#"href="/login/link/request""#));
+}
+
+#[tokio::test]
+async fn a_sign_in_link_logs_the_user_in() {
+    let app = spawn_app().await;
+    let link = request_login_link(&app).await;
+
+    // Following the link only asks for confirmation.
+    let response = app.get_login_link(&link).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains(&app.user.username));
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+
+    let response = use_link(&app, &link).await;
+
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", app.user.username)));
+    let event = sqlx::query!("SELECT changes FROM audit_log WHERE action = 'login'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(event.changes["method"], "link");
+}
+
+#[tokio::test]
+async fn an_unknown_address_gets_the_same_answer_but_no_email() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_login_link_request(&serde_json::json!({ "email": "nobody@example.com" }))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page
+        .contains("If an account uses nobody@example.com, a sign-in link has been sent to it."));
+}
+
+#[tokio::test]
+async fn only_a_few_links_are_sent_to_an_address() {
+    let app = spawn_app().await;
+    for _ in 0..3 {
+        request_login_link(&app).await;
+    }
+
+    let response = app
+        .post_login_link_request(&serde_json::json!({ "email": EMAIL }))
+        .await;
+
+    assert_is_redirect_to(&response, "/login/link/request");
+    let html_page = app.get_login_link_request_html().await;
+    assert!(html_page.contains("Too many sign-in links were requested."));
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    assert_eq!(app.email_server.received_requests().await.unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn a_sign_in_link_can_only_be_used_once() {
+    let app = spawn_app().await;
+    let link = request_login_link(&app).await;
+    assert_is_redirect_to(&use_link(&app, &link).await, "/admin/dashboard");
+    app.post_logout().await;
+
+    let response = use_link(&app, &link).await;
+
+    assert_is_redirect_to(&response, "/login");
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("This sign-in link is invalid or has expired."));
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn only_the_latest_sign_in_link_can_be_used() {
+    let app = spawn_app().await;
+    let first_link = request_login_link(&app).await;
+    let second_link = request_login_link(&app).await;
+
+    assert_is_redirect_to(&use_link(&app, &first_link).await, "/login");
+    assert_is_redirect_to(&use_link(&app, &second_link).await, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn an_expired_sign_in_link_is_rejected() {
+    let app = spawn_app().await;
+    let link = request_login_link(&app).await;
+    sqlx::query!("UPDATE login_link_tokens SET requested_at = now() - interval '16 minutes'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    assert_is_redirect_to(&app.get_login_link(&link).await, "/login");
+    assert_is_redirect_to(&use_link(&app, &link).await, "/login");
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn a_link_with_a_forged_signature_is_rejected() {
+    let app = spawn_app().await;
+    let link = request_login_link(&app).await;
+
+    let response = app
+        .post_login_link(&serde_json::json!({
+            "token": param(&link, "token"),
+            "signature": "00".repeat(32),
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+    // The link itself was not used up.
+    assert_is_redirect_to(&use_link(&app, &link).await, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn a_sign_in_link_goes_through_the_second_factor() {
+    let app = spawn_app().await;
+    let link = request_login_link(&app).await;
+    sqlx::query!(
+        "UPDATE users SET totp_secret = 'JBSWY3DPEHPK3PXP' WHERE username = $1",
+        app.user.username
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = use_link(&app, &link).await;
+
+    assert_is_redirect_to(&response, "/login/2fa");
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn a_locked_out_account_cannot_sign_in_with_a_link() {
+    let app = spawn_app().await;
+    let link = request_login_link(&app).await;
+    for _ in 0..10 {
+        app.post_login(&serde_json::json!({
+            "username": app.user.username,
+            "password": "wrong-password",
+        }))
+        .await;
+    }
+
+    let response = use_link(&app, &link).await;
+
+    assert_is_redirect_to(&response, "/login");
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("Too many failed login attempts."));
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn deactivated_users_cannot_sign_in_with_a_link() {
+    let app = spawn_app().await;
+    let link = request_login_link(&app).await;
+    sqlx::query!(
+        "UPDATE users SET deactivated_at = now() WHERE username = $1",
+        app.user.username
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    assert_is_redirect_to(&use_link(&app, &link).await, "/login");
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
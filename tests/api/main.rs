@@ -1,10 +1,28 @@
 mod admin_dashboard;
+mod admin_lists;
 mod admin_newsletters;
+mod admin_subscribers;
+mod admin_users;
+mod api_tokens;
+mod audit;
 mod change_password;
+mod csrf;
+mod email_change;
 mod health_check;
 mod helpers;
+mod idempotency;
+mod key_rotation;
 mod login;
+mod login_link;
 mod newsletter;
+mod password_reset;
+mod problem_details;
+mod rest_api;
+mod roles;
+mod sessions;
 mod signup;
+mod sso;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_manage;
+mod two_factor;
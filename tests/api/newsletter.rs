@@ -76,6 +76,99 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     app.dispatch_all_pending_deliveries().await;
 }
 
+#[tokio::test]
+async fn newsletter_emails_link_to_the_preference_center() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    app.post_newsletters(&newsletter_request_body).await;
+    app.dispatch_all_pending_deliveries().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let links = app.get_manage_links(&email_request);
+    assert_eq!(links.html, links.plain_text);
+    let response = app.get_manage_subscription(&links.html).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn subscribers_who_are_no_longer_eligible_at_send_time_are_skipped() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    app.post_newsletters(&newsletter_request_body).await;
+
+    // One subscriber leaves the list and another pauses delivery while the
+    // issue is still queued.
+    let subscribers = sqlx::query!("SELECT id FROM subscriptions ORDER BY id")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query!(
+        "DELETE FROM list_subscriptions WHERE subscriber_id = $1",
+        subscribers[0].id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "UPDATE subscriptions SET paused_until = now() + interval '1 week' WHERE id = $1",
+        subscribers[1].id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    app.dispatch_all_pending_deliveries().await;
+
+    let skipped =
+        sqlx::query!("SELECT subscriber_id, reason FROM skipped_deliveries ORDER BY subscriber_id")
+            .fetch_all(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(skipped.len(), 2);
+    assert_eq!(skipped[0].subscriber_id, subscribers[0].id);
+    assert_eq!(skipped[0].reason, "not_subscribed");
+    assert_eq!(skipped[1].subscriber_id, subscribers[1].id);
+    assert_eq!(skipped[1].reason, "paused");
+}
+
 #[tokio::test]
 async fn newsletter_returns_400_for_invalid_data() {
     // Arrange
@@ -204,13 +297,288 @@ async fn concurrent_form_submission_is_handled_gracefully() {
     app.dispatch_all_pending_deliveries().await;
 }
 
+#[tokio::test]
+async fn newsletters_are_only_delivered_to_the_selected_segment() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_tags(&app, "rust").await;
+    create_confirmed_subscriber_with_tags(&app, "rust,churned").await;
+    create_confirmed_subscriber_with_tags(&app, "go").await;
+    app.user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "include_tags": "rust",
+        "exclude_tags": "churned",
+    });
+
+    let response = app.post_newsletters(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_deliveries().await;
+}
+
+#[tokio::test]
+async fn the_recipient_preview_matches_the_deliveries_of_a_segment() {
+    let app = spawn_app().await;
+    for tags in ["rust", "rust,churned", "go", "rust,go", "python"] {
+        create_confirmed_subscriber_with_tags(&app, tags).await;
+    }
+    app.user.login(&app).await;
+    let segment = serde_json::json!({
+        "include_tags": "rust,go",
+        "exclude_tags": "churned",
+    });
+
+    let response = app.get_recipient_count(&segment).await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let mut newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    newsletter_request_body
+        .as_object_mut()
+        .unwrap()
+        .extend(segment.as_object().unwrap().clone());
+    let response = app.post_newsletters(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let deliveries = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(body["recipients"], 3);
+    assert_eq!(body["recipients"], deliveries);
+}
+
+#[tokio::test]
+async fn newsletters_are_not_sent_for_an_invalid_segment() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "subscribed_from": "2023-06-30",
+        "subscribed_until": "2023-06-01",
+    });
+
+    let response = app.post_newsletters(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let html_page = app.get_newsletter_publish_html().await;
+    assert!(html_page.contains("is after its end"));
+    app.dispatch_all_pending_deliveries().await;
+}
+
+#[tokio::test]
+async fn newsletters_are_only_delivered_to_the_selected_list() {
+    let app = spawn_app().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO lists (list_id, slug, name, confirmation_subject, confirmation_message, created_at)
+        VALUES ($1, 'weekly-digest', 'Weekly digest', 'Welcome!', 'Welcome!', now())
+        "#,
+        uuid::Uuid::new_v4(),
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber_on_list(&app, "weekly-digest").await;
+    create_unconfirmed_subscriber_on_list(&app, "weekly-digest").await;
+    app.user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "list": "weekly-digest",
+    });
+
+    let response = app.post_newsletters(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_deliveries().await;
+}
+
+#[tokio::test]
+async fn newsletters_are_not_sent_to_an_unknown_list() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "list": "does-not-exist",
+    });
+
+    let response = app.post_newsletters(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let html_page = app.get_newsletter_publish_html().await;
+    assert!(html_page.contains("does-not-exist is not a known list."));
+    app.dispatch_all_pending_deliveries().await;
+}
+
+#[tokio::test]
+async fn drafts_are_not_sent() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let author = app.add_user("author").await;
+    author.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletter_draft(&serde_json::json!({
+            "title": "Draft title",
+            "text_content": "Draft body as plain text",
+            "html_content": "<p>Draft body as HTML</p>",
+        }))
+        .await;
+    let draft = sqlx::query!("SELECT newsletter_issue_id, published_at FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_is_redirect_to(
+        &response,
+        &format!("/admin/newsletters?draft={}", draft.newsletter_issue_id),
+    );
+    assert!(draft.published_at.is_none());
+
+    let html_page = app.get_newsletter_publish_html().await;
+    assert!(html_page.contains("The draft has been saved."));
+    assert!(html_page.contains("Draft title"));
+    app.dispatch_all_pending_deliveries().await;
+}
+
+#[tokio::test]
+async fn editors_can_publish_a_draft() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let author = app.add_user("author").await;
+    author.login(&app).await;
+    app.post_newsletter_draft(&serde_json::json!({
+        "title": "Draft title",
+        "text_content": "Draft body as plain text",
+        "html_content": "<p>Draft body as HTML</p>",
+    }))
+    .await;
+    let draft_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let editor = app.add_user("editor").await;
+    editor.login(&app).await;
+    let newsletter_request_body = serde_json::json!({
+        "draft_id": draft_id,
+        "title": "Final title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = app.post_newsletters(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_deliveries().await;
+
+    let issue = sqlx::query!("SELECT title, published_at FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.title, "Final title");
+    assert!(issue.published_at.is_some());
+
+    // A second attempt with a new key does not send it again.
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "draft_id": draft_id,
+            "title": "Final title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_newsletter_publish_html().await;
+    assert!(html_page.contains("This draft has already been published."));
+    app.dispatch_all_pending_deliveries().await;
+}
+
 async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+    create_unconfirmed_subscriber_with_tags(app, "").await
+}
+
+async fn create_unconfirmed_subscriber_with_tags(app: &TestApp, tags: &str) -> ConfirmationLinks {
+    create_unconfirmed_subscriber_with(app, tags, "").await
+}
+
+async fn create_unconfirmed_subscriber_on_list(app: &TestApp, list: &str) -> ConfirmationLinks {
+    create_unconfirmed_subscriber_with(app, "", list).await
+}
+
+async fn create_unconfirmed_subscriber_with(
+    app: &TestApp,
+    tags: &str,
+    list: &str,
+) -> ConfirmationLinks {
     let name: String = Name().fake();
     let email: String = SafeEmail().fake();
 
     let body = serde_urlencoded::to_string(serde_json::json!({
         "name": name,
-        "email": email
+        "email": email,
+        "tags": tags,
+        "list": list,
     }))
     .unwrap();
 
@@ -238,7 +606,21 @@ async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
 }
 
 async fn create_confirmed_subscriber(app: &TestApp) {
-    let confirmation_link = create_unconfirmed_subscriber(app).await;
+    create_confirmed_subscriber_with_tags(app, "").await;
+}
+
+async fn create_confirmed_subscriber_with_tags(app: &TestApp, tags: &str) {
+    let confirmation_link = create_unconfirmed_subscriber_with_tags(app, tags).await;
+
+    reqwest::get(confirmation_link.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn create_confirmed_subscriber_on_list(app: &TestApp, list: &str) {
+    let confirmation_link = create_unconfirmed_subscriber_on_list(app, list).await;
 
     reqwest::get(confirmation_link.html)
         .await
@@ -0,0 +1,282 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, ConfirmationLinks, TestApp};
+
+const EMAIL: &str = "admin@example.com";
+
+/// Give the test user an address and ask for a reset link sent to it.
+async fn request_reset_link(app: &TestApp) -> ConfirmationLinks {
+    app.user.login(app).await;
+    app.post_account_email(&serde_json::json!({ "email": EMAIL }))
+        .await;
+    app.post_logout().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let sent = app.email_server.received_requests().await.unwrap().len();
+    let response = app
+        .post_forgot_password(&serde_json::json!({ "email": EMAIL }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let requests = app.wait_for_emails(sent + 1).await;
+    app.get_password_reset_links(requests.last().unwrap())
+}
+
+fn token(link: &reqwest::Url) -> String {
+    link.query_pairs()
+        .find(|(key, _)| key == "token")
+        .unwrap()
+        .1
+        .into_owned()
+}
+
+async fn login_with(app: &TestApp, password: &str) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "username": app.user.username,
+        "password": password,
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn admins_can_set_their_email_address() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+
+    let response = app
+        .post_account_email(&serde_json::json!({ "email": EMAIL }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/password");
+
+    let html_page = app.get_change_password_html().await;
+    assert!(html_page.contains("Your email address has been changed."));
+    assert!(html_page.contains(&format!(r#"value="{}""#, EMAIL)));
+}
+
+#[tokio::test]
+async fn an_unknown_address_gets_the_same_answer_but_no_email() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_forgot_password(&serde_json::json!({ "email": "nobody@example.com" }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains(
+        "If an account uses nobody@example.com, a password reset link has been sent to it."
+    ));
+}
+
+#[tokio::test]
+async fn only_a_few_reset_links_are_sent_to_an_address() {
+    let app = spawn_app().await;
+    for _ in 0..3 {
+        request_reset_link(&app).await;
+    }
+
+    let response = app
+        .post_forgot_password(&serde_json::json!({ "email": EMAIL }))
+        .await;
+
+    assert_is_redirect_to(&response, "/login/forgot_password");
+    let html_page = app.get_forgot_password_html().await;
+    assert!(html_page.contains("Too many password reset links were requested."));
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    assert_eq!(app.email_server.received_requests().await.unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn the_reset_link_sets_a_new_password() {
+    let app = spawn_app().await;
+    let links = request_reset_link(&app).await;
+    assert_eq!(links.html, links.plain_text);
+
+    let response = app.get_reset_password(&links.html).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let new_password = Uuid::new_v4().to_string();
+    let response = app
+        .post_reset_password(&serde_json::json!({
+            "token": token(&links.html),
+            "new_password": new_password,
+            "new_password_check": new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Your password has been reset. Please log in."));
+
+    let response = login_with(&app, &app.user.password).await;
+    assert_is_redirect_to(&response, "/login");
+    let response = login_with(&app, &new_password).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn a_reset_link_can_only_be_used_once() {
+    let app = spawn_app().await;
+    let links = request_reset_link(&app).await;
+    let body = serde_json::json!({
+        "token": token(&links.html),
+        "new_password": "a-new-password",
+        "new_password_check": "a-new-password",
+    });
+    app.post_reset_password(&body).await;
+
+    let response = app.post_reset_password(&body).await;
+    assert_is_redirect_to(&response, "/login/forgot_password");
+    let response = app.get_reset_password(&links.html).await;
+    assert_is_redirect_to(&response, "/login/forgot_password");
+    let html_page = app.get_forgot_password_html().await;
+    assert!(html_page.contains("This password reset link is invalid or has expired."));
+}
+
+#[tokio::test]
+async fn an_expired_reset_link_is_rejected() {
+    let app = spawn_app().await;
+    let links = request_reset_link(&app).await;
+    sqlx::query!("UPDATE password_reset_tokens SET requested_at = now() - interval '2 hours'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app
+        .post_reset_password(&serde_json::json!({
+            "token": token(&links.html),
+            "new_password": "a-new-password",
+            "new_password_check": "a-new-password",
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/login/forgot_password");
+    let response = login_with(&app, "a-new-password").await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_new_passwords_must_match() {
+    let app = spawn_app().await;
+    let links = request_reset_link(&app).await;
+
+    let response = app
+        .post_reset_password(&serde_json::json!({
+            "token": token(&links.html),
+            "new_password": "a-new-password",
+            "new_password_check": "another-password",
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, links.html.as_str().trim_start_matches(&app.url));
+    let html_page = app
+        .get_reset_password(&links.html)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("The new passwords did not match."));
+}
+
+#[tokio::test]
+async fn a_password_against_the_policy_keeps_the_link_usable() {
+    let app = spawn_app().await;
+    let links = request_reset_link(&app).await;
+
+    let response = app
+        .post_reset_password(&serde_json::json!({
+            "token": token(&links.html),
+            "new_password": "qwerty123456",
+            "new_password_check": "qwerty123456",
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, links.html.as_str().trim_start_matches(&app.url));
+    let html_page = app
+        .get_reset_password(&links.html)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("This password is too common, please choose another one."));
+    let response = login_with(&app, "qwerty123456").await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn resetting_the_password_logs_out_every_session() {
+    let app = spawn_app().await;
+    let links = request_reset_link(&app).await;
+    // A session opened elsewhere before the reset.
+    let other_client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+    other_client
+        .post(format!("{}/login", app.url))
+        .form(&serde_json::json!({
+            "username": app.user.username,
+            "password": app.user.password,
+        }))
+        .send()
+        .await
+        .unwrap();
+    let dashboard = format!("{}/admin/dashboard", app.url);
+    let response = other_client.get(&dashboard).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.post_reset_password(&serde_json::json!({
+        "token": token(&links.html),
+        "new_password": "a-new-password",
+        "new_password_check": "a-new-password",
+    }))
+    .await;
+
+    let response = other_client.get(&dashboard).send().await.unwrap();
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_reset_link_no_longer_works_once_the_user_is_deactivated() {
+    let app = spawn_app().await;
+    let links = request_reset_link(&app).await;
+    sqlx::query!(
+        "UPDATE users SET deactivated_at = now() WHERE username = $1",
+        app.user.username
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    assert_is_redirect_to(
+        &app.get_reset_password(&links.html).await,
+        "/login/forgot_password",
+    );
+    let response = app
+        .post_reset_password(&serde_json::json!({
+            "token": token(&links.html),
+            "new_password": "a-new-password",
+            "new_password_check": "a-new-password",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login/forgot_password");
+    // The token was not used up.
+    let tokens = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM password_reset_tokens"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(tokens.count, 1);
+}
@@ -0,0 +1,148 @@
+use reqwest::Method;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+const PROBLEM_JSON: &str = "application/problem+json";
+
+fn content_type(response: &reqwest::Response) -> &str {
+    response.headers()["Content-Type"].to_str().unwrap()
+}
+
+async fn post_invalid_subscription(app: &TestApp, accept: &str) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/subscriptions", &app.url))
+        .header("Accept", accept)
+        .form(&serde_json::json!({"name": "Ursula", "email": "not-an-email"}))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn validation_errors_name_the_invalid_field() {
+    let app = spawn_app().await;
+
+    let response = post_invalid_subscription(&app, "application/json").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(content_type(&response), PROBLEM_JSON);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["type"], "about:blank");
+    assert_eq!(problem["title"], "Bad Request");
+    assert_eq!(problem["status"], 400);
+    assert_eq!(problem["code"], "validation_failed");
+    assert_eq!(problem["errors"][0]["field"], "email");
+    assert!(problem["detail"].as_str().unwrap().contains("not-an-email"));
+}
+
+#[tokio::test]
+async fn missing_form_fields_are_validation_errors() {
+    let app = spawn_app().await;
+
+    let response = app.post_subscriptions("name=le%20guin".into()).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(content_type(&response), PROBLEM_JSON);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["code"], "validation_failed");
+    assert_eq!(problem["errors"][0]["field"], "email");
+}
+
+#[tokio::test]
+async fn browsers_get_problems_as_an_html_page() {
+    let app = spawn_app().await;
+
+    let response = post_invalid_subscription(
+        &app,
+        "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
+    )
+    .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(content_type(&response).starts_with("text/html"));
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Bad Request"));
+    assert!(html_page.contains("not-an-email"));
+}
+
+#[tokio::test]
+async fn browsers_get_middleware_failures_as_an_html_page() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    // Sabotage the database: checking the session now fails.
+    sqlx::query!("ALTER TABLE users DROP COLUMN session_epoch;")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app
+        .api_client
+        .get(format!("{}/admin/dashboard", &app.url))
+        .header("Accept", "text/html")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 500);
+    assert!(content_type(&response).starts_with("text/html"));
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Internal Server Error"));
+}
+
+#[tokio::test]
+async fn unknown_confirmation_tokens_are_unauthorized_problems() {
+    let app = spawn_app().await;
+
+    let response = app
+        .confirm_subscription("subscription_token=unknown".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(content_type(&response), PROBLEM_JSON);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["code"], "invalid_subscription_token");
+}
+
+#[tokio::test]
+async fn json_clients_get_login_failures_as_problems() {
+    let app = spawn_app().await;
+    let login_body = serde_json::json!({
+        "username": app.user.username,
+        "password": "wrong-password",
+    });
+
+    let response = app
+        .api_client
+        .post(format!("{}/login", &app.url))
+        .header("Accept", "application/json")
+        .form(&login_body)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(content_type(&response), PROBLEM_JSON);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["code"], "invalid_credentials");
+
+    // Other clients are still sent back to the login form.
+    let response = app.post_login(&login_body).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn api_authentication_failures_are_problems() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_request(Method::GET, "/subscribers")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(content_type(&response), PROBLEM_JSON);
+    assert_eq!(response.headers()["WWW-Authenticate"], "Bearer");
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["code"], "missing_api_token");
+}
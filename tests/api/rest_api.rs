@@ -0,0 +1,292 @@
+use reqwest::Method;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{spawn_app, TestApp};
+
+async fn api_token(app: &TestApp, scopes: &[&str]) -> String {
+    app.user.login(app).await;
+    app.create_api_token(scopes).await
+}
+
+async fn mock_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+async fn create_subscriber(
+    app: &TestApp,
+    token: &str,
+    subscriber: &serde_json::Value,
+) -> reqwest::Response {
+    app.api_request(Method::POST, "/subscribers")
+        .bearer_auth(token)
+        .json(subscriber)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+fn subscriber(n: usize) -> serde_json::Value {
+    serde_json::json!({
+        "email": format!("subscriber{}@example.com", n),
+        "name": format!("Subscriber {}", n),
+    })
+}
+
+async fn get(app: &TestApp, token: &str, path: &str) -> reqwest::Response {
+    app.api_request(Method::GET, path)
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn post(
+    app: &TestApp,
+    token: &str,
+    path: &str,
+    body: &serde_json::Value,
+) -> reqwest::Response {
+    app.api_request(Method::POST, path)
+        .bearer_auth(token)
+        .json(body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+fn total_count(response: &reqwest::Response) -> &str {
+    response.headers()["X-Total-Count"].to_str().unwrap()
+}
+
+async fn error_message(response: reqwest::Response) -> String {
+    let body: serde_json::Value = response.json().await.unwrap();
+    body["detail"].as_str().unwrap().to_owned()
+}
+
+#[tokio::test]
+async fn subscribers_are_listed_a_page_at_a_time() {
+    let app = spawn_app().await;
+    mock_email_server(&app).await;
+    let token = api_token(&app, &["subscribers:read", "subscribers:write"]).await;
+    for n in 0..3 {
+        create_subscriber(&app, &token, &subscriber(n)).await;
+    }
+
+    let first_page = get(&app, &token, "/subscribers?limit=2").await;
+    let second_page = get(&app, &token, "/subscribers?limit=2&offset=2").await;
+
+    assert_eq!(total_count(&first_page), "3");
+    assert_eq!(total_count(&second_page), "3");
+    let first_page: Vec<serde_json::Value> = first_page.json().await.unwrap();
+    let second_page: Vec<serde_json::Value> = second_page.json().await.unwrap();
+    assert_eq!(first_page.len(), 2);
+    assert_eq!(first_page[0]["email"], "subscriber0@example.com");
+    assert_eq!(second_page.len(), 1);
+    assert_eq!(second_page[0]["email"], "subscriber2@example.com");
+}
+
+#[tokio::test]
+async fn subscribers_can_be_filtered() {
+    let app = spawn_app().await;
+    mock_email_server(&app).await;
+    let token = api_token(&app, &["subscribers:read", "subscribers:write"]).await;
+    let mut tagged = subscriber(0);
+    tagged["tags"] = serde_json::json!(["rust"]);
+    create_subscriber(&app, &token, &tagged).await;
+    create_subscriber(&app, &token, &subscriber(1)).await;
+
+    let by_tag = get(&app, &token, "/subscribers?tag=Rust").await;
+    let by_status = get(&app, &token, "/subscribers?status=confirmed").await;
+    let by_list = get(&app, &token, "/subscribers?list=default").await;
+
+    assert_eq!(total_count(&by_tag), "1");
+    let by_tag: Vec<serde_json::Value> = by_tag.json().await.unwrap();
+    assert_eq!(by_tag[0]["email"], "subscriber0@example.com");
+    assert_eq!(total_count(&by_status), "0");
+    assert_eq!(total_count(&by_list), "2");
+}
+
+#[tokio::test]
+async fn invalid_query_parameters_are_rejected_in_json() {
+    let app = spawn_app().await;
+    let token = api_token(&app, &["subscribers:read"]).await;
+
+    for path in ["/subscribers?status=bogus", "/subscribers?limit=-1"] {
+        let response = get(&app, &token, path).await;
+
+        assert_eq!(response.status().as_u16(), 400, "{}", path);
+        assert!(!error_message(response).await.is_empty());
+    }
+}
+
+#[tokio::test]
+async fn a_subscriber_added_through_the_api_gets_a_confirmation_email() {
+    let app = spawn_app().await;
+    mock_email_server(&app).await;
+    let token = api_token(&app, &["subscribers:read", "subscribers:write"]).await;
+
+    let response = create_subscriber(&app, &token, &subscriber(0)).await;
+
+    assert_eq!(response.status().as_u16(), 201);
+    let location = response.headers()["Location"].to_str().unwrap().to_owned();
+    let created: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(created["status"], "pending_confirmation");
+    assert_eq!(
+        location,
+        format!("/api/v1/subscribers/{}", created["id"].as_str().unwrap())
+    );
+    assert_eq!(app.email_server.received_requests().await.unwrap().len(), 1);
+
+    let response = get(&app, &token, location.trim_start_matches("/api/v1")).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let fetched: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(fetched, created);
+}
+
+#[tokio::test]
+async fn invalid_subscribers_are_rejected_with_a_bad_request() {
+    let app = spawn_app().await;
+    let token = api_token(&app, &["subscribers:write"]).await;
+    let test_cases = [
+        (
+            serde_json::json!({"email": "not-an-email", "name": "Ursula"}),
+            "invalid email",
+        ),
+        (
+            serde_json::json!({"email": "ursula@example.com", "name": ""}),
+            "empty name",
+        ),
+        (
+            serde_json::json!({"email": "ursula@example.com", "name": "Ursula", "tags": ["a b"]}),
+            "invalid tag",
+        ),
+        (
+            serde_json::json!({"email": "ursula@example.com"}),
+            "missing name",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        let response = create_subscriber(&app, &token, &body).await;
+
+        assert_eq!(response.status().as_u16(), 400, "{}", description);
+        assert!(!error_message(response).await.is_empty(), "{}", description);
+    }
+}
+
+#[tokio::test]
+async fn adding_subscribers_needs_the_write_scope() {
+    let app = spawn_app().await;
+    let token = api_token(&app, &["subscribers:read"]).await;
+
+    let response = create_subscriber(&app, &token, &subscriber(0)).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(
+        error_message(response).await,
+        "The API token needs the subscribers:write scope."
+    );
+}
+
+#[tokio::test]
+async fn unknown_subscribers_and_issues_are_not_found() {
+    let app = spawn_app().await;
+    let token = api_token(&app, &["subscribers:read", "issues:read"]).await;
+    let id = uuid::Uuid::new_v4();
+
+    for path in [format!("/subscribers/{}", id), format!("/issues/{}", id)] {
+        let response = get(&app, &token, &path).await;
+
+        assert_eq!(response.status().as_u16(), 404, "{}", path);
+    }
+}
+
+#[tokio::test]
+async fn drafts_created_through_the_api_can_be_published() {
+    let app = spawn_app().await;
+    let token = api_token(&app, &["issues:read", "issues:write"]).await;
+    let draft = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    });
+
+    let response = post(&app, &token, "/issues", &draft).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let created: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(created["status"], "draft");
+    assert_eq!(created["list"], "default");
+    assert!(created["published_at"].is_null());
+    let issue_id = created["newsletter_issue_id"].as_str().unwrap();
+
+    let drafts = get(&app, &token, "/issues?status=draft").await;
+    assert_eq!(total_count(&drafts), "1");
+
+    let publish_path = format!("/issues/{}/publish", issue_id);
+    let response = post(&app, &token, &publish_path, &serde_json::json!({})).await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    let published: serde_json::Value = get(&app, &token, &format!("/issues/{}", issue_id))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(published["status"], "published");
+    assert_eq!(published["list"], "default");
+    assert!(published["published_at"].is_string());
+    let drafts = get(&app, &token, "/issues?status=draft").await;
+    assert_eq!(total_count(&drafts), "0");
+
+    let response = post(&app, &token, &publish_path, &serde_json::json!({})).await;
+    assert_eq!(response.status().as_u16(), 409);
+    assert_eq!(
+        error_message(response).await,
+        "This draft has already been published."
+    );
+}
+
+#[tokio::test]
+async fn reading_issues_needs_the_read_scope() {
+    let app = spawn_app().await;
+    let token = api_token(&app, &["issues:write"]).await;
+
+    let response = get(&app, &token, "/issues").await;
+
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(
+        error_message(response).await,
+        "The API token needs the issues:read scope."
+    );
+}
+
+#[tokio::test]
+async fn the_api_is_described_in_the_openapi_document() {
+    let app = spawn_app().await;
+
+    let document: serde_json::Value = app
+        .api_client
+        .get(format!("{}/api-docs/openapi.json", &app.url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    for path in [
+        "/api/v1/subscribers",
+        "/api/v1/subscribers/{subscriber_id}",
+        "/api/v1/issues",
+        "/api/v1/issues/{newsletter_issue_id}",
+        "/api/v1/issues/{newsletter_issue_id}/publish",
+    ] {
+        assert!(document["paths"][path].is_object(), "{} is missing", path);
+    }
+}
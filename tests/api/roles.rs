@@ -0,0 +1,142 @@
+use secrecy::ExposeSecret;
+use uuid::Uuid;
+use wiremock::{matchers::any, Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+fn newsletter_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    })
+}
+
+/// Check for a 403 page and return its body.
+async fn forbidden_page(response: reqwest::Response) -> String {
+    assert_eq!(response.status().as_u16(), 403);
+    response.text().await.unwrap()
+}
+
+#[tokio::test]
+async fn every_role_can_see_the_admin_pages() {
+    let app = spawn_app().await;
+    let viewer = app.add_user("viewer").await;
+    viewer.login(&app).await;
+
+    for response in [
+        app.get_admin_dashboard().await,
+        app.get_newsletter_publish().await,
+        app.get_admin_subscribers().await,
+        app.get_admin_lists().await,
+    ] {
+        assert_eq!(response.status().as_u16(), 200);
+    }
+}
+
+#[tokio::test]
+async fn authors_cannot_publish_newsletters() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let author = app.add_user("author").await;
+    author.login(&app).await;
+
+    let response = app.post_newsletters(&newsletter_body()).await;
+
+    let html_page = forbidden_page(response).await;
+    assert!(html_page.contains("You do not have permission to do this."));
+    assert!(html_page.contains("It needs the editor role."));
+    let issues = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.count, 0);
+}
+
+#[tokio::test]
+async fn viewers_cannot_save_drafts() {
+    let app = spawn_app().await;
+    let viewer = app.add_user("viewer").await;
+    viewer.login(&app).await;
+
+    let response = app.post_newsletter_draft(&newsletter_body()).await;
+
+    let html_page = forbidden_page(response).await;
+    assert!(html_page.contains("It needs the author role."));
+}
+
+#[tokio::test]
+async fn authors_cannot_manage_subscribers() {
+    let app = spawn_app().await;
+    let author = app.add_user("author").await;
+    author.login(&app).await;
+
+    let response = app
+        .post_import_subscribers(&serde_json::json!({
+            "subscribers": "ursula@example.com,Ursula",
+        }))
+        .await;
+
+    forbidden_page(response).await;
+}
+
+#[tokio::test]
+async fn editors_cannot_manage_lists() {
+    let app = spawn_app().await;
+    let editor = app.add_user("editor").await;
+    editor.login(&app).await;
+
+    let response = app
+        .post_lists(&serde_json::json!({
+            "slug": "weekly-digest",
+            "name": "Weekly digest",
+            "confirmation_subject": "Confirm your subscription",
+            "confirmation_message": "Thanks for signing up!",
+        }))
+        .await;
+
+    let html_page = forbidden_page(response).await;
+    assert!(html_page.contains("It needs the owner role."));
+}
+
+#[tokio::test]
+async fn a_role_change_applies_to_sessions_that_are_already_open() {
+    let app = spawn_app().await;
+    let editor = app.add_user("editor").await;
+    editor.login(&app).await;
+
+    sqlx::query!(
+        "UPDATE users SET role = 'viewer' WHERE username = $1",
+        editor.username
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let response = app.post_newsletters(&newsletter_body()).await;
+
+    forbidden_page(response).await;
+}
+
+#[tokio::test]
+async fn new_signups_are_viewers() {
+    let app = spawn_app().await;
+    let response = app
+        .post_signup(&serde_json::json!({
+            "username": "newcomer",
+            "password": Uuid::new_v4(),
+            "signup_token": app.signup_token.expose_secret(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let user = sqlx::query!("SELECT role FROM users WHERE username = 'newcomer'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(user.role, "viewer");
+}
@@ -0,0 +1,282 @@
+use uuid::Uuid;
+use zero2prod::configuration::SameSitePolicy;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+
+/// Another browser, logged in as the test user.
+async fn other_browser(app: &TestApp, user_agent: &str) -> reqwest::Client {
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .user_agent(user_agent)
+        .build()
+        .unwrap();
+    let response = client
+        .post(format!("{}/login", app.url))
+        .form(&serde_json::json!({
+            "username": app.user.username,
+            "password": app.user.password,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    client
+}
+
+async fn is_logged_in(app: &TestApp, client: &reqwest::Client) -> bool {
+    let response = client
+        .get(format!("{}/admin/dashboard", app.url))
+        .send()
+        .await
+        .unwrap();
+    response.status().as_u16() == 200
+}
+
+async fn session_id(app: &TestApp, user_agent: &str) -> Uuid {
+    sqlx::query!(
+        "SELECT session_id FROM user_sessions WHERE user_agent = $1",
+        user_agent
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .session_id
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_your_sessions() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/admin/sessions", app.url))
+        .send()
+        .await
+        .unwrap();
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_sessions_page_lists_every_session() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    other_browser(&app, "Phone browser").await;
+
+    let html_page = app.get_admin_sessions_html().await;
+
+    assert_eq!(html_page.matches(r#"<tr class="session">"#).count(), 2);
+    assert!(html_page.contains("Phone browser"));
+    assert!(html_page.contains("127.0.0.1"));
+    assert!(html_page.contains("This session"));
+}
+
+#[tokio::test]
+async fn another_session_can_be_signed_out() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    let phone = other_browser(&app, "Phone browser").await;
+    let laptop = other_browser(&app, "Laptop browser").await;
+
+    let response = app
+        .post_sign_out_session(session_id(&app, "Phone browser").await)
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/sessions");
+    let html_page = app.get_admin_sessions_html().await;
+    assert!(html_page.contains("The session has been signed out."));
+    assert!(!html_page.contains("Phone browser"));
+    assert!(!is_logged_in(&app, &phone).await);
+    assert!(is_logged_in(&app, &laptop).await);
+}
+
+#[tokio::test]
+async fn sessions_of_other_users_cannot_be_signed_out() {
+    let app = spawn_app().await;
+    let phone = other_browser(&app, "Phone browser").await;
+    let editor = app.add_user("editor").await;
+    editor.login(&app).await;
+
+    let response = app
+        .post_sign_out_session(session_id(&app, "Phone browser").await)
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/sessions");
+    assert!(app
+        .get_admin_sessions_html()
+        .await
+        .contains("The session does not exist."));
+    assert!(is_logged_in(&app, &phone).await);
+}
+
+#[tokio::test]
+async fn signing_out_everywhere_else_keeps_the_current_session() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    let phone = other_browser(&app, "Phone browser").await;
+
+    let response = app.post_sign_out_other_sessions().await;
+
+    assert_is_redirect_to(&response, "/admin/sessions");
+    let html_page = app.get_admin_sessions_html().await;
+    assert!(html_page.contains("Your other sessions have been signed out."));
+    assert_eq!(html_page.matches(r#"<tr class="session">"#).count(), 1);
+    assert!(!is_logged_in(&app, &phone).await);
+}
+
+#[tokio::test]
+async fn changing_the_password_signs_out_the_other_sessions() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    let phone = other_browser(&app, "Phone browser").await;
+    let new_password = Uuid::new_v4().to_string();
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/password");
+    assert!(!is_logged_in(&app, &phone).await);
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn logging_out_forgets_the_session() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+
+    app.post_logout().await;
+
+    let sessions = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM user_sessions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(sessions.count, 0);
+}
+
+async fn log_in(app: &TestApp, remember_me: bool) -> reqwest::Response {
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": app.user.username,
+            "password": app.user.password,
+            "remember_me": remember_me,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    response
+}
+
+fn session_cookie<'a>(response: &'a reqwest::Response, name: &str) -> &'a str {
+    response
+        .headers()
+        .get_all("Set-Cookie")
+        .iter()
+        .map(|value| value.to_str().unwrap())
+        .find(|value| value.starts_with(&format!("{}=", name)))
+        .expect("The session cookie was not set.")
+}
+
+/// Pretend the session started and was last used this long ago.
+async fn age_sessions(app: &TestApp, created: &str, last_seen: &str) {
+    sqlx::query!(
+        "UPDATE user_sessions
+        SET created_at = now() - $1::text::interval, last_seen_at = now() - $2::text::interval",
+        created,
+        last_seen
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn an_idle_session_expires() {
+    let app = spawn_app().await;
+    log_in(&app, false).await;
+
+    age_sessions(&app, "3 hours", "3 hours").await;
+
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn an_active_session_expires_at_the_end_of_its_lifetime() {
+    let app = spawn_app().await;
+    log_in(&app, false).await;
+
+    age_sessions(&app, "25 hours", "1 minute").await;
+
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn a_remembered_session_outlasts_the_idle_timeout_and_lifetime() {
+    let app = spawn_app().await;
+    log_in(&app, true).await;
+
+    age_sessions(&app, "10 days", "5 days").await;
+
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+    assert!(app.get_admin_sessions_html().await.contains("Remembered"));
+
+    age_sessions(&app, "31 days", "1 minute").await;
+
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn only_remembered_sessions_outlive_the_browser() {
+    let app = spawn_app().await;
+
+    let response = log_in(&app, false).await;
+    assert!(!session_cookie(&response, "id").contains("Max-Age"));
+    app.post_logout().await;
+
+    let response = log_in(&app, true).await;
+    assert!(session_cookie(&response, "id").contains(&format!("Max-Age={}", 30 * 24 * 60 * 60)));
+}
+
+#[tokio::test]
+async fn remember_me_is_offered_when_enabled() {
+    let app = spawn_app().await;
+
+    let html_page = app.get_login_html().await;
+
+    assert!(html_page.contains(r#"name="remember_me""#));
+    assert!(html_page.contains("Remember me for 30 days"));
+}
+
+#[tokio::test]
+async fn remember_me_is_ignored_when_disabled() {
+    let app = spawn_app_with(|c, _| c.application.session.remember_me_days = None).await;
+    assert!(!app.get_login_html().await.contains("remember_me"));
+    let response = log_in(&app, true).await;
+    assert!(!session_cookie(&response, "id").contains("Max-Age"));
+
+    age_sessions(&app, "3 hours", "3 hours").await;
+
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn the_session_cookie_follows_the_settings() {
+    let app = spawn_app_with(|c, _| {
+        c.application.session.cookie_name = "zero2prod_session".into();
+        c.application.session.same_site = SameSitePolicy::Strict;
+    })
+    .await;
+
+    let response = log_in(&app, true).await;
+
+    let cookie = session_cookie(&response, "zero2prod_session");
+    assert!(cookie.contains("SameSite=Strict"));
+    assert!(cookie.contains("HttpOnly"));
+    assert!(cookie.contains("Secure"));
+    assert!(cookie.contains(&format!("Max-Age={}", 30 * 24 * 60 * 60)));
+}
@@ -40,3 +40,22 @@ async fn signup_succeeds_with_valid_token() {
 
     assert!(html.contains("Successful signup!"));
 }
+
+#[tokio::test]
+async fn signup_rejects_passwords_against_the_policy() {
+    let app = spawn_app().await;
+
+    let body = serde_json::json!({
+        "username": "test",
+        "password": "password1234",
+        "signup_token": app.signup_token.expose_secret(),
+    });
+
+    let response = app.post_signup(&body).await;
+
+    assert_is_redirect_to(&response, "/signup");
+
+    let html = app.get_signup_html().await;
+
+    assert!(html.contains("This password is too common, please choose another one."));
+}
@@ -0,0 +1,398 @@
+use std::collections::HashMap;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use sha2::{Digest, Sha256};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with_sso, TestApp};
+
+async fn set_test_user_email(app: &TestApp, email: &str) {
+    sqlx::query!(
+        "UPDATE users SET email = $1 WHERE username = $2",
+        email,
+        app.user.username
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn the_login_page_offers_single_sign_on_when_configured() {
+    let app = spawn_app_with_sso(false).await;
+
+    let html_page = app.get_login_html().await;
+
+    assert!(html_page.contains(r#"href="/login/sso""#));
+    assert!(html_page.contains("Sign in with Example SSO"));
+}
+
+#[tokio::test]
+async fn single_sign_on_is_not_available_unless_configured() {
+    let app = spawn_app().await;
+
+    assert!(!app.get_login_html().await.contains("/login/sso"));
+    assert_eq!(app.start_sso_login().await.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn users_are_sent_to_the_provider_with_a_pkce_challenge() {
+    let app = spawn_app_with_sso(false).await;
+    app.mount_idp_discovery().await;
+
+    let response = app.start_sso_login().await;
+
+    assert_eq!(response.status().as_u16(), 303);
+    let location = reqwest::Url::parse(response.headers()["Location"].to_str().unwrap()).unwrap();
+    assert_eq!(
+        location.as_str().split('?').next().unwrap(),
+        format!("{}/authorize", app.idp_server.uri())
+    );
+    let params: HashMap<_, _> = location.query_pairs().into_owned().collect();
+    assert_eq!(params["response_type"], "code");
+    assert_eq!(params["client_id"], "zero2prod");
+    assert_eq!(
+        params["redirect_uri"],
+        format!("{}/login/sso/callback", app.base_url)
+    );
+    assert!(params["scope"].split(' ').any(|scope| scope == "openid"));
+    assert_eq!(params["code_challenge_method"], "S256");
+    assert!(!params["state"].is_empty());
+    assert!(!params["nonce"].is_empty());
+}
+
+#[tokio::test]
+async fn a_user_with_a_matching_email_is_logged_in() {
+    let app = spawn_app_with_sso(false).await;
+    set_test_user_email(&app, "ursula@example.com").await;
+
+    let response = app
+        .sso_login(serde_json::json!({ "email": "Ursula@example.com", "email_verified": true }))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", app.user.username)));
+    let event = sqlx::query!("SELECT changes FROM audit_log WHERE action = 'login'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(event.changes["method"], "sso");
+}
+
+#[tokio::test]
+async fn the_code_is_redeemed_with_the_verifier_behind_the_challenge() {
+    let app = spawn_app_with_sso(false).await;
+    set_test_user_email(&app, "ursula@example.com").await;
+    app.mount_idp_discovery().await;
+    let response = app.start_sso_login().await;
+    let authorization_url =
+        reqwest::Url::parse(response.headers()["Location"].to_str().unwrap()).unwrap();
+
+    app.complete_sso_login(
+        &authorization_url,
+        serde_json::json!({ "email": "ursula@example.com" }),
+    )
+    .await;
+
+    let requests = app.idp_server.received_requests().await.unwrap();
+    let token_request = requests
+        .iter()
+        .find(|request| request.url.path() == "/token")
+        .unwrap();
+    let form: HashMap<String, String> = serde_urlencoded::from_bytes(&token_request.body).unwrap();
+    assert_eq!(form["grant_type"], "authorization_code");
+    assert_eq!(form["code"], "authorization-code");
+    let params: HashMap<_, _> = authorization_url.query_pairs().into_owned().collect();
+    assert_eq!(
+        URL_SAFE_NO_PAD.encode(Sha256::digest(form["code_verifier"].as_bytes())),
+        params["code_challenge"]
+    );
+}
+
+#[tokio::test]
+async fn unknown_emails_are_refused_without_provisioning() {
+    let app = spawn_app_with_sso(false).await;
+
+    let response = app
+        .sso_login(serde_json::json!({ "email": "stranger@example.com" }))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("There is no account for stranger@example.com."));
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn unknown_emails_are_provisioned_with_the_mapped_role() {
+    let app = spawn_app_with_sso(true).await;
+
+    let response = app
+        .sso_login(serde_json::json!({
+            "email": "octavia@example.com",
+            "preferred_username": "octavia",
+            "groups": ["staff", "newsletter-editors"],
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let user = sqlx::query!("SELECT username, role FROM users WHERE email = 'octavia@example.com'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(user.username, "octavia");
+    assert_eq!(user.role, "editor");
+    let actions: Vec<String> = sqlx::query!("SELECT action FROM audit_log ORDER BY occurred_at")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|event| event.action)
+        .collect();
+    assert_eq!(actions, ["provision_user", "login"]);
+}
+
+#[tokio::test]
+async fn provisioned_users_without_a_mapped_group_get_the_default_role() {
+    let app = spawn_app_with_sso(true).await;
+
+    app.sso_login(serde_json::json!({ "email": "octavia@example.com" }))
+        .await;
+
+    let user = sqlx::query!("SELECT username, role FROM users WHERE email = 'octavia@example.com'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(user.username, "octavia");
+    assert_eq!(user.role, "viewer");
+}
+
+#[tokio::test]
+async fn the_group_mapping_is_applied_to_existing_users() {
+    let app = spawn_app_with_sso(false).await;
+    set_test_user_email(&app, "ursula@example.com").await;
+
+    let response = app
+        .sso_login(serde_json::json!({
+            "email": "ursula@example.com",
+            "groups": ["newsletter-editors"],
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let user = sqlx::query!(
+        "SELECT role FROM users WHERE username = $1",
+        app.user.username
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(user.role, "editor");
+}
+
+#[tokio::test]
+async fn unverified_emails_are_refused() {
+    let app = spawn_app_with_sso(false).await;
+    set_test_user_email(&app, "ursula@example.com").await;
+
+    // Without the claim the provider does not vouch for the address either.
+    for verified in [serde_json::json!(false), serde_json::Value::Null] {
+        let response = app
+            .sso_login(
+                serde_json::json!({ "email": "ursula@example.com", "email_verified": verified }),
+            )
+            .await;
+
+        assert_is_redirect_to(&response, "/login");
+        assert!(
+            app.get_login_html()
+                .await
+                .contains("did not share a verified email address"),
+            "email_verified {} was accepted",
+            verified
+        );
+        assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+    }
+}
+
+#[tokio::test]
+async fn a_provider_configuration_for_another_issuer_is_refused() {
+    let app = spawn_app_with_sso(false).await;
+    Mock::given(method("GET"))
+        .and(path("/.well-known/openid-configuration"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "issuer": "https://evil.example.com",
+            "authorization_endpoint": "https://evil.example.com/authorize",
+            "token_endpoint": "https://evil.example.com/token",
+        })))
+        .mount(&app.idp_server)
+        .await;
+
+    let response = app.start_sso_login().await;
+
+    assert_eq!(response.status().as_u16(), 500);
+}
+
+#[tokio::test]
+async fn a_callback_with_the_wrong_state_is_rejected() {
+    let app = spawn_app_with_sso(false).await;
+    set_test_user_email(&app, "ursula@example.com").await;
+    app.mount_idp_discovery().await;
+    app.start_sso_login().await;
+
+    let response = app
+        .get_sso_callback("code=authorization-code&state=forged")
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+    assert!(app.get_login_html().await.contains("has expired"));
+    let requests = app.idp_server.received_requests().await.unwrap();
+    assert!(!requests
+        .iter()
+        .any(|request| request.url.path() == "/token"));
+}
+
+#[tokio::test]
+async fn a_callback_without_a_pending_login_is_rejected() {
+    let app = spawn_app_with_sso(false).await;
+
+    let response = app
+        .get_sso_callback("code=authorization-code&state=anything")
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn id_tokens_with_the_wrong_nonce_or_audience_are_rejected() {
+    let app = spawn_app_with_sso(false).await;
+    set_test_user_email(&app, "ursula@example.com").await;
+
+    for claims in [
+        serde_json::json!({ "email": "ursula@example.com", "nonce": "replayed" }),
+        serde_json::json!({ "email": "ursula@example.com", "aud": "another-client" }),
+        serde_json::json!({ "email": "ursula@example.com", "iss": "https://evil.example.com" }),
+        serde_json::json!({ "email": "ursula@example.com", "exp": 1 }),
+    ] {
+        let response = app.sso_login(claims.clone()).await;
+
+        assert_is_redirect_to(&response, "/login");
+        assert!(
+            app.get_login_html().await.contains("invalid ID token"),
+            "{} was accepted",
+            claims
+        );
+        assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+    }
+}
+
+#[tokio::test]
+async fn errors_from_the_provider_are_shown() {
+    let app = spawn_app_with_sso(false).await;
+    app.mount_idp_discovery().await;
+    app.start_sso_login().await;
+
+    let response = app
+        .get_sso_callback("error=access_denied&error_description=User%20cancelled")
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+    assert!(app.get_login_html().await.contains("User cancelled"));
+}
+
+#[tokio::test]
+async fn deactivated_users_are_refused() {
+    let app = spawn_app_with_sso(false).await;
+    set_test_user_email(&app, "ursula@example.com").await;
+    sqlx::query!(
+        "UPDATE users SET deactivated_at = now() WHERE username = $1",
+        app.user.username
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = app
+        .sso_login(serde_json::json!({ "email": "ursula@example.com" }))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+    assert!(app.get_login_html().await.contains("deactivated"));
+    let requests = app.idp_server.received_requests().await.unwrap();
+    assert!(requests
+        .iter()
+        .any(|request| request.url.path() == "/token"));
+}
+
+#[tokio::test]
+async fn the_provider_configuration_is_fetched_once_for_several_sign_ins() {
+    let app = spawn_app_with_sso(false).await;
+    set_test_user_email(&app, "ursula@example.com").await;
+
+    for _ in 0..2 {
+        let response = app
+            .sso_login(serde_json::json!({ "email": "ursula@example.com" }))
+            .await;
+        assert_is_redirect_to(&response, "/admin/dashboard");
+    }
+
+    let requests = app.idp_server.received_requests().await.unwrap();
+    let discoveries = requests
+        .iter()
+        .filter(|request| request.url.path() == "/.well-known/openid-configuration")
+        .count();
+    assert_eq!(discoveries, 1);
+}
+
+#[tokio::test]
+async fn provisioned_users_get_a_free_username_when_theirs_are_taken() {
+    let app = spawn_app_with_sso(true).await;
+    for username in ["octavia", "octavia@example.com"] {
+        sqlx::query!(
+            "INSERT INTO users (user_id, username, password, role) \
+            SELECT gen_random_uuid(), $1, password, 'viewer' FROM users LIMIT 1",
+            username
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    }
+
+    let response = app
+        .sso_login(serde_json::json!({
+            "email": "octavia@example.com",
+            "preferred_username": "octavia",
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let user = sqlx::query!("SELECT username FROM users WHERE email = 'octavia@example.com'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(user.username.starts_with("octavia@example.com-"));
+}
+
+#[tokio::test]
+async fn addresses_differing_only_in_case_cannot_belong_to_two_users() {
+    let app = spawn_app_with_sso(false).await;
+    set_test_user_email(&app, "ursula@example.com").await;
+    let other = app.add_user("viewer").await;
+
+    let result = sqlx::query!(
+        "UPDATE users SET email = 'Ursula@Example.com' WHERE username = $1",
+        other.username
+    )
+    .execute(&app.db_pool)
+    .await;
+
+    let Err(sqlx::Error::Database(e)) = result else {
+        panic!("The address was accepted for a second user.");
+    };
+    assert_eq!(e.code().as_deref(), Some("23505"));
+}
@@ -3,7 +3,7 @@ use wiremock::{
     Mock, ResponseTemplate,
 };
 
-use crate::helpers::spawn_app;
+use crate::helpers::{spawn_app, TestApp};
 
 #[tokio::test]
 async fn subscribe_returns_ok_for_valid_form() {
@@ -138,3 +138,179 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
     // Assert
     assert_eq!(response.status().as_u16(), 500);
 }
+
+#[tokio::test]
+async fn subscribe_persists_the_tags_from_the_form() {
+    let test_app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&tags=Rust%2Cearly-adopter";
+
+    let response = test_app.post_subscriptions(body.into()).await;
+
+    assert_eq!(201, response.status().as_u16());
+    let saved = sqlx::query!("SELECT tags FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.tags, vec!["rust", "early-adopter"]);
+}
+
+#[tokio::test]
+async fn subscribe_returns_bad_request_for_invalid_tags() {
+    let test_app = spawn_app().await;
+
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&tags=not%20a%20tag";
+
+    let response = test_app.post_subscriptions(body.into()).await;
+
+    assert_eq!(400, response.status().as_u16());
+}
+
+async fn insert_list(app: &TestApp, slug: &str) {
+    sqlx::query!(
+        r#"
+        INSERT INTO lists (
+            list_id, slug, name, sender_name, sender_email,
+            confirmation_subject, confirmation_message, created_at
+        )
+        VALUES ($1, $2, 'Weekly digest', 'The Digest', 'digest@example.com',
+            'Confirm your digest subscription', 'Thanks for joining the digest!', now())
+        "#,
+        uuid::Uuid::new_v4(),
+        slug,
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to insert a list.");
+}
+
+#[tokio::test]
+async fn subscribe_returns_bad_request_for_an_unknown_list() {
+    let test_app = spawn_app().await;
+
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&list=does-not-exist";
+
+    let response = test_app.post_subscriptions(body.into()).await;
+
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn subscribe_to_a_list_uses_its_sender_and_confirmation_copy() {
+    let test_app = spawn_app().await;
+    insert_list(&test_app, "weekly-digest").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&list=weekly-digest";
+
+    let response = test_app.post_subscriptions(body.into()).await;
+
+    assert_eq!(201, response.status().as_u16());
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["From"], "\"The Digest\" <digest@example.com>");
+    assert_eq!(body["Subject"], "Confirm your digest subscription");
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains("Thanks for joining the digest!"));
+}
+
+#[tokio::test]
+async fn the_same_email_can_subscribe_to_two_lists() {
+    let test_app = spawn_app().await;
+    insert_list(&test_app, "weekly-digest").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+
+    for list in ["default", "weekly-digest"] {
+        let body = format!(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&list={}",
+            list
+        );
+        let response = test_app.post_subscriptions(body).await;
+        assert_eq!(201, response.status().as_u16());
+    }
+
+    let saved = sqlx::query!(
+        r#"
+        SELECT l.slug, ls.status
+        FROM list_subscriptions ls
+        JOIN lists l ON l.list_id = ls.list_id
+        ORDER BY l.slug
+        "#
+    )
+    .fetch_all(&test_app.db_pool)
+    .await
+    .expect("Failed to fetch saved list subscriptions.");
+    assert_eq!(saved.len(), 2);
+    assert_eq!(saved[0].slug, "default");
+    assert_eq!(saved[1].slug, "weekly-digest");
+    assert!(saved.iter().all(|s| s.status == "pending_confirmation"));
+}
+
+#[tokio::test]
+async fn resubmitting_an_address_does_not_change_its_tags_until_confirmed() {
+    let test_app = spawn_app().await;
+    insert_list(&test_app, "weekly-digest").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    let saved_tags = || async {
+        sqlx::query!("SELECT tags FROM subscriptions")
+            .fetch_one(&test_app.db_pool)
+            .await
+            .expect("Failed to fetch saved subscription.")
+            .tags
+    };
+    let confirm_last_email = || async {
+        let emails = test_app.email_server.received_requests().await.unwrap();
+        let links = test_app.get_confirmation_links(emails.last().unwrap());
+        reqwest::get(links.html)
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    };
+    test_app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com&tags=rust".into())
+        .await;
+    confirm_last_email().await;
+
+    // Anyone can submit the address of a confirmed subscriber.
+    let response = test_app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com&tags=vip".into())
+        .await;
+    assert_eq!(201, response.status().as_u16());
+    assert_eq!(saved_tags().await, vec!["rust"]);
+
+    let response = test_app
+        .post_subscriptions(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&tags=digest&list=weekly-digest".into(),
+        )
+        .await;
+    assert_eq!(201, response.status().as_u16());
+    assert_eq!(saved_tags().await, vec!["rust"]);
+
+    confirm_last_email().await;
+    assert_eq!(saved_tags().await, vec!["rust", "digest"]);
+}
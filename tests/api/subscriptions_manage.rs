@@ -0,0 +1,234 @@
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+/// Subscribe to the default list, returning the preference center link from
+/// the confirmation email.
+async fn subscribe(app: &TestApp) -> reqwest::Url {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let links = app.get_manage_links(email_request);
+    assert_eq!(links.html, links.plain_text);
+    links.html
+}
+
+#[tokio::test]
+async fn the_confirmation_email_links_to_the_preference_center() {
+    let app = spawn_app().await;
+    let link = subscribe(&app).await;
+
+    let response = app.get_manage_subscription(&link).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Preferences for ursula_le_guin@gmail.com"));
+}
+
+#[tokio::test]
+async fn a_link_with_a_tampered_signature_is_rejected() {
+    let app = spawn_app().await;
+    let mut link = subscribe(&app).await;
+    let query = link.query().unwrap().replace("signature=", "signature=00");
+    link.set_query(Some(&query));
+
+    let response = app.get_manage_subscription(&link).await;
+    assert_eq!(response.status().as_u16(), 401);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["code"], "invalid_manage_link");
+
+    let response = app
+        .post_manage_subscription(&link, &[("name", "Ursula")])
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_unsubscribe(&link).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn a_link_cannot_be_made_to_look_recent() {
+    let app = spawn_app().await;
+    let mut link = subscribe(&app).await;
+    let issued_at: i64 = link
+        .query_pairs()
+        .find(|(key, _)| key == "issued_at")
+        .unwrap()
+        .1
+        .parse()
+        .unwrap();
+    let query = link.query().unwrap().replace(
+        &format!("issued_at={}", issued_at),
+        &format!("issued_at={}", issued_at + 3600),
+    );
+    link.set_query(Some(&query));
+
+    let response = app.get_manage_subscription(&link).await;
+    assert_eq!(response.status().as_u16(), 401);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["code"], "invalid_manage_link");
+}
+
+#[tokio::test]
+async fn subscribers_can_update_their_name_topics_and_lists() {
+    let app = spawn_app().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO lists (list_id, slug, name, confirmation_subject, confirmation_message, created_at)
+        VALUES ($1, 'weekly-digest', 'Weekly digest', 'Welcome!', 'Welcome!', now())
+        "#,
+        uuid::Uuid::new_v4(),
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let link = subscribe(&app).await;
+
+    let response = app
+        .post_manage_subscription(
+            &link,
+            &[
+                ("name", "Ursula K. Le Guin"),
+                ("tags", "Rust, databases"),
+                ("lists", "weekly-digest"),
+                ("pause_weeks", ""),
+            ],
+        )
+        .await;
+    assert_is_redirect_to(&response, link.as_str().trim_start_matches(&app.url));
+
+    let html_page = app.get_manage_subscription_html(&link).await;
+    assert!(html_page.contains("Your preferences have been saved."));
+    let saved = sqlx::query!("SELECT name, tags, paused_until FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "Ursula K. Le Guin");
+    assert_eq!(saved.tags, vec!["rust", "databases"]);
+    assert!(saved.paused_until.is_none());
+    let lists = sqlx::query!(
+        r#"
+        SELECT l.slug, ls.status
+        FROM list_subscriptions ls
+        JOIN lists l ON l.list_id = ls.list_id
+        "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(lists.len(), 1);
+    assert_eq!(lists[0].slug, "weekly-digest");
+    assert_eq!(lists[0].status, "confirmed");
+}
+
+#[tokio::test]
+async fn invalid_preferences_are_rejected_with_a_flash_message() {
+    let app = spawn_app().await;
+    let link = subscribe(&app).await;
+
+    let test_cases = vec![
+        (vec![("name", "")], "is not a valid subscriber_name"),
+        (
+            vec![("name", "Ursula"), ("tags", "not valid")],
+            "not valid is not a valid subscriber tag",
+        ),
+        (
+            vec![("name", "Ursula"), ("lists", "does-not-exist")],
+            "One of the selected lists does not exist.",
+        ),
+        (
+            vec![("name", "Ursula")],
+            "Pick at least one list, or unsubscribe to stop receiving all emails.",
+        ),
+        (
+            vec![("name", "Ursula"), ("pause_weeks", "100")],
+            "Delivery can be paused for 0 to 52 weeks.",
+        ),
+    ];
+
+    for (body, error_message) in test_cases {
+        app.post_manage_subscription(&link, &body).await;
+
+        let html_page = app.get_manage_subscription_html(&link).await;
+        assert!(
+            html_page.contains(error_message),
+            "The page did not show `{}`.",
+            error_message
+        );
+    }
+    let saved = sqlx::query!("SELECT name FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "le guin");
+}
+
+#[tokio::test]
+async fn paused_subscribers_do_not_receive_newsletters() {
+    let app = spawn_app().await;
+    let link = subscribe(&app).await;
+    app.post_manage_subscription(
+        &link,
+        &[
+            ("name", "le guin"),
+            ("lists", "default"),
+            ("pause_weeks", "4"),
+        ],
+    )
+    .await;
+
+    let html_page = app.get_manage_subscription_html(&link).await;
+    assert!(html_page.contains("Delivery is paused until"));
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.user.login(&app).await;
+    app.post_newsletters(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    }))
+    .await;
+    app.dispatch_all_pending_deliveries().await;
+}
+
+#[tokio::test]
+async fn subscribers_can_unsubscribe_from_all_lists() {
+    let app = spawn_app().await;
+    let link = subscribe(&app).await;
+    app.post_manage_subscription(&link, &[("name", "le guin"), ("lists", "default")])
+        .await;
+
+    let response = app.post_unsubscribe(&link).await;
+    assert_is_redirect_to(&response, link.as_str().trim_start_matches(&app.url));
+
+    let html_page = app.get_manage_subscription_html(&link).await;
+    assert!(html_page.contains("You have been unsubscribed from all lists."));
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+    let memberships = sqlx::query!("SELECT list_id FROM list_subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(memberships.is_empty());
+}
@@ -0,0 +1,283 @@
+use chrono::Utc;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::authentication::totp::TotpSecret;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+/// A code for the time step `steps` away from the current one.
+fn code(secret: &TotpSecret, steps: i64) -> String {
+    secret.code_at((Utc::now().timestamp() + steps * 30) as u64)
+}
+
+/// Enroll the logged in test user, returning the secret and recovery codes.
+/// The code used to enroll is from the previous time step, so that the current
+/// one is still available to log in.
+async fn enable_two_factor(app: &TestApp) -> (TotpSecret, Vec<String>) {
+    let html_page = app.get_admin_two_factor_html().await;
+    let secret = html_page
+        .split(r#"<code id="totp-secret">"#)
+        .nth(1)
+        .and_then(|rest| rest.split("</code>").next())
+        .expect("The enrollment page does not show the secret.");
+    let secret = TotpSecret::parse(secret).unwrap();
+
+    let response = app
+        .post_enable_two_factor(&serde_json::json!({ "code": code(&secret, -1) }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Two-factor authentication has been enabled."));
+    let recovery_codes = html_page
+        .split(r#"<li class="recovery-code">"#)
+        .skip(1)
+        .map(|rest| rest.split("</li>").next().unwrap().to_string())
+        .collect();
+
+    (secret, recovery_codes)
+}
+
+async fn log_in_with_password(app: &TestApp) {
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": app.user.username,
+            "password": app.user.password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login/2fa");
+}
+
+#[tokio::test]
+async fn enrollment_shows_a_qr_code_and_an_otpauth_uri() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+
+    let html_page = app.get_admin_two_factor_html().await;
+
+    assert!(html_page.contains("<svg"));
+    assert!(html_page.contains("otpauth://totp/Zero2Prod:"));
+}
+
+#[tokio::test]
+async fn enrollment_requires_a_valid_code() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    app.get_admin_two_factor_html().await;
+
+    let response = app
+        .post_enable_two_factor(&serde_json::json!({ "code": "not-a-code" }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/2fa");
+
+    let html_page = app.get_admin_two_factor_html().await;
+    assert!(html_page.contains("Invalid authentication code."));
+    assert!(html_page.contains("Enable two-factor authentication"));
+}
+
+#[tokio::test]
+async fn enabling_shows_ten_recovery_codes() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+
+    let (_, recovery_codes) = enable_two_factor(&app).await;
+
+    assert_eq!(recovery_codes.len(), 10);
+    let html_page = app.get_admin_two_factor_html().await;
+    assert!(html_page.contains("10 unused recovery codes left."));
+    assert!(!html_page.contains(&recovery_codes[0]));
+}
+
+#[tokio::test]
+async fn a_pending_second_factor_is_not_logged_in() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    enable_two_factor(&app).await;
+    app.post_logout().await;
+
+    log_in_with_password(&app).await;
+
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_two_factor_html().await;
+    assert!(html_page.contains("Two-factor authentication"));
+}
+
+#[tokio::test]
+async fn a_valid_code_completes_the_login() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    let (secret, _) = enable_two_factor(&app).await;
+    app.post_logout().await;
+
+    log_in_with_password(&app).await;
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": code(&secret, 0) }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", app.user.username)));
+}
+
+#[tokio::test]
+async fn an_invalid_code_is_rejected() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    let (secret, _) = enable_two_factor(&app).await;
+    app.post_logout().await;
+
+    log_in_with_password(&app).await;
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": code(&secret, -10) }))
+        .await;
+    assert_is_redirect_to(&response, "/login/2fa");
+
+    let html_page = app.get_login_two_factor_html().await;
+    assert!(html_page.contains("Invalid authentication code."));
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_code_cannot_be_used_twice() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    let (secret, _) = enable_two_factor(&app).await;
+    app.post_logout().await;
+    let code = code(&secret, 0);
+
+    log_in_with_password(&app).await;
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": code }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    app.post_logout().await;
+
+    log_in_with_password(&app).await;
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": code }))
+        .await;
+    assert_is_redirect_to(&response, "/login/2fa");
+}
+
+#[tokio::test]
+async fn a_recovery_code_works_only_once() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    let (_, recovery_codes) = enable_two_factor(&app).await;
+    app.post_logout().await;
+
+    log_in_with_password(&app).await;
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": recovery_codes[0] }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_two_factor_html().await;
+    assert!(html_page.contains("9 unused recovery codes left."));
+    app.post_logout().await;
+
+    log_in_with_password(&app).await;
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": recovery_codes[0] }))
+        .await;
+    assert_is_redirect_to(&response, "/login/2fa");
+}
+
+#[tokio::test]
+async fn too_many_invalid_codes_require_the_password_again() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    enable_two_factor(&app).await;
+    app.post_logout().await;
+
+    log_in_with_password(&app).await;
+    for _ in 0..4 {
+        let response = app
+            .post_login_two_factor(&serde_json::json!({ "code": "wrong" }))
+            .await;
+        assert_is_redirect_to(&response, "/login/2fa");
+    }
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": "wrong" }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app.get_login_two_factor().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn wrong_codes_count_towards_the_lockout_of_the_account() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.user.login(&app).await;
+    enable_two_factor(&app).await;
+    app.post_logout().await;
+
+    // Entering the password again must not reset the count.
+    for _ in 0..2 {
+        log_in_with_password(&app).await;
+        for _ in 0..4 {
+            let response = app
+                .post_login_two_factor(&serde_json::json!({ "code": "wrong" }))
+                .await;
+            assert_is_redirect_to(&response, "/login/2fa");
+        }
+    }
+    log_in_with_password(&app).await;
+    app.post_login_two_factor(&serde_json::json!({ "code": "wrong" }))
+        .await;
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": "wrong" }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Too many failed login attempts."));
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": app.user.username,
+            "password": app.user.password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let emails = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&emails.last().unwrap().body).unwrap();
+    assert_eq!(body["To"], "alerts@example.com");
+}
+
+#[tokio::test]
+async fn disabling_requires_the_current_password() {
+    let app = spawn_app().await;
+    app.user.login(&app).await;
+    enable_two_factor(&app).await;
+
+    let response = app
+        .post_disable_two_factor(&serde_json::json!({ "password": "wrong-password" }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/2fa");
+    let html_page = app.get_admin_two_factor_html().await;
+    assert!(html_page.contains("The password is incorrect."));
+
+    let response = app
+        .post_disable_two_factor(&serde_json::json!({ "password": app.user.password }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/2fa");
+    let html_page = app.get_admin_two_factor_html().await;
+    assert!(html_page.contains("Two-factor authentication has been disabled."));
+    app.post_logout().await;
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": app.user.username,
+            "password": app.user.password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}